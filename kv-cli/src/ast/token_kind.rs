@@ -170,12 +170,22 @@ pub enum TokenKind {
     ADD,
     #[token("ANY", ignore(ascii_case))]
     ANY,
+    #[token("APPEND", ignore(ascii_case))]
+    APPEND,
     #[token("ARGS", ignore(ascii_case))]
     ARGS,
     #[token("AUTO", ignore(ascii_case))]
     AUTO,
+    #[token("CHECK", ignore(ascii_case))]
+    CHECK,
+    #[token("CHECKPOINT", ignore(ascii_case))]
+    CHECKPOINT,
     #[token("COMMENT", ignore(ascii_case))]
     COMMENT,
+    #[token("COMPACT", ignore(ascii_case))]
+    COMPACT,
+    #[token("COPY", ignore(ascii_case))]
+    COPY,
     #[token("CURRENT", ignore(ascii_case))]
     CURRENT,
     #[token("CURRENT_TIMESTAMP", ignore(ascii_case))]
@@ -186,8 +196,12 @@ pub enum TokenKind {
     DATETIME,
     #[token("DAY", ignore(ascii_case))]
     DAY,
+    #[token("DBSIZE", ignore(ascii_case))]
+    DBSIZE,
     #[token("DECIMAL", ignore(ascii_case))]
     DECIMAL,
+    #[token("DECRBY", ignore(ascii_case))]
+    DECRBY,
     #[token("DEFAULT", ignore(ascii_case))]
     DEFAULT,
     #[token("DELETE", ignore(ascii_case))]
@@ -200,20 +214,38 @@ pub enum TokenKind {
     DESCRIBE,
     #[token("DETECT", ignore(ascii_case))]
     DETECT,
+    #[token("DETECTSTATS", ignore(ascii_case))]
+    DETECTSTATS,
     #[token("DECODE", ignore(ascii_case))]
     DECODE,
+    #[token("DSCAN", ignore(ascii_case))]
+    DSCAN,
     #[token("ENCODE", ignore(ascii_case))]
     ENCODE,
     #[token("ENCODINGS", ignore(ascii_case))]
     ENCODINGS,
+    #[token("EXISTS", ignore(ascii_case))]
+    EXISTS,
     #[token("EXPIRE", ignore(ascii_case))]
     EXPIRE,
+    #[token("EXPORT", ignore(ascii_case))]
+    EXPORT,
+    #[token("FLUSHALL", ignore(ascii_case))]
+    FLUSHALL,
     #[token("FROM", ignore(ascii_case))]
     FROM,
     #[token("GET", ignore(ascii_case))]
     GET,
     #[token("GETSET", ignore(ascii_case))]
     GETSET,
+    #[token("HELP", ignore(ascii_case))]
+    HELP,
+    #[token("IMPORT", ignore(ascii_case))]
+    IMPORT,
+    #[token("INCR", ignore(ascii_case))]
+    INCR,
+    #[token("INCRBY", ignore(ascii_case))]
+    INCRBY,
     #[token("MDECODE", ignore(ascii_case))]
     MDECODE,
     #[token("MENCCODE", ignore(ascii_case))]
@@ -230,12 +262,26 @@ pub enum TokenKind {
     MINUTE,
     #[token("MONTH", ignore(ascii_case))]
     MONTH,
+    #[token("PATH", ignore(ascii_case))]
+    PATH,
     #[token("PATTERN", ignore(ascii_case))]
     PATTERN,
     #[token("PUT", ignore(ascii_case))]
     PUT,
+    #[token("RENAME", ignore(ascii_case))]
+    RENAME,
+    #[token("RENAMENX", ignore(ascii_case))]
+    RENAMENX,
+    #[token("REINDEX", ignore(ascii_case))]
+    REINDEX,
+    #[token("RESET", ignore(ascii_case))]
+    RESET,
     #[token("RLIKE", ignore(ascii_case))]
     RLIKE,
+    #[token("SAMPLE", ignore(ascii_case))]
+    SAMPLE,
+    #[token("SCAN", ignore(ascii_case))]
+    SCAN,
     #[token("SELECT", ignore(ascii_case))]
     SELECT,
     #[token("KEYS", ignore(ascii_case))]
@@ -250,8 +296,14 @@ pub enum TokenKind {
     SHOW,
     #[token("USAGE", ignore(ascii_case))]
     USAGE,
+    #[token("STAT", ignore(ascii_case))]
+    STAT,
+    #[token("STATS", ignore(ascii_case))]
+    STATS,
     #[token("STATUS", ignore(ascii_case))]
     STATUS,
+    #[token("SWAP", ignore(ascii_case))]
+    SWAP,
     #[token("STRING", ignore(ascii_case))]
     STRING,
     #[token("TIME", ignore(ascii_case))]
@@ -272,6 +324,8 @@ pub enum TokenKind {
     TIMEZONE,
     #[token("TOKEN", ignore(ascii_case))]
     TOKEN,
+    #[token("XGET", ignore(ascii_case))]
+    XGET,
     #[token("YEAR", ignore(ascii_case))]
     YEAR,
 }
@@ -292,6 +346,31 @@ pub enum Keywords {
     MGET,
     SETEX,
     SHOW,
+    COMPACT,
+    REINDEX,
+    SWAP,
+    CHECKPOINT,
+    DSCAN,
+    HELP,
+    COPY,
+    DETECTSTATS,
+    STATS,
+    SAMPLE,
+    XGET,
+    CHECK,
+    EXISTS,
+    INCR,
+    INCRBY,
+    DECRBY,
+    APPEND,
+    RENAME,
+    RENAMENX,
+    SCAN,
+    STAT,
+    EXPORT,
+    IMPORT,
+    FLUSHALL,
+    DBSIZE,
     EXIT,
 }
 
@@ -321,6 +400,31 @@ impl TokenKind {
                 | MGET
                 | SETEX
                 | SHOW
+                | COMPACT
+                | REINDEX
+                | SWAP
+                | CHECKPOINT
+                | DSCAN
+                | HELP
+                | COPY
+                | DETECTSTATS
+                | STATS
+                | SAMPLE
+                | XGET
+                | CHECK
+                | EXISTS
+                | INCR
+                | INCRBY
+                | DECRBY
+                | APPEND
+                | RENAME
+                | RENAMENX
+                | SCAN
+                | STAT
+                | EXPORT
+                | IMPORT
+                | FLUSHALL
+                | DBSIZE
                 | EXIT
         )
     }
@@ -3,7 +3,39 @@ use logos::{Lexer, Logos};
 use strum::IntoEnumIterator;
 use crate::ast::token_kind::{Keywords, TokenKind};
 
-type Result<T> = std::result::Result<T, String>;
+type Result<T> = std::result::Result<T, TokenizeError>;
+
+/// 词法分析错误，span 为无法识别部分在输入中的字节范围
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenizeError {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl TokenizeError {
+    fn new(source: &str, span: Range<usize>) -> Self {
+        let slice = &source[span.clone()];
+        let message = if slice.starts_with(['\'', '"', '`']) {
+            "unterminated quoted string".to_string()
+        } else {
+            format!("unrecognized token '{}'", slice)
+        };
+        TokenizeError { span, message }
+    }
+
+    /// 1-based column of the first unrecognized byte
+    pub fn column(&self) -> usize {
+        self.span.start + 1
+    }
+}
+
+impl std::fmt::Display for TokenizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column())
+    }
+}
+
+impl std::error::Error for TokenizeError {}
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct Token<'a> {
@@ -59,7 +91,7 @@ impl<'a> Iterator for Tokenizer<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.lexer.next() {
-            Some(Err(_)) => Some(Err("unable to recognize the rest tokens".to_string())),
+            Some(Err(_)) => Some(Err(TokenizeError::new(self.source, self.lexer.span()))),
             Some(Ok(kind)) => Some(Ok(Token {
                 source: self.source,
                 slice: self.lexer.slice(),
@@ -87,4 +119,18 @@ pub fn all_reserved_keywords() -> Vec<String> {
 
 pub fn tokenize_sql(sql: &str) -> Result<Vec<Token>> {
     Tokenizer::new(sql).collect::<Result<Vec<_>>>()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_quote_error() {
+        let err = tokenize_sql("SET a 'unterminated").unwrap_err();
+        assert_eq!(err.span.start, 6);
+        assert_eq!(err.to_string(), "unterminated quoted string at column 7");
+
+        let err = tokenize_sql("GET a\u{7f}").unwrap_err();
+        assert_eq!(err.column(), 6);
+    }
+}
@@ -0,0 +1,97 @@
+//! 内嵌的 HTTP API，通过 `kvcli http --addr <addr>` 启动，需要开启 feature `http`。
+//!
+//! - `GET /kv/:key`: 存在时返回 200 和 value 的原始字节，不存在时返回 404
+//! - `PUT /kv/:key`: 请求体即为 value，返回 200；请求体超过 [`MAX_BODY_BYTES`] 时返回 413
+//! - `DELETE /kv/:key`: 返回 200
+//!
+//! key 按 percent-encoding 解码(如 `/kv/a%20b` 对应 key `a b`)，value 按原始字节读写，不经过 encoding pipeline。
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use anyhow::Result;
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+use percent_encoding::percent_decode_str;
+use kv_rs::storage::async_engine::AsyncEngine;
+
+/// PUT 请求体的最大字节数
+pub const MAX_BODY_BYTES: usize = 16 << 20;
+
+/// 绑定 addr 并返回实际监听的地址(addr 的端口为 0 时由系统分配)以及运行服务的 future。
+/// running 被置为 false 后，服务在处理完当前请求后退出。
+pub fn serve(engine: AsyncEngine, addr: SocketAddr, running: Arc<AtomicBool>) -> Result<(SocketAddr, impl Future<Output = Result<()>>)> {
+    let make_svc = make_service_fn(move |_| {
+        let engine = engine.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(engine.clone(), req))) }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_svc);
+    let local_addr = server.local_addr();
+    info!("kvcli http api listening on {}", local_addr);
+
+    let server = server.with_graceful_shutdown(async move {
+        while running.load(Ordering::SeqCst) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    });
+    Ok((local_addr, async move { Ok(server.await?) }))
+}
+
+async fn handle(engine: AsyncEngine, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let key = match req.uri().path().strip_prefix("/kv/") {
+        Some(key) if !key.is_empty() => percent_decode_str(key).collect::<Vec<u8>>(),
+        _ => return Ok(status(StatusCode::NOT_FOUND)),
+    };
+
+    let rs = match *req.method() {
+        Method::GET => engine.get(key).await.map(|value| match value {
+            Some(value) => Response::new(Body::from(value)),
+            None => status(StatusCode::NOT_FOUND),
+        }),
+        Method::PUT => match read_body(req).await {
+            Ok(body) => engine.set(key, body).await.map(|_| status(StatusCode::OK)),
+            Err(code) => return Ok(status(code)),
+        },
+        Method::DELETE => engine.delete(key).await.map(|_| status(StatusCode::OK)),
+        _ => return Ok(status(StatusCode::METHOD_NOT_ALLOWED)),
+    };
+
+    Ok(rs.unwrap_or_else(|err| {
+        error!("http api error: {}", err);
+        status(StatusCode::INTERNAL_SERVER_ERROR)
+    }))
+}
+
+/// 读取请求体，超过 MAX_BODY_BYTES 时返回 413(先检查 Content-Length，再在读取过程中累计)
+async fn read_body(req: Request<Body>) -> Result<Vec<u8>, StatusCode> {
+    let content_length = req.headers().get(CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > MAX_BODY_BYTES as u64) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let mut body = req.into_body();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if buf.len() + chunk.len() > MAX_BODY_BYTES {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = code;
+    resp
+}
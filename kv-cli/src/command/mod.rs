@@ -1,6 +1,8 @@
 //! The various kinds of commands that `command` can execute.
 
 mod login;
+#[cfg(feature = "http")]
+pub mod http;
 
 use clap::Subcommand;
 use anyhow::Result;
@@ -31,6 +33,15 @@ pub enum Command {
         /// strategies besides classic username/password entry in legacy npm.
         auth_type: Option<String>,
     },
+
+    #[cfg(feature = "http")]
+    #[clap(name = "http")]
+    /// 🌐  serve GET/PUT/DELETE /kv/:key over HTTP, backed by the session engine.
+    Http {
+        #[clap(long = "addr", short = 'a', default_value = "127.0.0.1:8080")]
+        /// The address to listen on.
+        addr: std::net::SocketAddr,
+    },
 }
 
 /// Run a command with the given logger!
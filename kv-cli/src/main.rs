@@ -16,7 +16,7 @@ use log::info;
 use kv_rs::error::CResult;
 use kvcli::{command, PBAR, trace};
 use kvcli::command::{Command, run_pack};
-use kvcli::server::config::{ConfigLoad};
+use kvcli::server::config::{ConfigLoad, OutputFormat};
 use kvcli::server::session;
 
 #[derive(Debug, Parser, PartialEq)]
@@ -50,6 +50,14 @@ pub struct Args {
 
     #[clap(long, require_equals = true, help = "Query to execute")]
     query: Option<String>,
+
+    /// Base directory for a relative config path and data_dir, so the database does not depend on the working directory
+    #[clap(long = "base-dir", help = "Base directory for relative config and data paths")]
+    base_dir: Option<PathBuf>,
+
+    /// Output format of query results, overrides output_format in the config file
+    #[clap(long = "output", value_enum, help = "Output format of query results")]
+    output: Option<OutputFormat>,
 }
 
 /// CMD like:
@@ -86,7 +94,11 @@ pub async fn main() -> Result<()> {
         return Ok(());
     }
 
-    let mut cfg = match ConfigLoad::new(args.config.as_ref()) {
+    let config_path = match &args.base_dir {
+        Some(base_dir) => base_dir.join(&args.config).to_string_lossy().to_string(),
+        None => args.config.clone(),
+    };
+    let mut cfg = match ConfigLoad::new(&config_path) {
         Ok(c) => {
             c
         }
@@ -94,6 +106,12 @@ pub async fn main() -> Result<()> {
             ConfigLoad::default()
         }
     };
+    if let Some(base_dir) = &args.base_dir {
+        cfg.set_base_dir(Some(base_dir.to_string_lossy().to_string()));
+    }
+    if let Some(output) = args.output {
+        cfg.set_output_format(output);
+    }
     if args.debug {
         println!("{:?}", &cfg);
         eprintln!();
@@ -120,18 +138,28 @@ pub async fn main() -> Result<()> {
 
     info!("kvcli starting, Prepare Running packet with is_repl[{}].", is_repl);
 
+    #[cfg(feature = "http")]
+    if let Some(Command::Http { addr }) = args.cmd {
+        let (_, server) = command::http::serve(session.engine(), addr, running.clone())?;
+        return server.await;
+    }
+
     if is_repl {
         session.handle_repl().await;
         return Ok(());
     }
 
-    match args.query {
+    let rs = match args.query {
         None => {
-            session.handle_reader(stdin().lock()).await?;
+            session.handle_reader(stdin().lock()).await
         },
         Some(query) => {
-            session.handle_reader(std::io::Cursor::new(query)).await?;
+            session.handle_reader(std::io::Cursor::new(query)).await
         }
+    };
+    // 错误已由 session 输出，这里只设置退出码
+    if rs.is_err() {
+        std::process::exit(1);
     }
 
     run_pack(args.cmd.unwrap())?;
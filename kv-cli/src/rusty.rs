@@ -15,11 +15,14 @@ use rustyline::Helper;
 use rustyline::Result;
 use crate::ast::token_kind::TokenKind;
 use crate::ast::tokenizer::{all_reserved_keywords, tokenize_sql};
+use crate::show::numeric_key_cmp;
 
 /// CliHelper
 pub struct CliHelper {
     completer: FilenameCompleter,
     keywords: Arc<Vec<String>>,
+    /// 补全候选按数字大小排列纯数字片段，见 [`numeric_key_cmp`]
+    numeric_key_order: bool,
 }
 
 impl CliHelper {
@@ -27,6 +30,7 @@ impl CliHelper {
         Self {
             completer: FilenameCompleter::new(),
             keywords: Arc::new(Vec::new()),
+            numeric_key_order: false,
         }
     }
 
@@ -34,8 +38,19 @@ impl CliHelper {
         Self {
             completer: FilenameCompleter::new(),
             keywords,
+            numeric_key_order: false,
         }
     }
+
+    pub fn with_numeric_key_order(mut self, numeric_key_order: bool) -> Self {
+        self.numeric_key_order = numeric_key_order;
+        self
+    }
+
+    /// 补全 line 末尾的单词，候选为保留关键字和 keywords
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Pair>) {
+        KeyWordCompleter::complete(line, pos, &self.keywords, self.numeric_key_order)
+    }
 }
 
 impl Highlighter for CliHelper {
@@ -105,7 +120,7 @@ impl Hinter for CliHelper {
             return None;
         }
 
-        let (_, res) = KeyWordCompleter::complete(line, pos, &self.keywords);
+        let (_, res) = self.candidates(line, pos);
         if !res.is_empty() {
             Some(res[0].replacement[last_word.len()..].to_owned())
         } else {
@@ -123,7 +138,7 @@ impl Completer for CliHelper {
         pos: usize,
         ctx: &Context<'_>,
     ) -> std::result::Result<(usize, Vec<Pair>), ReadlineError> {
-        let keyword_candidates = KeyWordCompleter::complete(line, pos, self.keywords.as_ref());
+        let keyword_candidates = self.candidates(line, pos);
         if !keyword_candidates.1.is_empty() {
             return Ok(keyword_candidates);
         }
@@ -147,7 +162,7 @@ impl Helper for CliHelper {}
 struct KeyWordCompleter {}
 
 impl KeyWordCompleter {
-    fn complete(s: &str, pos: usize, keywords: &[String]) -> (usize, Vec<Pair>) {
+    fn complete(s: &str, pos: usize, keywords: &[String], numeric_key_order: bool) -> (usize, Vec<Pair>) {
         let hint = s
             .split(|p: char| p.is_whitespace() || p == '.')
             .last()
@@ -163,19 +178,23 @@ impl KeyWordCompleter {
             })
             .collect();
 
-        results.extend(
-            keywords
-                .iter()
-                .filter(|keyword| {
-                    keyword
-                        .to_lowercase()
-                        .starts_with(&hint.to_ascii_lowercase())
-                })
-                .map(|keyword| Pair {
-                    display: keyword.to_string(),
-                    replacement: keyword.to_string(),
-                }),
-        );
+        let mut matched: Vec<&String> = keywords
+            .iter()
+            .filter(|keyword| {
+                keyword
+                    .to_lowercase()
+                    .starts_with(&hint.to_ascii_lowercase())
+            })
+            .collect();
+        if numeric_key_order {
+            matched.sort_by(|a, b| numeric_key_cmp(a, b));
+        } else {
+            matched.sort();
+        }
+        results.extend(matched.into_iter().map(|keyword| Pair {
+            display: keyword.to_string(),
+            replacement: keyword.to_string(),
+        }));
 
         if pos >= hint.len() {
             (pos - hint.len(), results)
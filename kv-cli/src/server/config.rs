@@ -1,5 +1,6 @@
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use anyhow::anyhow;
 use serde_derive::{Serialize, Deserialize};
 use kv_rs::error::CResult;
@@ -10,6 +11,33 @@ const DEFAULT_DB: &str = "kvdb";
 pub const DEFAULT_PROMPT: &str = "kvcli";
 pub const DEFAULT_DB_NAME: &str = "kvdb";
 pub const AUTO_APPEND_PART_CMD_SYMBOL: char = ';';
+pub const DEFAULT_EMPTY_VALUE_MARKER: &str = "(empty)";
+/// 非交互模式下单行输入的默认上限，64MiB
+pub const DEFAULT_MAX_LINE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// 相对路径的 data_dir 的解析方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataDirMode {
+    /// 相对于当前工作目录，在不同目录下启动会打开不同的数据库
+    #[default]
+    Cwd,
+
+    /// 相对于可执行文件所在的目录，与启动时的工作目录无关
+    Exe,
+}
+
+/// GET、MGET、KEYS、STAT 等命令结果的输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// 供人阅读的文本
+    #[default]
+    Text,
+
+    /// 每条结果输出一行 JSON，便于脚本解析
+    Json,
+}
 
 /// Encoding configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +48,14 @@ pub struct EncodingConfig {
     pub auto_detect: bool,
     /// Batch processing size for bulk operations
     pub batch_size: usize,
+    /// Codecs applied in order to values on SET, and in reverse order on GET, e.g. ["json", "base64"].
+    /// Empty means values are stored as is.
+    #[serde(default)]
+    pub pipeline: Vec<String>,
+    /// Format marker prefixes, e.g. {"b64:": "base64"}. Values starting with a marker are detected
+    /// as its format without scoring, and the marker is stripped before decoding.
+    #[serde(default)]
+    pub format_markers: BTreeMap<String, String>,
 }
 
 impl Default for EncodingConfig {
@@ -28,6 +64,8 @@ impl Default for EncodingConfig {
             default_format: "base64".to_string(),
             auto_detect: true,
             batch_size: 100,
+            pipeline: vec![],
+            format_markers: BTreeMap::new(),
         }
     }
 }
@@ -44,10 +82,38 @@ impl EncodingConfig {
         self.default_format = format.to_string();
     }
 
+    /// Get the value pipeline as EncodingFormat list
+    pub fn get_pipeline(&self) -> Result<Vec<EncodingFormat>, anyhow::Error> {
+        self.pipeline
+            .iter()
+            .map(|name| name.parse().map_err(|e| anyhow!("Invalid pipeline encoding format '{}': {}", name, e)))
+            .collect()
+    }
+
+    /// Get the format markers as (marker, EncodingFormat) pairs
+    pub fn get_format_markers(&self) -> Result<Vec<(String, EncodingFormat)>, anyhow::Error> {
+        self.format_markers
+            .iter()
+            .map(|(marker, name)| {
+                if marker.is_empty() {
+                    return Err(anyhow!("Format marker for '{}' must not be empty", name));
+                }
+                let format = name.parse().map_err(|e| anyhow!("Invalid format '{}' for marker '{}': {}", name, marker, e))?;
+                Ok((marker.clone(), format))
+            })
+            .collect()
+    }
+
     /// Validate the encoding configuration
     pub fn validate(&self) -> Result<(), anyhow::Error> {
         // Validate default format
         self.get_default_format()?;
+
+        // Validate pipeline formats
+        self.get_pipeline()?;
+
+        // Validate format markers
+        self.get_format_markers()?;
         
         // Validate batch size
         if self.batch_size == 0 {
@@ -71,9 +137,22 @@ pub struct ConfigLoad {
 
     /// load config path, default '${pwd}/data'
     data_dir: String,
+    /// data_dir 为相对路径时的解析方式，default 'cwd'
+    data_dir_mode: Option<DataDirMode>,
+    /// 相对路径 data_dir 的基准目录，优先于 data_dir_mode，可通过 `--base-dir` 指定。 default None
+    base_dir: Option<String>,
     /// compact_threshold, default '0.2
     compact_threshold: f64,
 
+    /// 预估可回收的字节数低于该值时，启动时及 COMPACT 命令均跳过 compact。 default 0, 不限制
+    min_reclaim_bytes: Option<u64>,
+
+    /// GET 读缓存的容量，字节。 default 0, 不缓存
+    read_cache_size: Option<u64>,
+
+    /// 启动时预先读入读缓存的热点key前缀，需同时配置 read_cache_size。 default None
+    warmup_prefixes: Option<Vec<String>>,
+
     /// prompt, default 'kvcli'
     pub prompt: Option<String>,
 
@@ -102,6 +181,26 @@ pub struct ConfigLoad {
     /// Encoding configuration
     pub encoding: Option<EncodingConfig>,
 
+    /// 输出key时隐藏的前缀，如 'user:'，仅影响显示。 default None
+    strip_prefix: Option<String>,
+
+    /// GET 到空 value 时输出的标记，与 key 不存在时的 'N/A' 区分。设置为空字符串时原样输出空行。 default '(empty)'
+    empty_value_marker: Option<String>,
+
+    /// 存活key数量的上限，新增key超过时 SET 报错，更新已有的key不受影响。 default None, 不限制
+    max_keys: Option<u64>,
+
+    /// KEYS/DSCAN 等命令最多输出的行数，超出部分只输出 "... (N more)"。 default None, 不限制
+    max_display_rows: Option<u64>,
+
+    /// KEYS 输出和补全时按数字大小排列key中的纯数字片段，如 '2' 在 '10' 之前，仅影响显示。 default false, 按字典序
+    numeric_key_order: Option<bool>,
+
+    /// 非交互模式(管道/文件)下单行输入的最大字节数，超出时报错而不是将整行读入内存。 default 64MiB
+    max_line_bytes: Option<u64>,
+
+    /// 命令结果的输出格式 'text' 或 'json'，可通过 `--output` 指定。 default 'text'
+    output_format: Option<OutputFormat>,
 }
 
 impl Default for ConfigLoad {
@@ -110,7 +209,12 @@ impl Default for ConfigLoad {
             version: 1,
             api_key: "".to_string(),
             data_dir: "storage".to_owned(),
+            data_dir_mode: Some(DataDirMode::Cwd),
+            base_dir: None,
             compact_threshold: 0.2,
+            min_reclaim_bytes: Some(0),
+            read_cache_size: Some(0),
+            warmup_prefixes: None,
             prompt: Some(DEFAULT_PROMPT.to_string()),
             show_stats: Some(false),
             auto_append_part_cmd: Some(false),
@@ -120,6 +224,13 @@ impl Default for ConfigLoad {
             progress_color: None,
             show_progress: Some(false),
             encoding: Some(EncodingConfig::default()),
+            strip_prefix: None,
+            empty_value_marker: Some(DEFAULT_EMPTY_VALUE_MARKER.to_string()),
+            max_keys: None,
+            max_display_rows: None,
+            numeric_key_order: Some(false),
+            max_line_bytes: Some(DEFAULT_MAX_LINE_BYTES),
+            output_format: Some(OutputFormat::Text),
         }
     }
 }
@@ -139,7 +250,12 @@ impl ConfigLoad {
             .set_default("version", df.version)?
             .set_default("api_key", df.api_key)?
             .set_default("data_dir", df.data_dir)?
+            .set_default("data_dir_mode", "cwd")?
+            .set_default("base_dir", df.base_dir)?
             .set_default("compact_threshold", 0.2)?
+            .set_default("min_reclaim_bytes", df.min_reclaim_bytes)?
+            .set_default("read_cache_size", df.read_cache_size)?
+            .set_default("warmup_prefixes", df.warmup_prefixes)?
             .set_default("prompt", df.prompt)?
             .set_default("show_stats", df.show_stats)?
             .set_default("auto_append_part_cmd", df.auto_append_part_cmd)?
@@ -151,6 +267,13 @@ impl ConfigLoad {
             .set_default("encoding.default_format", "base64")?
             .set_default("encoding.auto_detect", true)?
             .set_default("encoding.batch_size", 100)?
+            .set_default("strip_prefix", df.strip_prefix)?
+            .set_default("empty_value_marker", df.empty_value_marker)?
+            .set_default("max_keys", df.max_keys)?
+            .set_default("max_display_rows", df.max_display_rows)?
+            .set_default("numeric_key_order", df.numeric_key_order)?
+            .set_default("max_line_bytes", df.max_line_bytes)?
+            .set_default("output_format", "text")?
             .add_source(config::File::with_name(file))
             .add_source(config::Environment::with_prefix("KVDB"))
             .build()?
@@ -158,14 +281,80 @@ impl ConfigLoad {
     }
 
     /// load config path
+    ///
+    /// 绝对路径原样使用；相对路径优先相对于 base_dir，其次按 data_dir_mode 相对于可执行文件所在目录或当前工作目录。
     pub fn get_data_dir(&self) -> PathBuf {
-        std::path::Path::new(&self.data_dir).join(DEFAULT_DB)
+        let data_dir = Path::new(&self.data_dir);
+        if data_dir.is_absolute() {
+            return data_dir.join(DEFAULT_DB);
+        }
+
+        let base = match (&self.base_dir, self.get_data_dir_mode()) {
+            (Some(base_dir), _) => Some(PathBuf::from(base_dir)),
+            (None, DataDirMode::Exe) => match std::env::current_exe() {
+                Ok(exe) => exe.parent().map(Path::to_path_buf),
+                Err(err) => {
+                    log::warn!("can not locate the executable, resolve data_dir against the working directory: {}", err);
+                    None
+                }
+            },
+            (None, DataDirMode::Cwd) => None,
+        };
+        match base {
+            Some(base) => base.join(data_dir).join(DEFAULT_DB),
+            None => data_dir.join(DEFAULT_DB),
+        }
+    }
+
+    /// data_dir 为相对路径时的解析方式
+    pub fn get_data_dir_mode(&self) -> DataDirMode {
+        self.data_dir_mode.unwrap_or_default()
+    }
+
+    pub fn set_data_dir_mode(&mut self, data_dir_mode: DataDirMode) {
+        self.data_dir_mode = Some(data_dir_mode);
+    }
+
+    /// 相对路径 data_dir 的基准目录
+    pub fn get_base_dir(&self) -> Option<&str> {
+        self.base_dir.as_deref()
+    }
+
+    pub fn set_base_dir(&mut self, base_dir: Option<String>) {
+        self.base_dir = base_dir;
     }
 
     pub fn get_compact_threshold(&self) -> f64 {
         self.compact_threshold
     }
 
+    /// compact 至少要回收的字节数
+    pub fn get_min_reclaim_bytes(&self) -> u64 {
+        self.min_reclaim_bytes.unwrap_or(0)
+    }
+
+    pub fn set_min_reclaim_bytes(&mut self, min_reclaim_bytes: u64) {
+        self.min_reclaim_bytes = Some(min_reclaim_bytes);
+    }
+
+    /// GET 读缓存的容量，字节
+    pub fn get_read_cache_size(&self) -> u64 {
+        self.read_cache_size.unwrap_or(0)
+    }
+
+    pub fn set_read_cache_size(&mut self, read_cache_size: u64) {
+        self.read_cache_size = Some(read_cache_size);
+    }
+
+    /// 启动时预热读缓存的key前缀
+    pub fn get_warmup_prefixes(&self) -> &[String] {
+        self.warmup_prefixes.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_warmup_prefixes(&mut self, warmup_prefixes: Option<Vec<String>>) {
+        self.warmup_prefixes = warmup_prefixes;
+    }
+
     /// fix part cmd options. default false
     pub fn get_auto_append_part_cmd(&self) -> bool {
         if self.auto_append_part_cmd.is_none() {
@@ -181,7 +370,7 @@ impl ConfigLoad {
     }
 
     /// change cmd:
-    /// show_progress、show_stats、show_affected、auto_append_part_cmd、auto_append_part_cmd_symbol、multi_line、replace_newline
+    /// show_progress、show_stats、show_affected、auto_append_part_cmd、auto_append_part_cmd_symbol、multi_line、replace_newline、strip_prefix、empty_value_marker、max_keys、max_display_rows、numeric_key_order、max_line_bytes、min_reclaim_bytes、read_cache_size、output_format
     /// default_encoding_format、auto_detect、batch_size
     pub fn inject_cmd(&mut self, cmd_name: &str, cmd_value: &str) -> anyhow::Result<()> {
         match cmd_name {
@@ -196,6 +385,19 @@ impl ConfigLoad {
             "auto_append_part_cmd" => self.auto_append_part_cmd = Some(cmd_value.parse()?),
             "multi_line" => self.multi_line = Some(cmd_value.parse()?),
             "replace_newline" => self.replace_newline = Some(cmd_value.parse()?),
+            "strip_prefix" => self.set_strip_prefix(Some(cmd_value.to_string())),
+            "empty_value_marker" => self.set_empty_value_marker(Some(cmd_value.to_string())),
+            "max_keys" => self.set_max_keys(Some(cmd_value.parse()?)),
+            "max_display_rows" => self.set_max_display_rows(Some(cmd_value.parse()?)),
+            "numeric_key_order" => self.set_numeric_key_order(cmd_value.parse()?),
+            "max_line_bytes" => self.set_max_line_bytes(cmd_value.parse()?),
+            "min_reclaim_bytes" => self.set_min_reclaim_bytes(cmd_value.parse()?),
+            "read_cache_size" => self.set_read_cache_size(cmd_value.parse()?),
+            "output_format" => {
+                let format = <OutputFormat as clap::ValueEnum>::from_str(cmd_value, true)
+                    .map_err(|e| anyhow!("Invalid output format '{}': {}", cmd_value, e))?;
+                self.set_output_format(format);
+            },
             // encoding
             "default_encoding_format" => {
                 let format: EncodingFormat = cmd_value.parse()
@@ -240,6 +442,81 @@ impl ConfigLoad {
         self.show_affected= Some(v)
     }
 
+    /// 输出key时隐藏的前缀
+    pub fn get_strip_prefix(&self) -> Option<&str> {
+        self.strip_prefix.as_deref().filter(|p| !p.is_empty())
+    }
+
+    pub fn set_strip_prefix(&mut self, prefix: Option<String>) {
+        self.strip_prefix = prefix;
+    }
+
+    /// 去掉key的显示前缀，key不以该前缀开头时原样返回
+    pub fn display_key<'a>(&self, key: &'a str) -> &'a str {
+        match self.get_strip_prefix() {
+            Some(prefix) => key.strip_prefix(prefix).unwrap_or(key),
+            None => key,
+        }
+    }
+
+    /// 存活key数量的上限
+    pub fn get_max_keys(&self) -> Option<u64> {
+        self.max_keys
+    }
+
+    pub fn set_max_keys(&mut self, max_keys: Option<u64>) {
+        self.max_keys = max_keys;
+    }
+
+    /// 命令最多输出的行数
+    pub fn get_max_display_rows(&self) -> Option<u64> {
+        self.max_display_rows
+    }
+
+    pub fn set_max_display_rows(&mut self, max_display_rows: Option<u64>) {
+        self.max_display_rows = max_display_rows;
+    }
+
+    /// 是否按数字大小排列key中的纯数字片段
+    pub fn is_numeric_key_order(&self) -> bool {
+        self.numeric_key_order.unwrap_or(false)
+    }
+
+    pub fn set_numeric_key_order(&mut self, numeric_key_order: bool) {
+        self.numeric_key_order = Some(numeric_key_order);
+    }
+
+    /// 非交互模式下单行输入的最大字节数
+    pub fn get_max_line_bytes(&self) -> u64 {
+        self.max_line_bytes.unwrap_or(DEFAULT_MAX_LINE_BYTES)
+    }
+
+    pub fn set_max_line_bytes(&mut self, max_line_bytes: u64) {
+        self.max_line_bytes = Some(max_line_bytes);
+    }
+
+    /// 命令结果的输出格式
+    pub fn get_output_format(&self) -> OutputFormat {
+        self.output_format.unwrap_or_default()
+    }
+
+    pub fn set_output_format(&mut self, output_format: OutputFormat) {
+        self.output_format = Some(output_format);
+    }
+
+    pub fn is_output_json(&self) -> bool {
+        self.get_output_format() == OutputFormat::Json
+    }
+
+    /// GET 到空 value 时输出的标记
+    pub fn get_empty_value_marker(&self) -> &str {
+        self.empty_value_marker.as_deref().unwrap_or(DEFAULT_EMPTY_VALUE_MARKER)
+    }
+
+    pub fn set_empty_value_marker(&mut self, marker: Option<String>) {
+        self.empty_value_marker = marker;
+    }
+
     /// Get encoding configuration with defaults
     pub fn get_encoding_config(&self) -> EncodingConfig {
         self.encoding.clone().unwrap_or_default()
@@ -1,30 +1,45 @@
 use std::convert::Infallible;
-use std::io::BufRead;
-use std::sync::Arc;
+use std::io::{BufRead, BufWriter, ErrorKind, Read, Write};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use crate::server::config::{ConfigLoad, DEFAULT_PROMPT};
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Local};
 use log::{info, debug, warn};
+use serde_derive::Serialize;
+use serde_json::json;
 use rustyline::{CompletionType, Editor};
 use rustyline::config::Builder;
 use rustyline::error::ReadlineError;
 use rustyline::history::DefaultHistory;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
 use tokio::time::Instant;
+use tokio_stream::StreamExt;
 use kv_rs::error::{CResult, Error};
-use kv_rs::info::get_info;
+use kv_rs::info::{format_fields, get_info_fields, status_fields};
 use kv_rs::row::rows::ServerStats;
-use kv_rs::storage::engine::Engine;
+use kv_rs::storage::async_engine::{AsyncEngine, DEFAULT_SCAN_BUFFER};
+use kv_rs::storage::dump::{self, DumpFormat};
+use kv_rs::storage::engine::{prefix_range, Engine};
+use kv_rs::storage::glob::{glob_literal_prefix, glob_match};
 use kv_rs::storage::log_cask::LogCask;
-use kv_rs::encoding::{EncodingEngine, EncodingFormat, EncodingError, Base64Codec, HexCodec, JsonCodec};
+use kv_rs::storage::ScanCursor;
+use kv_rs::encoding::{EncodingEngine, EncodingFormat, EncodingError, Base64Codec, Base64UrlCodec, Base32Codec, HexCodec, JsonCodec};
 use crate::ast::token_kind::TokenKind;
 use crate::ast::tokenizer::{Token, Tokenizer};
 use crate::rusty::CliHelper;
-use crate::show::Show;
+use crate::show::{hex_dump, numeric_key_cmp, Show};
 
 pub const SET_RESP_STR: &str = "OK";
 pub const GET_RESP_NOT_FOUND_STR: &str = "N/A";
 pub const SET_RESP_BYE_STR: &str = "Bye~";
+/// KEYS 每输出多少行 flush 一次
+const KEYS_FLUSH_ROWS: i64 = 128;
+
+/// SCAN 未指定 COUNT 时每页返回的key数量
+const DEFAULT_SCAN_COUNT: usize = 10;
 
 /// Session and kv storage cmd and running
 pub struct Session {
@@ -32,14 +47,19 @@ pub struct Session {
     debug_mode: bool,
 
     running: Arc<AtomicBool>,
-    engine: LogCask,
+    engine: AsyncEngine,
     encoding_engine: EncodingEngine,
+    /// SET/GET 时对 value 依次应用的编码，见 EncodingConfig::pipeline
+    value_pipeline: Vec<EncodingFormat>,
 
     settings: ConfigLoad,
     query: String,
     in_comment_block: bool,
 
     keywords: Arc<Vec<String>>,
+
+    /// 命令结果的输出，默认为带缓冲的 stdout，每条命令执行完后 flush
+    output: Box<dyn Write + Send>,
 }
 
 impl Session {
@@ -58,9 +78,22 @@ impl Session {
         
         // Register all available codecs
         encoding_engine.register_codec(EncodingFormat::Base64, Box::new(Base64Codec::new()));
+        encoding_engine.register_codec(EncodingFormat::Base64Url, Box::new(Base64UrlCodec::new()));
+        encoding_engine.register_codec(EncodingFormat::Base32, Box::new(Base32Codec::new()));
         encoding_engine.register_codec(EncodingFormat::Hex, Box::new(HexCodec::new()));
         encoding_engine.register_codec(EncodingFormat::Json, Box::new(JsonCodec::new()));
         
+        // Every pipeline stage must have a registered codec
+        let pipeline = settings.get_encoding_config().get_pipeline()?;
+        if !pipeline.is_empty() {
+            encoding_engine.validate_pipeline(&pipeline)
+                .map_err(|e| anyhow!("Invalid encoding pipeline: {}", e))?;
+        }
+
+        for (marker, format) in settings.get_encoding_config().get_format_markers()? {
+            encoding_engine.add_format_marker(marker, format);
+        }
+
         info!("Encoding engine initialized with default format: {}", default_format);
         info!("Auto-detection enabled: {}", settings.is_auto_detect_enabled());
         info!("Batch size: {}", settings.get_batch_size());
@@ -75,10 +108,23 @@ impl Session {
             println!();
         }
 
-        let engine = LogCask::new_compact(settings.get_data_dir().clone(), settings.get_compact_threshold())?;
+        let mut cask = LogCask::new_compact_min_reclaim(
+            settings.get_data_dir().clone(),
+            settings.get_compact_threshold(),
+            settings.get_min_reclaim_bytes(),
+        )?;
+        cask.set_max_keys(settings.get_max_keys());
+        cask.set_read_cache_size(settings.get_read_cache_size() as usize);
+        if !settings.get_warmup_prefixes().is_empty() {
+            let prefixes: Vec<Vec<u8>> = settings.get_warmup_prefixes().iter().map(|p| p.as_bytes().to_vec()).collect();
+            let warmed = cask.warmup(&prefixes)?;
+            info!("Warmed up {} keys into the read cache", warmed);
+        }
+        let engine = AsyncEngine::new(cask);
         
         // Initialize encoding engine with configuration
         let encoding_engine = Self::initialize_encoding_engine(&settings)?;
+        let value_pipeline = settings.get_encoding_config().get_pipeline()?;
 
         let mut keywords = Vec::with_capacity(1024);
 
@@ -88,18 +134,157 @@ impl Session {
             running,
             engine,
             encoding_engine,
+            value_pipeline,
             settings,
             query: String::new(),
             in_comment_block: false,
             keywords: Arc::new(keywords),
+            output: Box::new(BufWriter::new(std::io::stdout())),
         })
     }
 
+    /// 共享的存储引擎，clone 后可以在其他任务中使用，如 HTTP API
+    pub fn engine(&self) -> AsyncEngine {
+        self.engine.clone()
+    }
+
+    /// 执行一条命令，返回结构化的结果而不是写入 output：成功时为命令的完整输出，失败时为错误信息。
+    /// 供程序化的调用方区分成功与失败，无需解析 stderr
+    pub async fn execute(&mut self, query: &str) -> CommandOutcome {
+        let captured = CapturedOutput::default();
+        let output = std::mem::replace(&mut self.output, Box::new(captured.clone()));
+        let rs = self.handle_query(false, query).await;
+        self.output = output;
+
+        match rs {
+            Ok(_) => CommandOutcome::Ok(captured.take()),
+            Err(err) => CommandOutcome::Err(err.to_string()),
+        }
+    }
+
+    /// 将命令的结果写入 output，REPL 与非交互模式共用。
+    /// 文本模式下成功时原样输出 data，失败时输出 `error: <msg>`；JSON 模式下输出序列化后的 [`CommandOutcome`]
+    pub fn render(&mut self, outcome: &CommandOutcome) -> Result<()> {
+        if self.settings.is_output_json() {
+            writeln!(self.output, "{}", serde_json::to_string(outcome)?)?;
+        } else {
+            match outcome {
+                CommandOutcome::Ok(data) => write!(self.output, "{}", data)?,
+                CommandOutcome::Err(msg) => writeln!(self.output, "error: {}", msg)?,
+            }
+        }
+        self.flush_output()?;
+        Ok(())
+    }
+
+    /// 执行一条命令并输出结果。成功时命令已边执行边写入 output(如 KEYS 逐行输出)，无需再次输出；
+    /// 失败时通过 [`Self::render`] 输出错误后原样返回，调用方据此决定是否继续
+    async fn run_query(&mut self, is_repl: bool, query: &str) -> Result<Option<ServerStats>> {
+        let rs = self.handle_query(is_repl, query).await;
+        if let Err(err) = &rs {
+            self.render(&CommandOutcome::Err(err.to_string()))?;
+        }
+        rs
+    }
+
+    /// 输出 KEYS 的第 row 行(从 0 开始)，返回输出端是否仍然打开。
+    /// 逐步 flush，输出被管道接收时不会在内存中缓存整个 keyspace；读取端关闭后调用方应停止扫描，drop stream 会使后台的扫描提前结束
+    fn write_key_row(&mut self, key: &str, row: i64) -> Result<bool> {
+        let key = self.settings.display_key(key);
+        let written = if self.settings.is_output_json() {
+            writeln!(self.output, "{}", json!({ "key": key }))
+        } else {
+            writeln!(self.output, "{}", key)
+        };
+        match written {
+            Ok(_) if (row + 1) % KEYS_FLUSH_ROWS == 0 => self.flush_output(),
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// 替换命令结果的输出目标，如在测试中捕获输出
+    pub fn set_output(&mut self, output: Box<dyn Write + Send>) {
+        self.output = output;
+    }
+
+    /// flush 命令结果的输出。读取端已关闭(broken pipe)时返回 Ok(false)，调用方应停止输出
+    fn flush_output(&mut self) -> Result<bool> {
+        match self.output.flush() {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// 按配置的 pipeline 编码 SET 写入的 value，未配置时原样写入
+    fn pipeline_encode(&self, value: &[u8]) -> Result<Vec<u8>> {
+        if self.value_pipeline.is_empty() {
+            return Ok(value.to_vec());
+        }
+        let encoded = self.encoding_engine.encode_pipeline(value, &self.value_pipeline)
+            .map_err(|e| anyhow!(self.format_encoding_error(&e, "pipeline encode")))?;
+        Ok(encoded.into_bytes())
+    }
+
+    /// 按配置的 pipeline 逆序解码 GET 读取的 value
+    fn pipeline_decode(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if self.value_pipeline.is_empty() {
+            return Ok(value);
+        }
+        let encoded = String::from_utf8(value)?;
+        self.encoding_engine.decode_pipeline(&encoded, &self.value_pipeline)
+            .map_err(|e| anyhow!(self.format_encoding_error(&e, "pipeline decode")))
+    }
+
+    /// 自动识别 value 的编码格式并解码，value 不是文本、无法识别或解码失败时返回 None
+    fn detect_and_decode(&mut self, value: &[u8]) -> Option<(EncodingFormat, Vec<u8>)> {
+        let text = std::str::from_utf8(value).ok()?;
+        let best = self.encoding_engine.detect_best(text).ok()??;
+        let decoded = self.encoding_engine.decode(text, best.format).ok()?;
+        Some((best.format, decoded))
+    }
+
+    /// 命令最多输出的行数，未配置时不限制
+    fn max_display_rows(&self) -> i64 {
+        self.settings.get_max_display_rows().map_or(i64::MAX, |rows| rows.min(i64::MAX as u64) as i64)
+    }
+
+    /// 输出 GET/MGET 读取的一个 value。文本模式下 key 不存在时输出 'N/A'，value 为空时输出标记以区别于 key 不存在
+    fn write_get_row(&mut self, show: &Show, key: &str, value: Option<Vec<u8>>) -> Result<()> {
+        let (text, json) = match &value {
+            None => (GET_RESP_NOT_FOUND_STR.into(), json!({ "key": key, "value": null, "found": false })),
+            Some(val) => {
+                let val = String::from_utf8_lossy(val);
+                let json = json!({ "key": key, "value": val, "found": true });
+                if val.is_empty() {
+                    (self.settings.get_empty_value_marker().to_string().into(), json)
+                } else {
+                    (val, json)
+                }
+            }
+        };
+        show.write_row(&mut self.output, &text, json)?;
+        Ok(())
+    }
+
+    /// 输出行数超过 max_rows 时，追加 "... (N more)"
+    fn write_more_rows(&mut self, total: i64, max_rows: i64) -> Result<()> {
+        let more = total - total.min(max_rows);
+        if more > 0 && self.settings.is_output_json() {
+            writeln!(self.output, "{}", json!({ "more": more }))?;
+        } else if more > 0 {
+            writeln!(self.output, "... ({} more)", more)?;
+        }
+        Ok(())
+    }
+
     /// Format encoding error with user-friendly message and optional debug info
     fn format_encoding_error(&self, error: &EncodingError, context: &str) -> String {
         let user_message = match error {
             EncodingError::UnsupportedFormat(format) => {
-                format!("Unsupported encoding format '{}'. Use: base64, hex, or json", format)
+                format!("Unsupported encoding format '{}'. Use: base64, base64url, base32, hex, or json", format)
             }
             EncodingError::InvalidData(msg) => {
                 format!("Invalid encoded data: {}", msg)
@@ -163,7 +348,8 @@ impl Session {
             .build();
         let mut rl = Editor::<CliHelper, DefaultHistory>::with_config(config).unwrap();
 
-        rl.set_helper(Some(CliHelper::with_keywords(self.keywords.clone())));
+        rl.set_helper(Some(CliHelper::with_keywords(self.keywords.clone())
+            .with_numeric_key_order(self.settings.is_numeric_key_order())));
         rl.load_history(&get_history_path()).ok();
 
         'F: loop {
@@ -176,13 +362,12 @@ impl Session {
                     let queries = self.append_query(&line);
                     for query in queries {
                         let _ = rl.add_history_entry(&query);
-                        match self.handle_query(true, &query).await {
+                        match self.run_query(true, &query).await {
                             Ok(None) => {
                                 break 'F;
                             }
                             Ok(Some(_)) => {}
-                            Err(e) => {
-                                eprintln!("error: {}", e);
+                            Err(_) => {
                                 self.query.clear();
                                 break;
                             }
@@ -211,23 +396,26 @@ impl Session {
         let _ = rl.save_history(&get_history_path());
     }
 
-    pub async fn handle_reader<R: BufRead>(&mut self, r: R) -> Result<()> {
+    pub async fn handle_reader<R: BufRead>(&mut self, mut r: R) -> Result<()> {
         let start = Instant::now();
-        let mut lines = r.lines();
+        let max_line_bytes = self.settings.get_max_line_bytes();
+        let mut buf = Vec::new();
         let mut stats: Option<ServerStats> = None;
 
         loop {
-            match lines.next() {
-                Some(Ok(line)) => {
-                    let queries = self.append_query(&line);
+            match read_bounded_line(&mut r, max_line_bytes, &mut buf) {
+                Ok(Some(line)) => {
+                    let queries = self.append_query(line);
                     for query in queries {
-                        stats = self.handle_query(false, &query).await?;
+                        stats = self.run_query(false, &query).await?;
                     }
                 }
-                Some(Err(e)) => {
-                    return Err(anyhow!("read lines err: {}", e.to_string()));
+                Ok(None) => break,
+                Err(e) => {
+                    let err = anyhow!("read lines err: {}", e.to_string());
+                    self.render(&CommandOutcome::Err(err.to_string()))?;
+                    return Err(err);
                 }
-                None => break,
             }
         }
 
@@ -235,7 +423,7 @@ impl Session {
         let query = self.query.trim().to_owned();
         if !query.is_empty() {
             self.query.clear();
-            stats = self.handle_query(false, &query).await?;
+            stats = self.run_query(false, &query).await?;
         }
 
         // local time
@@ -278,13 +466,23 @@ impl Session {
         self.query.push(' ');
 
         let mut queries = Vec::new();
-        let mut tokenizer = Tokenizer::new(line);
+        let tokenizer = Tokenizer::new(line);
         let mut in_comment = false;
         let mut start = 0;
         let mut comment_block_start = 0;
 
         let append_part_cmd_symbol = self.settings.get_auto_append_part_cmd_symbol();
-        while let Some(Ok(token)) = tokenizer.next() {
+        for token in tokenizer {
+            let token = match token {
+                Ok(token) => token,
+                Err(_) => {
+                    // 保留剩余部分，由 handle_query 报告词法错误
+                    if !in_comment && !self.in_comment_block {
+                        self.query.push_str(&line[start..]);
+                    }
+                    break;
+                }
+            };
             match token.kind {
                 TokenKind::SemiColon => {
                     if in_comment || self.in_comment_block {
@@ -363,20 +561,23 @@ impl Session {
 
             self.settings.inject_cmd(query[0], query[1])?;
             info!("refresh config: {:?}", &self.settings);
-            eprintln!("Refresh Config OK");
+            writeln!(self.output, "Refresh Config OK")?;
 
             return Ok(Some(ServerStats::default()));
         }
 
-        let mut tokenizer = Tokenizer::new(query);
+        let tokenizer = Tokenizer::new(query);
         let mut token_list = Vec::<Token>::new();
-        while let Some(Ok(token)) = tokenizer.next() {
+        for token in tokenizer {
+            let token = token.map_err(|err| anyhow!("{}", err))?;
             if token.kind != TokenKind::EOI {
                 token_list.push(token);
             }
         }
 
-        self.dispatcher(is_repl, query, token_list).await
+        let rs = self.dispatcher(is_repl, query, token_list).await;
+        self.flush_output()?;
+        rs
     }
 
     /// executor cmd
@@ -417,29 +618,34 @@ impl Session {
         let start = Instant::now();
 
         match (kind, is_repl) {
-            (QueryKind::Info, _) => {
-                if is_repl {
-                    let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
-
-                    for info in get_info(&mut self.engine) {
-                        eprintln!("{}", info);
-                    }
-                    show.output(1);
+            (QueryKind::Info, _) | (QueryKind::DbSize, _) => {
+                if split_args(query, &token_list).len() != 1 {
+                    return Err(anyhow!("Usage: {}", kind.name()));
                 }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start)
+                    .with_output_json(self.settings.is_output_json());
+
+                let fields = if kind == QueryKind::Info {
+                    self.engine.call(get_info_fields).await?
+                } else {
+                    status_fields(&self.engine.call(|e| e.status()).await?)
+                };
+                let text = format_fields(&fields).join("\n");
+                let json = serde_json::Value::Object(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect());
+                show.write_row(&mut self.output, &text, json)?;
+                show.output(1);
 
                 Ok(Some(ServerStats::default()))
             },
             (QueryKind::Time, _) => {
-                if is_repl {
-                    let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
 
-                    // data
-                    let now: DateTime<Local> = Local::now();
-                    let now_format = now.format("%Y-%m-%d %H:%M:%S%.3f");
-                    eprintln!("{}", now_format);
+                // data
+                let now: DateTime<Local> = Local::now();
+                let now_format = now.format("%Y-%m-%d %H:%M:%S%.3f");
+                writeln!(self.output, "{}", now_format)?;
 
-                    show.output(1);
-                }
+                show.output(1);
 
                 Ok(Some(ServerStats::default()))
             },
@@ -449,18 +655,16 @@ impl Session {
                 // // 或者前缀搜索，或者检索元数据/索引, 或者直接元数据取size
                 // let mut scan_all = self.engine.scan(..).collect::<CResult<Vec<_>>>()?;
                 // let size = scan_all.len();
-                let status = self.engine.status();
+                let status = self.engine.call(|e| e.status()).await;
                 let size = if status.is_ok() {
                     status.unwrap().keys as i64
                 } else {
                     0
                 };
 
-                if is_repl {
-                    eprintln!("{}", size);
+                writeln!(self.output, "{}", size)?;
 
-                    show.output(size);
-                }
+                show.output(size);
 
                 // let c_rs = self.engine.compact();
                 // match c_rs {
@@ -472,131 +676,179 @@ impl Session {
 
                 Ok(Some(ServerStats::default()))
             },
-            (QueryKind::Show, _) => unsafe {
+            (QueryKind::Show, _) => {
                 let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
 
-                let option = &token_list[1].get_slice();
-
-                let path = self.engine.get_path();
-                if is_repl {
-                    eprintln!("{}", path.unwrap());
-
-                    show.output(1);
+                match token_list.get(1).map(|t| t.kind) {
+                    Some(TokenKind::STATUS) => {
+                        let status = self.engine.call(|e| e.status()).await?;
+                        writeln!(self.output, "{}", status)?;
+                    }
+                    Some(TokenKind::PATH) | None => {
+                        let path = self.engine.call(|e| Ok(e.get_path().map(|p| p.to_string()))).await?;
+                        writeln!(self.output, "{}", path.unwrap_or_default())?;
+                    }
+                    Some(_) => {
+                        return Err(anyhow!("Usage: SHOW STATUS | SHOW PATH | SHOW ENCODINGS"));
+                    }
                 }
+                show.output(1);
 
                 Ok(Some(ServerStats::default()))
             },
-            (QueryKind::Keys, _) => unsafe {
+            (QueryKind::Keys, _) => {
                 let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
 
-                // 或者前缀搜索，或者检索元数据/索引, 或者直接元数据取size
-                let mut scan_all = self.engine.scan_prefix(b"");
+                // KEYS [pattern]，`*` 在 tokenizer 中是运算符，因此直接从原始命令中解析。
+                // 只扫描以 pattern 字面前缀开头的key，再逐个按 glob 匹配。只需要key，因此不从磁盘读取 value
+                let pattern = query.split_whitespace().nth(1).unwrap_or("*").as_bytes();
+                let prefix = glob_literal_prefix(pattern);
+
+                let mut scan_all = self.engine.scan_keys(prefix_range(&prefix), DEFAULT_SCAN_BUFFER);
+
+                let max_rows = self.max_display_rows();
+                let mut size = 0;
+                let mut open = true;
+                if self.settings.is_numeric_key_order() {
+                    // 按数字排序需要先收集全部匹配的key，无法边扫描边输出
+                    let mut keys = Vec::new();
+                    while let Some(key) = scan_all.next().await.transpose()? {
+                        if glob_match(pattern, &key) {
+                            keys.push(String::from_utf8_lossy(&key).into_owned());
+                        }
+                    }
+                    drop(scan_all);
+                    keys.sort_by(|a, b| numeric_key_cmp(a, b));
 
-                if is_repl {
-                    let mut size = 0;
-                    while let Some((key, value)) = scan_all.next().transpose()? {
-                        eprintln!("{}", String::from_utf8_unchecked(key).as_str());
+                    for (row, key) in keys.iter().take(max_rows.max(0) as usize).enumerate() {
+                        open = self.write_key_row(key, row as i64)?;
+                        if !open {
+                            break;
+                        }
+                    }
+                    size = keys.len() as i64;
+                } else {
+                    while let Some(key) = scan_all.next().await.transpose()? {
+                        if !glob_match(pattern, &key) {
+                            continue;
+                        }
+                        if size < max_rows {
+                            open = self.write_key_row(&String::from_utf8_lossy(&key), size)?;
+                            if !open {
+                                break;
+                            }
+                        }
                         size += 1;
                     }
-
-                    show.output(size);
+                    drop(scan_all);
+                }
+                if open {
+                    self.write_more_rows(size, max_rows)?;
                 }
 
+                show.output(size);
+
                 Ok(Some(ServerStats::default()))
             },
             (QueryKind::Set, _) => {
-                if token_list.len() != 3 {
-                    eprintln!("set args are invalid, must be 2 argruments");
-                    return Ok(Some(ServerStats::default()));
+                let args = split_args(query, &token_list);
+                if args.len() != 3 {
+                    return Err(anyhow!("Usage: SET <key> <value>"));
                 }
 
                 let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
 
-                let key = &token_list[1].get_slice();
-                let value = &token_list[2].get_slice();
+                let key = args[1];
+                let value = self.pipeline_encode(args[2].as_bytes())?;
 
-                let rs = self.engine.set(key.as_bytes(), value.as_bytes().to_vec());
-                match rs {
-                    Ok(_) => {
-                        eprintln!("{}", SET_RESP_STR);
-                    }
-                    Err(err) => {
-                        eprintln!("{}", err.to_string());
-                    }
-                }
+                self.engine.set(key.as_bytes().to_vec(), value).await?;
+                writeln!(self.output, "{}", SET_RESP_STR)?;
                 show.output(1);
 
                 Ok(Some(ServerStats::default()))
             },
             (QueryKind::Get, _) => {
-                if token_list.len() != 2 {
-                    eprintln!("get args are invalid, must be 1 argruments");
-                    return Ok(Some(ServerStats::default()));
+                let args = split_args(query, &token_list);
+                if args.len() != 2 {
+                    return Err(anyhow!("Usage: GET <key>"));
                 }
-                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start)
+                    .with_output_json(self.settings.is_output_json());
 
-                let key = &token_list[1].get_slice();
-                let rs = self.engine.get(key.as_bytes());
-                match rs {
-                    Ok(v) => {
-                        if v.is_none() {
-                            eprintln!("{}", GET_RESP_NOT_FOUND_STR);
-                        } else {
-                            let val = v.unwrap();
-                            eprintln!("{}", String::from_utf8(val).expect("Get engine#get error"));
-                        }
-                    }
-                    Err(err) => {
-                        eprintln!("{}", err.to_string());
-                    }
-                };
+                let key = args[1];
+                let v = self.engine.get(key.as_bytes().to_vec()).await?;
+                let v = v.map(|val| self.pipeline_decode(val)).transpose()?;
+                self.write_get_row(&show, key, v)?;
 
                 show.output(1);
 
                 Ok(Some(ServerStats::default()))
             },
+            (QueryKind::GetSet, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 3 {
+                    return Err(anyhow!("Usage: GETSET <key> <value>"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let key = args[1].as_bytes().to_vec();
+                let value = self.pipeline_encode(args[2].as_bytes())?;
+
+                // 读取旧值和写入新值在同一次 call 中完成，其他共享引擎的客户端(如 HTTP API)无法在两者之间写入
+                let old = self.engine.call(move |e| {
+                    let old = e.get(&key)?;
+                    e.set(&key, value)?;
+                    Ok(old)
+                }).await?;
+                let old = old.map(|val| self.pipeline_decode(val)).transpose()?;
+                match old {
+                    None => writeln!(self.output, "{}", GET_RESP_NOT_FOUND_STR)?,
+                    Some(val) if val.is_empty() => writeln!(self.output, "{}", self.settings.get_empty_value_marker())?,
+                    Some(val) => writeln!(self.output, "{}", String::from_utf8_lossy(&val))?,
+                }
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            },
             (QueryKind::Del, _) => {
-                if token_list.len() != 2 {
-                    eprintln!("del args are invalid, must be 1 argruments");
-                    return Ok(Some(ServerStats::default()));
+                let args = split_args(query, &token_list);
+                if args.len() != 2 {
+                    return Err(anyhow!("Usage: DEL <key>"));
                 }
 
                 let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
 
-                let key = &token_list[1].get_slice();
-                let rs = self.engine.delete(key.as_bytes());
-                let mut effect_size = 0;
-                match rs {
-                    Ok(effect) => {
-                        effect_size = effect;
-                        eprintln!("effect {}", effect);
-                    }
-                    Err(err) => {
-                        eprintln!("{}", err.to_string());
-                    }
-                };
-                show.output(effect_size);
+                let key = args[1];
+                let effect = self.engine.delete(key.as_bytes().to_vec()).await?;
+                writeln!(self.output, "effect {}", effect)?;
+                show.output(effect);
 
                 Ok(Some(ServerStats::default()))
             }
             (QueryKind::Encode, _) => {
-                if token_list.len() < 3 {
-                    return Err(anyhow!("Usage: ENCODE <key> <format>\nSupported formats: base64, hex, json"));
+                if token_list.len() < 2 {
+                    return Err(anyhow!("Usage: ENCODE <key> [format]\nSupported formats: base64, base64url, base32, hex, json"));
                 }
                 
                 let key = token_list[1].get_slice();
-                let format_str = token_list[2].get_slice();
                 
-                // Parse format
-                let format = match format_str.to_lowercase().as_str() {
-                    "base64" => EncodingFormat::Base64,
-                    "hex" => EncodingFormat::Hex,
-                    "json" => EncodingFormat::Json,
-                    _ => return Err(anyhow!("Unsupported format: {}. Supported formats: base64, hex, json", format_str)),
+                // Parse format, fall back to the configured default when omitted
+                let format = if token_list.len() >= 3 {
+                    let format_str = token_list[2].get_slice();
+                    match format_str.to_lowercase().as_str() {
+                        "base64" => EncodingFormat::Base64,
+                        "base64url" => EncodingFormat::Base64Url,
+                        "base32" => EncodingFormat::Base32,
+                        "hex" => EncodingFormat::Hex,
+                        "json" => EncodingFormat::Json,
+                        _ => return Err(anyhow!("Unsupported format: {}. Supported formats: base64, base64url, base32, hex, json", format_str)),
+                    }
+                } else {
+                    self.get_default_encoding_format()?
                 };
                 
                 // Get the value from storage
-                let value = match self.engine.get(key.as_bytes())? {
+                let value = match self.engine.get(key.as_bytes().to_vec()).await? {
                     Some(data) => data,
                     None => return Err(anyhow!("Key not found: {}", key)),
                 };
@@ -604,11 +856,9 @@ impl Session {
                 // Encode the value
                 match self.encoding_engine.encode(&value, format) {
                     Ok(encoded) => {
-                        if is_repl {
-                            let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
-                            eprintln!("Encoded ({}): {}", format_str, encoded);
-                            show.output(1);
-                        }
+                        let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                        writeln!(self.output, "Encoded ({}): {}", format, encoded)?;
+                        show.output(1);
                         Ok(Some(ServerStats::default()))
                     }
                     Err(e) => Err(self.handle_encoding_error(e, &format!("ENCODE command for key '{}'", key))),
@@ -616,9 +866,20 @@ impl Session {
             }
             (QueryKind::Decode, _) => {
                 if token_list.len() < 2 {
-                    return Err(anyhow!("Usage: DECODE <key> [format]\nSupported formats: base64, hex, json"));
+                    return Err(anyhow!("Usage: DECODE <key> [format] [INTO <destkey>]\nSupported formats: base64, base64url, base32, hex, json"));
                 }
                 
+                // 可选的 INTO <destkey> 后缀: 解码结果写入 destkey 而不是输出
+                let mut token_list = token_list;
+                let dest = match token_list.len() {
+                    n if n >= 4 && token_list[n - 2].get_slice().eq_ignore_ascii_case("INTO") => {
+                        let dest = token_list[n - 1].get_slice().to_string();
+                        token_list.truncate(n - 2);
+                        Some(dest)
+                    }
+                    _ => None,
+                };
+
                 let key = token_list[1].get_slice();
                 let format_str = if token_list.len() >= 3 {
                     Some(token_list[2].get_slice())
@@ -627,9 +888,9 @@ impl Session {
                 };
                 
                 // Get the encoded value from storage
-                let encoded_value = match self.engine.get(key.as_bytes())? {
+                let encoded_value = match self.engine.get(key.as_bytes().to_vec()).await? {
                     Some(data) => String::from_utf8(data)
-                        .map_err(|_| anyhow!("Stored value is not valid UTF-8 text"))?,
+                        .map_err(|_| DecodeError::NotUtf8 { key: key.to_string() })?,
                     None => return Err(anyhow!("Key not found: {}", key)),
                 };
                 
@@ -637,16 +898,18 @@ impl Session {
                 let format = if let Some(fmt_str) = format_str {
                     match fmt_str.to_lowercase().as_str() {
                         "base64" => EncodingFormat::Base64,
+                        "base64url" => EncodingFormat::Base64Url,
+                        "base32" => EncodingFormat::Base32,
                         "hex" => EncodingFormat::Hex,
                         "json" => EncodingFormat::Json,
-                        _ => return Err(anyhow!("Unsupported format: {}. Supported formats: base64, hex, json", fmt_str)),
+                        _ => return Err(anyhow!("Unsupported format: {}. Supported formats: base64, base64url, base32, hex, json", fmt_str)),
                     }
                 } else {
                     // Auto-detect format
                     match self.encoding_engine.detect(&encoded_value) {
                         Ok(detected_formats) => {
                             if detected_formats.is_empty() {
-                                return Err(anyhow!("Could not detect encoding format. Please specify format explicitly."));
+                                return Err(DecodeError::NoFormatDetected { key: key.to_string() }.into());
                             }
                             detected_formats[0].format
                         }
@@ -656,13 +919,22 @@ impl Session {
                 
                 // Decode the value
                 match self.encoding_engine.decode(&encoded_value, format) {
+                    Err(e) if format_str.is_none() => {
+                        debug!("Encoding error in DECODE command for key '{}': {}", key, e);
+                        Err(DecodeError::DecodeFailed { key: key.to_string(), format, reason: e.to_string() }.into())
+                    }
+                    Ok(decoded) if dest.is_some() => {
+                        let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                        self.engine.set(dest.unwrap().into_bytes(), decoded).await?;
+                        writeln!(self.output, "{}", SET_RESP_STR)?;
+                        show.output(1);
+                        Ok(Some(ServerStats::default()))
+                    }
                     Ok(decoded) => {
-                        if is_repl {
-                            let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
-                            let decoded_str = String::from_utf8_lossy(&decoded);
-                            eprintln!("Decoded ({}): {}", format, decoded_str);
-                            show.output(1);
-                        }
+                        let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                        let decoded_str = String::from_utf8_lossy(&decoded);
+                        writeln!(self.output, "Decoded ({}): {}", format, decoded_str)?;
+                        show.output(1);
                         Ok(Some(ServerStats::default()))
                     }
                     Err(e) => Err(self.handle_encoding_error(e, &format!("DECODE command for key '{}'", key))),
@@ -670,7 +942,7 @@ impl Session {
             }
             (QueryKind::MEncode, _) => {
                 if token_list.len() < 3 {
-                    return Err(anyhow!("Usage: MENCCODE <key1> [key2] ... <format>\nSupported formats: base64, hex, json"));
+                    return Err(anyhow!("Usage: MENCCODE <key1> [key2] ... <format>\nSupported formats: base64, base64url, base32, hex, json"));
                 }
                 
                 // Last token is the format, all others are keys
@@ -686,50 +958,50 @@ impl Session {
                 // Parse format
                 let format = match format_str.to_lowercase().as_str() {
                     "base64" => EncodingFormat::Base64,
+                    "base64url" => EncodingFormat::Base64Url,
+                    "base32" => EncodingFormat::Base32,
                     "hex" => EncodingFormat::Hex,
                     "json" => EncodingFormat::Json,
-                    _ => return Err(anyhow!("Unsupported format: {}. Supported formats: base64, hex, json", format_str)),
+                    _ => return Err(anyhow!("Unsupported format: {}. Supported formats: base64, base64url, base32, hex, json", format_str)),
                 };
                 
-                if is_repl {
-                    let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
-                    
-                    let mut success_count = 0;
-                    let mut error_count = 0;
-                    
-                    eprintln!("Batch encoding {} keys with format {}:", keys.len(), format_str);
-                    
-                    for key in keys {
-                        match self.engine.get(key.as_bytes()) {
-                            Ok(Some(value)) => {
-                                match self.encoding_engine.encode(&value, format) {
-                                    Ok(encoded) => {
-                                        eprintln!("  {} -> {}", key, encoded);
-                                        success_count += 1;
-                                    }
-                                    Err(e) => {
-                                        let error_msg = self.format_encoding_error(&e, &format!("MENCCODE for key '{}'", key));
-                                        eprintln!("  {} -> ERROR: {}", key, error_msg);
-                                        error_count += 1;
-                                    }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                
+                let mut success_count = 0;
+                let mut error_count = 0;
+                
+                writeln!(self.output, "Batch encoding {} keys with format {}:", keys.len(), format_str)?;
+                
+                for key in keys {
+                    match self.engine.get(key.as_bytes().to_vec()).await {
+                        Ok(Some(value)) => {
+                            match self.encoding_engine.encode(&value, format) {
+                                Ok(encoded) => {
+                                    writeln!(self.output, "  {} -> {}", key, encoded)?;
+                                    success_count += 1;
+                                }
+                                Err(e) => {
+                                    let error_msg = self.format_encoding_error(&e, &format!("MENCCODE for key '{}'", key));
+                                    writeln!(self.output, "  {} -> ERROR: {}", key, error_msg)?;
+                                    error_count += 1;
                                 }
                             }
-                            Ok(None) => {
-                                eprintln!("  {} -> ERROR: Key not found", key);
-                                error_count += 1;
-                            }
-                            Err(e) => {
-                                eprintln!("  {} -> ERROR: {}", key, e);
-                                error_count += 1;
-                            }
+                        }
+                        Ok(None) => {
+                            writeln!(self.output, "  {} -> ERROR: Key not found", key)?;
+                            error_count += 1;
+                        }
+                        Err(e) => {
+                            writeln!(self.output, "  {} -> ERROR: {}", key, e)?;
+                            error_count += 1;
                         }
                     }
-                    
-                    eprintln!();
-                    eprintln!("Batch encoding completed: {} successful, {} errors", success_count, error_count);
-                    show.output(success_count + error_count);
                 }
                 
+                writeln!(self.output)?;
+                writeln!(self.output, "Batch encoding completed: {} successful, {} errors", success_count, error_count)?;
+                show.output(success_count + error_count);
+                
                 Ok(Some(ServerStats::default()))
             }
             (QueryKind::MDecode, _) => {
@@ -742,72 +1014,70 @@ impl Session {
                     .map(|token| token.get_slice())
                     .collect();
                 
-                if is_repl {
-                    let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
-                    
-                    let mut success_count = 0;
-                    let mut error_count = 0;
-                    
-                    eprintln!("Batch decoding {} keys (auto-detecting format):", keys.len());
-                    
-                    for key in keys {
-                        match self.engine.get(key.as_bytes()) {
-                            Ok(Some(data)) => {
-                                match String::from_utf8(data) {
-                                    Ok(encoded_value) => {
-                                        // Auto-detect format
-                                        match self.encoding_engine.detect(&encoded_value) {
-                                            Ok(detected_formats) => {
-                                                if detected_formats.is_empty() {
-                                                    eprintln!("  {} -> ERROR: Could not detect encoding format", key);
-                                                    error_count += 1;
-                                                } else {
-                                                    let format = detected_formats[0].format;
-                                                    let confidence = detected_formats[0].confidence;
-                                                    
-                                                    match self.encoding_engine.decode(&encoded_value, format) {
-                                                        Ok(decoded) => {
-                                                            let decoded_str = String::from_utf8_lossy(&decoded);
-                                                            eprintln!("  {} ({}, {:.1}%) -> {}", key, format, confidence * 100.0, decoded_str);
-                                                            success_count += 1;
-                                                        }
-                                                        Err(e) => {
-                                                            let error_msg = self.format_encoding_error(&e, &format!("MDECODE for key '{}'", key));
-                                                            eprintln!("  {} -> ERROR: {}", key, error_msg);
-                                                            error_count += 1;
-                                                        }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                
+                let mut success_count = 0;
+                let mut error_count = 0;
+                
+                writeln!(self.output, "Batch decoding {} keys (auto-detecting format):", keys.len())?;
+                
+                for key in keys {
+                    match self.engine.get(key.as_bytes().to_vec()).await {
+                        Ok(Some(data)) => {
+                            match String::from_utf8(data) {
+                                Ok(encoded_value) => {
+                                    // Auto-detect format
+                                    match self.encoding_engine.detect(&encoded_value) {
+                                        Ok(detected_formats) => {
+                                            if detected_formats.is_empty() {
+                                                writeln!(self.output, "  {} -> ERROR: Could not detect encoding format", key)?;
+                                                error_count += 1;
+                                            } else {
+                                                let format = detected_formats[0].format;
+                                                let confidence = detected_formats[0].confidence;
+                                                
+                                                match self.encoding_engine.decode(&encoded_value, format) {
+                                                    Ok(decoded) => {
+                                                        let decoded_str = String::from_utf8_lossy(&decoded);
+                                                        writeln!(self.output, "  {} ({}, {:.1}%) -> {}", key, format, confidence * 100.0, decoded_str)?;
+                                                        success_count += 1;
+                                                    }
+                                                    Err(e) => {
+                                                        let error_msg = self.format_encoding_error(&e, &format!("MDECODE for key '{}'", key));
+                                                        writeln!(self.output, "  {} -> ERROR: {}", key, error_msg)?;
+                                                        error_count += 1;
                                                     }
                                                 }
                                             }
-                                            Err(e) => {
-                                                let error_msg = self.format_encoding_error(&e, &format!("MDECODE format detection for key '{}'", key));
-                                                eprintln!("  {} -> ERROR: {}", key, error_msg);
-                                                error_count += 1;
-                                            }
                                         }
-                                    }
-                                    Err(_) => {
-                                        eprintln!("  {} -> ERROR: Stored value is not valid UTF-8 text", key);
-                                        error_count += 1;
+                                        Err(e) => {
+                                            let error_msg = self.format_encoding_error(&e, &format!("MDECODE format detection for key '{}'", key));
+                                            writeln!(self.output, "  {} -> ERROR: {}", key, error_msg)?;
+                                            error_count += 1;
+                                        }
                                     }
                                 }
+                                Err(_) => {
+                                    writeln!(self.output, "  {} -> ERROR: Stored value is not valid UTF-8 text", key)?;
+                                    error_count += 1;
+                                }
                             }
-                            Ok(None) => {
-                                eprintln!("  {} -> ERROR: Key not found", key);
-                                error_count += 1;
-                            }
-                            Err(e) => {
-                                eprintln!("  {} -> ERROR: {}", key, e);
-                                error_count += 1;
-                            }
+                        }
+                        Ok(None) => {
+                            writeln!(self.output, "  {} -> ERROR: Key not found", key)?;
+                            error_count += 1;
+                        }
+                        Err(e) => {
+                            writeln!(self.output, "  {} -> ERROR: {}", key, e)?;
+                            error_count += 1;
                         }
                     }
-                    
-                    eprintln!();
-                    eprintln!("Batch decoding completed: {} successful, {} errors", success_count, error_count);
-                    show.output(success_count + error_count);
                 }
                 
+                writeln!(self.output)?;
+                writeln!(self.output, "Batch decoding completed: {} successful, {} errors", success_count, error_count)?;
+                show.output(success_count + error_count);
+                
                 Ok(Some(ServerStats::default()))
             }
             (QueryKind::Detect, _) => {
@@ -818,7 +1088,7 @@ impl Session {
                 let key = token_list[1].get_slice();
                 
                 // Get the value from storage
-                let data = match self.engine.get(key.as_bytes())? {
+                let data = match self.engine.get(key.as_bytes().to_vec()).await? {
                     Some(data) => data,
                     None => return Err(anyhow!("Key not found: {}", key)),
                 };
@@ -830,107 +1100,582 @@ impl Session {
                 // Detect format
                 match self.encoding_engine.detect(&value_str) {
                     Ok(detected_formats) => {
-                        if is_repl {
-                            let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                        let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                        
+                        writeln!(self.output, "Format detection results for key '{}':", key)?;
+                        writeln!(self.output, "Value preview: {}", if value_str.len() > 50 { 
+                            format!("{}...", &value_str[..50]) 
+                        } else { 
+                            value_str.clone() 
+                        })?;
+                        writeln!(self.output)?;
+                        
+                        if detected_formats.is_empty() {
+                            writeln!(self.output, "❌ No encoding format detected")?;
+                            writeln!(self.output, "   The value appears to be plain text or an unsupported format.")?;
+                            writeln!(self.output)?;
+                            writeln!(self.output, "💡 Suggestions:")?;
+                            writeln!(self.output, "   • If this is plain text, no decoding is needed")?;
+                            writeln!(self.output, "   • If this should be encoded data, check the format manually")?;
+                            writeln!(self.output, "   • Try encoding the value first: ENCODE {} <format>", key)?;
+                        } else {
+                            writeln!(self.output, "✅ Detected {} possible format(s):", detected_formats.len())?;
+                            for (i, result) in detected_formats.iter().enumerate() {
+                                let confidence_percent = result.confidence * 100.0;
+                                let confidence_icon = if confidence_percent >= 90.0 { "🟢" } 
+                                                     else if confidence_percent >= 70.0 { "🟡" } 
+                                                     else { "🔴" };
+                                writeln!(self.output, "   {}. {} {} ({:.1}% confidence)", 
+                                         i + 1, confidence_icon, result.format, confidence_percent)?;
+                            }
                             
-                            eprintln!("Format detection results for key '{}':", key);
-                            eprintln!("Value preview: {}", if value_str.len() > 50 { 
-                                format!("{}...", &value_str[..50]) 
-                            } else { 
-                                value_str.clone() 
-                            });
-                            eprintln!();
+                            writeln!(self.output)?;
+                            let best_format = &detected_formats[0];
+                            writeln!(self.output, "🎯 Recommendation: Use format '{}'", best_format.format)?;
                             
-                            if detected_formats.is_empty() {
-                                eprintln!("❌ No encoding format detected");
-                                eprintln!("   The value appears to be plain text or an unsupported format.");
-                                eprintln!();
-                                eprintln!("💡 Suggestions:");
-                                eprintln!("   • If this is plain text, no decoding is needed");
-                                eprintln!("   • If this should be encoded data, check the format manually");
-                                eprintln!("   • Try encoding the value first: ENCODE {} <format>", key);
-                            } else {
-                                eprintln!("✅ Detected {} possible format(s):", detected_formats.len());
-                                for (i, result) in detected_formats.iter().enumerate() {
-                                    let confidence_percent = result.confidence * 100.0;
-                                    let confidence_icon = if confidence_percent >= 90.0 { "🟢" } 
-                                                         else if confidence_percent >= 70.0 { "🟡" } 
-                                                         else { "🔴" };
-                                    eprintln!("   {}. {} {} ({:.1}% confidence)", 
-                                             i + 1, confidence_icon, result.format, confidence_percent);
-                                }
-                                
-                                eprintln!();
-                                let best_format = &detected_formats[0];
-                                eprintln!("🎯 Recommendation: Use format '{}'", best_format.format);
-                                
-                                if detected_formats.len() > 1 {
-                                    eprintln!("⚠️  Multiple formats detected - use the highest confidence one");
-                                }
-                                
-                                eprintln!();
-                                eprintln!("💡 Next steps:");
-                                eprintln!("   • Decode: DECODE {} {}", key, best_format.format);
-                                eprintln!("   • Auto-decode: DECODE {}", key);
-                                if best_format.confidence < 0.9 {
-                                    eprintln!("   • Manual verification recommended due to low confidence");
-                                }
+                            if detected_formats.len() > 1 {
+                                writeln!(self.output, "⚠️  Multiple formats detected - use the highest confidence one")?;
                             }
                             
-                            // Show detection statistics if in debug mode
-                            if self.debug_mode {
-                                eprintln!();
-                                eprintln!("🔍 Debug: Detection statistics");
-                                let stats = self.encoding_engine.get_detection_stats(&value_str);
-                                for (format, score) in stats {
-                                    eprintln!("   {}: {:.3}", format, score);
-                                }
+                            writeln!(self.output)?;
+                            writeln!(self.output, "💡 Next steps:")?;
+                            writeln!(self.output, "   • Decode: DECODE {} {}", key, best_format.format)?;
+                            writeln!(self.output, "   • Auto-decode: DECODE {}", key)?;
+                            if best_format.confidence < 0.9 {
+                                writeln!(self.output, "   • Manual verification recommended due to low confidence")?;
                             }
-                            
-                            show.output(detected_formats.len().max(1) as i64);
                         }
+                        
+                        // Show detection statistics if in debug mode
+                        if self.debug_mode {
+                            writeln!(self.output)?;
+                            writeln!(self.output, "🔍 Debug: Detection statistics")?;
+                            let stats = self.encoding_engine.get_detection_stats(&value_str);
+                            for (format, score) in stats {
+                                writeln!(self.output, "   {}: {:.3}", format, score)?;
+                            }
+                        }
+                        
+                        show.output(detected_formats.len().max(1) as i64);
                         Ok(Some(ServerStats::default()))
                     }
                     Err(e) => Err(self.handle_encoding_error(e, &format!("DETECT command for key '{}'", key))),
                 }
             }
+            (QueryKind::Stat, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 2 {
+                    return Err(anyhow!("Usage: STAT <key>"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start)
+                    .with_output_json(self.settings.is_output_json());
+
+                // 只读取一次存储的 value，输出其长度、是否为 UTF-8 以及识别出的最可能的编码格式
+                let Some(data) = self.engine.get(args[1].as_bytes().to_vec()).await? else {
+                    show.write_row(&mut self.output, GET_RESP_NOT_FOUND_STR, json!({ "key": args[1], "found": false }))?;
+                    show.output(0);
+                    return Ok(Some(ServerStats::default()));
+                };
+                let text = std::str::from_utf8(&data).ok();
+                let best = match text {
+                    Some(text) => self.encoding_engine.detect_best(text)
+                        .map_err(|e| self.handle_encoding_error(e, &format!("STAT command for key '{}'", args[1])))?,
+                    None => None,
+                };
+
+                let encoding = match &best {
+                    Some(best) => format!("{} ({:.3})", best.format, best.confidence),
+                    None => "none".to_string(),
+                };
+                let text_row = format!("length: {}\nutf8: {}\nencoding: {}", data.len(), text.is_some(), encoding);
+                let json_row = json!({
+                    "key": args[1],
+                    "found": true,
+                    "length": data.len(),
+                    "utf8": text.is_some(),
+                    "encoding": best.as_ref().map(|best| best.format.to_string()),
+                    "confidence": best.as_ref().map(|best| best.confidence),
+                });
+                show.write_row(&mut self.output, &text_row, json_row)?;
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::DetectStats, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 2 {
+                    return Err(anyhow!("Usage: DETECTSTATS <key>"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let key = args[1];
+                let value_str = match self.engine.get(key.as_bytes().to_vec()).await? {
+                    Some(data) => String::from_utf8(data).map_err(|_| anyhow!("Stored value is not valid UTF-8 text"))?,
+                    None => return Err(anyhow!("Key not found: {}", key)),
+                };
+
+                // 列出所有支持的格式，包括低于 min_confidence 甚至被直接排除(记为 0)的格式，便于排查为何未被识别
+                let stats = self.encoding_engine.get_detection_stats(&value_str);
+                let mut scores: Vec<(EncodingFormat, f32)> = self.encoding_engine.supported_formats().into_iter()
+                    .map(|format| (format, stats.get(&format).copied().unwrap_or(0.0)))
+                    .collect();
+                scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.to_string().cmp(&b.0.to_string())));
+                for (format, score) in &scores {
+                    writeln!(self.output, "{}: {:.3}", format, score)?;
+                }
+                show.output(scores.len() as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Stats, _) => {
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                match token_list.get(1).map(|t| t.kind) {
+                    Some(TokenKind::RESET) => {
+                        self.engine.call(|e| {
+                            e.reset_counters();
+                            Ok(())
+                        }).await?;
+                        writeln!(self.output, "{}", SET_RESP_STR)?;
+                    }
+                    None => {
+                        let detailed = self.engine.call(|e| e.status_detailed()).await?;
+                        writeln!(self.output, "bytes_read: {}", detailed.bytes_read)?;
+                        writeln!(self.output, "bytes_written: {}", detailed.bytes_written)?;
+                    }
+                    Some(_) => {
+                        return Err(anyhow!("Usage: STATS [RESET]"));
+                    }
+                }
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Scan, _) => {
+                let args = split_args(query, &token_list);
+                let usage = || anyhow!("Usage: SCAN <cursor> [COUNT n]");
+                let count: usize = match args.as_slice() {
+                    [_, _] => DEFAULT_SCAN_COUNT,
+                    [_, _, option, n] if option.eq_ignore_ascii_case("COUNT") => {
+                        n.parse().ok().filter(|n| *n > 0).ok_or_else(usage)?
+                    }
+                    _ => return Err(usage()),
+                };
+                let cursor = ScanCursor::decode(args[1])?;
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // 游标记录的是key而不是位置，compact 之后仍然有效。第一行输出下一页的游标，没有更多数据时为 0
+                let (page, next) = self.engine.call(move |e| e.scan_from(&cursor, count)).await?;
+                writeln!(self.output, "{}", next.unwrap_or_else(ScanCursor::start).encode())?;
+                let max_rows = self.max_display_rows();
+                for (key, _) in page.iter().take(max_rows.max(0) as usize) {
+                    writeln!(self.output, "{}", self.settings.display_key(&String::from_utf8_lossy(key)))?;
+                }
+                self.write_more_rows(page.len() as i64, max_rows)?;
+                show.output(page.len() as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Sample, _) => {
+                let args = split_args(query, &token_list);
+                let n: usize = match args.as_slice() {
+                    [_, n] => n.parse().map_err(|_| anyhow!("Usage: SAMPLE <n>"))?,
+                    _ => return Err(anyhow!("Usage: SAMPLE <n>")),
+                };
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let mut sample = self.engine.call(move |e| Ok(e.sample_keys(n))).await?;
+                sample.sort();
+                for (key, size) in &sample {
+                    writeln!(self.output, "{} ({} bytes)", String::from_utf8_lossy(key), size)?;
+                }
+                show.output(sample.len() as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Check, _) => {
+                if split_args(query, &token_list).len() != 1 {
+                    return Err(anyhow!("Usage: CHECK"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let bad_keys = self.engine.call(|e| e.self_check()).await?;
+                if bad_keys.is_empty() {
+                    writeln!(self.output, "OK")?;
+                }
+                for key in &bad_keys {
+                    writeln!(self.output, "unreadable: {}", String::from_utf8_lossy(key))?;
+                }
+                show.output(bad_keys.len() as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Incr, _) | (QueryKind::IncrBy, _) | (QueryKind::DecrBy, _) => {
+                let args = split_args(query, &token_list);
+                let delta = match (kind, args.len()) {
+                    (QueryKind::Incr, 2) => Some(1),
+                    (QueryKind::IncrBy, 3) => args[2].parse::<i64>().ok(),
+                    (QueryKind::DecrBy, 3) => args[2].parse::<i64>().ok().and_then(i64::checked_neg),
+                    _ => None,
+                };
+                let Some(delta) = delta else {
+                    return Err(match kind {
+                        QueryKind::Incr => anyhow!("Usage: INCR <key>"),
+                        _ => anyhow!("Usage: {} <key> <n>", kind.name()),
+                    });
+                };
+                // 计数器以十进制文本存储，无法在引擎内对 pipeline 编码后的 value 做原子的读-改-写
+                if !self.value_pipeline.is_empty() {
+                    return Err(anyhow!("{} is not supported with a value pipeline", kind.name()));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let key = args[1].as_bytes().to_vec();
+                let value = self.engine.call(move |e| e.incr_by(&key, delta)).await?;
+                writeln!(self.output, "{}", value)?;
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Append, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 3 {
+                    return Err(anyhow!("Usage: APPEND <key> <value>"));
+                }
+                // 追加的是原始字节，pipeline 编码后的 value 拼接后无法再解码
+                if !self.value_pipeline.is_empty() {
+                    return Err(anyhow!("APPEND is not supported with a value pipeline"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // 读取、拼接与写入在同一次 engine 调用中完成，期间 keydir 不会被其他写入修改
+                let key = args[1].as_bytes().to_vec();
+                let suffix = args[2].as_bytes().to_vec();
+                let len = self.engine.call(move |e| e.append(&key, &suffix)).await?;
+                writeln!(self.output, "{}", len)?;
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Exists, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() < 2 {
+                    return Err(anyhow!("Usage: EXISTS <key> [key ...]"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // 只查询内存索引，不读取 value
+                let keys = args[1..].iter().map(|key| key.as_bytes().to_vec()).collect::<Vec<_>>();
+                let count = self.engine.call(move |e| Ok(keys.iter().filter(|key| e.contains_key(key)).count())).await?;
+                writeln!(self.output, "{}", count)?;
+                show.output(args.len() as i64 - 1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::MGet, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() < 2 {
+                    return Err(anyhow!("Usage: MGET <key> [key ...]"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start)
+                    .with_output_json(self.settings.is_output_json());
+
+                let keys = args[1..].iter().map(|key| key.as_bytes().to_vec()).collect();
+                let values = self.engine.get_many(keys).await?;
+                let max_rows = self.max_display_rows();
+                for (key, value) in args[1..].iter().zip(values).take(max_rows.max(0) as usize) {
+                    let value = value.map(|val| self.pipeline_decode(val)).transpose()?;
+                    self.write_get_row(&show, key, value)?;
+                }
+                self.write_more_rows(args.len() as i64 - 1, max_rows)?;
+                show.output(args.len() as i64 - 1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::XGet, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 2 {
+                    return Err(anyhow!("Usage: XGET <key>"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // 直接输出存储的字节，不经过 pipeline 解码
+                match self.engine.get(args[1].as_bytes().to_vec()).await? {
+                    Some(value) => write!(self.output, "{}", hex_dump(&value))?,
+                    None => writeln!(self.output, "{}", GET_RESP_NOT_FOUND_STR)?,
+                }
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            }
             (QueryKind::ShowEncodings, _) => {
-                if is_repl {
-                    let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
-                    
-                    // Display current configuration
-                    let default_format = self.encoding_engine.default_format();
-                    let auto_detect = self.settings.is_auto_detect_enabled();
-                    let batch_size = self.settings.get_batch_size();
-                    
-                    eprintln!("Current Encoding Configuration:");
-                    eprintln!("  Default format: {}", default_format);
-                    eprintln!("  Auto-detection: {}", if auto_detect { "enabled" } else { "disabled" });
-                    eprintln!("  Batch size: {}", batch_size);
-                    eprintln!();
-                    
-                    eprintln!("Supported encoding formats:");
-                    let supported_formats = self.encoding_engine.supported_formats();
-                    for format in &supported_formats {
-                        let marker = if *format == default_format { " (default)" } else { "" };
-                        match format {
-                            EncodingFormat::Base64 => eprintln!("  base64  - Base64 encoding{}", marker),
-                            EncodingFormat::Hex => eprintln!("  hex     - Hexadecimal encoding{}", marker),
-                            EncodingFormat::Json => eprintln!("  json    - JSON string encoding{}", marker),
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+                
+                // Display current configuration
+                let default_format = self.encoding_engine.default_format();
+                let auto_detect = self.settings.is_auto_detect_enabled();
+                let batch_size = self.settings.get_batch_size();
+                
+                writeln!(self.output, "Current Encoding Configuration:")?;
+                writeln!(self.output, "  Default format: {}", default_format)?;
+                writeln!(self.output, "  Auto-detection: {}", if auto_detect { "enabled" } else { "disabled" })?;
+                writeln!(self.output, "  Batch size: {}", batch_size)?;
+                writeln!(self.output)?;
+                
+                writeln!(self.output, "Supported encoding formats:")?;
+                let supported_formats = self.encoding_engine.supported_formats();
+                for format in &supported_formats {
+                    let marker = if *format == default_format { " (default)" } else { "" };
+                    match format {
+                        EncodingFormat::Base64 => writeln!(self.output, "  base64  - Base64 encoding{}", marker)?,
+                        EncodingFormat::Base64Url => writeln!(self.output, "  base64url - URL-safe Base64 encoding{}", marker)?,
+                        EncodingFormat::Base32 => writeln!(self.output, "  base32  - Base32 encoding (RFC 4648, unpadded){}", marker)?,
+                        EncodingFormat::Hex => writeln!(self.output, "  hex     - Hexadecimal encoding{}", marker)?,
+                        EncodingFormat::Json => writeln!(self.output, "  json    - JSON string encoding{}", marker)?,
+                    }
+                }
+                writeln!(self.output)?;
+                
+                writeln!(self.output, "Available commands:")?;
+                writeln!(self.output, "  ENCODE <key> <format>           - Encode value at key using specified format")?;
+                writeln!(self.output, "  DECODE <key> [format]           - Decode value at key (auto-detect if format omitted)")?;
+                writeln!(self.output, "  MENCCODE <key1> [key2] ... <format> - Batch encode multiple keys")?;
+                writeln!(self.output, "  MDECODE <key1> [key2] ...       - Batch decode multiple keys (auto-detect)")?;
+                writeln!(self.output, "  DETECT <key>                    - Detect encoding format of value at key")?;
+                writeln!(self.output, "  DETECTSTATS <key>               - Show raw detection confidence of every format")?;
+                writeln!(self.output, "  SHOW ENCODINGS                  - Show this information")?;
+                
+                show.output(supported_formats.len() as i64);
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Compact, _) => {
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // `--dry-run` 会被 tokenizer 视为注释, 因此直接从原始命令中解析
+                let dry_run = query.split_whitespace().skip(1).any(|arg| arg.eq_ignore_ascii_case("--dry-run"));
+
+                let estimate = self.engine.call(|e| e.compaction_estimate()).await?;
+                if dry_run {
+                    writeln!(self.output, "reclaimable_bytes: {}", estimate.reclaimable_bytes)?;
+                    writeln!(self.output, "live_entries: {}", estimate.live_entries)?;
+                    writeln!(self.output, "live_bytes: {}", estimate.live_bytes)?;
+                } else if estimate.reclaimable_bytes < self.settings.get_min_reclaim_bytes() {
+                    info!("compact skipped: {} reclaimable bytes is below min_reclaim_bytes {}",
+                        estimate.reclaimable_bytes, self.settings.get_min_reclaim_bytes());
+                    writeln!(self.output, "skipped, only {} bytes reclaimable (min_reclaim_bytes: {})",
+                        estimate.reclaimable_bytes, self.settings.get_min_reclaim_bytes())?;
+                } else {
+                    // Ctrl-C 会将 running 置为 false，此时取消正在进行的 compact
+                    let cancel = Arc::new(AtomicBool::new(false));
+                    let watcher = {
+                        let running = self.running.clone();
+                        let cancel = cancel.clone();
+                        tokio::spawn(async move {
+                            while running.load(Ordering::SeqCst) {
+                                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                            }
+                            cancel.store(true, Ordering::SeqCst);
+                        })
+                    };
+                    let before = self.engine.call(|e| e.status()).await?.total_disk_size;
+                    let rs = self.engine
+                        .compact_with(|done, total| debug!("compact progress: {}/{}", done, total), cancel)
+                        .await;
+                    watcher.abort();
+                    match rs {
+                        Err(Error::Abort) => return Err(anyhow!("compact cancelled, the log is unchanged")),
+                        // 替换日志文件失败时，错误信息中包含操作系统及文件路径
+                        Err(err) => return Err(anyhow!("compact failed: {}", err)),
+                        Ok(_) => {}
+                    }
+                    let after = self.engine.call(|e| e.status()).await?.total_disk_size;
+                    writeln!(self.output, "{}, total_disk_size {} -> {}, reclaimed {} bytes",
+                        SET_RESP_STR, before, after, before.saturating_sub(after))?;
+                }
+                show.output(estimate.live_entries as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Rename, _) | (QueryKind::RenameNx, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 3 {
+                    return Err(anyhow!("Usage: {} <oldkey> <newkey>", kind.name()));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let from = args[1].as_bytes().to_vec();
+                let to = args[2].as_bytes().to_vec();
+                let overwrite = kind == QueryKind::Rename;
+                self.engine.call(move |e| e.rename(&from, &to, overwrite)).await?;
+                writeln!(self.output, "{}", SET_RESP_STR)?;
+                show.output(1);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Swap, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 3 {
+                    return Err(anyhow!("Usage: SWAP <key1> <key2>"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let key1 = args[1].as_bytes().to_vec();
+                let key2 = args[2].as_bytes().to_vec();
+                self.engine.call(move |e| e.swap(&key1, &key2)).await?;
+                writeln!(self.output, "{}", SET_RESP_STR)?;
+                show.output(2);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::DScan, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 3 {
+                    return Err(anyhow!("Usage: DSCAN <from> <to>\nScans keys in [from, to] and decodes each value with the detected format"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let range = (Bound::Included(args[1].as_bytes().to_vec()), Bound::Included(args[2].as_bytes().to_vec()));
+                let mut stream = self.engine.scan(range, DEFAULT_SCAN_BUFFER);
+                let max_rows = self.max_display_rows();
+                let mut size = 0;
+                while let Some(item) = stream.next().await {
+                    let (key, value) = item?;
+                    size += 1;
+                    if size > max_rows {
+                        continue; // 只计数，不再解码
+                    }
+                    let key = String::from_utf8_lossy(&key).to_string();
+                    match self.detect_and_decode(&value) {
+                        Some((format, decoded)) => {
+                            writeln!(self.output, "{} ({}): {}", key, format, String::from_utf8_lossy(&decoded))?
                         }
+                        // 无法识别或解码失败的 value 原样输出
+                        None => writeln!(self.output, "{} (raw): {}", key, String::from_utf8_lossy(&value))?,
                     }
-                    eprintln!();
-                    
-                    eprintln!("Available commands:");
-                    eprintln!("  ENCODE <key> <format>           - Encode value at key using specified format");
-                    eprintln!("  DECODE <key> [format]           - Decode value at key (auto-detect if format omitted)");
-                    eprintln!("  MENCCODE <key1> [key2] ... <format> - Batch encode multiple keys");
-                    eprintln!("  MDECODE <key1> [key2] ...       - Batch decode multiple keys (auto-detect)");
-                    eprintln!("  DETECT <key>                    - Detect encoding format of value at key");
-                    eprintln!("  SHOW ENCODINGS                  - Show this information");
-                    
-                    show.output(supported_formats.len() as i64);
                 }
+                self.write_more_rows(size, max_rows)?;
+                show.output(size);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Checkpoint, _) => {
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let checkpoint = self.engine.call(|e| e.checkpoint()).await?;
+                writeln!(self.output, "{}, log_len: {}, keys: {}", SET_RESP_STR, checkpoint.log_len, checkpoint.keys)?;
+                show.output(checkpoint.keys as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Reindex, _) => {
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let keys = self.engine.call(|e| {
+                    e.rebuild_index()?;
+                    Ok(e.status()?.keys)
+                }).await?;
+                writeln!(self.output, "{}, {} keys", SET_RESP_STR, keys)?;
+                show.output(keys as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Copy, _) => {
+                let args = split_args(query, &token_list);
+                let overwrite = match args.get(3) {
+                    None => false,
+                    Some(mode) if mode.eq_ignore_ascii_case("MERGE") => false,
+                    Some(mode) if mode.eq_ignore_ascii_case("OVERWRITE") => true,
+                    Some(_) => return Err(anyhow!("Usage: COPY <src_path> <dest_path> [MERGE|OVERWRITE]")),
+                };
+                if args.len() < 3 || args.len() > 4 {
+                    return Err(anyhow!("Usage: COPY <src_path> <dest_path> [MERGE|OVERWRITE]"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let src = std::path::PathBuf::from(args[1]);
+                let dest = std::path::PathBuf::from(args[2]);
+                if !src.is_file() {
+                    return Err(anyhow!("Source database not found: {}", src.display()));
+                }
+                let copied = tokio::task::spawn_blocking(move || {
+                    // 源库以只读方式打开：不加锁，也不会截断其他进程正在写入的不完整 entry，因此也可以是当前会话正在使用的库
+                    let mut src = LogCask::open_read_only(src)?;
+                    let mut dest = LogCask::new(dest)?;
+                    src.copy_into(&mut dest, overwrite)
+                }).await??;
+                writeln!(self.output, "{}, {} keys copied", SET_RESP_STR, copied)?;
+                show.output(copied as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Export, _) | (QueryKind::Import, _) => {
+                let args = split_args(query, &token_list);
+                if args.len() != 2 {
+                    return Err(anyhow!("Usage: {} <path>", kind.name()));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // 备份文件中是存储的原始字节，不经过 value pipeline。边扫描边写入文件，不会缓存整个数据库
+                let path = std::path::PathBuf::from(args[1]);
+                let (count, action) = if kind == QueryKind::Export {
+                    let format = if self.settings.is_output_json() { DumpFormat::Json } else { DumpFormat::Binary };
+                    let count = self.engine.call(move |e| {
+                        let mut w = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                        let count = dump::export(e, &mut w, format)?;
+                        w.flush()?;
+                        Ok(count)
+                    }).await?;
+                    (count, "exported")
+                } else {
+                    let count = self.engine.call(move |e| {
+                        let mut r = std::io::BufReader::new(std::fs::File::open(&path)?);
+                        dump::import(e, &mut r)
+                    }).await?;
+                    (count, "imported")
+                };
+                writeln!(self.output, "{}, {} keys {}", SET_RESP_STR, count, action)?;
+                show.output(count as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::FlushAll, _) => {
+                let args = split_args(query, &token_list);
+                // 清空数据库不可恢复，交互模式下需要 `FLUSHALL YES` 确认
+                let confirmed = match args.as_slice() {
+                    [_] => !is_repl,
+                    [_, token] if token.eq_ignore_ascii_case("YES") => true,
+                    _ => return Err(anyhow!("Usage: FLUSHALL [YES]")),
+                };
+                if !confirmed {
+                    return Err(anyhow!("FLUSHALL removes every key, run FLUSHALL YES to confirm"));
+                }
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                // 先为所有 key 写入 tombstone，再 compact 回收旧数据和 tombstone 占用的磁盘空间
+                let removed = self.engine.call(|e| e.delete_range(..)).await?;
+                self.engine.compact().await?;
+                writeln!(self.output, "{}, {} keys removed", SET_RESP_STR, removed)?;
+                show.output(removed as i64);
+
+                Ok(Some(ServerStats::default()))
+            }
+            (QueryKind::Help, _) => {
+                let args = split_args(query, &token_list);
+                let show = Show::new_with_start(self.settings.is_show_affected(), is_repl, start);
+
+                let verb = args[1..].join(" ");
+                let usages = QueryKind::iter()
+                    .filter(|kind| verb.is_empty() || kind.name().eq_ignore_ascii_case(&verb))
+                    .filter_map(|kind| kind.usage())
+                    .collect::<Vec<_>>();
+                if usages.is_empty() {
+                    return Err(anyhow!("Unknown command: {}. Type HELP to list all commands", verb));
+                }
+                for usage in &usages {
+                    writeln!(self.output, "{}", usage)?;
+                }
+                show.output(usages.len() as i64);
+
                 Ok(Some(ServerStats::default()))
             }
             (_, _) => {
@@ -954,6 +1699,13 @@ impl Session {
         let new_default_format = new_config.get_default_format()
             .map_err(|e| anyhow!("Failed to parse default format: {}", e))?;
         self.encoding_engine.set_default_format(new_default_format);
+
+        let pipeline = new_config.get_pipeline()?;
+        if !pipeline.is_empty() {
+            self.encoding_engine.validate_pipeline(&pipeline)
+                .map_err(|e| anyhow!("Invalid encoding pipeline: {}", e))?;
+        }
+        self.value_pipeline = pipeline;
         
         info!("Encoding configuration updated - Default format: {}, Auto-detect: {}, Batch size: {}", 
               new_config.default_format, new_config.auto_detect, new_config.batch_size);
@@ -1014,6 +1766,90 @@ impl Session {
     }
 }
 
+/// 将 token 按空白切分为命令参数：相邻(中间没有空白)的 token 合并为一个参数，如 `user:123` 会被切分为3个token，但作为一个key。
+fn split_args<'a>(query: &'a str, token_list: &[Token<'_>]) -> Vec<&'a str> {
+    let mut args = Vec::with_capacity(token_list.len());
+    let mut current: Option<(usize, usize)> = None;
+    for token in token_list {
+        current = match current {
+            Some((start, end)) if end == token.span.start => Some((start, token.span.end)),
+            Some((start, end)) => {
+                args.push(&query[start..end]);
+                Some((token.span.start, token.span.end))
+            }
+            None => Some((token.span.start, token.span.end)),
+        };
+    }
+    if let Some((start, end)) = current {
+        args.push(&query[start..end]);
+    }
+    args
+}
+
+/// 同 `BufRead::lines`，但最多读取 max_bytes 字节(不含换行符)，超出时返回错误而不是将整行读入内存。
+/// 读到 EOF 时返回 None
+fn read_bounded_line<'a, R: BufRead>(r: &mut R, max_bytes: u64, buf: &'a mut Vec<u8>) -> std::io::Result<Option<&'a str>> {
+    buf.clear();
+    if r.take(max_bytes.saturating_add(1)).read_until(b'\n', buf)? == 0 {
+        return Ok(None);
+    }
+
+    if buf.last() == Some(&b'\n') {
+        buf.pop();
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+    } else if buf.len() as u64 > max_bytes {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("line exceeds max_line_bytes ({} bytes)", max_bytes),
+        ));
+    }
+
+    std::str::from_utf8(buf)
+        .map(Some)
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8"))
+}
+
+/// 单条命令的执行结果，见 [`Session::execute`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "data", rename_all = "lowercase")]
+pub enum CommandOutcome {
+    /// 执行成功，data 为命令的输出
+    Ok(String),
+
+    /// 执行失败，data 为错误信息
+    Err(String),
+}
+
+impl CommandOutcome {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, CommandOutcome::Ok(_))
+    }
+}
+
+/// execute 时捕获命令输出的缓冲区
+#[derive(Clone, Default)]
+struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedOutput {
+    fn take(&self) -> String {
+        let buf = std::mem::take(&mut *self.0.lock().unwrap());
+        String::from_utf8_lossy(&buf).to_string()
+    }
+}
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 fn get_history_path() -> String {
     format!(
         "{}/.kvcli_history",
@@ -1021,7 +1857,38 @@ fn get_history_path() -> String {
     )
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// DECODE 自动识别格式时的失败原因，可通过 `anyhow::Error::downcast_ref` 区分
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// 存储的 value 不是合法的 UTF-8 文本，无法识别格式
+    NotUtf8 { key: String },
+
+    /// 没有识别出任何编码格式
+    NoFormatDetected { key: String },
+
+    /// 识别出了格式，但按该格式解码失败
+    DecodeFailed { key: String, format: EncodingFormat, reason: String },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::NotUtf8 { key } => {
+                write!(f, "stored value of key '{}' is not UTF-8, it can not be decoded", key)
+            }
+            DecodeError::NoFormatDetected { key } => {
+                write!(f, "no format detected for key '{}', specify one: DECODE {} <format>", key, key)
+            }
+            DecodeError::DecodeFailed { key, format, reason } => {
+                write!(f, "detected {} for key '{}' but decode failed: {}", format, key, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, EnumIter)]
 pub enum QueryKind {
     Info,
     Time,
@@ -1042,6 +1909,133 @@ pub enum QueryKind {
     MDecode,
     Detect,
     ShowEncodings,
+    Compact,
+    Reindex,
+    Swap,
+    Checkpoint,
+    DScan,
+    Help,
+    Copy,
+    DetectStats,
+    Stats,
+    Sample,
+    XGet,
+    Check,
+    Exists,
+    Incr,
+    IncrBy,
+    DecrBy,
+    Append,
+    Rename,
+    RenameNx,
+    Scan,
+    Stat,
+    Export,
+    Import,
+    FlushAll,
+    DbSize,
+}
+
+impl QueryKind {
+    /// 命令名称，HELP 输出及 `HELP <verb>` 查找时使用
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueryKind::Info => "INFO",
+            QueryKind::Time => "TIME",
+            QueryKind::KSize => "KSIZE",
+            QueryKind::Exit => "EXIT",
+            QueryKind::Select => "SELECT",
+            QueryKind::Keys => "KEYS",
+            QueryKind::Show => "SHOW",
+            QueryKind::Set => "SET",
+            QueryKind::Get => "GET",
+            QueryKind::Del => "DEL",
+            QueryKind::GetSet => "GETSET",
+            QueryKind::MGet => "MGET",
+            QueryKind::SetEx => "SETEX",
+            QueryKind::Encode => "ENCODE",
+            QueryKind::Decode => "DECODE",
+            QueryKind::MEncode => "MENCCODE",
+            QueryKind::MDecode => "MDECODE",
+            QueryKind::Detect => "DETECT",
+            QueryKind::ShowEncodings => "SHOW ENCODINGS",
+            QueryKind::Compact => "COMPACT",
+            QueryKind::Reindex => "REINDEX",
+            QueryKind::Swap => "SWAP",
+            QueryKind::Checkpoint => "CHECKPOINT",
+            QueryKind::DScan => "DSCAN",
+            QueryKind::Help => "HELP",
+            QueryKind::Copy => "COPY",
+            QueryKind::DetectStats => "DETECTSTATS",
+            QueryKind::Stats => "STATS",
+            QueryKind::Sample => "SAMPLE",
+            QueryKind::XGet => "XGET",
+            QueryKind::Check => "CHECK",
+            QueryKind::Exists => "EXISTS",
+            QueryKind::Incr => "INCR",
+            QueryKind::IncrBy => "INCRBY",
+            QueryKind::DecrBy => "DECRBY",
+            QueryKind::Append => "APPEND",
+            QueryKind::Rename => "RENAME",
+            QueryKind::RenameNx => "RENAMENX",
+            QueryKind::Scan => "SCAN",
+            QueryKind::Stat => "STAT",
+            QueryKind::Export => "EXPORT",
+            QueryKind::Import => "IMPORT",
+            QueryKind::FlushAll => "FLUSHALL",
+            QueryKind::DbSize => "DBSIZE",
+        }
+    }
+
+    /// 命令的用法说明，None 表示 dispatcher 尚未实现该命令，不在 HELP 中列出。
+    /// 新增命令时需同步更新 dispatcher_executor 与此处。
+    pub fn usage(&self) -> Option<&'static str> {
+        match self {
+            QueryKind::Info => Some("INFO - Show server information: the full engine status and the bytes read/written"),
+            QueryKind::Time => Some("TIME - Show the current local time"),
+            QueryKind::KSize => Some("KSIZE - Show the number of keys"),
+            QueryKind::Exit => Some("EXIT | QUIT - Exit the REPL"),
+            QueryKind::Keys => Some("KEYS [pattern] - List keys, optionally matching a glob pattern with *, ? and [...]"),
+            QueryKind::Show => Some("SHOW STATUS | SHOW PATH - Show engine status or the data path"),
+            QueryKind::Set => Some("SET <key> <value> - Set key to hold the value"),
+            QueryKind::Get => Some("GET <key> - Get the value of key"),
+            QueryKind::Del => Some("DEL <key> - Delete the key"),
+            QueryKind::GetSet => Some("GETSET <key> <value> - Set key to hold the value and return its old value"),
+            QueryKind::Encode => Some("ENCODE <key> [format] - Encode value at key (default format if omitted)"),
+            QueryKind::Decode => Some("DECODE <key> [format] [INTO <destkey>] - Decode value at key (auto-detect if format omitted), optionally storing the result at destkey"),
+            QueryKind::MEncode => Some("MENCCODE <key1> [key2] ... <format> - Batch encode multiple keys"),
+            QueryKind::MDecode => Some("MDECODE <key1> [key2] ... - Batch decode multiple keys (auto-detect)"),
+            QueryKind::Detect => Some("DETECT <key> - Detect encoding format of value at key"),
+            QueryKind::ShowEncodings => Some("SHOW ENCODINGS - Show encoding configuration and formats"),
+            QueryKind::Compact => Some("COMPACT [--dry-run] - Compact the log and show the disk size before and after, or only estimate reclaimable bytes"),
+            QueryKind::Reindex => Some("REINDEX - Rebuild the in-memory index from the log"),
+            QueryKind::Swap => Some("SWAP <key1> <key2> - Swap the values of two keys"),
+            QueryKind::Checkpoint => Some("CHECKPOINT - Flush and record a checkpoint of the log"),
+            QueryKind::DScan => Some("DSCAN <from> <to> - Scan keys in [from, to] and decode each value"),
+            QueryKind::Help => Some("HELP [verb] - List all commands, or show the usage of one"),
+            QueryKind::Copy => Some("COPY <src_path> <dest_path> [MERGE|OVERWRITE] - Copy all live keys from one database into another"),
+            QueryKind::DetectStats => Some("DETECTSTATS <key> - Show every format's raw detection confidence for the value at key"),
+            QueryKind::Stats => Some("STATS [RESET] - Show the bytes read/written since startup or the last reset, or reset them"),
+            QueryKind::Sample => Some("SAMPLE <n> - Show up to n random keys with their value sizes"),
+            QueryKind::XGet => Some("XGET <key> - Show the stored bytes of the value at key as a hex dump"),
+            QueryKind::Check => Some("CHECK - Verify that the value of every key can be read from disk, listing the keys that cannot"),
+            QueryKind::MGet => Some("MGET <key> [key ...] - Get the values of several keys, one line per key"),
+            QueryKind::Exists => Some("EXISTS <key> [key ...] - Count how many of the keys exist, without reading their values"),
+            QueryKind::Incr => Some("INCR <key> - Increment the integer value of key by one, a missing key counts as 0"),
+            QueryKind::IncrBy => Some("INCRBY <key> <n> - Increment the integer value of key by n, a missing key counts as 0"),
+            QueryKind::DecrBy => Some("DECRBY <key> <n> - Decrement the integer value of key by n, a missing key counts as 0"),
+            QueryKind::Append => Some("APPEND <key> <value> - Append the value to the value of key and show the new length"),
+            QueryKind::Rename => Some("RENAME <oldkey> <newkey> - Move the value of oldkey to newkey, replacing any value of newkey"),
+            QueryKind::RenameNx => Some("RENAMENX <oldkey> <newkey> - Move the value of oldkey to newkey, failing if newkey exists"),
+            QueryKind::Stat => Some("STAT <key> - Show the length of the value at key, whether it is UTF-8 and its most likely encoding"),
+            QueryKind::Scan => Some("SCAN <cursor> [COUNT n] - List up to n keys (default 10) from the cursor, starting at 0; the first line is the next cursor, 0 when done"),
+            QueryKind::Export => Some("EXPORT <path> - Write all live keys and values to a backup file, as JSON lines with --output json"),
+            QueryKind::Import => Some("IMPORT <path> - Set every key and value from a backup file written by EXPORT"),
+            QueryKind::FlushAll => Some("FLUSHALL [YES] - Remove every key and compact the log, the REPL requires YES to confirm"),
+            QueryKind::DbSize => Some("DBSIZE - Show the number of keys, their size and the live/garbage disk usage"),
+            QueryKind::Select | QueryKind::SetEx => None,
+        }
+    }
 }
 
 impl TryFrom<TokenKind> for QueryKind {
@@ -1068,6 +2062,31 @@ impl TryFrom<TokenKind> for QueryKind {
             TokenKind::MENCCODE => Ok(QueryKind::MEncode),
             TokenKind::MDECODE => Ok(QueryKind::MDecode),
             TokenKind::DETECT => Ok(QueryKind::Detect),
+            TokenKind::COMPACT => Ok(QueryKind::Compact),
+            TokenKind::REINDEX => Ok(QueryKind::Reindex),
+            TokenKind::SWAP => Ok(QueryKind::Swap),
+            TokenKind::CHECKPOINT => Ok(QueryKind::Checkpoint),
+            TokenKind::DSCAN => Ok(QueryKind::DScan),
+            TokenKind::HELP => Ok(QueryKind::Help),
+            TokenKind::COPY => Ok(QueryKind::Copy),
+            TokenKind::DETECTSTATS => Ok(QueryKind::DetectStats),
+            TokenKind::STATS => Ok(QueryKind::Stats),
+            TokenKind::SAMPLE => Ok(QueryKind::Sample),
+            TokenKind::XGET => Ok(QueryKind::XGet),
+            TokenKind::CHECK => Ok(QueryKind::Check),
+            TokenKind::EXISTS => Ok(QueryKind::Exists),
+            TokenKind::INCR => Ok(QueryKind::Incr),
+            TokenKind::INCRBY => Ok(QueryKind::IncrBy),
+            TokenKind::DECRBY => Ok(QueryKind::DecrBy),
+            TokenKind::APPEND => Ok(QueryKind::Append),
+            TokenKind::RENAME => Ok(QueryKind::Rename),
+            TokenKind::RENAMENX => Ok(QueryKind::RenameNx),
+            TokenKind::SCAN => Ok(QueryKind::Scan),
+            TokenKind::STAT => Ok(QueryKind::Stat),
+            TokenKind::EXPORT => Ok(QueryKind::Export),
+            TokenKind::IMPORT => Ok(QueryKind::Import),
+            TokenKind::FLUSHALL => Ok(QueryKind::FlushAll),
+            TokenKind::DBSIZE => Ok(QueryKind::DbSize),
             _ => {
                 Err("UnSupport cmd".to_owned())
             }
@@ -1,9 +1,13 @@
+use std::cmp::Ordering;
+use std::io::Write;
 use tokio::time::Instant;
 
 /// Show affected Info
 pub struct Show {
     is_show_affected: bool,
     is_repl: bool,
+    /// 结果以 JSON 行输出，见 [`Show::write_row`]
+    output_json: bool,
 
     start: Instant,
 }
@@ -19,10 +23,25 @@ impl Show {
         Show {
             is_show_affected,
             is_repl,
+            output_json: false,
             start,
         }
     }
 
+    pub fn with_output_json(mut self, output_json: bool) -> Self {
+        self.output_json = output_json;
+        self
+    }
+
+    /// 输出一行结果：JSON 模式下输出 json 的单行序列化，否则输出 text
+    pub fn write_row(&self, out: &mut dyn Write, text: &str, json: serde_json::Value) -> std::io::Result<()> {
+        if self.output_json {
+            writeln!(out, "{}", json)
+        } else {
+            writeln!(out, "{}", text)
+        }
+    }
+
     pub fn output(&self, affected: i64) {
         if self.is_show_affected && self.is_repl {
             if affected > 0 {
@@ -37,4 +56,63 @@ impl Show {
             eprintln!();
         }
     }
-}
\ No newline at end of file
+}
+
+/// 显示key时的排序：key中的纯数字片段按数字大小比较，其余片段按字典序，如 "2" < "10"、"k2" < "k10"。
+/// 只用于输出和补全，存储中的key仍按字节序排列。
+pub fn numeric_key_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        if a.is_empty() || b.is_empty() {
+            return a.len().cmp(&b.len());
+        }
+        let (seg_a, rest_a) = split_segment(a);
+        let (seg_b, rest_b) = split_segment(b);
+
+        let is_digits = |seg: &str| seg.as_bytes()[0].is_ascii_digit();
+        let ord = if is_digits(seg_a) && is_digits(seg_b) {
+            // 去掉前导零后位数多的更大，位数相同时按字典序即数值序；数值相等时前导零少的在前
+            let (num_a, num_b) = (seg_a.trim_start_matches('0'), seg_b.trim_start_matches('0'));
+            num_a.len().cmp(&num_b.len())
+                .then_with(|| num_a.cmp(num_b))
+                .then_with(|| seg_a.len().cmp(&seg_b.len()))
+        } else {
+            seg_a.cmp(seg_b)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+}
+
+/// 切出开头的一段连续数字或连续非数字
+fn split_segment(s: &str) -> (&str, &str) {
+    let digit = s.as_bytes()[0].is_ascii_digit();
+    let end = s.bytes().position(|c| c.is_ascii_digit() != digit).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// 按 `hexdump -C` 的格式输出字节：每行为偏移量、16 个字节的 hex(第 8 个字节后多一个空格)以及 ASCII 栏，
+/// 不可打印的字节在 ASCII 栏中显示为 '.'，最后一行为总长度
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        out.push_str(&format!("{:08x} ", i * 16));
+        for j in 0..16 {
+            if j == 8 {
+                out.push(' ');
+            }
+            match chunk.get(j) {
+                Some(b) => out.push_str(&format!(" {:02x}", b)),
+                None => out.push_str("   "),
+            }
+        }
+        let ascii: String = chunk.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        out.push_str(&format!("  |{}|\n", ascii));
+    }
+    out.push_str(&format!("{:08x}\n", bytes.len()));
+    out
+}
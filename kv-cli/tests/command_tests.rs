@@ -0,0 +1,1104 @@
+//! Integration tests for the output of storage commands, captured via Session::set_output
+
+use std::io::{Cursor, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use anyhow::Result;
+use tempfile::TempDir;
+
+use kv_rs::storage::engine::Engine;
+use kv_rs::storage::log_cask::LogCask;
+use kvcli::server::config::{ConfigLoad, OutputFormat};
+use kv_rs::encoding::EncodingFormat;
+use kvcli::rusty::CliHelper;
+use kvcli::server::session::{CommandOutcome, DecodeError, Session};
+
+/// A cloneable writer, so the test keeps a handle to what the session wrote.
+#[derive(Clone, Default)]
+struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl SharedOutput {
+    fn take(&self) -> String {
+        let mut buf = self.0.lock().unwrap();
+        let out = String::from_utf8_lossy(&buf).to_string();
+        buf.clear();
+        out
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+async fn new_session(temp_dir: &TempDir, config: impl FnOnce(&mut ConfigLoad)) -> Result<(Session, SharedOutput)> {
+    let mut settings = ConfigLoad::new_with_data_dir(temp_dir.path().to_string_lossy().to_string());
+    config(&mut settings);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let mut session = Session::try_new(settings, false, false, running).await?;
+    let output = SharedOutput::default();
+    session.set_output(Box::new(output.clone()));
+    Ok((session, output))
+}
+
+#[tokio::test]
+async fn test_keys_glob() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET user:1 a\nSET user:2 b\nSET user:10 c\nSET userx d\nSET order:1 e")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("KEYS user:*")).await?;
+    assert_eq!(output.take(), "user:1\nuser:10\nuser:2\n");
+
+    session.handle_reader(Cursor::new("KEYS user:?")).await?;
+    assert_eq!(output.take(), "user:1\nuser:2\n");
+
+    session.handle_reader(Cursor::new("KEYS [ou]*:1")).await?;
+    assert_eq!(output.take(), "order:1\nuser:1\n");
+
+    session.handle_reader(Cursor::new("KEYS *")).await?;
+    assert_eq!(output.take(), "order:1\nuser:1\nuser:10\nuser:2\nuserx\n");
+
+    // the literal prefix is scanned, and the rest of the pattern still has to match
+    session.handle_reader(Cursor::new("KEYS user*1")).await?;
+    assert_eq!(output.take(), "user:1\n");
+
+    // without wildcards only the exact key matches
+    session.handle_reader(Cursor::new("KEYS user:1")).await?;
+    assert_eq!(output.take(), "user:1\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stat() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET b64 aGVsbG8gd29ybGQ=\nSET text plain_text!")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("STAT b64")).await?;
+    let stat = output.take();
+    assert!(stat.starts_with("length: 16\nutf8: true\nencoding: base64 ("), "{}", stat);
+
+    session.handle_reader(Cursor::new("STAT text")).await?;
+    assert_eq!(output.take(), "length: 11\nutf8: true\nencoding: none\n");
+
+    session.handle_reader(Cursor::new("STAT missing")).await?;
+    assert_eq!(output.take(), "N/A\n");
+
+    assert!(session.handle_reader(Cursor::new("STAT")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_import() -> Result<()> {
+    for format in [OutputFormat::Text, OutputFormat::Json] {
+        let (source_dir, dest_dir, dump_dir) = (TempDir::new()?, TempDir::new()?, TempDir::new()?);
+        let dump = dump_dir.path().join("backup.dump");
+        let sets = (0..100).map(|i| format!("SET k{:03} v{}", i, i)).collect::<Vec<_>>().join("\n");
+
+        let (mut source, output) = new_session(&source_dir, |c| c.set_output_format(format)).await?;
+        source.handle_reader(Cursor::new(sets)).await?;
+        source.handle_reader(Cursor::new("DEL k050")).await?;
+        output.take();
+        source.handle_reader(Cursor::new(format!("EXPORT {}", dump.display()))).await?;
+        assert_eq!(output.take(), "OK, 99 keys exported\n");
+
+        let (mut dest, output) = new_session(&dest_dir, |_| {}).await?;
+        dest.handle_reader(Cursor::new(format!("IMPORT {}", dump.display()))).await?;
+        assert_eq!(output.take(), "OK, 99 keys imported\n");
+
+        let source_engine = source.engine();
+        let dest_engine = dest.engine();
+        let expected = source_engine.call(|e| e.scan(..).collect::<kv_rs::error::CResult<Vec<_>>>()).await?;
+        let imported = dest_engine.call(|e| e.scan(..).collect::<kv_rs::error::CResult<Vec<_>>>()).await?;
+        assert_eq!(imported.len(), 99);
+        assert_eq!(imported, expected);
+    }
+
+    let temp_dir = TempDir::new()?;
+    let (mut session, _) = new_session(&temp_dir, |_| {}).await?;
+    assert!(session.handle_reader(Cursor::new("EXPORT")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("IMPORT /nonexistent/backup.dump")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flushall() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    let sets = (0..200).map(|i| format!("SET k{:03} {}", i, "v".repeat(64))).collect::<Vec<_>>().join("\n");
+    session.handle_reader(Cursor::new(sets)).await?;
+    output.take();
+
+    let engine = session.engine();
+    let before = engine.call(|e| e.status()).await?;
+    assert_eq!(before.keys, 200);
+
+    session.handle_reader(Cursor::new("FLUSHALL")).await?;
+    assert_eq!(output.take(), "OK, 200 keys removed\n");
+
+    let after = engine.call(|e| e.status()).await?;
+    assert_eq!(after.keys, 0);
+    assert!(after.total_disk_size < before.total_disk_size, "{} >= {}", after.total_disk_size, before.total_disk_size);
+
+    session.handle_reader(Cursor::new("GET k000\nFLUSHALL yes")).await?;
+    assert_eq!(output.take(), "N/A\nOK, 0 keys removed\n");
+    assert!(session.handle_reader(Cursor::new("FLUSHALL NOW")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dbsize() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2\nSET c 3\nSET a 11\nDEL b")).await?;
+    output.take();
+
+    let status = session.engine().call(|e| e.status()).await?;
+    assert!(status.garbage_disk_size > 0);
+
+    session.handle_reader(Cursor::new("DBSIZE")).await?;
+    let text = output.take();
+    let fields = text.lines()
+        .map(|line| line.split_once(':').map(|(name, value)| (name.to_string(), value.trim().to_string())).unwrap())
+        .collect::<std::collections::HashMap<_, _>>();
+    assert_eq!(fields["keys"], "2");
+    assert_eq!(fields["total_disk_size"], status.total_disk_size.to_string());
+    let ratio: f64 = fields["garbage_ratio"].parse()?;
+    assert_eq!(ratio, status.garbage_disk_size as f64 / status.total_disk_size as f64);
+
+    // values are aligned in one column
+    let columns = text.lines().map(|line| line.len() - line.split_once(':').unwrap().1.trim_start().len()).collect::<Vec<_>>();
+    assert!(columns.iter().all(|column| *column == columns[0]), "{}", text);
+
+    session.handle_reader(Cursor::new("INFO")).await?;
+    let info = output.take();
+    assert!(info.contains("garbage_disk_size:") && info.contains("bytes_written:"), "{}", info);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dbsize_json() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_output_format(OutputFormat::Json)).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET a 2")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("DBSIZE")).await?;
+    let json: serde_json::Value = serde_json::from_str(output.take().trim_end())?;
+    let status = session.engine().call(|e| e.status()).await?;
+    assert_eq!(json["keys"], 1);
+    assert_eq!(json["live_disk_size"], status.live_disk_size);
+    assert_eq!(json["garbage_ratio"], status.garbage_ratio());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_json() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_output_format(OutputFormat::Json)).await?;
+    session.handle_reader(Cursor::new("SET user:1 alice\nSET user:2 bob\nSET other plain_text!")).await?;
+    output.take();
+
+    let lines = |out: String| -> Vec<serde_json::Value> {
+        out.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    };
+
+    session.handle_reader(Cursor::new("GET user:1\nGET missing")).await?;
+    assert_eq!(lines(output.take()), vec![
+        serde_json::json!({"key": "user:1", "value": "alice", "found": true}),
+        serde_json::json!({"key": "missing", "value": null, "found": false}),
+    ]);
+
+    session.handle_reader(Cursor::new("MGET user:2 missing")).await?;
+    assert_eq!(lines(output.take()), vec![
+        serde_json::json!({"key": "user:2", "value": "bob", "found": true}),
+        serde_json::json!({"key": "missing", "value": null, "found": false}),
+    ]);
+
+    session.handle_reader(Cursor::new("KEYS user:*")).await?;
+    assert_eq!(lines(output.take()), vec![
+        serde_json::json!({"key": "user:1"}),
+        serde_json::json!({"key": "user:2"}),
+    ]);
+
+    session.handle_reader(Cursor::new("STAT other\nSTAT missing")).await?;
+    assert_eq!(lines(output.take()), vec![
+        serde_json::json!({"key": "other", "found": true, "length": 11, "utf8": true, "encoding": null, "confidence": null}),
+        serde_json::json!({"key": "missing", "found": false}),
+    ]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scan_cursor() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    let sets = (0..1000).map(|i| format!("SET k{:04} v", i)).collect::<Vec<_>>().join("\n");
+    session.handle_reader(Cursor::new(sets)).await?;
+    session.handle_reader(Cursor::new("SET k0500 w\nDEL k0999")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("KEYS")).await?;
+    let all = output.take().lines().map(|key| key.to_string()).collect::<Vec<_>>();
+    assert_eq!(all.len(), 999);
+
+    let mut cursor = "0".to_string();
+    let mut scanned = Vec::new();
+    let mut pages = 0;
+    loop {
+        session.handle_reader(Cursor::new(format!("SCAN {} COUNT 100", cursor))).await?;
+        let page = output.take();
+        let mut lines = page.lines();
+        cursor = lines.next().unwrap().to_string();
+        let keys = lines.map(|key| key.to_string()).collect::<Vec<_>>();
+        assert!(keys.len() <= 100);
+        scanned.extend(keys);
+        pages += 1;
+
+        // the cursor holds a key, not a log position, so it survives a compaction
+        if pages == 3 {
+            session.handle_reader(Cursor::new("COMPACT")).await?;
+            output.take();
+        }
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(pages, 10);
+    assert_eq!(scanned, all);
+
+    session.handle_reader(Cursor::new("SCAN 0")).await?;
+    assert_eq!(output.take().lines().count(), 11);
+
+    assert!(session.handle_reader(Cursor::new("SCAN")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("SCAN 0 COUNT 0")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("SCAN 0 LIMIT 5")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("SCAN zz")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_keys_strip_prefix() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_strip_prefix(Some("user:".to_string()))).await?;
+
+    session.handle_reader(Cursor::new("SET user:123 a\nSET user:456 b\nSET order:1 c")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("KEYS user:*")).await?;
+    assert_eq!(output.take(), "123\n456\n");
+
+    // keys without the prefix are printed unchanged
+    session.handle_reader(Cursor::new("KEYS")).await?;
+    assert_eq!(output.take(), "order:1\n123\n456\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_show_status_and_path() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("SHOW STATUS")).await?;
+    let status = output.take();
+    assert!(status.contains("keys: 2"), "{}", status);
+
+    session.handle_reader(Cursor::new("SHOW PATH")).await?;
+    let path = output.take();
+    assert!(path.contains(&*temp_dir.path().to_string_lossy()), "{}", path);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reindex() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2\nDEL a")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("REINDEX")).await?;
+    assert_eq!(output.take(), "OK, 1 keys\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_empty_value() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    // SET can not write an empty value, so write it with the engine before the session opens the db
+    let data_dir = ConfigLoad::new_with_data_dir(temp_dir.path().to_string_lossy().to_string()).get_data_dir();
+    let mut engine = LogCask::new(data_dir)?;
+    engine.set(b"empty", vec![])?;
+    drop(engine);
+
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("GET empty\nGET missing")).await?;
+    assert_eq!(output.take(), "(empty)\nN/A\n");
+    drop(session);
+
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_empty_value_marker(Some("".to_string()))).await?;
+    session.handle_reader(Cursor::new("GET empty\nGET missing")).await?;
+    assert_eq!(output.take(), "\nN/A\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("SWAP a b\nGET a\nGET b")).await?;
+    assert_eq!(output.take(), "OK\n2\n1\n");
+
+    session.handle_reader(Cursor::new("SWAP a c\nGET a\nGET c")).await?;
+    assert_eq!(output.take(), "OK\nN/A\n2\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rename() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("RENAME a c\nGET a\nGET c")).await?;
+    assert_eq!(output.take(), "OK\nN/A\n1\n");
+
+    // the source must exist
+    assert!(session.handle_reader(Cursor::new("RENAME a d")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("RENAMENX a d")).await.is_err());
+
+    // RENAMENX keeps an existing destination, RENAME replaces it
+    assert!(session.handle_reader(Cursor::new("RENAMENX c b")).await.is_err());
+    session.handle_reader(Cursor::new("GET b\nGET c")).await?;
+    assert_eq!(
+        output.take(),
+        "error: key a does not exist\nerror: key a does not exist\nerror: key b already exists\n2\n1\n"
+    );
+    session.handle_reader(Cursor::new("RENAME c b\nGET b\nGET c")).await?;
+    assert_eq!(output.take(), "OK\n1\nN/A\n");
+
+    session.handle_reader(Cursor::new("RENAMENX b e\nGET e")).await?;
+    assert_eq!(output.take(), "OK\n1\n");
+
+    assert!(session.handle_reader(Cursor::new("RENAME e")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checkpoint() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2")).await?;
+    output.take();
+
+    // each entry is 4 + 4 + 1 + 1 bytes
+    session.handle_reader(Cursor::new("CHECKPOINT")).await?;
+    assert_eq!(output.take(), "OK, log_len: 20, keys: 2\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encoding_pipeline() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| {
+        let mut encoding = c.get_encoding_config();
+        encoding.pipeline = vec!["hex".to_string(), "base64".to_string()];
+        c.set_encoding_config(encoding);
+    }).await?;
+
+    session.handle_reader(Cursor::new("SET a hi\nGET a")).await?;
+    assert_eq!(output.take(), "OK\nhi\n");
+    drop(session);
+
+    // the stored value is base64(hex(value))
+    let data_dir = ConfigLoad::new_with_data_dir(temp_dir.path().to_string_lossy().to_string()).get_data_dir();
+    let engine = LogCask::new(data_dir)?;
+    assert_eq!(engine.get(b"a")?, Some(b"Njg2OQ==".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encode_default_format() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| {
+        let mut encoding = c.get_encoding_config();
+        encoding.set_default_format(EncodingFormat::Hex);
+        c.set_encoding_config(encoding);
+    }).await?;
+
+    session.handle_reader(Cursor::new("SET a hi")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("ENCODE a")).await?;
+    assert_eq!(output.take(), "Encoded (hex): 6869\n");
+
+    session.handle_reader(Cursor::new("ENCODE a base64")).await?;
+    assert_eq!(output.take(), "Encoded (base64): aGk=\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encode_base32() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET a foobar")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("ENCODE a base32")).await?;
+    assert_eq!(output.take(), "Encoded (base32): MZXW6YTBOI\n");
+
+    // padded values are detected as base32 and decoded
+    session.handle_reader(Cursor::new("SET b MZXW6YTBOI======")).await?;
+    session.handle_reader(Cursor::new("DECODE b INTO c")).await?;
+    output.take();
+    session.handle_reader(Cursor::new("GET c")).await?;
+    assert_eq!(output.take(), "foobar\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_decode_base64url() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    // "<<?>>" encodes to "PDw_Pj4=" in the URL-safe alphabet and "PDw/Pj4=" in the standard one
+    session.handle_reader(Cursor::new("SET token PDw_Pj4=")).await?;
+    session.handle_reader(Cursor::new("DECODE token INTO plain")).await?;
+    output.take();
+    session.handle_reader(Cursor::new("GET plain")).await?;
+    assert_eq!(output.take(), "<<?>>\n");
+
+    assert!(session.handle_reader(Cursor::new("DECODE token base64 INTO x")).await.is_err());
+    output.take();
+
+    session.handle_reader(Cursor::new("ENCODE plain base64url")).await?;
+    assert_eq!(output.take(), "Encoded (base64url): PDw_Pj4=\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_decode_into() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET src SGVsbG8=")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("DECODE src base64 INTO dest")).await?;
+    assert_eq!(output.take(), "OK\n");
+
+    session.handle_reader(Cursor::new("GET dest\nGET src")).await?;
+    assert_eq!(output.take(), "Hello\nSGVsbG8=\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_numeric_key_order() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET 2 b\nSET 10 c\nSET 1 a\nSET k10 x\nSET k9 y")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("KEYS")).await?;
+    assert_eq!(output.take(), "1\n10\n2\nk10\nk9\n");
+    drop(session);
+
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_numeric_key_order(true)).await?;
+    session.handle_reader(Cursor::new("KEYS")).await?;
+    assert_eq!(output.take(), "1\n2\n10\nk9\nk10\n");
+
+    // completion candidates follow the same order
+    let keywords = Arc::new(vec!["v10".to_string(), "v2".to_string()]);
+    let display = |helper: CliHelper| helper.candidates("GET v", 5).1.into_iter().map(|p| p.display).collect::<Vec<_>>();
+    assert_eq!(display(CliHelper::with_keywords(keywords.clone())), vec!["v10", "v2"]);
+    assert_eq!(display(CliHelper::with_keywords(keywords).with_numeric_key_order(true)), vec!["v2", "v10"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_detect_stats() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET b64 SGVsbG8gV29ybGQ=")).await?;
+    output.take();
+
+    // formats that are ruled out are still listed, with a zero score
+    session.handle_reader(Cursor::new("DETECTSTATS b64")).await?;
+    assert_eq!(output.take(), "base64: 1.000\nbase64url: 0.900\nbase32: 0.000\nhex: 0.000\njson: 0.000\n");
+
+    assert!(session.handle_reader(Cursor::new("DETECTSTATS missing")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stats_reset() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET foo hello\nGET foo")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("STATS RESET")).await?;
+    assert_eq!(output.take(), "OK\n");
+
+    // only the activity after the reset is counted
+    session.handle_reader(Cursor::new("GET foo\nSTATS")).await?;
+    assert_eq!(output.take(), "hello\nbytes_read: 5\nbytes_written: 0\n");
+
+    assert!(session.handle_reader(Cursor::new("STATS foo")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_line_bytes() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_max_line_bytes(16)).await?;
+
+    // lines within the limit, including the trailing CRLF, are accepted
+    session.handle_reader(Cursor::new("SET k 012345678\r\nGET k\n")).await?;
+    assert_eq!(output.take(), "OK\n012345678\n");
+
+    // an over-limit line is rejected before it is read into memory, and nothing after it runs
+    let input = format!("SET big {}\nSET after 1\n", "x".repeat(1 << 20));
+    let err = session.handle_reader(Cursor::new(input)).await.unwrap_err();
+    assert!(err.to_string().contains("max_line_bytes (16 bytes)"), "{}", err);
+    session.handle_reader(Cursor::new("GET after")).await?;
+    assert_eq!(output.take(), "error: read lines err: line exceeds max_line_bytes (16 bytes)\nN/A\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sample() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET b 22\nSET c 333")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("SAMPLE 2")).await?;
+    let rows = output.take();
+    assert_eq!(rows.lines().count(), 2);
+    assert!(rows.lines().all(|row| ["a (1 bytes)", "b (2 bytes)", "c (3 bytes)"].contains(&row)), "{}", rows);
+
+    session.handle_reader(Cursor::new("SAMPLE 10")).await?;
+    assert_eq!(output.take(), "a (1 bytes)\nb (2 bytes)\nc (3 bytes)\n");
+
+    assert!(session.handle_reader(Cursor::new("SAMPLE x")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_check() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET b 22\nDEL a")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("CHECK")).await?;
+    assert_eq!(output.take(), "OK\n");
+
+    assert!(session.handle_reader(Cursor::new("CHECK a")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mget() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET b 2\nSET a 1\nSET c 3\nDEL c")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("MGET c a x b")).await?;
+    assert_eq!(output.take(), "N/A\n1\nN/A\n2\n");
+
+    assert!(session.handle_reader(Cursor::new("MGET")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_append() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    // appending to a missing key starts from an empty value
+    session.handle_reader(Cursor::new("APPEND log a1\nAPPEND log ,b22\nAPPEND log ,c333")).await?;
+    assert_eq!(output.take(), "2\n6\n11\n");
+
+    session.handle_reader(Cursor::new("GET log")).await?;
+    assert_eq!(output.take(), "a1,b22,c333\n");
+
+    assert!(session.handle_reader(Cursor::new("APPEND log")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_incr() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    // a missing key is initialized to the delta
+    session.handle_reader(Cursor::new("INCRBY hits 5\nINCR hits\nDECRBY hits 10\nINCR new\nGET hits")).await?;
+    assert_eq!(output.take(), "5\n6\n-4\n1\n-4\n");
+
+    // a value that is not an integer is an error and is left as is
+    session.handle_reader(Cursor::new("SET name abc")).await?;
+    assert!(session.handle_reader(Cursor::new("INCR name")).await.is_err());
+    session.handle_reader(Cursor::new("GET name")).await?;
+    assert_eq!(output.take(), "OK\nerror: value is not an integer\nabc\n");
+
+    // overflow is an error
+    session.handle_reader(Cursor::new(format!("SET max {}", i64::MAX))).await?;
+    assert!(session.handle_reader(Cursor::new("INCR max")).await.is_err());
+    session.handle_reader(Cursor::new("GET max")).await?;
+    assert_eq!(output.take(), format!("OK\nerror: increment or decrement would overflow\n{}\n", i64::MAX));
+
+    assert!(session.handle_reader(Cursor::new("INCR")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("INCR a 1")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("INCRBY a x")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("DECRBY a")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_exists() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET b 2\nSET c 3\nDEL c")).await?;
+    output.take();
+
+    // a deleted key does not count, a repeated key counts each time
+    session.handle_reader(Cursor::new("EXISTS a b c x a")).await?;
+    assert_eq!(output.take(), "3\n");
+
+    session.handle_reader(Cursor::new("EXISTS c")).await?;
+    assert_eq!(output.take(), "0\n");
+
+    assert!(session.handle_reader(Cursor::new("EXISTS")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_getset() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    // the key did not exist before
+    session.handle_reader(Cursor::new("GETSET a 1")).await?;
+    assert_eq!(output.take(), "N/A\n");
+
+    session.handle_reader(Cursor::new("GETSET a 2\nGET a")).await?;
+    assert_eq!(output.take(), "1\n2\n");
+
+    assert!(session.handle_reader(Cursor::new("GETSET a")).await.is_err());
+    assert!(session.handle_reader(Cursor::new("GETSET a 3 4")).await.is_err());
+    output.take();
+    session.handle_reader(Cursor::new("GET a")).await?;
+    assert_eq!(output.take(), "2\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_command_outcome() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    assert_eq!(session.execute("SET foo bar").await, CommandOutcome::Ok("OK\n".to_string()));
+    assert_eq!(session.execute("GET foo").await, CommandOutcome::Ok("bar\n".to_string()));
+    assert_eq!(session.execute("SET tmp 1").await, CommandOutcome::Ok("OK\n".to_string()));
+    assert_eq!(session.execute("DEL tmp").await, CommandOutcome::Ok("effect 1\n".to_string()));
+    assert_eq!(session.execute("GET foo bar").await, CommandOutcome::Err("Usage: GET <key>".to_string()));
+    assert!(!session.execute("NOSUCH foo").await.is_ok());
+
+    // the outcome is captured instead of written to the session output
+    assert_eq!(output.take(), "");
+    session.handle_reader(Cursor::new("GET foo")).await?;
+    assert_eq!(output.take(), "bar\n");
+
+    assert_eq!(serde_json::to_string(&session.execute("GET foo").await)?, r#"{"status":"ok","data":"bar\n"}"#);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_render_outcome() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET b SGVsbG8=")).await?;
+    output.take();
+
+    // commands that used to print only in the REPL write to the session output
+    session.handle_reader(Cursor::new("KSIZE\nDECODE b")).await?;
+    assert_eq!(output.take(), "2\nDecoded (base64): Hello\n");
+    session.handle_reader(Cursor::new("MENCCODE a hex")).await?;
+    assert_eq!(output.take(), "Batch encoding 1 keys with format hex:\n  a -> 31\n\nBatch encoding completed: 1 successful, 0 errors\n");
+
+    // errors are rendered to the session output, and still returned
+    assert!(session.handle_reader(Cursor::new("GET a b")).await.is_err());
+    assert_eq!(output.take(), "error: Usage: GET <key>\n");
+
+    let outcome = session.execute("GET a").await;
+    session.render(&outcome)?;
+    assert_eq!(output.take(), "1\n");
+    drop(session);
+
+    // in JSON mode a failure is written as a serialized CommandOutcome
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_output_format(OutputFormat::Json)).await?;
+    assert!(session.handle_reader(Cursor::new("GET a b")).await.is_err());
+    assert_eq!(output.take(), "{\"status\":\"err\",\"data\":\"Usage: GET <key>\"}\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compact_min_reclaim_bytes() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_min_reclaim_bytes(1024)).await?;
+    session.handle_reader(Cursor::new("SET a 1\nSET a 2\nCOMPACT")).await?;
+
+    // a few bytes of garbage is below the floor, the log is left as is
+    let garbage = session.engine().call(|e| Ok(e.status()?.garbage_disk_size)).await?;
+    assert!(garbage > 0);
+    assert_eq!(output.take(), format!("OK\nOK\nskipped, only {} bytes reclaimable (min_reclaim_bytes: 1024)\n", garbage));
+    drop(session);
+
+    let (mut session, _) = new_session(&temp_dir, |c| c.set_min_reclaim_bytes(garbage)).await?;
+    session.handle_reader(Cursor::new("COMPACT")).await?;
+    assert_eq!(session.engine().call(|e| Ok(e.status()?.garbage_disk_size)).await?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compact_reclaims_garbage() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    let sets = (0..3).flat_map(|round| (0..50).map(move |i| format!("SET k{} {}", i, round))).collect::<Vec<_>>().join("\n");
+    session.handle_reader(Cursor::new(sets)).await?;
+    output.take();
+
+    let engine = session.engine();
+    let before = engine.call(|e| e.status()).await?;
+    assert!(before.garbage_disk_size > 0);
+
+    // a dry run reports the estimate and leaves the log unchanged
+    let estimate = engine.call(|e| e.compaction_estimate()).await?;
+    assert_eq!(session.execute("COMPACT --dry-run").await, CommandOutcome::Ok(format!(
+        "reclaimable_bytes: {}\nlive_entries: 50\nlive_bytes: {}\n",
+        estimate.reclaimable_bytes, estimate.live_bytes,
+    )));
+    assert_eq!(engine.call(|e| e.status()).await?, before);
+
+    session.handle_reader(Cursor::new("COMPACT")).await?;
+    let after = engine.call(|e| e.status()).await?;
+    assert_eq!(after.garbage_disk_size, 0);
+    assert_eq!(after.keys, 50);
+    assert_eq!(output.take(), format!(
+        "OK, total_disk_size {} -> {}, reclaimed {} bytes\n",
+        before.total_disk_size, after.total_disk_size, before.total_disk_size - after.total_disk_size,
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_xget() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+    session.engine().set(b"bin".to_vec(), vec![0x00, 0x41, 0xff]).await?;
+
+    session.handle_reader(Cursor::new("XGET bin")).await?;
+    let dump = output.take();
+    assert!(dump.starts_with("00000000  00 41 ff "), "{}", dump);
+    assert!(dump.contains("  |.A.|\n"), "{}", dump);
+    assert!(dump.ends_with("00000003\n"), "{}", dump);
+
+    // longer values wrap every 16 bytes, with a gap after the 8th
+    session.handle_reader(Cursor::new("SET text abcdefghijklmnopq\nXGET text")).await?;
+    assert_eq!(output.take(), "\
+OK
+00000000  61 62 63 64 65 66 67 68  69 6a 6b 6c 6d 6e 6f 70  |abcdefghijklmnop|
+00000010  71                                                |q|
+00000011
+");
+
+    session.handle_reader(Cursor::new("XGET missing")).await?;
+    assert_eq!(output.take(), "N/A\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dscan() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("SET k1 SGVsbG8=\nSET k2 48656c6c6f\nSET k3 a!b\nSET z1 SGVsbG8=")).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("DSCAN k1 k3")).await?;
+    assert_eq!(output.take(), "k1 (base64): Hello\nk2 (hex): Hello\nk3 (raw): a!b\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tokenize_error() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, _output) = new_session(&temp_dir, |_| {}).await?;
+
+    let err = session.handle_reader(Cursor::new("SET a 'unterminated")).await.unwrap_err();
+    assert_eq!(err.to_string(), "unterminated quoted string at column 7");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_help() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    session.handle_reader(Cursor::new("HELP")).await?;
+    let help = output.take();
+    for verb in ["SET", "GET", "DEL", "ENCODE", "DETECT"] {
+        assert!(help.lines().any(|line| line.starts_with(&format!("{} ", verb))), "missing {}", verb);
+    }
+
+    session.handle_reader(Cursor::new("HELP set")).await?;
+    let help = output.take();
+    assert_eq!(help.lines().count(), 1);
+    assert!(help.contains("<key>") && help.contains("<value>"));
+
+    assert!(session.handle_reader(Cursor::new("HELP nope")).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_copy() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    let src_path = temp_dir.path().join("src").join("db");
+    let dest_path = temp_dir.path().join("dest").join("db");
+    {
+        let mut src = LogCask::new(src_path.clone())?;
+        src.set(b"a", b"1".to_vec())?;
+        src.set(b"b", b"2".to_vec())?;
+        src.set(b"c", b"3".to_vec())?;
+        src.delete(b"c")?;
+
+        let mut dest = LogCask::new(dest_path.clone())?;
+        dest.set(b"a", b"old".to_vec())?;
+        dest.set(b"z", b"26".to_vec())?;
+    }
+
+    // merge by default: existing keys in dest are kept, conflicting keys are replaced
+    session.handle_reader(Cursor::new(format!("COPY {} {}", src_path.display(), dest_path.display()))).await?;
+    assert_eq!(output.take(), "OK, 2 keys copied\n");
+    {
+        let dest = LogCask::new(dest_path.clone())?;
+        let keys = dest.scan(..).collect::<kv_rs::error::CResult<Vec<_>>>()?;
+        assert_eq!(keys, vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"2".to_vec()),
+            (b"z".to_vec(), b"26".to_vec()),
+        ]);
+    }
+
+    session.handle_reader(Cursor::new(format!("COPY {} {} OVERWRITE", src_path.display(), dest_path.display()))).await?;
+    assert_eq!(output.take(), "OK, 2 keys copied\n");
+    let src = LogCask::new(src_path)?;
+    let dest = LogCask::new(dest_path)?;
+    assert_eq!(
+        dest.scan(..).collect::<kv_rs::error::CResult<Vec<_>>>()?,
+        src.scan(..).collect::<kv_rs::error::CResult<Vec<_>>>()?,
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_copy_from_locked_db() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |_| {}).await?;
+
+    let src_path = temp_dir.path().join("src").join("db");
+    let dest_path = temp_dir.path().join("dest").join("db");
+    // another writer holds the lock on the source and is in the middle of appending an entry
+    let mut writer = LogCask::new(src_path.clone())?;
+    writer.set(b"a", b"1".to_vec())?;
+    writer.set(b"b", b"2".to_vec())?;
+    writer.flush()?;
+    std::fs::OpenOptions::new().append(true).open(&src_path)?.write_all(&[0, 0, 0, 1, 0])?;
+    let before = std::fs::read(&src_path)?;
+
+    session.handle_reader(Cursor::new(format!("COPY {} {}", src_path.display(), dest_path.display()))).await?;
+    assert_eq!(output.take(), "OK, 2 keys copied\n");
+    assert_eq!(std::fs::read(&src_path)?, before);
+
+    let dest = LogCask::new(dest_path)?;
+    assert_eq!(dest.scan(..).collect::<kv_rs::error::CResult<Vec<_>>>()?, vec![
+        (b"a".to_vec(), b"1".to_vec()),
+        (b"b".to_vec(), b"2".to_vec()),
+    ]);
+    assert_eq!(writer.get(b"b")?, Some(b"2".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_display_rows() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, output) = new_session(&temp_dir, |c| c.set_max_display_rows(Some(3))).await?;
+
+    let sets = (0..10).map(|i| format!("SET k{} v{}", i, i)).collect::<Vec<_>>().join("\n");
+    session.handle_reader(Cursor::new(sets)).await?;
+    output.take();
+
+    session.handle_reader(Cursor::new("KEYS")).await?;
+    assert_eq!(output.take(), "k0\nk1\nk2\n... (7 more)\n");
+
+    session.handle_reader(Cursor::new("DSCAN k0 k9")).await?;
+    assert_eq!(output.take(), "k0 (raw): v0\nk1 (raw): v1\nk2 (raw): v2\n... (7 more)\n");
+
+    // the cursor line is not counted, the page is still COUNT keys long
+    session.handle_reader(Cursor::new("SCAN 0 COUNT 5")).await?;
+    let scanned = output.take();
+    assert_eq!(scanned.lines().skip(1).collect::<Vec<_>>(), ["k0", "k1", "k2", "... (2 more)"]);
+
+    session.handle_reader(Cursor::new("MGET k0 k1 missing k3 k4")).await?;
+    assert_eq!(output.take(), "v0\nv1\nN/A\n... (2 more)\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_decode_errors() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let data_dir = ConfigLoad::new_with_data_dir(temp_dir.path().to_string_lossy().to_string()).get_data_dir();
+        let mut engine = LogCask::new(data_dir)?;
+        engine.set(b"bin", vec![0xff, 0xfe])?;
+    }
+    let (mut session, _output) = new_session(&temp_dir, |c| {
+        let mut encoding = c.get_encoding_config();
+        encoding.format_markers.insert("b64:".to_string(), "base64".to_string());
+        c.set_encoding_config(encoding);
+    }).await?;
+    session.handle_reader(Cursor::new("SET plain a!b\nSET marked b64:!!!!")).await?;
+
+    let decode_error = |err: anyhow::Error| err.downcast::<DecodeError>().expect("a DecodeError");
+
+    let err = decode_error(session.handle_reader(Cursor::new("DECODE bin")).await.unwrap_err());
+    assert_eq!(err, DecodeError::NotUtf8 { key: "bin".to_string() });
+    assert_eq!(err.to_string(), "stored value of key 'bin' is not UTF-8, it can not be decoded");
+
+    let err = decode_error(session.handle_reader(Cursor::new("DECODE plain")).await.unwrap_err());
+    assert_eq!(err, DecodeError::NoFormatDetected { key: "plain".to_string() });
+    assert!(err.to_string().starts_with("no format detected for key 'plain'"));
+
+    let err = decode_error(session.handle_reader(Cursor::new("DECODE marked")).await.unwrap_err());
+    assert!(matches!(err, DecodeError::DecodeFailed { ref key, format: EncodingFormat::Base64, .. } if key == "marked"));
+    assert!(err.to_string().starts_with("detected base64 for key 'marked' but decode failed: "));
+
+    Ok(())
+}
+
+/// A consumer that accepts a number of writes, then behaves like a closed pipe.
+#[derive(Clone, Default)]
+struct ClosingPipe {
+    remaining: Arc<Mutex<usize>>,
+    rejected: Arc<Mutex<usize>>,
+}
+
+impl Write for ClosingPipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut remaining = self.remaining.lock().unwrap();
+        if *remaining == 0 {
+            *self.rejected.lock().unwrap() += 1;
+            return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        }
+        *remaining -= 1;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if *self.remaining.lock().unwrap() == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_keys_broken_pipe() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (mut session, _output) = new_session(&temp_dir, |_| {}).await?;
+
+    let sets = (0..1000).map(|i| format!("SET k{:04} v", i)).collect::<Vec<_>>().join("\n");
+    session.handle_reader(Cursor::new(sets)).await?;
+
+    let pipe = ClosingPipe::default();
+    *pipe.remaining.lock().unwrap() = 10;
+    session.set_output(Box::new(pipe.clone()));
+
+    // the consumer goes away after 10 writes: KEYS stops instead of failing or writing the remaining keys
+    session.handle_reader(Cursor::new("KEYS")).await?;
+    assert_eq!(*pipe.rejected.lock().unwrap(), 1);
+
+    // the session keeps working afterwards
+    let output = SharedOutput::default();
+    session.set_output(Box::new(output.clone()));
+    session.handle_reader(Cursor::new("KEYS k0999")).await?;
+    assert_eq!(output.take(), "k0999\n");
+
+    Ok(())
+}
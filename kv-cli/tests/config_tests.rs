@@ -1,7 +1,7 @@
 use tempfile::TempDir;
 use anyhow::Result;
 
-use kvcli::server::config::{ConfigLoad, EncodingConfig};
+use kvcli::server::config::{ConfigLoad, DataDirMode, EncodingConfig};
 use kv_rs::encoding::EncodingFormat;
 
 #[test]
@@ -118,6 +118,8 @@ fn test_config_load_encoding_config_persistence() -> Result<()> {
         default_format: "hex".to_string(),
         auto_detect: false,
         batch_size: 150,
+        pipeline: vec![],
+        format_markers: Default::default(),
     };
     
     // Set the encoding config
@@ -182,11 +184,151 @@ fn test_encoding_format_string_parsing() -> Result<()> {
     assert_eq!("json".parse::<EncodingFormat>()?, EncodingFormat::Json);
     assert_eq!("JSON".parse::<EncodingFormat>()?, EncodingFormat::Json);
     assert_eq!("Json".parse::<EncodingFormat>()?, EncodingFormat::Json);
+
+    assert_eq!("base32".parse::<EncodingFormat>()?, EncodingFormat::Base32);
+    assert_eq!("BASE32".parse::<EncodingFormat>()?, EncodingFormat::Base32);
     
     // Test invalid format strings
     assert!("invalid".parse::<EncodingFormat>().is_err());
-    assert!("base32".parse::<EncodingFormat>().is_err());
+    assert!("base58".parse::<EncodingFormat>().is_err());
     assert!("".parse::<EncodingFormat>().is_err());
     
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_strip_prefix_config() -> Result<()> {
+    let mut config = ConfigLoad::default();
+    assert_eq!(config.get_strip_prefix(), None);
+    assert_eq!(config.display_key("user:123"), "user:123");
+
+    config.inject_cmd("strip_prefix", "user:")?;
+    assert_eq!(config.get_strip_prefix(), Some("user:"));
+    assert_eq!(config.display_key("user:123"), "123");
+    assert_eq!(config.display_key("order:1"), "order:1");
+
+    Ok(())
+}
+
+#[test]
+fn test_encoding_pipeline_config() -> Result<()> {
+    let mut encoding_config = EncodingConfig::default();
+    assert!(encoding_config.get_pipeline()?.is_empty());
+
+    encoding_config.pipeline = vec!["json".to_string(), "base64".to_string()];
+    assert_eq!(encoding_config.get_pipeline()?, vec![EncodingFormat::Json, EncodingFormat::Base64]);
+    assert!(encoding_config.validate().is_ok());
+
+    // only codecs known to the encoding engine are accepted
+    encoding_config.pipeline = vec!["gzip".to_string(), "base64".to_string()];
+    assert!(encoding_config.validate().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_format_markers_config() -> Result<()> {
+    let mut encoding_config = EncodingConfig::default();
+    assert!(encoding_config.get_format_markers()?.is_empty());
+
+    encoding_config.format_markers.insert("b64:".to_string(), "base64".to_string());
+    assert_eq!(encoding_config.get_format_markers()?, vec![("b64:".to_string(), EncodingFormat::Base64)]);
+    assert!(encoding_config.validate().is_ok());
+
+    encoding_config.format_markers.insert("gz:".to_string(), "gzip".to_string());
+    assert!(encoding_config.validate().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_max_keys_config() -> Result<()> {
+    let mut config = ConfigLoad::default();
+    assert_eq!(config.get_max_keys(), None);
+
+    config.inject_cmd("max_keys", "100")?;
+    assert_eq!(config.get_max_keys(), Some(100));
+    assert!(config.inject_cmd("max_keys", "many").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_max_display_rows_config() -> Result<()> {
+    let mut config = ConfigLoad::default();
+    assert_eq!(config.get_max_display_rows(), None);
+
+    config.inject_cmd("max_display_rows", "3")?;
+    assert_eq!(config.get_max_display_rows(), Some(3));
+    assert!(config.inject_cmd("max_display_rows", "-1").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_numeric_key_order_config() -> Result<()> {
+    let mut config = ConfigLoad::default();
+    assert!(!config.is_numeric_key_order());
+
+    config.inject_cmd("numeric_key_order", "true")?;
+    assert!(config.is_numeric_key_order());
+    assert!(config.inject_cmd("numeric_key_order", "yes").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_data_dir_mode() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut config = ConfigLoad::new_with_data_dir("storage".to_string());
+    assert_eq!(config.get_data_dir_mode(), DataDirMode::Cwd);
+    assert!(config.get_data_dir().is_relative());
+
+    // exe-relative resolution does not depend on the working directory
+    config.set_data_dir_mode(DataDirMode::Exe);
+    let cwd = std::env::current_dir()?;
+    let from_cwd = config.get_data_dir();
+    std::env::set_current_dir(temp_dir.path())?;
+    let from_temp = config.get_data_dir();
+    std::env::set_current_dir(cwd)?;
+
+    assert_eq!(from_cwd, from_temp);
+    let exe_dir = std::env::current_exe()?.parent().unwrap().to_path_buf();
+    assert_eq!(from_cwd, exe_dir.join("storage").join("kvdb"));
+
+    // an explicit base dir takes precedence, absolute data dirs are kept as is
+    config.set_base_dir(Some(temp_dir.path().to_string_lossy().to_string()));
+    assert_eq!(config.get_data_dir(), temp_dir.path().join("storage").join("kvdb"));
+    let config = ConfigLoad::new_with_data_dir(temp_dir.path().to_string_lossy().to_string());
+    assert_eq!(config.get_data_dir(), temp_dir.path().join("kvdb"));
+
+    Ok(())
+}
+
+#[test]
+fn test_max_line_bytes_config() -> Result<()> {
+    let mut config = ConfigLoad::default();
+    assert_eq!(config.get_max_line_bytes(), 64 * 1024 * 1024);
+
+    config.inject_cmd("max_line_bytes", "1024")?;
+    assert_eq!(config.get_max_line_bytes(), 1024);
+    assert!(config.inject_cmd("max_line_bytes", "-1").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_warmup_config() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config_path = temp_dir.path().join("kvdb.yaml");
+    std::fs::write(&config_path, "read_cache_size: 4096\nwarmup_prefixes: [\"user:\", \"session:\"]\n")?;
+
+    let config = ConfigLoad::new(config_path.to_str().unwrap())?;
+    assert_eq!(config.get_read_cache_size(), 4096);
+    assert_eq!(config.get_warmup_prefixes(), &["user:".to_string(), "session:".to_string()]);
+
+    let config = ConfigLoad::default();
+    assert_eq!(config.get_read_cache_size(), 0);
+    assert!(config.get_warmup_prefixes().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "http")]
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use anyhow::Result;
+use reqwest::StatusCode;
+use tempfile::TempDir;
+
+use kvcli::command::http;
+use kvcli::server::config::ConfigLoad;
+use kvcli::server::session::Session;
+
+/// Integration tests for the embedded HTTP API, run with `--features http`
+
+#[tokio::test]
+async fn test_http_get_put_delete() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let settings = ConfigLoad::new_with_data_dir(temp_dir.path().to_string_lossy().to_string());
+    let running = Arc::new(AtomicBool::new(true));
+    let session = Session::try_new(settings, false, false, running.clone()).await?;
+
+    let (addr, server) = http::serve(session.engine(), "127.0.0.1:0".parse()?, running.clone())?;
+    let server = tokio::spawn(server);
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/kv/greeting", addr);
+
+    let resp = client.get(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = client.put(&url).body(vec![b'h', b'i', 0xff]).send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = client.get(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.bytes().await?.to_vec(), vec![b'h', b'i', 0xff]);
+
+    // writes through the API are visible to the session's engine
+    assert_eq!(session.engine().get(b"greeting".to_vec()).await?, Some(vec![b'h', b'i', 0xff]));
+
+    let resp = client.delete(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = client.get(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    let resp = client.post(&url).send().await?;
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // the key in the path is percent-decoded
+    let resp = client.put(format!("http://{}/kv/a%20b%2Fc", addr)).body("v").send().await?;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(session.engine().get(b"a b/c".to_vec()).await?, Some(b"v".to_vec()));
+    let resp = client.get(format!("http://{}/kv/a%20b%2Fc", addr)).send().await?;
+    assert_eq!(resp.bytes().await?.to_vec(), b"v".to_vec());
+
+    // oversized bodies are rejected without being written
+    let resp = client.put(&url).body(vec![0u8; http::MAX_BODY_BYTES + 1]).send().await?;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(session.engine().get(b"greeting".to_vec()).await?, None);
+
+    running.store(false, Ordering::SeqCst);
+    server.await??;
+
+    Ok(())
+}
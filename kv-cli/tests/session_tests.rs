@@ -123,10 +123,11 @@ fn test_encoding_format_case_insensitive() -> Result<()> {
     assert_eq!("Hex".parse::<EncodingFormat>()?, EncodingFormat::Hex);
     assert_eq!("JSON".parse::<EncodingFormat>()?, EncodingFormat::Json);
     assert_eq!("Json".parse::<EncodingFormat>()?, EncodingFormat::Json);
+    assert_eq!("Base32".parse::<EncodingFormat>()?, EncodingFormat::Base32);
     
     // 测试无效格式
     assert!("invalid".parse::<EncodingFormat>().is_err());
-    assert!("base32".parse::<EncodingFormat>().is_err());
+    assert!("base58".parse::<EncodingFormat>().is_err());
     assert!("".parse::<EncodingFormat>().is_err());
     
     Ok(())
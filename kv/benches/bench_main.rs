@@ -1,47 +1,161 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use kv_rs::storage::engine::Engine;
+use kv_rs::storage::log_cask::{LogCask, SyncPolicy};
 use rand::Rng;
-use serde_derive::{Deserialize, Serialize};
 
-fn codec_bytes(num: u64) -> u64 {
-    let list = MockStu::get_mock_list(num as usize);
+// LogCask 的基本操作：set / get / scan / compact，以及写入路径和 fsync 的开销
 
-    match num {
-        0 => 1,
-        1 => 1,
-        n => codec_bytes(n-1) + codec_bytes(n-2),
+const VALUE_SIZE: usize = 64;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:08}", i).into_bytes()
+}
+
+fn setup(dir: &tempdir::TempDir, name: &str, num: usize) -> LogCask {
+    let mut engine = LogCask::new_with_lock(dir.path().join(name), false).unwrap();
+    for i in 0..num {
+        engine.set(&key(i), vec![0u8; VALUE_SIZE]).unwrap();
     }
+    engine
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct MockStu {
-    name: String,
-    age: u32,
-    address: String,
-    sex: u8,
+fn bench_set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_set");
+    group.throughput(Throughput::Elements(1));
+
+    // SyncPolicy::Always 每次写入后 fsync，与 Never 的差值即为 fsync 的开销
+    for (name, policy) in [("never", SyncPolicy::Never), ("every_100", SyncPolicy::EveryN(100)), ("always", SyncPolicy::Always)] {
+        let dir = tempdir::TempDir::new("engine_bench").unwrap();
+        let mut engine = setup(&dir, name, 0);
+        engine.set_sync_policy(policy);
+
+        group.bench_function(BenchmarkId::new("sync", name), |b| {
+            let mut i = 0;
+            b.iter(|| {
+                i += 1;
+                engine.set(&key(i), black_box(vec![0u8; VALUE_SIZE])).unwrap()
+            })
+        });
+    }
+
+    group.finish();
 }
 
-impl MockStu {
-    fn get_mock_list(num: usize) -> Vec<MockStu> {
-        let mut list = Vec::with_capacity(num);
-        let mut rng = rand::thread_rng();
+fn bench_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_get");
+    group.throughput(Throughput::Elements(1));
 
-        for _ in 0..num {
-            let s = MockStu {
-                name: "张三".to_string(),
-                age: rng.gen_range(0..80),
-                address: "杭州余杭区".to_string(),
-                sex: 1,
-            };
-            list.push(s);
-        }
+    for size in [1_000, 100_000] {
+        let dir = tempdir::TempDir::new("engine_bench").unwrap();
+        let engine = setup(&dir, "get", size);
+        let keys: Vec<Vec<u8>> = {
+            let mut rng = rand::thread_rng();
+            (0..1024).map(|_| key(rng.gen_range(0..size))).collect()
+        };
 
-        list
+        group.bench_with_input(BenchmarkId::from_parameter(size), &keys, |b, keys| {
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                black_box(engine.get(&keys[i]).unwrap())
+            })
+        });
     }
+
+    group.finish();
 }
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("codec bytes 20", |b| b.iter(|| codec_bytes(black_box(20))));
+fn bench_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_scan");
+
+    for size in [100, 10_000] {
+        let dir = tempdir::TempDir::new("engine_bench").unwrap();
+        let engine = setup(&dir, "scan", size);
+
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                for item in engine.scan(..) {
+                    black_box(item.unwrap());
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_compact(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_compact");
+    group.sample_size(10);
+
+    for size in [1_000, 10_000] {
+        let dir = tempdir::TempDir::new("engine_bench").unwrap();
+
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_batched(
+                || {
+                    // 每个key写两次，一半的日志为垃圾数据
+                    let mut engine = setup(&dir, "compact", size);
+                    for i in 0..size {
+                        engine.set(&key(i), vec![1u8; VALUE_SIZE]).unwrap();
+                    }
+                    engine
+                },
+                |mut engine| {
+                    engine.compact().unwrap();
+                    engine
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+/// 写入一条与 V1 日志格式相同的 entry
+fn write_entry<W: Write>(w: &mut W, key: &[u8], value: &[u8]) {
+    w.write_all(&(key.len() as u32).to_be_bytes()).unwrap();
+    w.write_all(&(value.len() as i32).to_be_bytes()).unwrap();
+    w.write_all(key).unwrap();
+    w.write_all(value).unwrap();
+}
+
+/// Log::append 每次写入都新建 BufWriter 并 flush，与持有一个 BufWriter 连续写入的对比
+fn bench_write_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_path");
+    group.throughput(Throughput::Elements(1));
+    let value = vec![0u8; VALUE_SIZE];
+
+    let dir = tempdir::TempDir::new("engine_bench").unwrap();
+    let mut file = File::create(dir.path().join("per_write")).unwrap();
+    group.bench_function("per_write_bufwriter", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            file.seek(SeekFrom::End(0)).unwrap();
+            let mut w = BufWriter::with_capacity(8 + 12 + VALUE_SIZE, &mut file);
+            write_entry(&mut w, &key(i), black_box(&value));
+            w.flush().unwrap();
+        })
+    });
+
+    let mut w = BufWriter::new(File::create(dir.path().join("persistent")).unwrap());
+    group.bench_function("persistent_bufwriter", |b| {
+        let mut i = 0;
+        b.iter(|| {
+            i += 1;
+            write_entry(&mut w, &key(i), black_box(&value));
+        })
+    });
+    w.flush().unwrap();
+
+    group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
-criterion_main!(benches);
\ No newline at end of file
+criterion_group!(engine_benches, bench_set, bench_get, bench_scan, bench_compact, bench_write_path);
+criterion_main!(engine_benches);
@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kv_rs::storage::engine::Engine;
+use kv_rs::storage::index::{HashKeyDir, Index};
+use kv_rs::storage::log_cask::LogCask;
+use kv_rs::storage::KeyDir;
+use rand::Rng;
+
+// 比较不同内存索引(BTreeMap / HashMap)下 LogCask 的点查吞吐
+
+fn setup<I: Index>(dir: &tempdir::TempDir, num: usize) -> LogCask<I> {
+    let path = dir.path().join(format!("bench_{}", I::name()));
+    let mut engine = LogCask::<I>::new_with_index(path, false).unwrap();
+    for i in 0..num {
+        engine.set(format!("key_{:08}", i).as_bytes(), vec![0u8; 64]).unwrap();
+    }
+    engine
+}
+
+fn bench_point_get<I: Index>(c: &mut Criterion, sizes: &[usize]) {
+    let mut group = c.benchmark_group("index_point_get");
+
+    for &size in sizes {
+        let dir = tempdir::TempDir::new("index_bench").unwrap();
+        let engine = setup::<I>(&dir, size);
+        let keys: Vec<Vec<u8>> = {
+            let mut rng = rand::thread_rng();
+            (0..1024).map(|_| format!("key_{:08}", rng.gen_range(0..size)).into_bytes()).collect()
+        };
+
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::new(I::name(), size), &keys, |b, keys| {
+            let mut i = 0;
+            b.iter(|| {
+                i = (i + 1) % keys.len();
+                black_box(engine.get(&keys[i]).unwrap())
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let sizes = [1_000, 10_000, 100_000];
+    bench_point_get::<KeyDir>(c, &sizes);
+    bench_point_get::<HashKeyDir>(c, &sizes);
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
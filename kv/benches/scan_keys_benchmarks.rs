@@ -0,0 +1,40 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kv_rs::storage::engine::Engine;
+use kv_rs::storage::log_cask::LogCask;
+
+// 比较只枚举 key 时 scan_keys 与 scan 的耗时：scan 需要从磁盘读取每个 value，scan_keys 只遍历 keydir
+
+const VALUE_SIZE: usize = 4 * 1024 * 1024;
+
+fn setup(dir: &tempdir::TempDir, num: usize) -> LogCask {
+    let mut engine = LogCask::new_with_lock(dir.path().join("bench_scan_keys"), false).unwrap();
+    for i in 0..num {
+        engine.set(format!("key_{:08}", i).as_bytes(), vec![i as u8; VALUE_SIZE]).unwrap();
+    }
+    engine.flush().unwrap();
+    engine
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan_keys_4mb_values");
+    group.sample_size(10);
+
+    for size in [16, 64] {
+        let dir = tempdir::TempDir::new("scan_keys_bench").unwrap();
+        let engine = setup(&dir, size);
+        assert!(engine.scan_keys(..).map(Result::unwrap).eq(engine.scan(..).map(|kv| kv.unwrap().0)));
+
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("scan_keys", size), &engine, |b, engine| {
+            b.iter(|| black_box(engine.scan_keys(..).collect::<Result<Vec<_>, _>>().unwrap()))
+        });
+        group.bench_with_input(BenchmarkId::new("scan", size), &engine, |b, engine| {
+            b.iter(|| black_box(engine.scan(..).map(|kv| kv.map(|kv| kv.0)).collect::<Result<Vec<_>, _>>().unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -2,7 +2,7 @@ use std::io::{Cursor, Read};
 use byteorder::ReadBytesExt;
 use serde::Deserialize;
 use tokio_util::bytes::{BufMut, BytesMut};
-use crate::codec::Codec;
+use crate::codec::{payload, Codec};
 use crate::error::{CResult, Error};
 
 #[derive(Clone, Copy)]
@@ -39,29 +39,7 @@ impl BytesCodec {
     }
 
     pub fn decode_bytes<R>(&self, value: &[u8], decode_len: bool) -> CResult<R> where R: for<'a> Deserialize<'a> {
-
-        let mut bytes = Vec::new();
-        if decode_len {
-            let mut cursor = Cursor::new(value);
-
-            let len = cursor.read_u64::<byteorder::BigEndian>().unwrap() as usize;
-            let mut b = vec![0; len];
-            cursor.read_exact(&mut b).unwrap();
-            bytes = b.to_vec();
-        } else {
-            bytes = value.to_vec();
-        }
-
-        let str = String::from_utf8(bytes).unwrap();
-        let r: serde_json::Result<R> = serde_json::from_str(&str);
-        match r {
-            Ok(r) => {
-                Ok(r)
-            }
-            Err(err) => {
-                Err(Error::Parse(err.to_string()))
-            }
-        }
+        Codec::decode(self, value, decode_len)
     }
 
     pub fn decode_cursor<R>(&self, cursor: &mut Cursor<&[u8]>) -> CResult<Option<R>> where R: for<'a> Deserialize<'a> {
@@ -88,6 +66,22 @@ impl Codec for BytesCodec {
     fn codec_name<T>(&self) -> String {
         "BytesCodec".to_string()
     }
+
+    fn decode<R>(&self, value: &[u8], contains_len: bool) -> CResult<R>
+        where R: for<'a> Deserialize<'a> {
+        let bytes = payload(value, contains_len)?;
+
+        let str = std::str::from_utf8(bytes).map_err(|err| Error::Parse(err.to_string()))?;
+        let r: serde_json::Result<R> = serde_json::from_str(str);
+        match r {
+            Ok(r) => {
+                Ok(r)
+            }
+            Err(err) => {
+                Err(Error::Parse(err.to_string()))
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +91,8 @@ mod test {
     use bytes::{BufMut, BytesMut};
     use serde_derive::{Deserialize, Serialize};
     use crate::codec::bytes_codec::BytesCodec;
+    use crate::codec::Codec;
+    use crate::error::Error;
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct Persion {
@@ -195,4 +191,33 @@ mod test {
         }
         assert!(cursor.position() >= cursor.get_ref().len() as u64);
     }
+
+    #[test]
+    fn test_decode_contains_len() {
+        let codec = BytesCodec::new();
+        let p = Persion { name: "name".to_string(), age: 18, address: "address".to_string() };
+
+        // with the length prefix
+        let framed = codec.encode(&p).unwrap();
+        let r: Persion = Codec::decode(&codec, &framed, true).unwrap();
+        assert_eq!(r.name, p.name);
+
+        // without the length prefix
+        let r: Persion = Codec::decode(&codec, &framed[8..], false).unwrap();
+        assert_eq!(r.age, p.age);
+
+        // a prefixed value is not valid without stripping the prefix
+        assert!(Codec::decode::<Persion>(&codec, &framed, false).is_err());
+
+        // length mismatch
+        let mut truncated = framed.clone();
+        truncated.pop();
+        assert_eq!(
+            Codec::decode::<Persion>(&codec, &truncated, true).err(),
+            Some(Error::Parse(format!(
+                "length prefix mismatch: prefix says {} bytes, but {} bytes remain", framed.len() - 8, framed.len() - 9
+            )))
+        );
+        assert!(Codec::decode::<Persion>(&codec, &[0, 0, 0], true).is_err());
+    }
 }
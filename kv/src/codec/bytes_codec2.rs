@@ -3,7 +3,7 @@ use byteorder::ReadBytesExt;
 use bytes::{BufMut, BytesMut};
 use serde::Deserialize;
 use crate::codec::bytes_codec::BytesCodec;
-use crate::codec::Codec;
+use crate::codec::{payload, Codec};
 use crate::error::{CResult, Error};
 
 #[derive(Clone, Copy)]
@@ -85,6 +85,13 @@ impl Codec for BytesCodec2 {
     fn codec_name<T>(&self) -> String {
         "BytesCodec2".to_string()
     }
+
+    fn decode<R>(&self, value: &[u8], contains_len: bool) -> CResult<R>
+        where R: for<'a> Deserialize<'a> {
+        let bytes = payload(value, contains_len)?;
+
+        bincode::deserialize(bytes).map_err(|err| Error::Parse(err.to_string()))
+    }
 }
 
 #[cfg(test)]
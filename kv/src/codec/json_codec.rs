@@ -1,7 +1,7 @@
 use std::io::{Read, Write};
 use serde::{de, Deserialize};
 use tokio::io::AsyncWriteExt;
-use crate::codec::Codec;
+use crate::codec::{payload, Codec};
 use crate::error::{CResult, Error};
 
 #[derive(Clone, Copy)]
@@ -50,12 +50,29 @@ impl Codec for JsonCodec {
     fn codec_name<T>(&self) -> String {
         "JsonCodec".to_string()
     }
+
+    fn decode<R>(&self, value: &[u8], contains_len: bool) -> CResult<R>
+        where R: for<'a> Deserialize<'a> {
+        let bytes = payload(value, contains_len)?;
+
+        let r: serde_json::Result<R> = serde_json::from_slice(bytes);
+        match r {
+            Ok(r) => {
+                Ok(r)
+            }
+            Err(err) => {
+                Err(Error::Parse(err.to_string()))
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use serde_derive::{Deserialize, Serialize};
     use crate::codec::json_codec::JsonCodec;
+    use crate::codec::Codec;
+    use crate::error::Error;
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct Persion {
@@ -103,4 +120,22 @@ mod test {
         assert_eq!(1, 1);
     }
 
+    #[test]
+    fn test_json_decode_contains_len() {
+        let codec = JsonCodec::new();
+        let p = Persion { name: "name".to_string(), age: 18, address: "address".to_string() };
+        let json = codec.encode(&p).unwrap().into_bytes();
+
+        let r: Persion = Codec::decode(&codec, &json, false).unwrap();
+        assert_eq!(r.name, p.name);
+
+        let mut framed = (json.len() as u64).to_be_bytes().to_vec();
+        framed.extend_from_slice(&json);
+        let r: Persion = Codec::decode(&codec, &framed, true).unwrap();
+        assert_eq!(r.address, p.address);
+
+        // length mismatch
+        framed[7] += 1;
+        assert!(matches!(Codec::decode::<Persion>(&codec, &framed, true), Err(Error::Parse(_))));
+    }
 }
@@ -2,7 +2,41 @@ pub mod json_codec;
 pub mod bytes_codec;
 mod bytes_codec2;
 
+use serde::Deserialize;
+use crate::error::{CResult, Error};
+
+/// 长度前缀的字节数，big-endian u64
+pub const LEN_PREFIX_SIZE: usize = 8;
+
 /// Define a codec type and implement the Codec trait
 pub trait Codec {
     fn codec_name<T>(&self) -> String;
+
+    /// 解码 value。
+    ///
+    /// contains_len 为 true 时，value 的前8个字节为 big-endian 的长度前缀，需先去掉，并校验其与剩余的字节数一致；
+    /// 为 false 时，解码整个 value。
+    fn decode<R>(&self, value: &[u8], contains_len: bool) -> CResult<R>
+        where R: for<'a> Deserialize<'a>;
+}
+
+/// 按 contains_len 去掉长度前缀，返回需要解码的部分
+pub(crate) fn payload(value: &[u8], contains_len: bool) -> CResult<&[u8]> {
+    if !contains_len {
+        return Ok(value);
+    }
+
+    if value.len() < LEN_PREFIX_SIZE {
+        return Err(Error::Parse(format!(
+            "value too short for a length prefix: {} bytes", value.len()
+        )));
+    }
+    let (prefix, rest) = value.split_at(LEN_PREFIX_SIZE);
+    let len = u64::from_be_bytes(prefix.try_into()?);
+    if len != rest.len() as u64 {
+        return Err(Error::Parse(format!(
+            "length prefix mismatch: prefix says {} bytes, but {} bytes remain", len, rest.len()
+        )));
+    }
+    Ok(rest)
 }
@@ -0,0 +1,171 @@
+use crate::encoding::{DataCodec, EncodingError};
+
+/// RFC 4648 Base32 alphabet
+const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Base32 (RFC 4648) encoding/decoding implementation.
+///
+/// Encodes without `=` padding; decoding accepts both padded and unpadded input.
+pub struct Base32Codec;
+
+impl Base32Codec {
+    /// Create a new Base32 codec instance
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for Base32Codec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encode bytes as unpadded Base32
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Decode padded or unpadded Base32, rejecting invalid lengths, misplaced padding and non-zero trailing bits.
+/// Lowercase letters are accepted.
+pub(crate) fn decode(encoded: &str) -> Result<Vec<u8>, String> {
+    let unpadded = encoded.trim_end_matches('=');
+    let padding = encoded.len() - unpadded.len();
+    if padding > 0 && (!encoded.len().is_multiple_of(8) || padding > 6) {
+        return Err(format!("invalid padding of {} characters", padding));
+    }
+    // Every group of 8 characters encodes 5 bytes, the last group can only stop at a byte boundary
+    if matches!(unpadded.len() % 8, 1 | 3 | 6) {
+        return Err(format!("invalid length {}", unpadded.len()));
+    }
+
+    let mut out = Vec::with_capacity(unpadded.len() * 5 / 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for (i, c) in unpadded.bytes().enumerate() {
+        let value = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return Err(format!("invalid character {:?} at position {}", c as char, i)),
+        };
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    if buffer & ((1 << bits) - 1) != 0 {
+        return Err("non-zero trailing bits".to_string());
+    }
+    Ok(out)
+}
+
+impl DataCodec for Base32Codec {
+    fn encode(&self, data: &[u8]) -> Result<String, EncodingError> {
+        Ok(encode(data))
+    }
+
+    fn decode(&self, encoded: &str) -> Result<Vec<u8>, EncodingError> {
+        decode(encoded.trim())
+            .map_err(|e| EncodingError::DecodingFailed(format!("Base32 decode error: {}", e)))
+    }
+
+    fn can_decode(&self, data: &str) -> bool {
+        decode(data.trim()).is_ok()
+    }
+
+    fn format_name(&self) -> &'static str {
+        "base32"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base32_encode() {
+        let codec = Base32Codec::new();
+
+        // RFC 4648 test vectors, without padding
+        assert_eq!(codec.encode(b"").unwrap(), "");
+        assert_eq!(codec.encode(b"f").unwrap(), "MY");
+        assert_eq!(codec.encode(b"fo").unwrap(), "MZXQ");
+        assert_eq!(codec.encode(b"foo").unwrap(), "MZXW6");
+        assert_eq!(codec.encode(b"foob").unwrap(), "MZXW6YQ");
+        assert_eq!(codec.encode(b"fooba").unwrap(), "MZXW6YTB");
+        assert_eq!(codec.encode(b"foobar").unwrap(), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn test_base32_decode() {
+        let codec = Base32Codec::new();
+
+        assert_eq!(codec.decode("").unwrap(), b"");
+        assert_eq!(codec.decode("MZXW6YTBOI").unwrap(), b"foobar");
+
+        // Padded input
+        assert_eq!(codec.decode("MY======").unwrap(), b"f");
+        assert_eq!(codec.decode("MZXW6YQ=").unwrap(), b"foob");
+        assert_eq!(codec.decode("MZXW6YTBOI======").unwrap(), b"foobar");
+
+        // Lowercase and surrounding whitespace
+        assert_eq!(codec.decode("  mzxw6  ").unwrap(), b"foo");
+    }
+
+    #[test]
+    fn test_base32_decode_invalid() {
+        let codec = Base32Codec::new();
+
+        assert!(codec.decode("MZXW1").is_err()); // '1' is not in the alphabet
+        assert!(codec.decode("MZX").is_err()); // Invalid length
+        assert!(codec.decode("MY=").is_err()); // Padding to a non-multiple of 8
+        assert!(codec.decode("MZ======").is_err()); // Non-zero trailing bits
+        assert!(codec.decode("M=Y=====").is_err()); // Padding in the middle
+        assert!(!codec.can_decode("hello world"));
+        assert!(codec.can_decode("MZXW6YQ="));
+    }
+
+    #[test]
+    fn test_base32_roundtrip() {
+        let codec = Base32Codec::new();
+
+        let all_bytes = (0..=255).collect::<Vec<u8>>();
+        let test_cases = vec![
+            b"".as_slice(),
+            b"a",
+            b"hello",
+            b"The quick brown fox jumps over the lazy dog",
+            &[0, 1, 2, 3, 4, 5, 255, 254, 253],
+            &all_bytes,
+        ];
+
+        for data in test_cases {
+            let encoded = codec.encode(data).unwrap();
+            assert!(!encoded.contains('='));
+            let decoded = codec.decode(&encoded).unwrap();
+            assert_eq!(decoded, data, "Roundtrip failed for: {:?}", data);
+        }
+    }
+
+    #[test]
+    fn test_base32_format_name() {
+        let codec = Base32Codec::new();
+        assert_eq!(codec.format_name(), "base32");
+    }
+}
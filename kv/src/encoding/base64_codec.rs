@@ -74,6 +74,45 @@ impl DataCodec for Base64Codec {
     }
 }
 
+/// URL-safe Base64 encoding/decoding implementation, using `-` and `_` instead of `+` and `/`
+pub struct Base64UrlCodec;
+
+impl Base64UrlCodec {
+    /// Create a new URL-safe Base64 codec instance
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for Base64UrlCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DataCodec for Base64UrlCodec {
+    fn encode(&self, data: &[u8]) -> Result<String, EncodingError> {
+        Ok(general_purpose::URL_SAFE.encode(data))
+    }
+
+    fn decode(&self, encoded: &str) -> Result<Vec<u8>, EncodingError> {
+        // Tokens frequently omit the padding, accept both forms
+        let trimmed = encoded.trim();
+        general_purpose::URL_SAFE
+            .decode(trimmed)
+            .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(trimmed))
+            .map_err(|e| EncodingError::DecodingFailed(format!("Base64Url decode error: {}", e)))
+    }
+
+    fn can_decode(&self, data: &str) -> bool {
+        self.decode(data).is_ok()
+    }
+
+    fn format_name(&self) -> &'static str {
+        "base64url"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,6 +210,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_base64url() {
+        let standard = Base64Codec::new();
+        let url = Base64UrlCodec::new();
+
+        assert_eq!(url.encode(&[0, 1, 2, 3, 255]).unwrap(), "AAECA_8=");
+        assert_eq!(url.encode(&[251, 255]).unwrap(), "-_8=");
+
+        // `-` and `_` only decode under the URL-safe alphabet
+        for encoded in ["AAECA_8=", "AAECA_8", "-_8="] {
+            assert!(url.can_decode(encoded), "{}", encoded);
+            assert!(!standard.can_decode(encoded), "{}", encoded);
+            assert!(standard.decode(encoded).is_err(), "{}", encoded);
+        }
+        assert_eq!(url.decode("AAECA_8").unwrap(), vec![0, 1, 2, 3, 255]);
+        assert_eq!(url.decode(" -_8= ").unwrap(), vec![251, 255]);
+
+        // and `+` and `/` only under the standard one
+        assert!(!url.can_decode("AAECA/8="));
+        assert!(url.decode("+/8=").is_err());
+
+        for data in [b"".as_slice(), b"a", b"hello world", &[0xfb, 0xff, 0xfe]] {
+            assert_eq!(url.decode(&url.encode(data).unwrap()).unwrap(), data);
+        }
+        assert_eq!(url.format_name(), "base64url");
+    }
+
     #[test]
     fn test_base64_format_name() {
         let codec = Base64Codec::new();
@@ -1,4 +1,5 @@
 use crate::encoding::{EncodingFormat, EncodingError};
+use crate::encoding::json_codec::try_parse_json_string;
 use std::collections::HashMap;
 
 /// Detection result with format and confidence score
@@ -14,10 +15,48 @@ impl DetectionResult {
     }
 }
 
+/// Weights of the signals that make up a detection confidence score
+///
+/// Each weight scales the score its signal contributes in every `detect_*` method, the defaults
+/// of 1.0 keep the built-in scores. For example lowering `decode_success` makes formats that
+/// merely decode rank below formats whose length and character set fit better.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectionWeights {
+    /// Length and padding checks, e.g. a multiple of 4 for Base64 or an even length for hex
+    pub length_factor: f32,
+    /// All characters belong to the format's alphabet
+    pub charset_match: f32,
+    /// The data actually decodes in the format
+    pub decode_success: f32,
+    /// Character distribution analysis, and escape sequences for JSON
+    pub distribution: f32,
+}
+
+impl Default for DetectionWeights {
+    fn default() -> Self {
+        Self {
+            length_factor: 1.0,
+            charset_match: 1.0,
+            decode_success: 1.0,
+            distribution: 1.0,
+        }
+    }
+}
+
+/// Upper bound of the confidence for raw (non-string) JSON values
+const JSON_VALUE_MAX_CONFIDENCE: f32 = 0.6;
+
+/// Confidence for bare JSON numbers, booleans and null
+const JSON_SCALAR_CONFIDENCE: f32 = 0.15;
+
 /// Format detector for automatic encoding format detection
 pub struct FormatDetector {
     /// Minimum confidence threshold for detection results
     min_confidence: f32,
+    /// Format marker prefixes such as `b64:`, values starting with one are detected without scoring
+    markers: Vec<(String, EncodingFormat)>,
+    /// Weights of the scoring signals
+    weights: DetectionWeights,
 }
 
 impl FormatDetector {
@@ -25,17 +64,50 @@ impl FormatDetector {
     pub fn new() -> Self {
         Self {
             min_confidence: 0.1,
+            markers: Vec::new(),
+            weights: DetectionWeights::default(),
         }
     }
 
     /// Create a new format detector with custom minimum confidence threshold
     pub fn with_min_confidence(min_confidence: f32) -> Self {
-        Self { min_confidence }
+        Self { min_confidence, markers: Vec::new(), weights: DetectionWeights::default() }
+    }
+
+    /// Create a new format detector with custom scoring weights
+    pub fn with_weights(weights: DetectionWeights) -> Self {
+        Self { weights, ..Self::new() }
+    }
+
+    /// The scoring weights in use
+    pub fn weights(&self) -> &DetectionWeights {
+        &self.weights
+    }
+
+    /// Register a marker prefix for a format, e.g. `add_marker("b64:", EncodingFormat::Base64)`
+    pub fn add_marker(&mut self, marker: impl Into<String>, format: EncodingFormat) {
+        let marker = marker.into();
+        self.markers.retain(|(m, _)| *m != marker);
+        self.markers.push((marker, format));
+    }
+
+    /// Match the longest registered marker prefix, returning its format and the data with the marker stripped
+    pub fn match_marker<'a>(&self, data: &'a str) -> Option<(EncodingFormat, &'a str)> {
+        self.markers
+            .iter()
+            .filter(|(marker, _)| data.starts_with(marker.as_str()))
+            .max_by_key(|(marker, _)| marker.len())
+            .map(|(marker, format)| (*format, &data[marker.len()..]))
     }
 
     /// Detect the encoding format of the given data
     /// Returns a sorted list of possible formats with confidence scores (highest first)
     pub fn detect(&self, data: &str) -> Vec<DetectionResult> {
+        // Marked values short-circuit the scoring below
+        if let Some((format, _)) = self.match_marker(data) {
+            return vec![DetectionResult::new(format, 1.0)];
+        }
+
         let mut results = Vec::new();
         
         // Detect Base64
@@ -45,6 +117,20 @@ impl FormatDetector {
             }
         }
         
+        // Detect URL-safe Base64
+        if let Some(confidence) = self.detect_base64url(data) {
+            if confidence >= self.min_confidence {
+                results.push(DetectionResult::new(EncodingFormat::Base64Url, confidence));
+            }
+        }
+
+        // Detect Base32
+        if let Some(confidence) = self.detect_base32(data) {
+            if confidence >= self.min_confidence {
+                results.push(DetectionResult::new(EncodingFormat::Base32, confidence));
+            }
+        }
+
         // Detect Hex
         if let Some(confidence) = self.detect_hex(data) {
             if confidence >= self.min_confidence {
@@ -83,6 +169,14 @@ impl FormatDetector {
             stats.insert(EncodingFormat::Base64, confidence);
         }
         
+        if let Some(confidence) = self.detect_base64url(data) {
+            stats.insert(EncodingFormat::Base64Url, confidence);
+        }
+
+        if let Some(confidence) = self.detect_base32(data) {
+            stats.insert(EncodingFormat::Base32, confidence);
+        }
+
         if let Some(confidence) = self.detect_hex(data) {
             stats.insert(EncodingFormat::Hex, confidence);
         }
@@ -109,7 +203,7 @@ impl FormatDetector {
         if trimmed.len() % 4 != 0 {
             return None;
         }
-        confidence += 0.2;
+        confidence += 0.2 * self.weights.length_factor;
         
         // Check for valid Base64 characters
         let valid_chars = trimmed.chars().all(|c| {
@@ -119,7 +213,7 @@ impl FormatDetector {
         if !valid_chars {
             return None;
         }
-        confidence += 0.3;
+        confidence += 0.3 * self.weights.charset_match;
         
         // Check padding rules
         let padding_count = trimmed.chars().rev().take_while(|&c| c == '=').count();
@@ -129,7 +223,7 @@ impl FormatDetector {
         
         // Proper padding increases confidence
         if padding_count <= 2 {
-            confidence += 0.2;
+            confidence += 0.2 * self.weights.length_factor;
         }
         
         // If there's padding, it should only be at the end
@@ -138,16 +232,16 @@ impl FormatDetector {
             if non_padding_part.contains('=') {
                 return None;
             }
-            confidence += 0.1;
+            confidence += 0.1 * self.weights.length_factor;
         }
         
         // Character distribution analysis
         let char_distribution = self.analyze_base64_char_distribution(trimmed);
-        confidence += char_distribution * 0.2;
+        confidence += char_distribution * 0.2 * self.weights.distribution;
         
         // Try to decode to verify it's valid Base64
         if base64::Engine::decode(&base64::engine::general_purpose::STANDARD, trimmed).is_ok() {
-            confidence += 0.3;
+            confidence += 0.3 * self.weights.decode_success;
         } else {
             return None;
         }
@@ -156,6 +250,107 @@ impl FormatDetector {
         Some(confidence.min(1.0))
     }
 
+    /// Detect URL-safe Base64 format with confidence scoring
+    ///
+    /// Without `-` or `_` the data is equally valid standard Base64, so it scores slightly below
+    /// `detect_base64` and both formats are reported.
+    fn detect_base64url(&self, data: &str) -> Option<f32> {
+        let trimmed = data.trim();
+
+        // Empty string is valid but very low confidence
+        if trimmed.is_empty() {
+            return Some(0.05);
+        }
+
+        // Check for valid URL-safe Base64 characters
+        let valid_chars = trimmed.chars().all(|c| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '='
+        });
+
+        if !valid_chars {
+            return None;
+        }
+
+        if !trimmed.contains(['-', '_']) {
+            return self.detect_base64(trimmed).map(|confidence| confidence * 0.9);
+        }
+
+        let mut confidence: f32 = 0.3 * self.weights.charset_match;
+
+        // Padding is optional, but if present must fill the last group of 4
+        let padding_count = trimmed.chars().rev().take_while(|&c| c == '=').count();
+        if padding_count > 2 || trimmed[..trimmed.len() - padding_count].contains('=') {
+            return None;
+        }
+        if trimmed.len().is_multiple_of(4) {
+            confidence += 0.2 * self.weights.length_factor;
+        } else if padding_count > 0 {
+            return None;
+        }
+
+        // URL-safe characters should be a small fraction of the data
+        let url_char_count = trimmed.chars().filter(|&c| c == '-' || c == '_').count();
+        if (url_char_count as f32 / trimmed.len() as f32) < 0.3 {
+            confidence += 0.2 * self.weights.distribution;
+        }
+
+        // Try to decode to verify it's valid URL-safe Base64
+        let decodes = base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE, trimmed).is_ok()
+            || base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, trimmed).is_ok();
+        if decodes {
+            confidence += 0.3 * self.weights.decode_success;
+        } else {
+            return None;
+        }
+
+        // Cap confidence at 1.0
+        Some(confidence.min(1.0))
+    }
+
+    /// Detect Base32 (RFC 4648) format with confidence scoring
+    fn detect_base32(&self, data: &str) -> Option<f32> {
+        let trimmed = data.trim();
+
+        // Empty string is valid Base32 but very low confidence
+        if trimmed.is_empty() {
+            return Some(0.05);
+        }
+
+        let mut confidence: f32 = 0.0;
+
+        // Check for valid Base32 characters, only the uppercase alphabet is accepted here
+        let valid_chars = trimmed.chars().all(|c| {
+            c.is_ascii_uppercase() || ('2'..='7').contains(&c) || c == '='
+        });
+
+        if !valid_chars {
+            return None;
+        }
+        confidence += 0.3 * self.weights.charset_match;
+
+        // Padded data must fill whole 8 character groups, unpadded data may end mid-group
+        let padding_count = trimmed.len() - trimmed.trim_end_matches('=').len();
+        if trimmed.len().is_multiple_of(8) {
+            confidence += 0.2 * self.weights.length_factor;
+        } else if padding_count > 0 {
+            return None;
+        }
+
+        // Character distribution analysis
+        let char_distribution = self.analyze_base32_char_distribution(trimmed);
+        confidence += char_distribution * 0.2 * self.weights.distribution;
+
+        // Try to decode to verify it's valid Base32
+        if crate::encoding::base32_codec::decode(trimmed).is_ok() {
+            confidence += 0.3 * self.weights.decode_success;
+        } else {
+            return None;
+        }
+
+        // Cap confidence at 1.0
+        Some(confidence.min(1.0))
+    }
+
     /// Detect Hex format with confidence scoring
     fn detect_hex(&self, data: &str) -> Option<f32> {
         let trimmed = data.trim();
@@ -171,7 +366,7 @@ impl FormatDetector {
         if trimmed.len() % 2 != 0 {
             return None;
         }
-        confidence += 0.3;
+        confidence += 0.3 * self.weights.length_factor;
         
         // Check for valid hex characters (case insensitive)
         let valid_chars = trimmed.chars().all(|c| c.is_ascii_hexdigit());
@@ -179,11 +374,11 @@ impl FormatDetector {
         if !valid_chars {
             return None;
         }
-        confidence += 0.4;
+        confidence += 0.4 * self.weights.charset_match;
         
         // Character distribution analysis for hex
         let char_distribution = self.analyze_hex_char_distribution(trimmed);
-        confidence += char_distribution * 0.2;
+        confidence += char_distribution * 0.2 * self.weights.distribution;
         
         // Length-based confidence adjustment
         let length_factor = match trimmed.len() {
@@ -192,11 +387,11 @@ impl FormatDetector {
             34..=128 => 0.3,   // Good length for encoded data
             _ => 0.1,          // Very long or very short
         };
-        confidence += length_factor;
+        confidence += length_factor * self.weights.length_factor;
         
         // Try to decode to verify it's valid hex
         if hex::decode(trimmed).is_ok() {
-            confidence += 0.2;
+            confidence += 0.2 * self.weights.decode_success;
         } else {
             return None;
         }
@@ -205,7 +400,12 @@ impl FormatDetector {
         Some(confidence.min(1.0))
     }
 
-    /// Detect JSON string format with confidence scoring
+    /// Detect JSON format with confidence scoring
+    ///
+    /// JSON string literals, the only values `JsonCodec` can decode, score from 0.7 up.
+    /// Raw values (objects, arrays, numbers, booleans, null) score in a separate bucket capped
+    /// at `JSON_VALUE_MAX_CONFIDENCE`, so that they never outrank a string literal or another format
+    /// that actually decodes the data.
     fn detect_json(&self, data: &str) -> Option<f32> {
         let trimmed = data.trim();
         
@@ -218,25 +418,25 @@ impl FormatDetector {
         
         // Must start and end with quotes for JSON string
         if !trimmed.starts_with('"') || !trimmed.ends_with('"') {
-            return None;
+            return self.detect_json_value(trimmed);
         }
-        confidence += 0.4;
-        
-        // Must have at least 2 characters (opening and closing quotes)
-        if trimmed.len() < 2 {
-            return None;
+
+        // The whole data must be a single string literal, shared with `JsonCodec::can_decode`
+        match try_parse_json_string(trimmed) {
+            Some((_, len)) if len == trimmed.len() => {}
+            _ => return None,
         }
+        confidence += 0.4 * self.weights.charset_match;
         
-        // Check for JSON escape sequences
-        let escape_sequences = [r#"\""#, r#"\\"#, r#"\/"#, r#"\b"#, r#"\f"#, r#"\n"#, r#"\r"#, r#"\t"#];
-        let has_escapes = escape_sequences.iter().any(|seq| trimmed.contains(seq));
+        // Check for JSON escape sequences, counting each kind once
+        let (has_escapes, has_unicode_escapes) = Self::json_escape_kinds(trimmed);
         if has_escapes {
-            confidence += 0.2;
+            confidence += 0.2 * self.weights.distribution;
         }
         
         // Check for unicode escape sequences
-        if trimmed.contains(r#"\u"#) {
-            confidence += 0.1;
+        if has_unicode_escapes {
+            confidence += 0.1 * self.weights.distribution;
         }
         
         // Length-based confidence
@@ -246,19 +446,93 @@ impl FormatDetector {
             21..=100 => 0.3,   // Medium string
             _ => 0.2,          // Long string
         };
-        confidence += length_factor;
+        confidence += length_factor * self.weights.length_factor;
         
-        // Try to parse as JSON string
-        if serde_json::from_str::<String>(trimmed).is_ok() {
-            confidence += 0.3;
-        } else {
-            return None;
-        }
+        // Parsed above
+        confidence += 0.3 * self.weights.decode_success;
         
         // Cap confidence at 1.0
         Some(confidence.min(1.0))
     }
 
+    /// Scan a JSON string literal for simple (`\n`, `\"`, ...) and unicode (`\uXXXX`) escapes
+    ///
+    /// Escapes are matched in order, so the `\u` in an escaped backslash followed by `u` is not
+    /// counted as a unicode escape.
+    fn json_escape_kinds(literal: &str) -> (bool, bool) {
+        let mut has_escapes = false;
+        let mut has_unicode_escapes = false;
+        let mut chars = literal.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('u') => has_unicode_escapes = true,
+                    Some(_) => has_escapes = true,
+                    None => {}
+                }
+            }
+        }
+        (has_escapes, has_unicode_escapes)
+    }
+
+    /// Detect a raw (non-string) JSON value, scored by its structure
+    fn detect_json_value(&self, trimmed: &str) -> Option<f32> {
+        // Containers must have balanced brackets, checked before the full parse
+        let is_container = trimmed.starts_with(['{', '[']);
+        if is_container && !Self::brackets_balanced(trimmed) {
+            return None;
+        }
+
+        let value = serde_json::from_str::<serde_json::Value>(trimmed).ok()?;
+        let confidence = match &value {
+            serde_json::Value::Object(map) if !map.is_empty() => 0.3,
+            serde_json::Value::Array(items) if !items.is_empty() => 0.3,
+            // `{}` and `[]`
+            serde_json::Value::Object(_) | serde_json::Value::Array(_) => 0.2,
+            // Scalars are easily coincidental, e.g. digits that are also hex
+            _ => return Some(JSON_SCALAR_CONFIDENCE),
+        };
+
+        // Nested structure is a strong sign of real JSON
+        let depth_bonus = (Self::json_depth(&value).min(4) - 1) as f32 * 0.1;
+        Some((confidence + depth_bonus).min(JSON_VALUE_MAX_CONFIDENCE))
+    }
+
+    /// Nesting depth of a JSON value, scalars have depth 0
+    fn json_depth(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::Object(map) => 1 + map.values().map(Self::json_depth).max().unwrap_or(0),
+            serde_json::Value::Array(items) => 1 + items.iter().map(Self::json_depth).max().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Check that `{}`/`[]` outside string literals are balanced and properly nested
+    fn brackets_balanced(data: &str) -> bool {
+        let mut stack = Vec::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in data.chars() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' | '[' => stack.push(c),
+                '}' if stack.pop() != Some('{') => return false,
+                ']' if stack.pop() != Some('[') => return false,
+                _ => {}
+            }
+        }
+        stack.is_empty() && !in_string
+    }
+
     /// Analyze character distribution for Base64 detection
     fn analyze_base64_char_distribution(&self, data: &str) -> f32 {
         if data.is_empty() {
@@ -299,6 +573,26 @@ impl FormatDetector {
         score
     }
 
+    /// Analyze character distribution for Base32 detection
+    fn analyze_base32_char_distribution(&self, data: &str) -> f32 {
+        let data = data.trim_end_matches('=');
+        if data.is_empty() {
+            return 0.0;
+        }
+
+        let digit_count = data.chars().filter(|c| ('2'..='7').contains(c)).count() as f32;
+        let digit_ratio = digit_count / data.len() as f32;
+
+        // Encoded data mixes letters with the digits 2-7, all-letter strings are more likely plain uppercase text
+        if digit_ratio == 0.0 {
+            0.0
+        } else if digit_ratio < 0.5 {
+            1.0
+        } else {
+            0.5
+        }
+    }
+
     /// Analyze character distribution for Hex detection
     fn analyze_hex_char_distribution(&self, data: &str) -> f32 {
         if data.is_empty() {
@@ -358,6 +652,32 @@ mod tests {
         assert_eq!(detector.min_confidence, 0.5);
     }
 
+    #[test]
+    fn test_format_detector_with_weights() {
+        let detector = FormatDetector::new();
+        assert_eq!(*detector.weights(), DetectionWeights::default());
+
+        // Ignoring decode success and distribution favours hex, its charset and length checks are stronger
+        let hex_first = FormatDetector::with_weights(DetectionWeights {
+            decode_success: 0.0,
+            distribution: 0.0,
+            ..DetectionWeights::default()
+        });
+        assert_eq!(hex_first.min_confidence, 0.1);
+        let results = hex_first.detect("41414141");
+        assert_eq!(results[0].format, EncodingFormat::Hex);
+        assert!(results.iter().any(|r| r.format == EncodingFormat::Base64));
+
+        // Ignoring length favours base64, which decodes with a good distribution
+        let base64_first = FormatDetector::with_weights(DetectionWeights {
+            length_factor: 0.0,
+            ..DetectionWeights::default()
+        });
+        let results = base64_first.detect("41414141");
+        assert_eq!(results[0].format, EncodingFormat::Base64);
+        assert!(results.iter().any(|r| r.format == EncodingFormat::Hex));
+    }
+
     #[test]
     fn test_detect_base64() {
         let detector = FormatDetector::new();
@@ -380,6 +700,30 @@ mod tests {
         assert!(results.iter().all(|r| r.format != EncodingFormat::Base64));
     }
 
+    #[test]
+    fn test_detect_base32() {
+        let detector = FormatDetector::new();
+
+        // Valid Base32 strings, padded and unpadded
+        let results = detector.detect("MZXW6YTBOI======");
+        assert!(!results.is_empty());
+        assert_eq!(results[0].format, EncodingFormat::Base32);
+        assert!(results[0].confidence > 0.5);
+
+        let results = detector.detect("JBSWY3DPEBLW64TMMQ");
+        assert_eq!(results[0].format, EncodingFormat::Base32);
+
+        // A full 8 character group scores higher than a partial one
+        let full = detector.detect_base32("JBSWY3DP").unwrap();
+        let partial = detector.detect_base32("JBSWY3DPEE").unwrap();
+        assert!(full > partial);
+
+        // Invalid Base32
+        for data in ["MZXW1", "mzxw6ytboi", "MY=", "MZ======", "aGVsbG8=", "41414141"] {
+            assert!(detector.detect_base32(data).is_none(), "{}", data);
+        }
+    }
+
     #[test]
     fn test_detect_hex() {
         let detector = FormatDetector::new();
@@ -420,8 +764,62 @@ mod tests {
         let results = detector.detect("hello"); // No quotes
         assert!(results.iter().all(|r| r.format != EncodingFormat::Json));
         
-        let results = detector.detect("123"); // Number, not string
+        let results = detector.detect("{invalid");
         assert!(results.iter().all(|r| r.format != EncodingFormat::Json));
+
+        // Trailing data after the literal
+        assert!(detector.detect_json(r#""ok"junk"#).is_none());
+        assert!(detector.detect_json(r#""ok"junk""#).is_none());
+        assert!(detector.detect_json(r#""ok" "x""#).is_none());
+    }
+
+    #[test]
+    fn test_detect_json_escapes() {
+        let detector = FormatDetector::new();
+        let plain = detector.detect_json(r#""he said hi""#).unwrap();
+
+        // Escaped quotes, nested escapes and unicode escapes are all valid string literals
+        let quoted = detector.detect_json(r#""he said \"hi\"""#).unwrap();
+        let nested = detector.detect_json(r#""he said \"hi \\\"there\\\"\"""#).unwrap();
+        let unicode = detector.detect_json(r#""caf\u00e9""#).unwrap();
+        assert!(quoted > plain);
+        assert_eq!(nested, quoted);
+        assert!(unicode > plain);
+
+        // Each kind of escape counts once
+        assert_eq!(FormatDetector::json_escape_kinds(r#""a\n\t\"b""#), (true, false));
+        assert_eq!(FormatDetector::json_escape_kinds(r#""caf\u00e9""#), (false, true));
+        assert_eq!(FormatDetector::json_escape_kinds(r#""\\u00e9""#), (true, false));
+        assert_eq!(FormatDetector::json_escape_kinds(r#""plain""#), (false, false));
+    }
+
+    #[test]
+    fn test_detect_json_value() {
+        let detector = FormatDetector::new();
+
+        let object = detector.detect_json(r#"{"k":"v"}"#).unwrap();
+        let array = detector.detect_json("[1,2,3]").unwrap();
+        let nested = detector.detect_json(r#"{"k":[{"a":1}]}"#).unwrap();
+        let number = detector.detect_json("123").unwrap();
+        let string = detector.detect_json(r#""hello""#).unwrap();
+
+        // Raw values stay below string literals, and deeper structures score higher
+        assert!(object > number && array > number);
+        assert!(nested > object);
+        assert!(nested <= JSON_VALUE_MAX_CONFIDENCE && string > JSON_VALUE_MAX_CONFIDENCE);
+        assert_eq!(detector.detect_best(r#"{"k":"v"}"#).unwrap().format, EncodingFormat::Json);
+        assert_eq!(detector.detect_best("[1,2,3]").unwrap().format, EncodingFormat::Json);
+
+        // A bare number is detected, but digits are more likely hex or base64
+        assert_eq!(detector.detect_json("-1.5e3"), Some(JSON_SCALAR_CONFIDENCE));
+        assert_ne!(detector.detect_best("1234").unwrap().format, EncodingFormat::Json);
+        assert!(detector.detect("1234").iter().any(|r| r.format == EncodingFormat::Json));
+
+        // Brackets inside string literals are ignored by the balance check
+        assert!(detector.detect_json(r#"{"k":"}]\"{"}"#).is_some());
+        for data in ["{invalid", "[1,2", "{\"a\":1}}", "[1,2}", "{\"a\":}"] {
+            assert!(detector.detect_json(data).is_none(), "{}", data);
+        }
     }
 
     #[test]
@@ -18,6 +18,26 @@ impl Default for JsonCodec {
     }
 }
 
+/// Parse a JSON string literal at the start of `data`, returning the decoded string and the
+/// length in bytes of the literal, so callers can reject trailing data such as `"ok"junk`
+pub(crate) fn try_parse_json_string(data: &str) -> Option<(String, usize)> {
+    if !data.starts_with('"') {
+        return None;
+    }
+    let mut stream = serde_json::Deserializer::from_str(data).into_iter::<String>();
+    let decoded = stream.next()?.ok()?;
+    Some((decoded, stream.byte_offset()))
+}
+
+/// Check whether `data` is exactly one JSON string literal
+pub(crate) fn is_json_string(data: &str) -> bool {
+    // Fast path, a literal must be enclosed in quotes
+    if data.len() < 2 || !data.starts_with('"') || !data.ends_with('"') {
+        return false;
+    }
+    matches!(try_parse_json_string(data), Some((_, len)) if len == data.len())
+}
+
 impl DataCodec for JsonCodec {
     fn encode(&self, data: &[u8]) -> Result<String, EncodingError> {
         // Convert bytes to string (lossy conversion for non-UTF8 data)
@@ -40,25 +60,7 @@ impl DataCodec for JsonCodec {
     }
 
     fn can_decode(&self, data: &str) -> bool {
-        let trimmed = data.trim();
-        
-        // Empty string is not valid JSON
-        if trimmed.is_empty() {
-            return false;
-        }
-        
-        // Must start and end with quotes for JSON string
-        if !trimmed.starts_with('"') || !trimmed.ends_with('"') {
-            return false;
-        }
-        
-        // Must have at least 2 characters (opening and closing quotes)
-        if trimmed.len() < 2 {
-            return false;
-        }
-        
-        // Try to parse as JSON string
-        serde_json::from_str::<String>(trimmed).is_ok()
+        is_json_string(data.trim())
     }
 
     fn format_name(&self) -> &'static str {
@@ -166,6 +168,30 @@ mod tests {
         assert!(!codec.can_decode(r#""invalid\escape""#));
     }
 
+    #[test]
+    fn test_try_parse_json_string() {
+        // Nested escapes
+        let data = r#""he said \"hi \\\"there\\\"\"""#;
+        assert_eq!(try_parse_json_string(data), Some((r#"he said "hi \"there\"""#.to_string(), data.len())));
+        assert!(is_json_string(data));
+
+        // Unicode escapes
+        let data = r#""caf\u00e9""#;
+        assert_eq!(try_parse_json_string(data), Some(("café".to_string(), data.len())));
+        assert!(is_json_string(data));
+
+        // Trailing data is not part of the literal
+        assert_eq!(try_parse_json_string(r#""ok"junk"#), Some(("ok".to_string(), 4)));
+        assert!(!is_json_string(r#""ok"junk"#));
+        assert!(!is_json_string(r#""ok" "x""#));
+        assert!(!JsonCodec::new().can_decode(r#""ok"junk"#));
+        assert!(!JsonCodec::new().can_decode(r#""ok"junk""#));
+
+        assert_eq!(try_parse_json_string("123"), None);
+        assert_eq!(try_parse_json_string(r#""unterminated"#), None);
+        assert!(!is_json_string(r#"""#));
+    }
+
     #[test]
     fn test_json_roundtrip() {
         let codec = JsonCodec::new();
@@ -1,21 +1,26 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use crate::error::Error;
 
 pub mod base64_codec;
+pub mod base32_codec;
 pub mod hex_codec;
 pub mod json_codec;
 pub mod format_detector;
 
-pub use base64_codec::Base64Codec;
+pub use base64_codec::{Base64Codec, Base64UrlCodec};
+pub use base32_codec::Base32Codec;
 pub use hex_codec::HexCodec;
 pub use json_codec::JsonCodec;
-pub use format_detector::{FormatDetector, DetectionResult};
+pub use format_detector::{FormatDetector, DetectionResult, DetectionWeights};
 
 /// Supported encoding formats for data transformation
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EncodingFormat {
     Base64,
+    Base64Url,
+    Base32,
     Hex,
     Json,
 }
@@ -24,6 +29,8 @@ impl fmt::Display for EncodingFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             EncodingFormat::Base64 => write!(f, "base64"),
+            EncodingFormat::Base64Url => write!(f, "base64url"),
+            EncodingFormat::Base32 => write!(f, "base32"),
             EncodingFormat::Hex => write!(f, "hex"),
             EncodingFormat::Json => write!(f, "json"),
         }
@@ -36,6 +43,8 @@ impl std::str::FromStr for EncodingFormat {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "base64" => Ok(EncodingFormat::Base64),
+            "base64url" => Ok(EncodingFormat::Base64Url),
+            "base32" => Ok(EncodingFormat::Base32),
             "hex" => Ok(EncodingFormat::Hex),
             "json" => Ok(EncodingFormat::Json),
             _ => Err(EncodingError::UnsupportedFormat(s.to_string())),
@@ -95,6 +104,25 @@ struct CacheEntry {
     timestamp: std::time::Instant,
 }
 
+/// Detection cache counters, see `EncodingEngine::cache_metrics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheMetrics {
+    /// Detections answered from the cache
+    pub hits: u64,
+    /// Detections not found in the cache, or found expired
+    pub misses: u64,
+    /// Entries removed by cleanup, either expired or over `max_cache_size`
+    pub evictions: u64,
+}
+
+/// Atomic counters behind `CacheMetrics`, so lookups through `&self` can count too
+#[derive(Debug, Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
 /// Core encoding engine that manages different encoding formats
 pub struct EncodingEngine {
     default_format: EncodingFormat,
@@ -103,6 +131,7 @@ pub struct EncodingEngine {
     detection_cache: HashMap<String, CacheEntry>,
     cache_ttl: std::time::Duration,
     max_cache_size: usize,
+    cache_counters: CacheCounters,
 }
 
 impl EncodingEngine {
@@ -115,6 +144,7 @@ impl EncodingEngine {
             detection_cache: HashMap::new(),
             cache_ttl: std::time::Duration::from_secs(300), // 5 minutes
             max_cache_size: 1000,
+            cache_counters: CacheCounters::default(),
         }
     }
 
@@ -127,6 +157,7 @@ impl EncodingEngine {
             detection_cache: HashMap::new(),
             cache_ttl: std::time::Duration::from_secs(300),
             max_cache_size: 1000,
+            cache_counters: CacheCounters::default(),
         }
     }
 
@@ -143,6 +174,7 @@ impl EncodingEngine {
             detection_cache: HashMap::new(),
             cache_ttl,
             max_cache_size,
+            cache_counters: CacheCounters::default(),
         }
     }
 
@@ -171,17 +203,65 @@ impl EncodingEngine {
 
     /// Decode data using the specified format
     pub fn decode(&self, encoded: &str, format: EncodingFormat) -> Result<Vec<u8>, EncodingError> {
+        // Strip a format marker of the same format before decoding
+        let encoded = match self.detector.match_marker(encoded) {
+            Some((marked, stripped)) if marked == format => stripped,
+            _ => encoded,
+        };
         match self.codecs.get(&format) {
             Some(codec) => codec.decode(encoded),
             None => Err(EncodingError::UnsupportedFormat(format.to_string())),
         }
     }
 
+    /// Register a format marker prefix, values starting with it are detected as that format
+    /// and the marker is stripped before decoding with it
+    pub fn add_format_marker(&mut self, marker: impl Into<String>, format: EncodingFormat) {
+        self.detector.add_marker(marker, format);
+        self.clear_cache();
+    }
+
     /// Encode data using the default format
     pub fn encode_default(&self, data: &[u8]) -> Result<String, EncodingError> {
         self.encode(data, self.default_format)
     }
 
+    /// Check that the pipeline is non-empty and every stage has a registered codec
+    pub fn validate_pipeline(&self, pipeline: &[EncodingFormat]) -> Result<(), EncodingError> {
+        if pipeline.is_empty() {
+            return Err(EncodingError::InvalidData("encoding pipeline is empty".to_string()));
+        }
+        match pipeline.iter().find(|format| !self.codecs.contains_key(format)) {
+            Some(format) => Err(EncodingError::UnsupportedFormat(format.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Encode data through each stage of the pipeline in order, feeding each
+    /// stage's output into the next one. `[Hex, Base64]` yields base64(hex(data)).
+    pub fn encode_pipeline(&self, data: &[u8], pipeline: &[EncodingFormat]) -> Result<String, EncodingError> {
+        self.validate_pipeline(pipeline)?;
+        let mut encoded = self.encode(data, pipeline[0])?;
+        for format in &pipeline[1..] {
+            encoded = self.encode(encoded.as_bytes(), *format)?;
+        }
+        Ok(encoded)
+    }
+
+    /// Decode data encoded by encode_pipeline with the same pipeline, applying the stages in reverse order
+    pub fn decode_pipeline(&self, encoded: &str, pipeline: &[EncodingFormat]) -> Result<Vec<u8>, EncodingError> {
+        self.validate_pipeline(pipeline)?;
+        let (last, stages) = pipeline.split_last().expect("validated non-empty pipeline");
+        let mut decoded = self.decode(encoded, *last)?;
+        for format in stages.iter().rev() {
+            let intermediate = String::from_utf8(decoded).map_err(|e| {
+                EncodingError::DecodingFailed(format!("pipeline stage {} is not valid UTF-8: {}", format, e))
+            })?;
+            decoded = self.decode(&intermediate, *format)?;
+        }
+        Ok(decoded)
+    }
+
     /// Detect the encoding format of the given data with caching
     pub fn detect(&mut self, data: &str) -> Result<Vec<DetectionResult>, EncodingError> {
         // Check cache first
@@ -235,9 +315,11 @@ impl EncodingEngine {
     fn get_cached_detection(&self, data: &str) -> Option<Vec<DetectionResult>> {
         if let Some(entry) = self.detection_cache.get(data) {
             if entry.timestamp.elapsed() < self.cache_ttl {
+                self.cache_counters.hits.fetch_add(1, Ordering::Relaxed);
                 return Some(entry.results.clone());
             }
         }
+        self.cache_counters.misses.fetch_add(1, Ordering::Relaxed);
         None
     }
 
@@ -257,6 +339,7 @@ impl EncodingEngine {
     /// Clean up expired cache entries and enforce size limits
     fn cleanup_cache(&mut self) {
         let now = std::time::Instant::now();
+        let len_before = self.detection_cache.len();
         
         // Remove expired entries
         self.detection_cache.retain(|_, entry| {
@@ -280,6 +363,9 @@ impl EncodingEngine {
                 self.detection_cache.remove(&key);
             }
         }
+
+        let evicted = (len_before - self.detection_cache.len()) as u64;
+        self.cache_counters.evictions.fetch_add(evicted, Ordering::Relaxed);
     }
 
     /// Clear the detection cache
@@ -292,6 +378,22 @@ impl EncodingEngine {
         (self.detection_cache.len(), self.max_cache_size)
     }
 
+    /// Get the cache hit, miss and eviction counters, they are kept across `clear_cache`
+    pub fn cache_metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            hits: self.cache_counters.hits.load(Ordering::Relaxed),
+            misses: self.cache_counters.misses.load(Ordering::Relaxed),
+            evictions: self.cache_counters.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reset the cache hit, miss and eviction counters to zero
+    pub fn reset_cache_metrics(&self) {
+        self.cache_counters.hits.store(0, Ordering::Relaxed);
+        self.cache_counters.misses.store(0, Ordering::Relaxed);
+        self.cache_counters.evictions.store(0, Ordering::Relaxed);
+    }
+
     /// Set cache TTL
     pub fn set_cache_ttl(&mut self, ttl: std::time::Duration) {
         self.cache_ttl = ttl;
@@ -343,6 +445,8 @@ mod tests {
     #[test]
     fn test_encoding_format_display() {
         assert_eq!(EncodingFormat::Base64.to_string(), "base64");
+        assert_eq!(EncodingFormat::Base64Url.to_string(), "base64url");
+        assert_eq!(EncodingFormat::Base32.to_string(), "base32");
         assert_eq!(EncodingFormat::Hex.to_string(), "hex");
         assert_eq!(EncodingFormat::Json.to_string(), "json");
     }
@@ -353,6 +457,8 @@ mod tests {
         assert_eq!("hex".parse::<EncodingFormat>().unwrap(), EncodingFormat::Hex);
         assert_eq!("json".parse::<EncodingFormat>().unwrap(), EncodingFormat::Json);
         assert_eq!("BASE64".parse::<EncodingFormat>().unwrap(), EncodingFormat::Base64);
+        assert_eq!("base32".parse::<EncodingFormat>().unwrap(), EncodingFormat::Base32);
+        assert_eq!("Base64Url".parse::<EncodingFormat>().unwrap(), EncodingFormat::Base64Url);
         
         assert!("invalid".parse::<EncodingFormat>().is_err());
     }
@@ -488,6 +594,54 @@ mod tests {
         assert_eq!(cache_size, 0);
     }
 
+    #[test]
+    fn test_cache_metrics() {
+        let mut engine = EncodingEngine::new(EncodingFormat::Base64);
+        engine.register_codec(EncodingFormat::Base64, Box::new(Base64Codec::new()));
+        assert_eq!(engine.cache_metrics(), CacheMetrics::default());
+
+        // One miss populates the cache, the next two detections hit it
+        for _ in 0..3 {
+            engine.detect("aGVsbG8=").unwrap();
+        }
+        assert_eq!(engine.cache_metrics(), CacheMetrics { hits: 2, misses: 1, evictions: 0 });
+
+        // Counters survive clearing the cache
+        engine.clear_cache();
+        engine.detect("aGVsbG8=").unwrap();
+        assert_eq!(engine.cache_metrics(), CacheMetrics { hits: 2, misses: 2, evictions: 0 });
+
+        engine.reset_cache_metrics();
+        assert_eq!(engine.cache_metrics(), CacheMetrics::default());
+        engine.detect("aGVsbG8=").unwrap();
+        assert_eq!(engine.cache_metrics().hits, 1);
+    }
+
+    #[test]
+    fn test_cache_metrics_evictions() {
+        let mut engine = EncodingEngine::with_cache_settings(
+            EncodingFormat::Base64,
+            std::time::Duration::from_secs(300),
+            2,
+        );
+        engine.register_codec(EncodingFormat::Base64, Box::new(Base64Codec::new()));
+
+        engine.detect("aGVsbG8x").unwrap();
+        engine.detect("aGVsbG8y").unwrap();
+        assert_eq!(engine.cache_metrics().evictions, 0);
+
+        // A third entry exceeds max_cache_size and evicts the oldest one
+        engine.detect("aGVsbG8z").unwrap();
+        let metrics = engine.cache_metrics();
+        assert_eq!(metrics.evictions, 1);
+        assert_eq!(metrics.misses, 3);
+        assert_eq!(engine.get_cache_stats().0, 2);
+
+        // Shrinking the cache evicts as well
+        engine.set_max_cache_size(1);
+        assert_eq!(engine.cache_metrics().evictions, 2);
+    }
+
     #[test]
     fn test_get_detection_stats() {
         let engine = EncodingEngine::new(EncodingFormat::Base64);
@@ -500,6 +654,8 @@ mod tests {
     fn create_test_engine() -> EncodingEngine {
         let mut engine = EncodingEngine::new(EncodingFormat::Base64);
         engine.register_codec(EncodingFormat::Base64, Box::new(Base64Codec::new()));
+        engine.register_codec(EncodingFormat::Base64Url, Box::new(Base64UrlCodec::new()));
+        engine.register_codec(EncodingFormat::Base32, Box::new(Base32Codec::new()));
         engine.register_codec(EncodingFormat::Hex, Box::new(HexCodec::new()));
         engine.register_codec(EncodingFormat::Json, Box::new(JsonCodec::new()));
         engine
@@ -607,6 +763,65 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_detect_base64url() {
+        let mut engine = create_test_engine();
+
+        // `-`/`_` rule out standard Base64
+        let results = engine.detect("AAECA_8=").unwrap();
+        assert_eq!(results[0].format, EncodingFormat::Base64Url);
+        assert!(results.iter().all(|r| r.format != EncodingFormat::Base64));
+        assert_eq!(engine.decode("AAECA_8=", EncodingFormat::Base64Url).unwrap(), vec![0, 1, 2, 3, 255]);
+        assert!(engine.decode("AAECA_8=", EncodingFormat::Base64).is_err());
+
+        // Strings valid under both alphabets are reported for both, standard Base64 first
+        let results = engine.detect("aGVsbG8=").unwrap();
+        let formats: Vec<_> = results.iter().map(|r| r.format).collect();
+        assert_eq!(formats[..2], [EncodingFormat::Base64, EncodingFormat::Base64Url]);
+        assert!(results[1].confidence > 0.5);
+    }
+
+    #[test]
+    fn test_pipeline_roundtrip() {
+        let engine = create_test_engine();
+        let pipeline = [EncodingFormat::Hex, EncodingFormat::Base64];
+
+        let encoded = engine.encode_pipeline(b"hi", &pipeline).unwrap();
+        // hex first ("6869"), then base64
+        assert_eq!(encoded, "Njg2OQ==");
+        assert_eq!(engine.decode_pipeline(&encoded, &pipeline).unwrap(), b"hi");
+
+        // decoding reverses the order, so the stages can not be swapped
+        let reversed = [EncodingFormat::Base64, EncodingFormat::Hex];
+        assert_ne!(engine.encode_pipeline(b"hi", &reversed).unwrap(), encoded);
+        assert!(engine.decode_pipeline(&encoded, &reversed).is_err());
+
+        // every stage must be registered
+        let mut engine = EncodingEngine::new(EncodingFormat::Base64);
+        engine.register_codec(EncodingFormat::Base64, Box::new(Base64Codec::new()));
+        assert_eq!(
+            engine.encode_pipeline(b"hi", &pipeline),
+            Err(EncodingError::UnsupportedFormat("hex".to_string()))
+        );
+        assert!(engine.validate_pipeline(&[]).is_err());
+    }
+
+    #[test]
+    fn test_format_marker() {
+        let mut engine = create_test_engine();
+        engine.add_format_marker("b64:", EncodingFormat::Base64);
+        engine.add_format_marker("hex:", EncodingFormat::Hex);
+
+        let best = engine.detect_best("b64:aGVsbG8=").unwrap().unwrap();
+        assert_eq!(best.format, EncodingFormat::Base64);
+        assert!((best.confidence - 1.0).abs() < f32::EPSILON);
+        assert_eq!(engine.decode("b64:aGVsbG8=", best.format).unwrap(), b"hello");
+
+        // the marker is only stripped when decoding with its own format
+        assert_eq!(engine.decode("hex:68", EncodingFormat::Hex).unwrap(), b"h");
+        assert!(engine.decode("hex:68", EncodingFormat::Base64).is_err());
+    }
+
     #[test]
     fn test_roundtrip_all_formats() {
         let engine = create_test_engine();
@@ -625,6 +840,11 @@ mod tests {
             let decoded = engine.decode(&encoded, EncodingFormat::Base64).unwrap();
             assert_eq!(decoded, data);
             
+            // Test Base32 roundtrip
+            let encoded = engine.encode(data, EncodingFormat::Base32).unwrap();
+            let decoded = engine.decode(&encoded, EncodingFormat::Base32).unwrap();
+            assert_eq!(decoded, data);
+
             // Test Hex roundtrip
             let encoded = engine.encode(data, EncodingFormat::Hex).unwrap();
             let decoded = engine.decode(&encoded, EncodingFormat::Hex).unwrap();
@@ -10,9 +10,13 @@ pub type CResult<T> = std::result::Result<T, Error>;
 pub enum Error {
     Abort,
     Config(String),
+    /// 日志中的数据与校验和不一致
+    Corruption(String),
     Encoding(String),
     Internal(String),
     Parse(String),
+    /// 新增key会超过 max_keys 的上限
+    QuotaExceeded(u64),
     ReadOnly,
     Serialization,
     Value(String),
@@ -23,10 +27,11 @@ impl std::error::Error for Error {}
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> fmt::Result {
         match self {
-            Error::Config(s) | Error::Encoding(s) | Error::Internal(s) | Error::Parse(s) | Error::Value(s) => {
+            Error::Config(s) | Error::Corruption(s) | Error::Encoding(s) | Error::Internal(s) | Error::Parse(s) | Error::Value(s) => {
                 write!(f, "{}", s)
             }
             Error::Abort => write!(f, "Operation aborted"),
+            Error::QuotaExceeded(max_keys) => write!(f, "Key quota exceeded, at most {} keys are allowed", max_keys),
             Error::Serialization => write!(f, "Serialization failure, retry transaction"),
             Error::ReadOnly => write!(f, "Read-only transaction"),
         }
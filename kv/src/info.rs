@@ -1,16 +1,53 @@
-use crate::storage::engine::Engine;
+use serde_json::Value;
+
+use crate::error::CResult;
 use crate::storage::log_cask::LogCask;
+use crate::storage::Status;
 
 pub fn get_info(engine: &mut LogCask) -> Vec<String> {
     let mut infos = Vec::<String>::new();
     infos.push("KV Storage:".to_ascii_lowercase());
 
-    let status = engine.status();
-    let size = if status.is_ok() {
-        status.unwrap().keys as i64
-    } else {
-        0
-    };
+    if let Ok(fields) = get_info_fields(engine) {
+        infos.extend(format_fields(&fields));
+    }
 
     infos
-}
\ No newline at end of file
+}
+
+/// INFO 输出的各项指标(名称, 值)：完整的 status 以及读写字节计数
+pub fn get_info_fields(engine: &mut LogCask) -> CResult<Vec<(&'static str, Value)>> {
+    let detailed = engine.status_detailed()?;
+    let mut fields = status_fields(&detailed.status);
+    fields.push(("bytes_read", detailed.bytes_read.into()));
+    fields.push(("bytes_written", detailed.bytes_written.into()));
+    Ok(fields)
+}
+
+/// status 的各项指标(名称, 值)，按输出顺序排列。garbage_ratio 为 garbage_disk_size / total_disk_size
+pub fn status_fields(status: &Status) -> Vec<(&'static str, Value)> {
+    vec![
+        ("name", status.name.clone().into()),
+        ("keys", status.keys.into()),
+        ("size", status.size.into()),
+        ("total_disk_size", status.total_disk_size.into()),
+        ("live_disk_size", status.live_disk_size.into()),
+        ("garbage_disk_size", status.garbage_disk_size.into()),
+        ("garbage_ratio", status.garbage_ratio().into()),
+    ]
+}
+
+/// 按 `name: value` 两列输出，value 左对齐到同一列
+pub fn format_fields(fields: &[(&str, Value)]) -> Vec<String> {
+    let width = fields.iter().map(|(name, _)| name.len() + 1).max().unwrap_or(0);
+    fields
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            format!("{:<width$} {}", format!("{}:", name), value, width = width)
+        })
+        .collect()
+}
@@ -80,12 +80,15 @@
 //!   Readers don't block writers.
 
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::Duration;
 use std::sync::{Arc, Mutex};
 use serde_derive::{Deserialize, Serialize};
-use crate::error::CResult;
+use crate::error::{CResult, Error};
 use crate::mvcc::transaction::{Transaction, TransactionDef, TransactionState};
 use crate::mvcc::Version;
 use crate::storage::engine::Engine;
+use crate::storage::log::now_millis;
 
 /// 基于MVCC的事务键值引擎，提供最基本的ACID和MVCC支持。它包装了一个用于键/值存储的基础存储引擎。
 /// MVCC所提供的隔离级别为快照隔离，事务只能看到数据库的一个一致性快照，而这个快照是根据事务创建的时间决定的，即事务只能够看到事务创建前的最新的数据，以及由自己写入的新数据。
@@ -104,12 +107,24 @@ pub(crate) trait MVCCDef<E: Engine> {
 
     fn begin_read_only(&self) -> CResult<Transaction<E>>;
 
+    /// 开启一个 time travel 的只读事务，看到的是版本 version 开始时的状态
+    #[cfg(test)]
+    fn begin_as_of(&self, version: Version) -> CResult<Transaction<E>>;
+
+    #[cfg(test)]
     fn resume(&self, state: TransactionState) -> CResult<Transaction<E>>;
 
+    /// 回滚开始时间早于 min_age 之前、仍未结束的事务，返回回滚的事务数。
+    ///
+    /// 进程在事务中途崩溃时，事务的 `Key::TxnActive` 标记和写入的版本会一直留在存储中，这些版本永远不可见，并且与之后的写入冲突。
+    /// 比 min_age 新的事务不会被处理，因此 min_age 应大于事务的最长执行时间，以免回滚仍在运行的事务。
+    fn garbage_collect(&self, min_age: Duration) -> CResult<u64>;
+
+    #[cfg(test)]
     fn status(&self) -> CResult<Status>;
 }
 
-/// MVCC键，使用KeyCode编码，保留键的顺序和分组。
+/// MVCC键，使用保序编码(见 `Key::encode`)，保留键的顺序和分组：版本号为 big-endian，字节串转义 0x00 并以 0x00 0x00 结尾。
 /// Cow byte slices允许对借用值进行编码并将其解码为自有值。
 #[derive(Debug, Deserialize, Serialize)]
 pub enum Key<'a> {
@@ -150,17 +165,108 @@ pub enum Key<'a> {
 
 impl<'a> Key<'a> {
     pub fn decode(bytes: &'a [u8]) -> CResult<Self> {
-        bincode::deserialize(bytes).map_err(|e| crate::error::Error::Internal(e.to_string()))
+        let mut input = bytes;
+        let key = match take_tag(&mut input)? {
+            0 => Key::NextVersion,
+            1 => Key::TxnActive(take_u64(&mut input)?),
+            2 => Key::TxnActiveSnapshot(take_u64(&mut input)?),
+            3 => Key::TxnWrite(take_u64(&mut input)?, take_bytes(&mut input)?.into()),
+            4 => Key::Version(take_bytes(&mut input)?.into(), take_u64(&mut input)?),
+            5 => Key::Unversioned(take_bytes(&mut input)?.into()),
+            tag => return Err(Error::Internal(format!("invalid mvcc key tag {}", tag))),
+        };
+        if !input.is_empty() {
+            return Err(Error::Internal(format!("unexpected {} trailing bytes in mvcc key", input.len())));
+        }
+
+        Ok(key)
     }
 
     pub fn encode(&self) -> CResult<Vec<u8>> {
-        bincode::serialize(self).map_err(|e| crate::error::Error::Internal(e.to_string()))
+        let mut out = Vec::new();
+        match self {
+            Key::NextVersion => out.push(0),
+            Key::TxnActive(version) => {
+                out.push(1);
+                out.extend_from_slice(&version.to_be_bytes());
+            }
+            Key::TxnActiveSnapshot(version) => {
+                out.push(2);
+                out.extend_from_slice(&version.to_be_bytes());
+            }
+            Key::TxnWrite(version, key) => {
+                out.push(3);
+                out.extend_from_slice(&version.to_be_bytes());
+                put_bytes(&mut out, key);
+            }
+            Key::Version(key, version) => {
+                out.push(4);
+                put_bytes(&mut out, key);
+                out.extend_from_slice(&version.to_be_bytes());
+            }
+            Key::Unversioned(key) => {
+                out.push(5);
+                put_bytes(&mut out, key);
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// 保序的字节串编码：0x00 转义为 0x00 0xff，并以 0x00 0x00 结尾。
+/// 这样编码后的字节序与原字节序一致，且一个 key 的编码不会是另一个 key 编码的前缀。
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    for &b in bytes {
+        if b == 0x00 {
+            out.extend_from_slice(&[0x00, 0xff]);
+        } else {
+            out.push(b);
+        }
+    }
+    out.extend_from_slice(&[0x00, 0x00]);
+}
+
+fn take_tag(input: &mut &[u8]) -> CResult<u8> {
+    let (&tag, rest) = input.split_first().ok_or_else(|| Error::Internal("empty mvcc key".to_string()))?;
+    *input = rest;
+    Ok(tag)
+}
+
+/// 版本号使用 big-endian，保证按版本号有序
+fn take_u64(input: &mut &[u8]) -> CResult<u64> {
+    if input.len() < 8 {
+        return Err(Error::Internal(format!("mvcc key too short for a version: {} bytes", input.len())));
+    }
+    let (bytes, rest) = input.split_at(8);
+    *input = rest;
+    Ok(u64::from_be_bytes(bytes.try_into()?))
+}
+
+fn take_bytes(input: &mut &[u8]) -> CResult<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut iter = input.iter().enumerate();
+    while let Some((_, &b)) = iter.next() {
+        if b != 0x00 {
+            decoded.push(b);
+            continue;
+        }
+        match iter.next() {
+            Some((_, 0xff)) => decoded.push(0x00),
+            Some((i, 0x00)) => {
+                *input = &input[i + 1..];
+                return Ok(decoded);
+            }
+            _ => return Err(Error::Internal("invalid escape sequence in mvcc key".to_string())),
+        }
     }
+
+    Err(Error::Internal("unterminated byte string in mvcc key".to_string()))
 }
 
 /// MVCC 键前缀，用于前缀扫描。这些必须与上面的键匹配，包括枚举变量索引。
 #[derive(Debug, Deserialize, Serialize)]
-enum KeyPrefix<'a> {
+pub(crate) enum KeyPrefix<'a> {
     NextVersion,
 
     TxnActive,
@@ -179,8 +285,24 @@ enum KeyPrefix<'a> {
 }
 
 impl<'a> KeyPrefix<'a> {
-    fn encode(&self) -> CResult<Vec<u8>> {
-        todo!()
+    pub(crate) fn encode(&self) -> CResult<Vec<u8>> {
+        let mut out = Vec::new();
+        match self {
+            KeyPrefix::NextVersion => out.push(0),
+            KeyPrefix::TxnActive => out.push(1),
+            KeyPrefix::TxnActiveSnapshot => out.push(2),
+            KeyPrefix::TxnWrite(version) => {
+                out.push(3);
+                out.extend_from_slice(&version.to_be_bytes());
+            }
+            KeyPrefix::Version(key) => {
+                out.push(4);
+                put_bytes(&mut out, key);
+            }
+            KeyPrefix::Unversioned => out.push(5),
+        }
+
+        Ok(out)
     }
 }
 
@@ -199,12 +321,60 @@ impl <E: Engine> MVCCDef<E> for MVCC<E> {
         Transaction::begin_read_only(self.engine.clone(), None)
     }
 
+    #[cfg(test)]
+    fn begin_as_of(&self, version: Version) -> CResult<Transaction<E>> {
+        Transaction::begin_read_only(self.engine.clone(), Some(version))
+    }
+
+    #[cfg(test)]
     fn resume(&self, state: TransactionState) -> CResult<Transaction<E>> {
         Transaction::resume(self.engine.clone(), state)
     }
 
+    fn garbage_collect(&self, min_age: Duration) -> CResult<u64> {
+        let cutoff = now_millis().saturating_sub(min_age.as_millis() as u64);
+
+        let mut abandoned = vec![];
+        {
+            let session = self.engine.lock()?;
+            let mut scan = session.scan_prefix(&KeyPrefix::TxnActive.encode()?);
+            while let Some((key, value)) = scan.next().transpose()? {
+                let version = match Key::decode(&key)? {
+                    Key::TxnActive(version) => version,
+                    key => return Err(Error::Internal(format!("Expected TxnActive key, got {:?}", key))),
+                };
+                // 没有记录开始时间的标记视为足够老
+                let began_at = if value.is_empty() { 0 } else { bincode::deserialize::<u64>(&value)? };
+                if began_at <= cutoff {
+                    abandoned.push(version);
+                }
+            }
+        }
+
+        let mut rolled_back = 0;
+        for version in abandoned {
+            let state = TransactionState { version, read_only: false, active: HashSet::new() };
+            match Transaction::resume(self.engine.clone(), state) {
+                Ok(txn) => txn.rollback()?,
+                // 扫描之后事务已经提交或回滚
+                Err(Error::Internal(_)) => continue,
+                Err(err) => return Err(err),
+            }
+            rolled_back += 1;
+        }
+
+        Ok(rolled_back)
+    }
+
+    #[cfg(test)]
     fn status(&self) -> CResult<Status> {
-        todo!()
+        let mut session = self.engine.lock()?;
+        let versions = match session.get(&Key::NextVersion.encode()?)? {
+            Some(ref v) => bincode::deserialize::<u64>(v)? - 1,
+            None => 0,
+        };
+        let active_txns = session.scan_prefix(&KeyPrefix::TxnActive.encode()?).count() as u64;
+        Ok(Status { versions, active_txns, storage: session.status()? })
     }
 }
 
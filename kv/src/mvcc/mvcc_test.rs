@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod mvcc_tests {
+    use std::time::Duration;
+    use crate::error::{CResult, Error};
+    use crate::mvcc::mvcc::{Key, MVCCDef, MVCC};
+    use crate::mvcc::transaction::TransactionDef;
+    use crate::storage::engine::Engine;
+    use crate::storage::memory::Memory;
+
+    #[test]
+    fn garbage_collect() -> CResult<()> {
+        let mut engine = Memory::new();
+
+        // 伪造一个崩溃前遗留的事务：活动集标记、TxnWrite 记录以及写入的版本
+        engine.set(&Key::NextVersion.encode()?, bincode::serialize(&2u64)?)?;
+        engine.set(&Key::TxnActive(1).encode()?, vec![])?;
+        engine.set(&Key::TxnWrite(1, b"a".as_slice().into()).encode()?, vec![])?;
+        engine.set(&Key::Version(b"a".as_slice().into(), 1).encode()?, bincode::serialize(&Some(b"orphan".to_vec()))?)?;
+        let mvcc = MVCC::new(engine);
+
+        let t2 = mvcc.begin()?;
+        assert_eq!(t2.get(b"a")?, None);
+        assert_eq!(t2.set(b"a", b"t2".to_vec()), Err(Error::Serialization));
+
+        // 刚开始的 t2 比 min_age 新，不会被回滚
+        assert_eq!(mvcc.garbage_collect(Duration::from_secs(3600))?, 1);
+        assert_eq!(mvcc.garbage_collect(Duration::from_secs(3600))?, 0);
+        assert_eq!(mvcc.begin()?.state().active, [2].into());
+
+        // 遗留的版本已被删除，key 可以再次写入
+        t2.set(b"a", b"t2".to_vec())?;
+        t2.commit()?;
+        assert_eq!(mvcc.begin_read_only()?.get(b"a")?, Some(b"t2".to_vec()));
+
+        // min_age 为 0 时，所有未结束的事务都会被回滚，包括上面未提交就丢弃的 t3
+        assert_eq!(mvcc.garbage_collect(Duration::ZERO)?, 1);
+        assert!(mvcc.begin()?.state().active.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn begin_as_of_resume_status() -> CResult<()> {
+        let mvcc = MVCC::new(Memory::new());
+        assert_eq!((mvcc.status()?.versions, mvcc.status()?.active_txns), (0, 0));
+
+        let t1 = mvcc.begin()?;
+        t1.set(b"a", b"t1".to_vec())?;
+        t1.commit()?;
+
+        // t2 仍未结束，通过导出的状态恢复后提交
+        let t2 = mvcc.begin()?;
+        t2.set(b"a", b"t2".to_vec())?;
+        let status = mvcc.status()?;
+        assert_eq!((status.versions, status.active_txns), (2, 1));
+        mvcc.resume(t2.state().clone())?.commit()?;
+        assert_eq!(mvcc.status()?.active_txns, 0);
+
+        // as_of(2) 只能看到版本 2 开始之前提交的 t1
+        assert_eq!(mvcc.begin_as_of(2)?.get(b"a")?, Some(b"t1".to_vec()));
+        assert_eq!(mvcc.begin_read_only()?.get(b"a")?, Some(b"t2".to_vec()));
+
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 use std::collections::Bound;
 use std::sync::MutexGuard;
 use tokio_stream::StreamExt;
-use crate::error::CResult;
-use crate::mvcc::transaction::TransactionState;
+use crate::error::{CResult, Error};
+use crate::mvcc::mvcc::Key;
+use crate::mvcc::transaction::{TransactionState, TransactionStateDef};
 use crate::mvcc::Version;
 use crate::storage::engine::Engine;
 
@@ -27,7 +28,7 @@ enum ScanType {
 }
 
 impl<'a, E: Engine + 'a> Scan<'a, E> {
-    fn new(engine: MutexGuard<'a, E>, txn: &'a TransactionState, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>, ) -> Self {
+    pub(crate) fn new(engine: MutexGuard<'a, E>, txn: &'a TransactionState, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>, ) -> Self {
         Self { engine, txn, param: ScanType::Range((start, end)) }
     }
 
@@ -35,6 +36,20 @@ impl<'a, E: Engine + 'a> Scan<'a, E> {
     fn new_prefix(engine: MutexGuard<'a, E>, txn: &'a TransactionState, prefix: Vec<u8>) -> Self {
         Self { engine, txn, param: ScanType::Prefix(prefix) }
     }
+
+    /// Returns an iterator over the visible key/value pairs.
+    pub fn iter(&mut self) -> ScanIterator<'_, E> {
+        let inner = match &self.param {
+            ScanType::Range((start, end)) => self.engine.scan((start.clone(), end.clone())),
+            ScanType::Prefix(prefix) => self.engine.scan_prefix(prefix),
+        };
+        ScanIterator::new(self.txn, inner)
+    }
+
+    /// Collects the visible key/value pairs into a Vec.
+    pub fn collect_vec(&mut self) -> CResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.iter().collect()
+    }
 }
 
 /// An iterator over the latest live and visible key/value pairs at the txn
@@ -43,22 +58,58 @@ pub struct ScanIterator<'a, E: Engine + 'a> {
     /// Decodes and filters visible MVCC versions from the inner engine iterator.
     inner: std::iter::Peekable<VersionIterator<'a, E>>,
 
+    /// 从后往前最近返回的 key。前后两端交替迭代时，用于跳过同一 key 的旧版本，并在两端相遇时结束
     last: Option<Vec<u8>>,
 }
 
 impl<'a, E: Engine + 'a> ScanIterator<'a, E> {
     fn new(txn: &'a TransactionState, inner: E::ScanIterator<'a>) -> Self {
-        todo!()
+        Self { inner: VersionIterator::new(txn, inner).peekable(), last: None }
     }
 
     /// Fallible next(), emitting the next item, or None if exhausted.
     fn try_next(&mut self) -> CResult<Option<(Vec<u8>, Vec<u8>)>> {
-        todo!()
+        while let Some((key, _version, value)) = self.inner.next().transpose()? {
+            // 版本按 (key, version) 升序排列，下一个 entry 仍是同一个 key 时，当前版本不是最新的可见版本
+            match self.inner.peek() {
+                Some(Ok((peek_key, _, _))) if *peek_key == key => continue,
+                Some(Err(err)) => return Err(err.clone()),
+                Some(Ok(_)) | None => {}
+            }
+
+            // 已经从后往前返回过这个 key，两端相遇
+            if let Some(last) = &self.last {
+                if key >= *last {
+                    return Ok(None);
+                }
+            }
+
+            // 跳过 tombstone
+            match bincode::deserialize::<Option<Vec<u8>>>(&value)? {
+                Some(value) => return Ok(Some((key, value))),
+                None => continue,
+            }
+        }
+
+        Ok(None)
     }
 
     /// Fallible next_back(), emitting the next item from the back, or None if exhausted.
     fn try_next_back(&mut self) -> CResult<Option<(Vec<u8>, Vec<u8>)>> {
-        todo!()
+        while let Some((key, _version, value)) = self.inner.next_back().transpose()? {
+            // 从后往前时先遇到的是最新的可见版本，同一个 key 的其余版本都更旧
+            if self.last.as_ref() == Some(&key) {
+                continue;
+            }
+            self.last = Some(key.clone());
+
+            match bincode::deserialize::<Option<Vec<u8>>>(&value)? {
+                Some(value) => return Ok(Some((key, value))),
+                None => continue,
+            }
+        }
+
+        Ok(None)
     }
 }
 
@@ -87,17 +138,37 @@ struct VersionIterator<'a, E: Engine + 'a> {
 
 impl<'a, E: Engine> VersionIterator<'a, E> {
     fn new(txn: &'a TransactionState, inner: E::ScanIterator<'a>) -> Self {
-        todo!()
+        Self { txn, inner }
+    }
+
+    /// Decodes a raw engine key, returning the user key and version if it is visible to the transaction.
+    fn decode_visible(&self, key: &[u8]) -> CResult<Option<(Vec<u8>, Version)>> {
+        match Key::decode(key)? {
+            Key::Version(key, version) => Ok(self.txn.is_visible(version).then(|| (key.into_owned(), version))),
+            key => Err(Error::Internal(format!("Expected Version key, got {:?}", key))),
+        }
     }
 
     /// Fallible next(), emitting the next item, or None if exhausted.
     fn try_next(&mut self) -> CResult<Option<(Vec<u8>, Version, Vec<u8>)>> {
-        todo!()
+        while let Some((key, value)) = self.inner.next().transpose()? {
+            if let Some((key, version)) = self.decode_visible(&key)? {
+                return Ok(Some((key, version, value)));
+            }
+        }
+
+        Ok(None)
     }
 
     /// Fallible next_back(), emitting the previous item, or None if exhausted.
     fn try_next_back(&mut self) -> CResult<Option<(Vec<u8>, Version, Vec<u8>)>> {
-        todo!()
+        while let Some((key, value)) = self.inner.next_back().transpose()? {
+            if let Some((key, version)) = self.decode_visible(&key)? {
+                return Ok(Some((key, version, value)));
+            }
+        }
+
+        Ok(None)
     }
 }
 
@@ -3,12 +3,13 @@ use std::fmt::{Display, Formatter};
 use std::ops::RangeBounds;
 use std::sync::{Arc, Mutex, MutexGuard};
 use serde_derive::{Deserialize, Serialize};
-use crate::error::CResult;
-use crate::mvcc::mvcc::Key;
+use crate::error::{CResult, Error};
+use crate::mvcc::mvcc::{Key, KeyPrefix};
 use crate::mvcc::scan::Scan;
 use crate::mvcc::transaction::seals::EngineSealedMut;
 use crate::mvcc::Version;
 use crate::storage::engine::Engine;
+use crate::storage::log::now_millis;
 use crate::storage::{ScanIteratorT, Status};
 
 /// 事务最基础的结构体
@@ -76,6 +77,9 @@ pub trait TransactionDef<E: Engine> {
     fn begin_read_only(engine: Arc<Mutex<E>>, as_of: Option<Version>) -> CResult<Transaction<E>>;
 
     /// Resumes a transaction from the given state.
+    ///
+    /// 不会分配新版本，也不会修改活动集。读写事务必须仍处于活动集中，
+    /// 恢复一个已提交或已回滚的事务是逻辑错误，返回 [`Error::Internal`]。
     fn resume(engine: Arc<Mutex<E>>, s: TransactionState) -> CResult<Self> where Self: Sized;
 
     /// 获取当前活动事务的集合。
@@ -125,9 +129,47 @@ impl <E: Engine> EngineSealedMut<E> for Transaction<E> {
 }
 
 impl <E: Engine> Transaction<E> {
-    /// write data with tx
-    fn write_data() -> CResult<()> {
-        todo!()
+    /// write data with tx。value 为 None 时写入 tombstone，表示删除
+    fn write_data(&self, key: &[u8], value: Option<Vec<u8>>) -> CResult<()> {
+        if self.st.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let mut session = self.engine.lock()?;
+
+        // 写冲突检测：比活动集中最老的版本更新的版本中，只要 key 的最新版本对当前事务不可见，
+        // 就说明有并发的事务(未提交或在当前事务开始后提交)写过这个 key，当前事务需要重试
+        let from = self.st.active.iter().min().copied().unwrap_or(self.st.version + 1);
+        let from = Key::Version(key.into(), from).encode()?;
+        let to = Key::Version(key.into(), u64::MAX).encode()?;
+        if let Some((latest, _)) = session.scan(from..=to).next_back().transpose()? {
+            match Key::decode(&latest)? {
+                Key::Version(_, version) => {
+                    if !self.st.is_visible(version) {
+                        return Err(Error::Serialization);
+                    }
+                }
+                key => return Err(Error::Internal(format!("Expected Version key, got {:?}", key))),
+            }
+        }
+
+        // 先记录 TxnWrite，保证回滚时能找到所有写入的版本
+        session.set(&Key::TxnWrite(self.st.version, key.into()).encode()?, vec![])?;
+        session.set(&Key::Version(key.into(), self.st.version).encode()?, bincode::serialize(&value)?)
+    }
+
+    /// 扫描给定版本的事务写过的 key，返回 (TxnWrite 记录的 key, 用户 key)
+    fn scan_writes(session: &mut MutexGuard<E>, version: Version) -> CResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut writes = vec![];
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnWrite(version).encode()?);
+        while let Some((write_key, _)) = scan.next().transpose()? {
+            let key = match Key::decode(&write_key)? {
+                Key::TxnWrite(_, key) => key.into_owned(),
+                key => return Err(Error::Internal(format!("Expected TxnWrite key, got {:?}", key))),
+            };
+            writes.push((write_key, key));
+        }
+
+        Ok(writes)
     }
 }
 
@@ -146,14 +188,23 @@ impl <E: Engine> TransactionDef<E> for Transaction<E> {
         // 从存储引擎当中扫描，恢复出当前的active_set。开启一个事务后，就向存储引擎当中写入一条Key::TxnActive，带上自己的version，之后扫描出所有Key::TxnActive的key，恢复出active_set，
         // 由于存储引擎本身是一个append-only的存储设计， 就算是将value设置为完整的active_set，那么每次写入也是追加写入，并且需要完整的写入整个active_set，写入量反而增大，
         // active_set只会在事务begin的时候进行读取
-        let mut active = HashSet::new();
+        let active = Self::scan_active(&mut session)?;
+
+        // 记录开始时的活动集快照，供 time travel 查询在该版本重建可见性。活动集为空时不写入
+        if !active.is_empty() {
+            session.set(&Key::TxnActiveSnapshot(version).encode()?, bincode::serialize(&active)?)?;
+        }
+
+        // 将自己加入活动集，value 为开始时间(unix millis)，供 garbage_collect 判断事务是否已被遗弃
+        session.set(&Key::TxnActive(version).encode()?, bincode::serialize(&now_millis())?)?;
+        drop(session);
 
         Ok(
             Self {
-                engine: engine.clone(),
+                engine,
                 st: TransactionState {
                     version,
-                    read_only: true,
+                    read_only: false,
                     active
                 }
             }
@@ -161,15 +212,60 @@ impl <E: Engine> TransactionDef<E> for Transaction<E> {
     }
 
     fn begin_read_only(engine: Arc<Mutex<E>>, as_of: Option<Version>) -> CResult<Transaction<E>> {
-        todo!()
+        let mut session = engine.lock()?;
+
+        // 只读事务不分配新版本，使用下一个版本号，即能看到所有已提交的写入
+        let mut version = match session.get(&Key::NextVersion.encode()?)? {
+            Some(ref v) => bincode::deserialize(v)?,
+            None => 1,
+        };
+
+        let active = match as_of {
+            Some(as_of) => {
+                if as_of >= version {
+                    return Err(Error::Value(format!("Version {} does not exist", as_of)));
+                }
+                version = as_of;
+                match session.get(&Key::TxnActiveSnapshot(version).encode()?)? {
+                    Some(ref v) => bincode::deserialize(v)?,
+                    None => HashSet::new(),
+                }
+            }
+            None => Self::scan_active(&mut session)?,
+        };
+        drop(session);
+
+        Ok(
+            Self {
+                engine,
+                st: TransactionState {
+                    version,
+                    read_only: true,
+                    active
+                }
+            }
+        )
     }
 
     fn resume(engine: Arc<Mutex<E>>, s: TransactionState) -> CResult<Self> where Self: Sized {
-        todo!()
+        if !s.read_only && engine.lock()?.get(&Key::TxnActive(s.version).encode()?)?.is_none() {
+            return Err(Error::Internal(format!("No active transaction at version {}", s.version)));
+        }
+
+        Ok(Self { engine, st: s })
     }
 
     fn scan_active(session: &mut MutexGuard<E>) -> CResult<HashSet<Version>> {
-        todo!()
+        let mut active = HashSet::new();
+        let mut scan = session.scan_prefix(&KeyPrefix::TxnActive.encode()?);
+        while let Some((key, _)) = scan.next().transpose()? {
+            match Key::decode(&key)? {
+                Key::TxnActive(version) => active.insert(version),
+                key => return Err(Error::Internal(format!("Expected TxnActive key, got {:?}", key))),
+            };
+        }
+
+        Ok(active)
     }
 
     fn version(&self) -> Version {
@@ -185,27 +281,80 @@ impl <E: Engine> TransactionDef<E> for Transaction<E> {
     }
 
     fn commit(self) -> CResult<()> {
-        todo!()
+        if self.st.read_only {
+            return Ok(());
+        }
+        let mut session = self.engine.lock()?;
+
+        // TxnWrite 记录只在回滚时需要。最后删除活动集中的标记，中途崩溃时事务仍处于活动集中，写入保持不可见
+        for (write_key, _) in Self::scan_writes(&mut session, self.st.version)? {
+            session.delete(&write_key)?;
+        }
+        session.delete(&Key::TxnActive(self.st.version).encode()?)?;
+
+        Ok(())
     }
 
     fn rollback(self) -> CResult<()> {
-        todo!()
+        if self.st.read_only {
+            return Ok(());
+        }
+        let mut session = self.engine.lock()?;
+
+        for (write_key, key) in Self::scan_writes(&mut session, self.st.version)? {
+            session.delete(&Key::Version(key.into(), self.st.version).encode()?)?;
+            session.delete(&write_key)?;
+        }
+        session.delete(&Key::TxnActive(self.st.version).encode()?)?;
+
+        Ok(())
     }
 
     fn delete(&self, key: &[u8]) -> CResult<i64> {
-        todo!()
+        self.write_data(key, None)?;
+
+        Ok(1)
     }
 
     fn set(&self, key: &[u8], value: Vec<u8>) -> CResult<()> {
-        todo!()
+        self.write_data(key, Some(value))
     }
 
     fn get(&self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
-        todo!()
+        let session = self.engine.lock()?;
+
+        // 从当前版本往前找，第一个可见的版本即为当前事务看到的值
+        let from = Key::Version(key.into(), 0).encode()?;
+        let to = Key::Version(key.into(), self.st.version).encode()?;
+        let mut scan = session.scan(from..=to).rev();
+        while let Some((k, v)) = scan.next().transpose()? {
+            match Key::decode(&k)? {
+                Key::Version(_, version) => {
+                    if self.st.is_visible(version) {
+                        return Ok(bincode::deserialize(&v)?);
+                    }
+                }
+                key => return Err(Error::Internal(format!("Expected Version key, got {:?}", key))),
+            }
+        }
+
+        Ok(None)
     }
 
     fn scan<R: RangeBounds<Vec<u8>>>(&self, range: R) -> CResult<Scan<E>> {
-        todo!()
+        // 将用户 key 的范围转换为版本 key 的范围：起点包含该 key 的所有版本，终点同理
+        let start = match range.start_bound() {
+            Bound::Excluded(k) => Bound::Excluded(Key::Version(k.into(), u64::MAX).encode()?),
+            Bound::Included(k) => Bound::Included(Key::Version(k.into(), 0).encode()?),
+            Bound::Unbounded => Bound::Included(Key::Version(b"".as_slice().into(), 0).encode()?),
+        };
+        let end = match range.end_bound() {
+            Bound::Excluded(k) => Bound::Excluded(Key::Version(k.into(), 0).encode()?),
+            Bound::Included(k) => Bound::Included(Key::Version(k.into(), u64::MAX).encode()?),
+            Bound::Unbounded => Bound::Excluded(KeyPrefix::Unversioned.encode()?),
+        };
+
+        Ok(Scan::new(self.engine.lock()?, &self.st, start, end))
     }
 }
 
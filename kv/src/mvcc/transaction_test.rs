@@ -1,9 +1,12 @@
-
 #[cfg(test)]
 mod tx_test {
+    use std::collections::{BTreeMap, Bound, HashSet};
     use std::sync::{Arc, Mutex};
-    use crate::error::CResult;
-    use crate::mvcc::transaction::{Transaction, TransactionDef};
+    use crate::error::{CResult, Error};
+    use crate::mvcc::mvcc::{Key, KeyPrefix};
+    use crate::mvcc::transaction::{Transaction, TransactionDef, TransactionStateDef};
+    use crate::storage::engine::Engine;
+    use crate::storage::log_cask::LogCask;
     use crate::storage::memory::Memory;
 
     #[test]
@@ -15,4 +18,377 @@ mod tx_test {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// 直接写入事务的版本记录及其 TxnWrite 记录，模拟事务内的一次写入
+    fn write<E: Engine>(engine: &Arc<Mutex<E>>, tx: &Transaction<E>, key: &[u8], value: &[u8]) -> CResult<()> {
+        let mut session = engine.lock()?;
+        session.set(&Key::TxnWrite(tx.version(), key.into()).encode()?, vec![])?;
+        session.set(&Key::Version(key.into(), tx.version()).encode()?, value.to_vec())
+    }
+
+    fn count_prefix<E: Engine>(engine: &Arc<Mutex<E>>, prefix: KeyPrefix) -> CResult<usize> {
+        let session = engine.lock()?;
+        let count = session.scan_prefix(&prefix.encode()?).count();
+        Ok(count)
+    }
+
+    fn commit_and_rollback<E: Engine>(engine: E) -> CResult<()> {
+        let engine = Arc::new(Mutex::new(engine));
+
+        let t1 = Transaction::begin(engine.clone())?;
+        let t2 = Transaction::begin(engine.clone())?;
+        assert!(!t1.is_read_only());
+        assert_eq!(t2.state().active, [1].into());
+        assert_eq!(count_prefix(&engine, KeyPrefix::TxnActive)?, 2);
+
+        write(&engine, &t1, b"a", b"t1")?;
+        write(&engine, &t2, b"b", b"t2")?;
+
+        let v1 = t1.version();
+        let v2 = t2.version();
+        t1.commit()?;
+        t2.rollback()?;
+
+        // 两个事务都已离开活动集，t1 的写入对之后的事务可见，t2 的写入已被撤销
+        let t3 = Transaction::begin(engine.clone())?;
+        assert!(t3.state().active.is_empty());
+        assert!(t3.state().is_visible(v1));
+        assert_eq!(engine.lock()?.get(&Key::Version(b"a".as_slice().into(), v1).encode()?)?, Some(b"t1".to_vec()));
+        assert_eq!(engine.lock()?.get(&Key::Version(b"b".as_slice().into(), v2).encode()?)?, None);
+        assert_eq!(count_prefix(&engine, KeyPrefix::TxnWrite(v1))?, 0);
+        assert_eq!(count_prefix(&engine, KeyPrefix::TxnWrite(v2))?, 0);
+        assert_eq!(count_prefix(&engine, KeyPrefix::TxnActive)?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn commit_and_rollback_memory() -> CResult<()> {
+        commit_and_rollback(Memory::new())
+    }
+
+    #[test]
+    fn commit_and_rollback_log_cask() -> CResult<()> {
+        let path = tempdir::TempDir::new("demo")?.path().join("whosdb");
+        commit_and_rollback(LogCask::new(path.clone())?)?;
+
+        // 重新打开后，活动集从 TxnActive 记录中恢复
+        let engine = Arc::new(Mutex::new(LogCask::new(path)?));
+        let t4 = Transaction::begin(engine)?;
+        assert_eq!(t4.version(), 4);
+        assert_eq!(t4.state().active, [3].into());
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_isolation() -> CResult<()> {
+        let engine = Arc::new(Mutex::new(Memory::new()));
+
+        let t1 = Transaction::begin(engine.clone())?;
+        t1.set(b"a", b"a1".to_vec())?;
+        t1.set(b"b", b"b1".to_vec())?;
+        t1.commit()?;
+
+        let t2 = Transaction::begin(engine.clone())?;
+        let t3 = Transaction::begin(engine.clone())?;
+        t2.set(b"a", b"a2".to_vec())?;
+        t2.delete(b"b")?;
+
+        // 事务能看到自己的写入，并发事务看不到未提交的写入
+        assert_eq!(t2.get(b"a")?, Some(b"a2".to_vec()));
+        assert_eq!(t2.get(b"b")?, None);
+        assert_eq!(t3.get(b"a")?, Some(b"a1".to_vec()));
+        assert_eq!(t3.get(b"b")?, Some(b"b1".to_vec()));
+
+        // 提交后，t3 仍然看到开始时的快照，之后开始的事务才能看到 t2 的写入
+        t2.commit()?;
+        assert_eq!(t3.get(b"a")?, Some(b"a1".to_vec()));
+        assert_eq!(t3.get(b"b")?, Some(b"b1".to_vec()));
+
+        let t4 = Transaction::begin(engine.clone())?;
+        assert_eq!(t4.get(b"a")?, Some(b"a2".to_vec()));
+        assert_eq!(t4.get(b"b")?, None);
+        assert_eq!(t4.get(b"c")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_conflict() -> CResult<()> {
+        let engine = Arc::new(Mutex::new(Memory::new()));
+
+        let t1 = Transaction::begin(engine.clone())?;
+        let t2 = Transaction::begin(engine.clone())?;
+        let t3 = Transaction::begin(engine.clone())?;
+
+        // 写入未提交的事务已写过的 key
+        t1.set(b"a", b"t1".to_vec())?;
+        assert_eq!(t2.set(b"a", b"t2".to_vec()), Err(Error::Serialization));
+        assert_eq!(t2.delete(b"a"), Err(Error::Serialization));
+
+        // 写入在当前事务开始后才提交的 key
+        t3.set(b"b", b"t3".to_vec())?;
+        t3.commit()?;
+        assert_eq!(t2.set(b"b", b"t2".to_vec()), Err(Error::Serialization));
+
+        // t1 回滚后，它写过的 key 不再冲突
+        t1.rollback()?;
+        let t4 = Transaction::begin(engine.clone())?;
+        t4.set(b"a", b"t4".to_vec())?;
+        t4.commit()?;
+        let t5 = Transaction::begin(engine)?;
+        assert_eq!(t5.get(b"a")?, Some(b"t4".to_vec()));
+        assert_eq!(t5.get(b"b")?, Some(b"t3".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn begin_read_only_as_of() -> CResult<()> {
+        let engine = Arc::new(Mutex::new(Memory::new()));
+
+        let t1 = Transaction::begin(engine.clone())?;
+        t1.set(b"a", b"v1".to_vec())?;
+        t1.commit()?;
+
+        // t2 在 t3 开始时仍未提交，因此对 as_of(3) 不可见
+        let t2 = Transaction::begin(engine.clone())?;
+        t2.set(b"b", b"v2".to_vec())?;
+        let t3 = Transaction::begin(engine.clone())?;
+        t2.commit()?;
+        t3.set(b"a", b"v3".to_vec())?;
+        t3.commit()?;
+
+        // as_of 看到的是该版本开始时的状态，不包括该版本自身的写入
+        let r2 = Transaction::begin_read_only(engine.clone(), Some(2))?;
+        assert!(r2.is_read_only());
+        assert_eq!(r2.get(b"a")?, Some(b"v1".to_vec()));
+        assert_eq!(r2.get(b"b")?, None);
+
+        let r3 = Transaction::begin_read_only(engine.clone(), Some(3))?;
+        assert_eq!(r3.state().active, [2].into());
+        assert_eq!(r3.get(b"a")?, Some(b"v1".to_vec()));
+        assert_eq!(r3.get(b"b")?, None);
+
+        let t4 = Transaction::begin(engine.clone())?;
+        t4.commit()?;
+        let r4 = Transaction::begin_read_only(engine.clone(), Some(4))?;
+        assert_eq!(r4.get(b"a")?, Some(b"v3".to_vec()));
+        assert_eq!(r4.get(b"b")?, Some(b"v2".to_vec()));
+
+        // 不指定版本时看到最新的已提交状态，且不能写入
+        let latest = Transaction::begin_read_only(engine.clone(), None)?;
+        assert_eq!(latest.version(), 5);
+        assert_eq!(latest.get(b"a")?, Some(b"v3".to_vec()));
+        assert_eq!(latest.set(b"a", vec![]), Err(Error::ReadOnly));
+
+        assert!(Transaction::begin_read_only(engine, Some(5)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn scan() -> CResult<()> {
+        let engine = Arc::new(Mutex::new(Memory::new()));
+
+        let t1 = Transaction::begin(engine.clone())?;
+        for key in [b"a".as_slice(), b"b", b"b\x00", b"c", b"d"] {
+            t1.set(key, key.to_vec())?;
+        }
+        t1.commit()?;
+
+        let t2 = Transaction::begin(engine.clone())?;
+        t2.set(b"b", b"b2".to_vec())?;
+        t2.delete(b"c")?;
+        t2.set(b"e", b"e2".to_vec())?;
+        let t3 = Transaction::begin(engine.clone())?;
+        t3.set(b"f", b"f3".to_vec())?;
+
+        let kv = |k: &[u8], v: &[u8]| (k.to_vec(), v.to_vec());
+        assert_eq!(
+            t2.scan(..)?.collect_vec()?,
+            vec![kv(b"a", b"a"), kv(b"b", b"b2"), kv(b"b\x00", b"b\x00"), kv(b"d", b"d"), kv(b"e", b"e2")]
+        );
+        assert_eq!(
+            t2.scan(b"b".to_vec()..b"e".to_vec())?.iter().rev().collect::<CResult<Vec<_>>>()?,
+            vec![kv(b"d", b"d"), kv(b"b\x00", b"b\x00"), kv(b"b", b"b2")]
+        );
+        assert_eq!(
+            t2.scan((Bound::Excluded(b"b".to_vec()), Bound::Included(b"d".to_vec())))?.collect_vec()?,
+            vec![kv(b"b\x00", b"b\x00"), kv(b"d", b"d")]
+        );
+
+        // 前后两端交替迭代
+        let mut scan = t3.scan(..)?;
+        let mut iter = scan.iter();
+        assert_eq!(iter.next().transpose()?, Some(kv(b"a", b"a")));
+        assert_eq!(iter.next_back().transpose()?, Some(kv(b"f", b"f3")));
+        assert_eq!(iter.next_back().transpose()?, Some(kv(b"d", b"d")));
+        assert_eq!(iter.next().transpose()?, Some(kv(b"b", b"b")));
+        assert_eq!(iter.next().transpose()?, Some(kv(b"b\x00", b"b\x00")));
+        assert_eq!(iter.next().transpose()?, Some(kv(b"c", b"c")));
+        assert_eq!(iter.next().transpose()?, None);
+        assert_eq!(iter.next_back().transpose()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scan_random_ops() -> CResult<()> {
+        const NUM_OPS: u64 = 1000;
+
+        use rand::{seq::SliceRandom, Rng, RngCore};
+        let seed: u64 = rand::thread_rng().gen();
+        let mut rng: rand::rngs::StdRng = rand::SeedableRng::seed_from_u64(seed);
+        println!("seed = {}", seed);
+
+        let engine = Arc::new(Mutex::new(Memory::new()));
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        let mut m = BTreeMap::new();
+
+        // Pick an already-used key with 80% probability, or generate a new key.
+        let mut random_key = |mut rng: &mut rand::rngs::StdRng| -> Vec<u8> {
+            if rng.gen::<f64>() < 0.8 && !keys.is_empty() {
+                keys.choose(&mut rng).unwrap().clone()
+            } else {
+                let mut key = vec![0; rng.gen_range(0..=16)];
+                rng.fill_bytes(&mut key);
+                keys.push(key.clone());
+                key
+            }
+        };
+
+        let random_value = |rng: &mut rand::rngs::StdRng| -> Vec<u8> {
+            let mut value = vec![0; rng.gen_range(0..=16)];
+            rng.fill_bytes(&mut value);
+            value
+        };
+
+        // tx 开始之后，并发的 other 写入但不提交，这些写入对 tx 始终不可见。
+        // 双方写入对方写过的 key 会发生写冲突，因此各自跳过这些 key
+        let tx = Transaction::begin(engine.clone())?;
+        let other = Transaction::begin(engine.clone())?;
+        let mut other_keys = HashSet::new();
+        let mut tx_keys = HashSet::new();
+
+        for _ in 0..NUM_OPS {
+            let key = random_key(&mut rng);
+            match rng.gen_range(0..=5) {
+                0 if !tx_keys.contains(&key) && !other_keys.contains(&key) => {
+                    other.set(&key, random_value(&mut rng))?;
+                    other_keys.insert(key);
+                }
+                0 | 1 if !other_keys.contains(&key) => {
+                    let value = random_value(&mut rng);
+                    tx.set(&key, value.clone())?;
+                    tx_keys.insert(key.clone());
+                    m.insert(key, value);
+                }
+                2 if !other_keys.contains(&key) => {
+                    tx.delete(&key)?;
+                    m.remove(&key);
+                    tx_keys.insert(key);
+                }
+                3 => {
+                    assert_eq!(tx.get(&key)?, m.get(&key).cloned());
+                }
+                4 | 5 => {
+                    let mut from = key;
+                    let mut to = random_key(&mut rng);
+                    if to < from {
+                        (from, to) = (to, from)
+                    }
+                    let mut scan = tx.scan(from.clone()..to.clone())?;
+                    let mut expect = m
+                        .range(from..to)
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect::<Vec<_>>();
+                    if rng.gen::<bool>() {
+                        assert_eq!(scan.collect_vec()?, expect);
+                    } else {
+                        expect.reverse();
+                        assert_eq!(scan.iter().rev().collect::<CResult<Vec<_>>>()?, expect);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Compare the final states.
+        let expect = m.iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>();
+        assert_eq!(tx.scan(..)?.collect_vec()?, expect);
+
+        tx.commit()?;
+        other.rollback()?;
+        assert_eq!(Transaction::begin(engine)?.scan(..)?.collect_vec()?, expect);
+
+        Ok(())
+    }
+
+    #[test]
+    fn resume() -> CResult<()> {
+        let engine = Arc::new(Mutex::new(Memory::new()));
+
+        let t1 = Transaction::begin(engine.clone())?;
+        t1.set(b"a", b"t1".to_vec())?;
+        let t2 = Transaction::begin(engine.clone())?;
+        t2.set(b"b", b"t2".to_vec())?;
+
+        // 序列化状态后丢弃事务对象，不提交
+        let state = bincode::serialize(t1.state())?;
+        drop(t1);
+
+        let t1 = Transaction::resume(engine.clone(), bincode::deserialize(&state)?)?;
+        assert_eq!(t1.version(), 1);
+        assert_eq!(t1.get(b"a")?, Some(b"t1".to_vec()));
+        assert_eq!(t1.get(b"b")?, None);
+        t1.set(b"c", b"t1".to_vec())?;
+        let committed = t1.state().clone();
+        t1.commit()?;
+
+        // 恢复不会分配新版本
+        let t3 = Transaction::begin(engine.clone())?;
+        assert_eq!(t3.version(), 3);
+        assert_eq!(t3.get(b"c")?, Some(b"t1".to_vec()));
+
+        assert!(matches!(Transaction::resume(engine.clone(), committed), Err(Error::Internal(_))));
+
+        // 只读事务不在活动集中，可以随时恢复
+        let r = Transaction::begin_read_only(engine.clone(), None)?;
+        let r = Transaction::resume(engine, r.state().clone())?;
+        assert_eq!(r.get(b"a")?, Some(b"t1".to_vec()));
+        assert_eq!(r.get(b"b")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_encoding() -> CResult<()> {
+        let keys = [
+            Key::NextVersion,
+            Key::TxnActive(7),
+            Key::TxnWrite(7, b"a\x00b".as_slice().into()),
+            Key::Version(b"a".as_slice().into(), 1),
+            Key::Version(b"a".as_slice().into(), 256),
+            Key::Version(b"a\x00".as_slice().into(), 1),
+            Key::Version(b"ab".as_slice().into(), 1),
+            Key::Unversioned(b"".as_slice().into()),
+        ];
+        let encoded = keys.iter().map(|k| k.encode()).collect::<CResult<Vec<_>>>()?;
+
+        // 编码后的字节序与 (变体, key, version) 的顺序一致
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(sorted, encoded);
+
+        for (key, bytes) in keys.iter().zip(&encoded) {
+            assert_eq!(format!("{:?}", Key::decode(bytes)?), format!("{:?}", key));
+        }
+
+        let prefix = KeyPrefix::Version(b"a".as_slice().into()).encode()?;
+        assert_eq!(encoded.iter().filter(|k| k.starts_with(&prefix)).count(), 2);
+
+        Ok(())
+    }
+}
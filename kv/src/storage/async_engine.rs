@@ -0,0 +1,236 @@
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use crate::error::{CResult, Error};
+use crate::storage::engine::{prefix_range, Engine};
+use crate::storage::index::Index;
+use crate::storage::log_cask::{CompactionHandle, LogCask};
+
+/// 供 tokio 使用的异步 Engine 包装。
+///
+/// Engine 的方法都是同步且阻塞的(磁盘IO)，直接在 async 上下文中调用会阻塞 runtime 的工作线程。
+/// AsyncEngine 将每次调用放到 `tokio::task::spawn_blocking` 中执行，并通过 `Arc<Mutex<E>>` 串行访问底层引擎，
+/// 可以被 clone 后在多个任务之间共享。
+pub struct AsyncEngine<E: Engine + 'static = LogCask> {
+    inner: Arc<Mutex<E>>,
+
+    /// 保证同一时间只有一个 compact 在重写日志
+    compaction: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl<E: Engine + 'static> Clone for AsyncEngine<E> {
+    fn clone(&self) -> Self {
+        AsyncEngine { inner: self.inner.clone(), compaction: self.compaction.clone() }
+    }
+}
+
+/// scan 默认的 channel 容量
+pub const DEFAULT_SCAN_BUFFER: usize = 128;
+
+impl<E: Engine + 'static> AsyncEngine<E> {
+    pub fn new(engine: E) -> Self {
+        AsyncEngine { inner: Arc::new(Mutex::new(engine)), compaction: Arc::new(tokio::sync::Mutex::new(())) }
+    }
+
+    /// 在阻塞线程池中持有引擎的锁并执行 f，用于调用 Engine 之外的方法，如 `engine.call(|e| e.compact())`
+    pub async fn call<F, T>(&self, f: F) -> CResult<T>
+        where
+            F: FnOnce(&mut E) -> CResult<T> + Send + 'static,
+            T: Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut engine = inner.lock().map_err(|err| Error::Internal(err.to_string()))?;
+            f(&mut engine)
+        })
+            .await
+            .map_err(|err| Error::Internal(err.to_string()))?
+    }
+
+    /// Gets a value for a key, if it exists.
+    pub async fn get(&self, key: Vec<u8>) -> CResult<Option<Vec<u8>>> {
+        self.call(move |e| e.get(&key)).await
+    }
+
+    /// 批量读取，见 [`Engine::get_many`]
+    pub async fn get_many(&self, keys: Vec<Vec<u8>>) -> CResult<Vec<Option<Vec<u8>>>> {
+        self.call(move |e| e.get_many(&keys.iter().map(|key| key.as_slice()).collect::<Vec<_>>())).await
+    }
+
+    /// Sets a value for a key, replacing the existing value if any.
+    pub async fn set(&self, key: Vec<u8>, value: Vec<u8>) -> CResult<()> {
+        self.call(move |e| e.set(&key, value)).await
+    }
+
+    /// Deletes a key, or does nothing if it does not exist.
+    pub async fn delete(&self, key: Vec<u8>) -> CResult<i64> {
+        self.call(move |e| e.delete(&key)).await
+    }
+
+    /// 范围扫描，结果通过容量为 buffer 的 channel 以 stream 的形式返回。
+    /// 扫描期间一直持有引擎的锁；当 stream 被 drop 时，扫描提前结束并释放锁。
+    pub fn scan(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>), buffer: usize) -> ReceiverStream<CResult<(Vec<u8>, Vec<u8>)>> {
+        self.stream(buffer, move |e, send| e.scan(range).all(send))
+    }
+
+    /// Iterates over all key/value pairs starting with prefix.
+    pub fn scan_prefix(&self, prefix: &[u8], buffer: usize) -> ReceiverStream<CResult<(Vec<u8>, Vec<u8>)>> {
+        self.scan(prefix_range(prefix), buffer)
+    }
+
+    /// 同 scan，但只返回 key，见 [`Engine::scan_keys`]
+    pub fn scan_keys(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>), buffer: usize) -> ReceiverStream<CResult<Vec<u8>>> {
+        self.stream(buffer, move |e, send| e.scan_keys(range).all(send))
+    }
+
+    /// 在阻塞线程池中持有引擎的锁执行 f，f 通过 send 逐项发送结果，send 返回 false 表示 stream 已被 drop，应停止扫描
+    fn stream<T, F>(&self, buffer: usize, f: F) -> ReceiverStream<CResult<T>>
+        where
+            T: Send + 'static,
+            F: FnOnce(&E, &mut dyn FnMut(CResult<T>) -> bool) -> bool + Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::mpsc::channel(buffer.max(1));
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let engine = match inner.lock() {
+                Ok(engine) => engine,
+                Err(err) => {
+                    let _ = tx.blocking_send(Err(Error::Internal(err.to_string())));
+                    return;
+                }
+            };
+            f(&engine, &mut |item| tx.blocking_send(item).is_ok());
+        });
+        ReceiverStream::new(rx)
+    }
+}
+
+impl<I: Index + 'static> AsyncEngine<LogCask<I>> {
+    /// 不阻塞读写的 compact：只在拷贝 keydir 快照和最后替换日志文件时持有引擎的锁，
+    /// 重写日志期间其他任务可以继续读写，期间的写入会在替换前合并到新日志中。
+    pub async fn compact(&self) -> CResult<()> {
+        self.compact_with(|_, _| {}, Arc::new(AtomicBool::new(false))).await
+    }
+
+    /// 同 compact，支持进度报告和取消，见 [`LogCask::compact_with`]
+    pub async fn compact_with<F>(&self, progress: F, cancel: Arc<AtomicBool>) -> CResult<()>
+        where
+            F: FnMut(u64, u64) + Send + 'static,
+    {
+        let _guard = self.compaction.lock().await;
+
+        let snapshot = self.call(|e| e.compaction_snapshot()).await?;
+        let compacted = tokio::task::spawn_blocking(move || snapshot.write_with(progress, &cancel))
+            .await
+            .map_err(|err| Error::Internal(err.to_string()))??;
+        self.call(move |e| e.finish_compaction(compacted)).await
+    }
+
+    /// 在后台线程中按周期检查并 compact 共享的引擎，见 [`LogCask::spawn_compactor`]
+    pub fn spawn_compactor(&self, garbage_ratio_threshold: f64, check_interval: Duration) -> CompactionHandle {
+        LogCask::spawn_compactor(self.inner.clone(), garbage_ratio_threshold, check_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+    use crate::error::CResult;
+    use crate::storage::async_engine::AsyncEngine;
+    use crate::storage::engine::Engine;
+    use crate::storage::log_cask::LogCask;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_get_set() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let engine = AsyncEngine::new(LogCask::new(dir.path().join("async"))?);
+
+        let mut tasks = Vec::new();
+        for i in 0..16u8 {
+            let engine = engine.clone();
+            tasks.push(tokio::spawn(async move {
+                for j in 0..32u8 {
+                    engine.set(vec![i, j], vec![j]).await?;
+                    assert_eq!(engine.get(vec![i, j]).await?, Some(vec![j]));
+                }
+                CResult::Ok(())
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap()?;
+        }
+
+        assert_eq!(engine.call(|e| Ok(e.status()?.keys)).await?, 16 * 32);
+        assert_eq!(engine.delete(vec![0, 0]).await?, 1);
+        assert_eq!(engine.get(vec![0, 0]).await?, None);
+
+        let keys = engine.scan_prefix(&[1], 4).collect::<CResult<Vec<_>>>().await?;
+        assert_eq!(keys.len(), 32);
+        assert_eq!(keys[31], (vec![1, 31], vec![31]));
+
+        // dropping a scan stream early releases the lock
+        let mut stream = engine.scan_prefix(&[], 1);
+        stream.next().await.unwrap()?;
+        drop(stream);
+        engine.set(vec![0xff], vec![]).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    /// Tests that readers and writers keep working while a compaction runs, and observe consistent values.
+    async fn concurrent_compact() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let engine = AsyncEngine::new(LogCask::new(dir.path().join("compact"))?);
+
+        // every key is written twice, half of the log is garbage
+        for round in 0..2u8 {
+            for i in 0..500u16 {
+                engine.set(i.to_be_bytes().to_vec(), vec![round; 64]).await?;
+            }
+        }
+
+        let compaction = {
+            let engine = engine.clone();
+            tokio::spawn(async move { engine.compact().await })
+        };
+
+        let mut readers = Vec::new();
+        for r in 0..4u16 {
+            let engine = engine.clone();
+            readers.push(tokio::spawn(async move {
+                // the writer below only touches keys outside of 100..400
+                for i in (100 + r..400).step_by(4) {
+                    assert_eq!(engine.get(i.to_be_bytes().to_vec()).await?, Some(vec![1; 64]));
+                }
+                CResult::Ok(())
+            }));
+        }
+        let writer = {
+            let engine = engine.clone();
+            tokio::spawn(async move {
+                for i in 0..100u16 {
+                    engine.set(i.to_be_bytes().to_vec(), vec![2; 8]).await?;
+                    engine.delete((400 + i).to_be_bytes().to_vec()).await?;
+                }
+                CResult::Ok(())
+            })
+        };
+
+        compaction.await.unwrap()?;
+        writer.await.unwrap()?;
+        for reader in readers {
+            reader.await.unwrap()?;
+        }
+
+        // writes made during the compaction survive it
+        assert_eq!(engine.get(0u16.to_be_bytes().to_vec()).await?, Some(vec![2; 8]));
+        assert_eq!(engine.get(200u16.to_be_bytes().to_vec()).await?, Some(vec![1; 64]));
+        assert_eq!(engine.get(450u16.to_be_bytes().to_vec()).await?, None);
+        assert_eq!(engine.call(|e| Ok(e.status()?.keys)).await?, 400);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::{BufRead, Read, Write};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::{CResult, Error};
+use crate::storage::engine::Engine;
+
+/// 导入二进制备份时单个 key 或 value 允许的最大长度，超过时视为文件损坏，避免按损坏的长度前缀分配内存
+const MAX_FIELD_LEN: u64 = 256 << 20;
+
+/// EXPORT 写入的备份文件格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// 每个 kv 依次写入 key_len(u32 BE)、value_len(u32 BE)、key、value，与日志 entry 的长度前缀一致，支持任意字节
+    #[default]
+    Binary,
+    /// 每行一个 `{"key": ..., "value": ...}` JSON 对象，只支持 UTF-8 的 key 和 value
+    Json,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonEntry<'a> {
+    key: std::borrow::Cow<'a, str>,
+    value: std::borrow::Cow<'a, str>,
+}
+
+/// 将 engine 中所有 live kv 按 key 的顺序写入 w，返回写入的 kv 数量。
+/// 边扫描边写入，不会把整个数据库读入内存；value 按存储的原始字节写出
+pub fn export<E: Engine>(engine: &mut E, w: &mut impl Write, format: DumpFormat) -> CResult<u64> {
+    let mut count = 0;
+    for item in engine.scan(..) {
+        let (key, value) = item?;
+        match format {
+            DumpFormat::Binary => {
+                w.write_all(&u32::try_from(key.len())?.to_be_bytes())?;
+                w.write_all(&u32::try_from(value.len())?.to_be_bytes())?;
+                w.write_all(&key)?;
+                w.write_all(&value)?;
+            }
+            DumpFormat::Json => {
+                let (Ok(key), Ok(value)) = (std::str::from_utf8(&key), std::str::from_utf8(&value)) else {
+                    return Err(Error::Value(format!(
+                        "key {} or its value is not valid UTF-8, use the binary format instead",
+                        String::from_utf8_lossy(&key)
+                    )));
+                };
+                let entry = JsonEntry { key: key.into(), value: value.into() };
+                serde_json::to_writer(&mut *w, &entry).map_err(|err| Error::Value(err.to_string()))?;
+                w.write_all(b"\n")?;
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 读取 [`export`] 写入的备份，逐个 set 到 engine，返回导入的 kv 数量，已存在的 key 会被覆盖。
+/// 两种格式按第一个字节自动识别：以 `{` 开头的为 JSON，否则为二进制
+pub fn import<E: Engine + ?Sized>(engine: &mut E, r: &mut impl BufRead) -> CResult<u64> {
+    if r.fill_buf()?.first() == Some(&b'{') {
+        return import_json(engine, r);
+    }
+
+    let mut count = 0;
+    let mut lens = [0u8; 8];
+    loop {
+        // 在 entry 的边界处读到文件末尾表示导入完成，entry 中途结束说明文件不完整
+        match r.read(&mut lens[..1])? {
+            0 => return Ok(count),
+            _ => r.read_exact(&mut lens[1..]).map_err(|err| truncated(count, err))?,
+        }
+        let key = read_field(r, u32::from_be_bytes(lens[..4].try_into()?), count)?;
+        let value = read_field(r, u32::from_be_bytes(lens[4..].try_into()?), count)?;
+        engine.set(&key, value)?;
+        count += 1;
+    }
+}
+
+fn import_json<E: Engine + ?Sized>(engine: &mut E, r: &mut impl BufRead) -> CResult<u64> {
+    let mut count = 0;
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: JsonEntry = serde_json::from_str(&line)
+            .map_err(|err| Error::Parse(format!("invalid dump entry at line {}: {}", i + 1, err)))?;
+        engine.set(entry.key.as_bytes(), entry.value.into_owned().into_bytes())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 读取长度为 len 的 key 或 value。按实际读到的字节增长缓冲，剩余字节不足时返回 truncated 错误
+fn read_field(r: &mut impl BufRead, len: u32, count: u64) -> CResult<Vec<u8>> {
+    let len = u64::from(len);
+    if len > MAX_FIELD_LEN {
+        return Err(Error::Corruption(format!(
+            "dump entry {} has a field of {} bytes, more than the maximum {}", count + 1, len, MAX_FIELD_LEN
+        )));
+    }
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(truncated(count, std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(buf)
+}
+
+fn truncated(count: u64, err: std::io::Error) -> Error {
+    Error::Corruption(format!("dump truncated after {} entries: {}", count, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::Memory;
+
+    fn populated() -> CResult<Memory> {
+        let mut engine = Memory::new();
+        engine.set(b"a", b"1".to_vec())?;
+        engine.set(b"b", vec![])?;
+        engine.set(b"c", b"line\nbreak \"quoted\"".to_vec())?;
+        engine.set(b"d", b"deleted".to_vec())?;
+        engine.delete(b"d")?;
+        Ok(engine)
+    }
+
+    fn entries(engine: &mut Memory) -> CResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        engine.scan(..).collect()
+    }
+
+    #[test]
+    fn roundtrip() -> CResult<()> {
+        for format in [DumpFormat::Binary, DumpFormat::Json] {
+            let mut source = populated()?;
+            let mut dump = Vec::new();
+            assert_eq!(export(&mut source, &mut dump, format)?, 3);
+
+            let mut restored = Memory::new();
+            assert_eq!(import(&mut restored, &mut dump.as_slice())?, 3);
+            assert_eq!(entries(&mut restored)?, entries(&mut source)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn binary_values() -> CResult<()> {
+        let mut source = Memory::new();
+        source.set(&[0xff, 0x00], vec![0x80, 0x81])?;
+
+        let mut dump = Vec::new();
+        export(&mut source, &mut dump, DumpFormat::Binary)?;
+        assert_eq!(dump, [0, 0, 0, 2, 0, 0, 0, 2, 0xff, 0x00, 0x80, 0x81]);
+        assert!(export(&mut source, &mut Vec::new(), DumpFormat::Json).is_err());
+
+        let mut restored = Memory::new();
+        import(&mut restored, &mut dump.as_slice())?;
+        assert_eq!(restored.get(&[0xff, 0x00])?, Some(vec![0x80, 0x81]));
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_dump() -> CResult<()> {
+        let mut dump = Vec::new();
+        export(&mut populated()?, &mut dump, DumpFormat::Binary)?;
+        dump.pop();
+
+        let mut restored = Memory::new();
+        assert!(matches!(import(&mut restored, &mut dump.as_slice()), Err(Error::Corruption(_))));
+        assert_eq!(import(&mut Memory::new(), &mut &b""[..])?, 0);
+
+        // a corrupt length prefix is rejected before any allocation
+        let dump = [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1, b'k'];
+        assert!(matches!(import(&mut restored, &mut &dump[..]), Err(Error::Corruption(_))));
+        Ok(())
+    }
+}
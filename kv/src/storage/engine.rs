@@ -1,17 +1,42 @@
-use crate::error::CResult;
-use crate::storage::{ScanIteratorT, Status};
+use crate::error::{CResult, Error};
+use crate::storage::{ScanCursor, ScanIteratorT, ScanPage, Status};
+
+/// [`Engine::merge`] 使用的合并函数：参数为 key 当前的 value(不存在时为 None)和 operand，返回合并后的 value
+pub type MergeOp = dyn Fn(Option<&[u8]>, &[u8]) -> Vec<u8> + Send + Sync;
+
+/// 以 prefix 开头的所有key构成的范围。prefix 全为 0xff(或为空)时没有上界
+pub fn prefix_range(prefix: &[u8]) -> (std::ops::Bound<Vec<u8>>, std::ops::Bound<Vec<u8>>) {
+    let start = std::ops::Bound::Included(prefix.to_vec());
+    let end = match prefix.iter().rposition(|b| *b != 0xff) {
+        Some(i) => std::ops::Bound::Excluded(
+            prefix.iter().take(i).copied().chain(std::iter::once(prefix[i] + 1)).collect(),
+        ),
+        None => std::ops::Bound::Unbounded,
+    };
+    (start, end)
+}
 
 /// A key/value storage engine, where both keys and values are arbitrary byte strings between 0 B and 2 GB, stored in lexicographical key order.
 /// Writes are only guaranteed durable after calling flush().
 ///
-/// Only supports single-threaded use since all methods (including reads) take a mutable reference -- serialized access can't be avoided anyway,
-/// since both 'Raft' execution and file access is serial.
+/// get() and scan() only take a shared reference, so an engine behind an `Arc` can serve several readers concurrently --
+/// implementations must read without mutating shared state (e.g. via positional file reads). Writes and the remaining
+/// methods take a mutable reference and require serialized access.
 pub trait Engine: std::fmt::Display + Send + Sync {
     /// The iterator returned by scan().
     type ScanIterator<'a>: ScanIteratorT + 'a
         where
             Self: Sized + 'a; // omit in trait objects, for object safety
 
+    /// 在 key 的 value 末尾追加 suffix，返回追加后 value 的长度。key 不存在时等同于 set(key, suffix)。
+    fn append(&mut self, key: &[u8], suffix: &[u8]) -> CResult<u64> {
+        let mut value = self.get(key)?.unwrap_or_default();
+        value.extend_from_slice(suffix);
+        let len = value.len() as u64;
+        self.set(key, value)?;
+        Ok(len)
+    }
+
     /// Deletes a key, or does nothing if it does not exist.
     fn delete(&mut self, key: &[u8]) -> CResult<i64>;
 
@@ -19,32 +44,135 @@ pub trait Engine: std::fmt::Display + Send + Sync {
     fn flush(&mut self) -> CResult<()>;
 
     /// Gets a value for a key, if it exists.
-    fn get(&mut self, key: &[u8]) -> CResult<Option<Vec<u8>>>;
+    fn get(&self, key: &[u8]) -> CResult<Option<Vec<u8>>>;
+
+    /// key 是否存在(已删除或已过期的key视为不存在)。默认通过 get 判断，读取失败视为不存在；
+    /// 有内存索引的引擎应覆盖为只查询索引，不读取 value
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).map(|value| value.is_some()).unwrap_or(false)
+    }
+
+    /// 批量读取，返回的 value 与 keys 的顺序一一对应，不存在的key为 None
+    fn get_many(&mut self, keys: &[&[u8]]) -> CResult<Vec<Option<Vec<u8>>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// 同 get，同时返回 value 的写入时间(unix millis)。不记录写入时间的引擎返回错误
+    fn get_with_meta(&mut self, _key: &[u8]) -> CResult<Option<(Vec<u8>, u64)>> {
+        Err(Error::Value(format!("{} does not record write times", self)))
+    }
+
+    /// 按顺序写入一组 kv，value 为 None 表示删除该key。
+    /// 默认实现逐个调用 set/delete，不保证原子性；LogCask 将整组作为一次写入，崩溃后要么全部生效，要么全部不生效
+    fn write_batch(&mut self, ops: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> CResult<()> {
+        for (key, value) in ops {
+            match value {
+                Some(value) => self.set(&key, value)?,
+                None => {
+                    self.delete(&key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 写入 kv，并在 ttl 之后过期：过期的key不再被 get 和 scan 返回，compact 时被丢弃。
+    /// 之后不带 ttl 的 set 会清除过期时间。不支持过期的引擎返回错误
+    fn set_ex(&mut self, _key: &[u8], _value: Vec<u8>, _ttl: std::time::Duration) -> CResult<()> {
+        Err(Error::Value(format!("{} does not support expiry", self)))
+    }
+
+    /// 读-改-写：在同一个 `&mut self` 下读取 key 当前的 value，与 operand 经 op 合并后写回，返回合并后的 value。
+    /// 调用方无需先 get 再 set，INCR、APPEND 等都可以表示为合并函数。
+    fn merge(&mut self, key: &[u8], operand: &[u8], op: &MergeOp) -> CResult<Vec<u8>> {
+        let value = op(self.get(key)?.as_deref(), operand);
+        self.set(key, value.clone())?;
+        Ok(value)
+    }
+
+    /// 将 key 的 value 作为十进制 i64 文本加上 delta 并写回，返回相加后的值。key 不存在时视为 0。
+    /// value 不是整数或相加溢出时返回错误，value 保持不变。读取和写入在同一个 `&mut self` 下完成
+    fn incr_by(&mut self, key: &[u8], delta: i64) -> CResult<i64> {
+        let current = match self.get(key)? {
+            Some(value) => std::str::from_utf8(&value)
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or_else(|| Error::Value("value is not an integer".to_string()))?,
+            None => 0,
+        };
+        let value = current
+            .checked_add(delta)
+            .ok_or_else(|| Error::Value("increment or decrement would overflow".to_string()))?;
+        self.set(key, value.to_string().into_bytes())?;
+        Ok(value)
+    }
 
     /// Iterates over an ordered range of key/value pairs.
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_>
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_>
         where Self: Sized; // omit in trait objects, for object safety
 
     /// Like scan, but can be used from trait objects. The iterator will use
     /// dynamic dispatch, which has a minor performance penalty.
     fn scan_dyn(
-        &mut self,
+        &self,
         range: (std::ops::Bound<Vec<u8>>, std::ops::Bound<Vec<u8>>),
     ) -> Box<dyn ScanIteratorT + '_>;
 
+    /// 按顺序迭代 range 范围内的 key，不读取 value。默认实现基于 scan；LogCask 只遍历内存中的 keydir，没有磁盘 IO
+    fn scan_keys(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> impl Iterator<Item = CResult<Vec<u8>>> + '_
+        where
+            Self: Sized, // omit in trait objects, for object safety
+    {
+        self.scan(range).map(|item| item.map(|(key, _)| key))
+    }
+
     /// Iterates over all key/value pairs starting with prefix.
-    fn scan_prefix(&mut self, prefix: &[u8]) -> Self::ScanIterator<'_>
+    fn scan_prefix(&self, prefix: &[u8]) -> Self::ScanIterator<'_>
+        where
+            Self: Sized, // omit in trait objects, for object safety
+    {
+        self.scan(prefix_range(prefix))
+    }
+
+    /// 删除 range 范围内的所有key，返回删除的key数量
+    fn delete_range(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> CResult<u64>
         where
             Self: Sized, // omit in trait objects, for object safety
     {
-        let start = std::ops::Bound::Included(prefix.to_vec());
-        let end = match prefix.iter().rposition(|b| *b != 0xff) {
-            Some(i) => std::ops::Bound::Excluded(
-                prefix.iter().take(i).copied().chain(std::iter::once(prefix[i] + 1)).collect(),
-            ),
-            None => std::ops::Bound::Unbounded,
+        let keys = self.scan_keys(range).collect::<CResult<Vec<_>>>()?;
+        for key in &keys {
+            self.delete(key)?;
+        }
+        Ok(keys.len() as u64)
+    }
+
+    /// 删除所有以 prefix 开头的key，返回删除的key数量
+    fn delete_prefix(&mut self, prefix: &[u8]) -> CResult<u64>
+        where
+            Self: Sized, // omit in trait objects, for object safety
+    {
+        self.delete_range(prefix_range(prefix))
+    }
+
+    /// 分页扫描：从游标处开始最多返回 limit 个key/value，以及下一页的游标(没有更多数据时为 None)。
+    /// 下一页严格从本页最后一个key之后开始，一致性保证见 [`ScanCursor`]。
+    fn scan_from(
+        &mut self,
+        cursor: &ScanCursor,
+        limit: usize,
+    ) -> CResult<ScanPage>
+        where
+            Self: Sized, // omit in trait objects, for object safety
+    {
+        let mut iter = self.scan((cursor.lower_bound(), std::ops::Bound::Unbounded));
+        let page = iter.by_ref().take(limit).collect::<CResult<Vec<_>>>()?;
+        let has_more = iter.next().is_some();
+
+        let next = match page.last() {
+            Some((key, _)) if has_more => Some(ScanCursor::after(key.clone())),
+            _ => None,
         };
-        self.scan((start, end))
+        Ok((page, next))
     }
 
     /// Sets a value for a key, replacing the existing value if any.
@@ -52,6 +180,109 @@ pub trait Engine: std::fmt::Display + Send + Sync {
 
     /// Returns engine status.
     fn status(&mut self) -> CResult<Status>;
+
+    /// 交换两个key的value，在同一个 `&mut self` 下先读取两个value，再分别写入对方的key。
+    /// 不存在的key视为缺失：如果只有一个key存在，其value移动到另一个key，原key被删除；两个key都不存在时不做任何操作。
+    ///
+    /// 交换对并发访问是原子的，但不保证崩溃时的原子性：两次写入之间崩溃，可能只写入了其中一个。
+    fn swap(&mut self, key1: &[u8], key2: &[u8]) -> CResult<()> {
+        if key1 == key2 {
+            return Ok(());
+        }
+        let value1 = self.get(key1)?;
+        let value2 = self.get(key2)?;
+        if value1.is_none() && value2.is_none() {
+            return Ok(());
+        }
+
+        for (key, value) in [(key1, value2), (key2, value1)] {
+            match value {
+                Some(value) => self.set(key, value)?,
+                None => {
+                    self.delete(key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 将 from 的 value 移动到 to，并删除 from。from 不存在时返回错误；overwrite 为 false 且 to 已存在时返回错误，不做任何修改。
+    /// from 与 to 相同时，只检查 from 存在(以及 overwrite 为 false 时报告 to 已存在)。
+    ///
+    /// 与 swap 一样，对并发访问是原子的，但不保证崩溃时的原子性：写入 to 之后、删除 from 之前崩溃，两个key都会保留该 value。
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> CResult<()> {
+        let Some(value) = self.get(from)? else {
+            return Err(Error::Value(format!("key {} does not exist", String::from_utf8_lossy(from))));
+        };
+        if !overwrite && self.get(to)?.is_some() {
+            return Err(Error::Value(format!("key {} already exists", String::from_utf8_lossy(to))));
+        }
+        if from == to {
+            return Ok(());
+        }
+        self.set(to, value)?;
+        self.delete(from)?;
+        Ok(())
+    }
+
+    /// 将所有存活的 key/value 拷贝到另一个引擎中，返回拷贝的key数量。
+    /// dest 中已有的同名key会被覆盖；overwrite 为 true 时，dest 中源引擎不存在的key也会被删除，使 dest 与源引擎的 keyspace 一致，否则保留(合并)。
+    /// 写入约每 4 MiB 一批，通过 write_batch 进行
+    fn copy_into<D: Engine>(&mut self, dest: &mut D, overwrite: bool) -> CResult<u64>
+        where
+            Self: Sized, // omit in trait objects, for object safety
+    {
+        let mut copied = std::collections::BTreeSet::new();
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        for item in self.scan(..) {
+            let (key, value) = item?;
+            batch_bytes += key.len() + value.len();
+            copied.insert(key.clone());
+            batch.push((key, Some(value)));
+            if batch_bytes >= COPY_BATCH_BYTES {
+                write_chunk(dest, std::mem::take(&mut batch))?;
+                batch_bytes = 0;
+            }
+        }
+        write_chunk(dest, batch)?;
+
+        if overwrite {
+            let stale = dest
+                .scan_keys(..)
+                .filter(|key| !matches!(key, Ok(key) if copied.contains(key)))
+                .map(|key| key.map(|key| (key, None)))
+                .collect::<CResult<Vec<_>>>()?;
+            for chunk in stale.chunks(COPY_BATCH_KEYS) {
+                write_chunk(dest, chunk.to_vec())?;
+            }
+        }
+        dest.flush()?;
+        Ok(copied.len() as u64)
+    }
+}
+
+/// copy_into 每批写入的 key/value 字节数上限(超过后开始下一批)
+const COPY_BATCH_BYTES: usize = 4 << 20;
+
+/// copy_into 删除 dest 中多余的key时每批的key数量
+const COPY_BATCH_KEYS: usize = 1024;
+
+/// 通过 write_batch 写入一批。write_batch 失败时不会生效任何 entry(如 V1 格式的 LogCask 不支持批量写入)，此时改为逐个写入，
+/// 真正的写入错误会在逐个写入时再次返回
+fn write_chunk<D: Engine>(dest: &mut D, batch: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> CResult<()> {
+    if batch.is_empty() || dest.write_batch(batch.clone()).is_ok() {
+        return Ok(());
+    }
+    for (key, value) in batch {
+        match value {
+            Some(value) => dest.set(&key, value)?,
+            None => {
+                dest.delete(&key)?;
+            }
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -0,0 +1,164 @@
+/// 按字节匹配的 glob 模式，用于 KEYS 等按模式过滤 key 的场景：
+///
+/// - `*` 匹配任意长度(包括 0)的字节序列
+/// - `?` 匹配任意单个字节(多字节的 UTF-8 字符需要对应数量的 `?`)
+/// - `[abc]`、`[a-z]` 匹配其中的任一字节，`[^a]` 或 `[!a]` 匹配不在其中的字节；缺少 `]` 时 `[` 按普通字节处理
+/// - `\x` 匹配字节 x 本身，用于转义以上特殊字符
+///
+/// key 不要求是合法的 UTF-8。
+pub fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // 最近一个 `*` 在 pattern 中的位置，以及它当前匹配到的 key 的结束位置，失配时回溯到这里让 `*` 多匹配一个字节
+    let mut star: Option<(usize, usize)> = None;
+
+    while k < key.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, k));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(1),
+            Some(b'[') => match match_class(&pattern[p..], key[k]) {
+                Some((matched, len)) => matched.then_some(len),
+                None => (key[k] == b'[').then_some(1),
+            },
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == key[k]).then_some(2),
+            Some(&c) => (c == key[k]).then_some(1),
+            None => None,
+        };
+
+        match (step, star) {
+            (Some(len), _) => {
+                p += len;
+                k += 1;
+            }
+            (None, Some((star_p, star_k))) => {
+                p = star_p + 1;
+                k = star_k + 1;
+                star = Some((star_p, k));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// pattern 中第一个通配符之前的字面前缀(已去掉转义)，匹配 pattern 的 key 一定以它开头，可用于 scan_prefix 缩小扫描范围
+pub fn glob_literal_prefix(pattern: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'*' | b'?' | b'[' => break,
+            b'\\' if i + 1 < pattern.len() => {
+                prefix.push(pattern[i + 1]);
+                i += 2;
+            }
+            c => {
+                prefix.push(c);
+                i += 1;
+            }
+        }
+    }
+    prefix
+}
+
+/// 匹配以 `[` 开头的字符类，返回是否匹配以及字符类在 pattern 中的长度(包括两端的括号)，缺少 `]` 时返回 None。
+/// 紧跟在 `[`(或 `[^`)之后的 `]` 作为普通字节
+fn match_class(class: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(class.get(i), Some(b'^' | b'!'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut lo = *class.get(i)?;
+        if lo == b']' && !first {
+            break;
+        }
+        if lo == b'\\' {
+            i += 1;
+            lo = *class.get(i)?;
+        }
+        let hi = match (class.get(i + 1), class.get(i + 2)) {
+            (Some(b'-'), Some(&hi)) if hi != b']' => {
+                i += 2;
+                hi
+            }
+            _ => lo,
+        };
+        matched |= lo <= c && c <= hi;
+        first = false;
+        i += 1;
+    }
+
+    Some((matched != negate, i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"user:*", b"user:"));
+        assert!(glob_match(b"user:*", b"user:123"));
+        assert!(!glob_match(b"user:*", b"order:1"));
+        assert!(glob_match(b"user:?", b"user:1"));
+        assert!(!glob_match(b"user:?", b"user:12"));
+        assert!(!glob_match(b"user:?", b"user:"));
+        assert!(glob_match(b"*:*:end", b"a:b:c:end"));
+        assert!(!glob_match(b"*:*:end", b"a:end"));
+        assert!(glob_match(b"a*b*c", b"aXbYbZc"));
+        assert!(glob_match(b"user", b"user"));
+        assert!(!glob_match(b"user", b"users"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(glob_match(b"k[abc]", b"kb"));
+        assert!(!glob_match(b"k[abc]", b"kd"));
+        assert!(glob_match(b"k[0-9][0-9]", b"k42"));
+        assert!(!glob_match(b"k[0-9]", b"kx"));
+        assert!(glob_match(b"k[^0-9]", b"kx"));
+        assert!(glob_match(b"k[!0-9]", b"kx"));
+        assert!(!glob_match(b"k[^0-9]", b"k1"));
+        assert!(glob_match(b"k[]x]", b"k]"));
+        assert!(glob_match(b"k[a-]", b"k-"));
+
+        // an unterminated class is a literal '['
+        assert!(glob_match(b"k[ab", b"k[ab"));
+        assert!(!glob_match(b"k[ab", b"ka"));
+    }
+
+    #[test]
+    fn escapes_and_bytes() {
+        assert!(glob_match(br"a\*b", b"a*b"));
+        assert!(!glob_match(br"a\*b", b"axb"));
+        assert!(glob_match(br"a\?", b"a?"));
+        assert!(glob_match(br"k[\]]", b"k]"));
+
+        // keys are matched bytewise
+        assert!(glob_match(b"bin:*", &[b'b', b'i', b'n', b':', 0xff, 0x00]));
+        assert!(glob_match(b"bin:??", &[b'b', b'i', b'n', b':', 0xff, 0x00]));
+        assert!(glob_match("é?".as_bytes(), "éa".as_bytes()));
+        assert!(glob_match("??".as_bytes(), "é".as_bytes()));
+    }
+
+    #[test]
+    fn literal_prefix() {
+        assert_eq!(glob_literal_prefix(b"user:*"), b"user:");
+        assert_eq!(glob_literal_prefix(b"user:?1"), b"user:");
+        assert_eq!(glob_literal_prefix(b"k[ab]*"), b"k");
+        assert_eq!(glob_literal_prefix(br"a\*b*"), b"a*b");
+        assert_eq!(glob_literal_prefix(b"*"), b"");
+        assert_eq!(glob_literal_prefix(b"exact"), b"exact");
+    }
+}
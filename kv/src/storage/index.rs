@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeBounds;
+use crate::storage::KeyDirEntry;
+
+/// 内存索引(keydir)的抽象，LogCask 对其泛型化，以便按负载选择不同的实现。
+///
+/// - `BTreeMap`(即 [`crate::storage::KeyDir`]): 有序存储，范围扫描时无需额外开销，是默认实现。
+/// - `HashMap`(即 [`HashKeyDir`]): 点查更快，但无序，范围扫描时需要按需收集并排序，代价为 O(n log n)。
+pub trait Index: Default + Clone + Send + Sync {
+    /// The ordered iterator returned by range().
+    type Range<'a>: DoubleEndedIterator<Item = (&'a Vec<u8>, &'a KeyDirEntry)>
+        where
+            Self: 'a;
+
+    /// The name of the index implementation.
+    fn name() -> &'static str;
+
+    /// Gets the entry for a key, if it exists.
+    fn get(&self, key: &[u8]) -> Option<&KeyDirEntry>;
+
+    /// Inserts an entry for a key, returning the replaced entry if any.
+    fn insert(&mut self, key: Vec<u8>, entry: KeyDirEntry) -> Option<KeyDirEntry>;
+
+    /// Removes a key, returning its entry if it existed.
+    fn remove(&mut self, key: &[u8]) -> Option<KeyDirEntry>;
+
+    /// Returns the number of keys in the index.
+    fn len(&self) -> usize;
+
+    /// Returns true if the index holds no keys.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over all entries in an unspecified order.
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &KeyDirEntry)> + '_>;
+
+    /// Iterates over the entries within the range, in lexicographical key order.
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Self::Range<'_>;
+}
+
+/// A point-lookup optimized keydir. Scans sort the matching keys on demand.
+pub type HashKeyDir = HashMap<Vec<u8>, KeyDirEntry>;
+
+impl Index for BTreeMap<Vec<u8>, KeyDirEntry> {
+    type Range<'a> = std::collections::btree_map::Range<'a, Vec<u8>, KeyDirEntry>;
+
+    fn name() -> &'static str {
+        "btree"
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&KeyDirEntry> {
+        BTreeMap::get(self, key)
+    }
+
+    fn insert(&mut self, key: Vec<u8>, entry: KeyDirEntry) -> Option<KeyDirEntry> {
+        BTreeMap::insert(self, key, entry)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<KeyDirEntry> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &KeyDirEntry)> + '_> {
+        Box::new(BTreeMap::iter(self))
+    }
+
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Self::Range<'_> {
+        BTreeMap::range(self, range)
+    }
+}
+
+impl Index for HashMap<Vec<u8>, KeyDirEntry> {
+    type Range<'a> = std::vec::IntoIter<(&'a Vec<u8>, &'a KeyDirEntry)>;
+
+    fn name() -> &'static str {
+        "hash"
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&KeyDirEntry> {
+        HashMap::get(self, key)
+    }
+
+    fn insert(&mut self, key: Vec<u8>, entry: KeyDirEntry) -> Option<KeyDirEntry> {
+        HashMap::insert(self, key, entry)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<KeyDirEntry> {
+        HashMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &KeyDirEntry)> + '_> {
+        Box::new(HashMap::iter(self))
+    }
+
+    /// HashMap 无序，因此先过滤出范围内的key，再排序。
+    fn range<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Self::Range<'_> {
+        let mut items: Vec<_> = HashMap::iter(self).filter(|(key, _)| range.contains(*key)).collect();
+        items.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        items.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::*;
+
+    fn fill<I: Index>() -> I {
+        let mut index = I::default();
+        for (i, key) in [b"c".to_vec(), b"a".to_vec(), b"d".to_vec(), b"b".to_vec()].into_iter().enumerate() {
+            index.insert(key, (0, i as u64, 1));
+        }
+        index
+    }
+
+    #[test]
+    fn range_is_ordered() {
+        let btree: BTreeMap<Vec<u8>, KeyDirEntry> = fill();
+        let hash: HashKeyDir = fill();
+
+        let expect: Vec<_> = btree.range(b"b".to_vec()..).map(|(k, v)| (k.clone(), *v)).collect();
+        let actual: Vec<_> = Index::range(&hash, b"b".to_vec()..).map(|(k, v)| (k.clone(), *v)).collect();
+        assert_eq!(expect, actual);
+
+        let actual: Vec<_> = Index::range(&hash, ..).rev().map(|(k, _)| k.clone()).collect();
+        assert_eq!(actual, vec![b"d".to_vec(), b"c".to_vec(), b"b".to_vec(), b"a".to_vec()]);
+    }
+
+    #[test]
+    fn point_ops() {
+        let mut hash: HashKeyDir = fill();
+        assert_eq!(Index::get(&hash, b"a"), Some(&(0, 1, 1)));
+        assert_eq!(Index::remove(&mut hash, b"a"), Some((0, 1, 1)));
+        assert_eq!(Index::get(&hash, b"a"), None);
+        assert_eq!(Index::len(&hash), 3);
+    }
+}
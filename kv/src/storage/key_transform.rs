@@ -0,0 +1,59 @@
+use std::borrow::Cow;
+#[cfg(feature = "sha256-key")]
+use sha2::Digest;
+
+/// LogCask 在 set/get/delete 时对 key 做的变换：调用方使用逻辑 key，日志和 keydir 中保存变换后的 key。
+///
+/// 变换只作用于单 key 的读写，scan 等遍历接口返回的是变换后的 key(如 `Sha256Hex` 下为摘要)，无法还原出逻辑 key。
+/// 同一份数据需始终使用相同的变换打开，否则已有的 key 将无法被查到。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyTransform {
+    /// 不做变换
+    #[default]
+    Identity,
+
+    /// ASCII 字母转为小写，使 key 大小写不敏感
+    LowerAscii,
+
+    /// 替换为 key 的 SHA-256 摘要的小写 hex 形式，固定为 64 字节，需开启 feature `sha256-key`
+    #[cfg(feature = "sha256-key")]
+    Sha256Hex,
+}
+
+impl KeyTransform {
+    /// 返回存储时使用的 key
+    pub fn apply<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            KeyTransform::Identity => Cow::Borrowed(key),
+            KeyTransform::LowerAscii if !key.iter().any(u8::is_ascii_uppercase) => Cow::Borrowed(key),
+            KeyTransform::LowerAscii => Cow::Owned(key.to_ascii_lowercase()),
+            #[cfg(feature = "sha256-key")]
+            KeyTransform::Sha256Hex => Cow::Owned(hex::encode(sha2::Sha256::digest(key)).into_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "sha256-key")]
+    fn sha256_vectors() {
+        let sha256 = |data: &[u8]| String::from_utf8(KeyTransform::Sha256Hex.apply(data).into_owned()).unwrap();
+        assert_eq!(sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // two blocks after padding
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn apply() {
+        assert_eq!(KeyTransform::Identity.apply(b"Foo"), Cow::Borrowed(&b"Foo"[..]));
+        assert_eq!(KeyTransform::LowerAscii.apply(b"Foo:BAR"), Cow::<[u8]>::Owned(b"foo:bar".to_vec()));
+        assert!(matches!(KeyTransform::LowerAscii.apply(b"foo"), Cow::Borrowed(_)));
+    }
+}
@@ -1,8 +1,12 @@
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::borrow::Cow;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use fs4::FileExt;
 use crate::error::{CResult, Error};
-use crate::storage::KeyDir;
+use crate::storage::index::Index;
+use crate::storage::{DeltaChains, Expiries, KeyDir};
 
 /// 一个仅追加的日志文件，包含如下要素；
 ///
@@ -10,11 +14,418 @@ use crate::storage::KeyDir;
 /// - Value length as big-endian i32, or -1 for tombstones.
 /// - Key as raw bytes (max 2 GB).
 /// - Value as raw bytes (max 2 GB).
+///
+/// 以上为 [`LogFormat::V1`] 的格式。[`LogFormat::V2`] 在文件头写入 magic 和版本号，每个 entry 带有显式的类型字节，见 [`EntryType`]。
+/// [`LogFormat::V3`] 在 V2 的基础上为每个 entry 追加 CRC32 校验和，见 [`Log::new_with_checksum`]。[`LogFormat::V4`] 再记录每个 entry 的写入时间。
 pub struct Log {
     /// Path to the log file.
     pub(crate) path: PathBuf,
     /// The opened file containing the log.
     pub(crate) file: std::fs::File,
+    /// 读写字节计数，compact 时在新旧日志间共享，因此为自打开以来的累计值
+    pub(crate) io_stats: Arc<IoStats>,
+    /// 日志文件的格式版本
+    pub(crate) format: LogFormat,
+    /// value 的编码，见 [`ValueCodec`]
+    pub(crate) codec: ValueCodec,
+    /// 跨多次写入的写缓冲，默认关闭
+    write_buffer: WriteBuffer,
+    /// 以只读方式打开：不写入文件头，加载索引时遇到不完整的 entry 只停止扫描，不截断文件
+    read_only: bool,
+}
+
+/// 跨多次写入的写缓冲，见 [`Log::set_write_buffer`]
+#[derive(Debug)]
+struct WriteBuffer {
+    /// 缓冲的字节数上限，0 表示不缓冲，每个 entry 直接写入文件
+    capacity: usize,
+    /// 尚未写入文件的 entry
+    pending: Vec<u8>,
+    /// pending 的第一个字节在文件中的位置，即已写入文件的长度
+    flushed_len: u64,
+    /// 读取的 value 尚在缓冲中时，是否先将缓冲写入文件。关闭时这样的读取会失败
+    read_your_writes: bool,
+}
+
+impl Default for WriteBuffer {
+    fn default() -> Self {
+        WriteBuffer { capacity: 0, pending: Vec::new(), flushed_len: 0, read_your_writes: true }
+    }
+}
+
+/// V2 日志文件头的 magic。首字节为 0xFF，作为 V1 的 key_len 时超过了 2GB 的上限，因此不会与 V1 的文件混淆。
+const LOG_MAGIC: [u8; 4] = [0xFF, b'K', b'V', b'L'];
+
+/// V2 日志文件头的长度: magic(4) + version u16 + flags u16
+const LOG_HEADER_LEN: u64 = 8;
+
+/// 文件头 flags 的第 0 位：每个 value 前带有一个字节的编码标记，见 [`ValueCodec`]
+const FLAG_VALUE_CODEC: u16 = 1;
+
+/// 编码标记：value 按原样存储
+const TAG_RAW: u8 = 0;
+
+/// 编码标记：之后为 value 原长度(big-endian u32)以及 deflate 压缩后的字节
+const TAG_DEFLATE: u8 = 1;
+
+/// 日志中 value 的编码，记录在 V2 及以上格式的文件头 flags 中
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValueCodec {
+    /// value 按原样存储
+    #[default]
+    None,
+
+    /// 每个 value 前带有一个字节的编码标记，写入时以 level(0-10) 进行 deflate 压缩，压缩后没有变小的 value 按原样存储。
+    /// 标记使压缩和未压缩的 value 可以在同一个日志中共存
+    Deflate(u8),
+}
+
+impl ValueCodec {
+    /// 打开已有的压缩日志且未指定 level 时使用的压缩级别
+    pub const DEFAULT_LEVEL: u8 = 6;
+
+    fn encode<'a>(&self, value: &'a [u8]) -> Cow<'a, [u8]> {
+        let ValueCodec::Deflate(level) = *self else {
+            return Cow::Borrowed(value);
+        };
+        let compressed = miniz_oxide::deflate::compress_to_vec(value, level);
+        let mut encoded = Vec::with_capacity(value.len().min(compressed.len() + 4) + 1);
+        if compressed.len() + 4 < value.len() {
+            encoded.push(TAG_DEFLATE);
+            encoded.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(&compressed);
+        } else {
+            encoded.push(TAG_RAW);
+            encoded.extend_from_slice(value);
+        }
+        Cow::Owned(encoded)
+    }
+
+    fn decode(&self, mut encoded: Vec<u8>) -> CResult<Vec<u8>> {
+        if *self == ValueCodec::None {
+            return Ok(encoded);
+        }
+        match encoded.first() {
+            Some(&TAG_RAW) => {
+                encoded.remove(0);
+                Ok(encoded)
+            }
+            Some(&TAG_DEFLATE) if encoded.len() >= 5 => {
+                let len = u32::from_be_bytes(encoded[1..5].try_into()?) as usize;
+                let value = miniz_oxide::inflate::decompress_to_vec_with_limit(&encoded[5..], len)
+                    .map_err(|err| Error::Corruption(format!("failed to decompress value: {:?}", err.status)))?;
+                if value.len() != len {
+                    return Err(Error::Corruption(format!("decompressed {} bytes, expected {}", value.len(), len)));
+                }
+                Ok(value)
+            }
+            tag => Err(Error::Corruption(format!("unknown value codec tag {:?}", tag))),
+        }
+    }
+}
+
+/// 日志文件的格式版本
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// 无文件头，value_len 为 -1 表示 tombstone
+    #[default]
+    V1,
+
+    /// 文件头 + 带类型字节的 entry:
+    ///
+    /// - Entry type as u8.
+    /// - Key length as big-endian u32.
+    /// - Value length as big-endian u32.
+    /// - Expire time as big-endian u64 (unix millis), only for SetWithTtl.
+    /// - Key as raw bytes (max 2 GB).
+    /// - Value as raw bytes (max 2 GB).
+    V2,
+
+    /// 同 V2，每个 entry 末尾追加 key+value 的 CRC32(big-endian u32)，tombstone 只对 key 计算。
+    /// 打开日志和读取 value 时校验，不一致时返回 [`Error::Corruption`]
+    V3,
+
+    /// 同 V3，key 之前(Expire time 之后)记录 entry 的写入时间，big-endian u64 (unix millis)，见 [`Log::read_written_at`]
+    V4,
+}
+
+impl LogFormat {
+    fn version(&self) -> u16 {
+        match self {
+            LogFormat::V1 => 1,
+            LogFormat::V2 => 2,
+            LogFormat::V3 => 3,
+            LogFormat::V4 => 4,
+        }
+    }
+
+    /// 文件头的长度
+    pub fn file_header_len(&self) -> u64 {
+        match self {
+            LogFormat::V1 => 0,
+            LogFormat::V2 | LogFormat::V3 | LogFormat::V4 => LOG_HEADER_LEN,
+        }
+    }
+
+    /// 一个 Set entry 除 key 和 value 以外的固定开销
+    pub fn entry_overhead(&self) -> u64 {
+        match self {
+            LogFormat::V1 => 4 + 4,
+            LogFormat::V2 => 1 + 4 + 4,
+            LogFormat::V3 => 1 + 4 + 4 + 4,
+            LogFormat::V4 => 1 + 4 + 4 + 8 + 4,
+        }
+    }
+
+    /// entry 末尾校验和的长度，没有校验和时为 0
+    pub fn checksum_len(&self) -> u64 {
+        match self {
+            LogFormat::V3 | LogFormat::V4 => 4,
+            _ => 0,
+        }
+    }
+}
+
+/// V2 格式中 entry 的类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryType {
+    Set = 1,
+    Tombstone = 2,
+    SetWithTtl = 3,
+    /// 只包含追加到上一个版本 value 末尾的字节，见 [`Log::write_append_entry`]
+    Append = 4,
+    /// 批量写入的标记，key 为空，value 为之后属于该批次的 entry 的总字节数(big-endian u64)，见 [`Log::write_batch`]
+    Batch = 5,
+}
+
+impl TryFrom<u8> for EntryType {
+    type Error = std::io::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(EntryType::Set),
+            2 => Ok(EntryType::Tombstone),
+            3 => Ok(EntryType::SetWithTtl),
+            4 => Ok(EntryType::Append),
+            5 => Ok(EntryType::Batch),
+            t => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("unknown entry type {}", t))),
+        }
+    }
+}
+
+/// write_entry 写入的 entry 在日志文件中的位置，调用方无需再自行计算偏移量
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryLocation {
+    /// entry 的起始位置
+    pub entry_pos: u64,
+    /// value 的起始位置。tombstone 没有 value，此时为 key 的结束位置
+    pub value_pos: u64,
+    /// value 的长度，tombstone 为 0
+    pub value_len: u32,
+    /// value 之后校验和的长度，见 [`LogFormat::checksum_len`]
+    pub checksum_len: u32,
+}
+
+impl EntryLocation {
+    /// entry 的结束位置，即下一个 entry 的起始位置
+    pub fn entry_end(&self) -> u64 {
+        self.value_pos + self.value_len as u64 + self.checksum_len as u64
+    }
+
+    /// entry 在磁盘上占用的字节数
+    pub fn entry_len(&self) -> u64 {
+        self.entry_end() - self.entry_pos
+    }
+
+    /// 将相对于缓冲区起始位置的位置转换为文件中的位置
+    fn offset_by(self, base: u64) -> Self {
+        EntryLocation { entry_pos: self.entry_pos + base, value_pos: self.value_pos + base, ..self }
+    }
+}
+
+/// entry 中 key 之前的部分
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct EntryHeader {
+    pub(crate) entry_type: EntryType,
+    pub(crate) key_len: u32,
+    /// tombstone 时为 0
+    pub(crate) value_len: u32,
+    /// 过期时间(unix millis)，仅 SetWithTtl 有
+    pub(crate) expires_at: Option<u64>,
+    /// 写入时间(unix millis)，仅 V4 有。写入时为 None 则记录当前时间
+    pub(crate) written_at: Option<u64>,
+}
+
+impl EntryHeader {
+    /// 编码后的长度
+    fn len(&self, format: LogFormat) -> u64 {
+        let len = format.entry_overhead() - format.checksum_len();
+        match (format, self.entry_type) {
+            (LogFormat::V2 | LogFormat::V3 | LogFormat::V4, EntryType::SetWithTtl) => len + 8,
+            _ => len,
+        }
+    }
+
+    pub(crate) fn read<R: Read>(r: &mut R, format: LogFormat) -> std::io::Result<Self> {
+        let mut len_buf = [0u8; 4];
+        match format {
+            LogFormat::V1 => {
+                r.read_exact(&mut len_buf)?;
+                let key_len = u32::from_be_bytes(len_buf);
+                r.read_exact(&mut len_buf)?;
+                Ok(match i32::from_be_bytes(len_buf) {
+                    l if l >= 0 => EntryHeader { entry_type: EntryType::Set, key_len, value_len: l as u32, expires_at: None, written_at: None },
+                    _ => EntryHeader { entry_type: EntryType::Tombstone, key_len, value_len: 0, expires_at: None, written_at: None }, // -1 for tombstones
+                })
+            }
+            LogFormat::V2 | LogFormat::V3 | LogFormat::V4 => {
+                let mut type_buf = [0u8; 1];
+                r.read_exact(&mut type_buf)?;
+                let entry_type = EntryType::try_from(type_buf[0])?;
+                r.read_exact(&mut len_buf)?;
+                let key_len = u32::from_be_bytes(len_buf);
+                r.read_exact(&mut len_buf)?;
+                let value_len = u32::from_be_bytes(len_buf);
+                let expires_at = if entry_type == EntryType::SetWithTtl {
+                    let mut ts_buf = [0u8; 8];
+                    r.read_exact(&mut ts_buf)?;
+                    Some(u64::from_be_bytes(ts_buf))
+                } else {
+                    None
+                };
+                let written_at = if format == LogFormat::V4 {
+                    let mut ts_buf = [0u8; 8];
+                    r.read_exact(&mut ts_buf)?;
+                    Some(u64::from_be_bytes(ts_buf))
+                } else {
+                    None
+                };
+                Ok(EntryHeader { entry_type, key_len, value_len, expires_at, written_at })
+            }
+        }
+    }
+
+    pub(crate) fn write<W: Write>(&self, w: &mut W, format: LogFormat) -> std::io::Result<()> {
+        match format {
+            LogFormat::V1 => {
+                let value_len_or_tombstone = match self.entry_type {
+                    EntryType::Tombstone => -1,
+                    _ => self.value_len as i32,
+                };
+                w.write_all(&self.key_len.to_be_bytes())?;
+                w.write_all(&value_len_or_tombstone.to_be_bytes())?;
+            }
+            LogFormat::V2 | LogFormat::V3 | LogFormat::V4 => {
+                w.write_all(&[self.entry_type as u8])?;
+                w.write_all(&self.key_len.to_be_bytes())?;
+                w.write_all(&self.value_len.to_be_bytes())?;
+                if let Some(expires_at) = self.expires_at {
+                    w.write_all(&expires_at.to_be_bytes())?;
+                }
+                if format == LogFormat::V4 {
+                    w.write_all(&self.written_at.unwrap_or_default().to_be_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 将 entry 编码到 w 的末尾，返回相对于 w 起始位置的 entry 位置。V4 格式未指定写入时间时记录当前时间
+fn encode_entry(format: LogFormat, w: &mut Vec<u8>, mut header: EntryHeader, key: &[u8], value: Option<&[u8]>) -> CResult<EntryLocation> {
+    if format == LogFormat::V4 && header.written_at.is_none() {
+        header.written_at = Some(now_millis());
+    }
+    let pos = w.len() as u64;
+    let checksum_len = format.checksum_len();
+    header.write(w, format)?;
+    w.write_all(key)?;
+    if let Some(value) = value {
+        w.write_all(value)?;
+    }
+    if checksum_len > 0 {
+        w.write_all(&crc32(&[key, value.unwrap_or_default()]).to_be_bytes())?;
+    }
+    Ok(EntryLocation {
+        entry_pos: pos,
+        value_pos: pos + header.len(format) + header.key_len as u64,
+        value_len: header.value_len,
+        checksum_len: checksum_len as u32,
+    })
+}
+
+/// CRC32 (IEEE 802.3) 的查找表
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// 依次对 parts 计算 CRC32，结果与对拼接后的字节计算相同
+pub(crate) fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        crc = CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// 当前时间(unix millis)
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// load_index 读出的 entry：entry header，key，value 的位置，以及校验和是否一致
+type LoadedEntry = (EntryHeader, Vec<u8>, u64, bool);
+
+/// 日志文件的读写字节计数
+#[derive(Debug, Default)]
+pub struct IoStats {
+    /// read_value 读取的字节数
+    bytes_read: AtomicU64,
+    /// write_entry 写入的字节数
+    bytes_written: AtomicU64,
+}
+
+impl IoStats {
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// 计数清零
+    pub fn reset(&self) {
+        self.bytes_read.store(0, Ordering::Relaxed);
+        self.bytes_written.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 打开日志文件时对文件加的锁
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// 不加锁
+    None,
+
+    /// 共享锁，用于只读的句柄。多个读者可以同时持有，写者的独占锁会失败，直到所有读者关闭
+    Shared,
+
+    /// 独占锁，同一时间只能有一个写者，读者的共享锁也会失败
+    Exclusive,
 }
 
 impl Log {
@@ -25,6 +436,31 @@ impl Log {
     }
 
     pub fn new_with_lock(path: PathBuf, try_lock: bool) -> CResult<Self> {
+        Self::new_with_format(path, try_lock, LogFormat::V1)
+    }
+
+    /// 打开日志文件，checksum 为 true 时新建(或空)的文件使用 [`LogFormat::V3`]，每个 entry 带有 CRC32 校验和。
+    /// 已有数据的文件以文件头检测到的格式为准，因此不带校验和的旧日志仍可打开。持有文件的独占锁，同 [`Self::new`]
+    pub fn new_with_checksum(path: PathBuf, checksum: bool) -> CResult<Self> {
+        let format = if checksum { LogFormat::V3 } else { LogFormat::V1 };
+        Self::new_with_format(path, true, format)
+    }
+
+    /// 打开日志文件。新建(或空)的文件使用 format 指定的格式；已有数据的文件则以文件头检测到的格式为准。
+    pub fn new_with_format(path: PathBuf, try_lock: bool, format: LogFormat) -> CResult<Self> {
+        let lock = if try_lock { LockMode::Exclusive } else { LockMode::None };
+        Self::new_with_lock_mode(path, lock, format)
+    }
+
+    /// 按 lock 指定的锁打开日志文件。[`LockMode::Shared`] 以只读方式打开已存在的文件，不会创建或写入文件。
+    pub fn new_with_lock_mode(path: PathBuf, lock: LockMode, format: LogFormat) -> CResult<Self> {
+        if lock == LockMode::Shared {
+            let log = Self::open_read_only(path, format)?;
+            // 共享锁与其他共享锁共存，但与写者的独占锁互斥
+            FileExt::try_lock_shared(&log.file)?;
+            return Ok(log);
+        }
+
         if let Some(dir) = path.parent() {
             match std::fs::create_dir_all(dir) {
                 Ok(_) => {}
@@ -41,12 +477,124 @@ impl Log {
                             // .create_new(true)
                             .open(&path)?;
 
-        if try_lock {
+        if lock == LockMode::Exclusive {
             // 锁文件。 不允许其他进程篡改。 如果其他进程尝试篡改，则报错： "另一个程序已锁定文件的一部分，进程无法访问。 (os error 33)"
             file.try_lock_exclusive()?;
         }
 
-        Ok(Self { path, file })
+        let mut log = Self { path, file, io_stats: Arc::new(IoStats::default()), format, codec: ValueCodec::None, write_buffer: WriteBuffer::default(), read_only: false };
+        log.detect_format()?;
+        Ok(log)
+    }
+
+    /// 以只读方式打开已存在的日志文件，不加锁，也不会创建、截断或写入文件
+    pub fn open_read_only(path: PathBuf, format: LogFormat) -> CResult<Self> {
+        let file = std::fs::OpenOptions::new().read(true).open(&path)?;
+        let mut log = Self { path, file, io_stats: Arc::new(IoStats::default()), format, codec: ValueCodec::None, write_buffer: WriteBuffer::default(), read_only: true };
+        // 空文件无需检测格式，也不能写入文件头
+        if log.file.metadata()?.len() > 0 {
+            log.detect_format()?;
+        }
+        Ok(log)
+    }
+
+    /// 根据文件头检测格式，空文件则写入 self.format 对应的文件头
+    fn detect_format(&mut self) -> CResult<()> {
+        let file_len = self.file.metadata()?.len();
+        let mut magic = [0u8; 4];
+        if file_len >= LOG_HEADER_LEN {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.read_exact(&mut magic)?;
+            if magic != LOG_MAGIC {
+                self.format = LogFormat::V1;
+                return Ok(());
+            }
+            let mut version_buf = [0u8; 2];
+            self.file.read_exact(&mut version_buf)?;
+            self.format = match u16::from_be_bytes(version_buf) {
+                2 => LogFormat::V2,
+                3 => LogFormat::V3,
+                4 => LogFormat::V4,
+                v => return Err(Error::Internal(format!("unsupported log format version {}", v))),
+            };
+            let mut flags_buf = [0u8; 2];
+            self.file.read_exact(&mut flags_buf)?;
+            self.codec = match (u16::from_be_bytes(flags_buf) & FLAG_VALUE_CODEC != 0, self.codec) {
+                (false, _) => ValueCodec::None,
+                (true, ValueCodec::Deflate(level)) => ValueCodec::Deflate(level),
+                (true, ValueCodec::None) => ValueCodec::Deflate(ValueCodec::DEFAULT_LEVEL),
+            };
+            return Ok(());
+        }
+
+        // 文件头写入不完整(首字节 0xFF 在 V1 中不可能出现)，视为空文件
+        if file_len > 0 {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.read_exact(&mut magic[..1])?;
+            if magic[0] != LOG_MAGIC[0] {
+                self.format = LogFormat::V1;
+                return Ok(());
+            }
+        }
+        // 只读打开时不修复不完整的文件头，文件头之后没有 entry 可读
+        if self.read_only {
+            return Ok(());
+        }
+        self.truncate(self.format)
+    }
+
+    /// 清空日志文件，并按 format 写入文件头
+    pub(crate) fn truncate(&mut self, format: LogFormat) -> CResult<()> {
+        self.write_buffer.pending.clear();
+        self.file.set_len(0)?;
+        self.format = format;
+        if format != LogFormat::V1 {
+            let mut header = Vec::with_capacity(LOG_HEADER_LEN as usize);
+            header.extend_from_slice(&LOG_MAGIC);
+            header.extend_from_slice(&format.version().to_be_bytes());
+            let flags = if self.codec == ValueCodec::None { 0 } else { FLAG_VALUE_CODEC };
+            header.extend_from_slice(&flags.to_be_bytes());
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header)?;
+        }
+        Ok(())
+    }
+
+    /// 设置 value 的编码。已压缩的日志只能修改压缩级别；未压缩的日志只有在还没有 entry 时才能开启压缩，开启后写入带有标记的文件头
+    pub(crate) fn set_value_codec(&mut self, codec: ValueCodec) -> CResult<()> {
+        match (self.codec, codec) {
+            (ValueCodec::Deflate(_), ValueCodec::Deflate(_)) | (ValueCodec::None, ValueCodec::None) => {
+                self.codec = codec;
+                Ok(())
+            }
+            (ValueCodec::Deflate(_), ValueCodec::None) => {
+                Err(Error::Value(format!("log {} stores compressed values", self.path.display())))
+            }
+            (ValueCodec::None, ValueCodec::Deflate(_)) => {
+                if self.format == LogFormat::V1 {
+                    return Err(Error::Value("value compression requires log format v2".to_string()));
+                }
+                self.flush_buffer()?;
+                if self.file.metadata()?.len() > self.format.file_header_len() {
+                    return Err(Error::Value(format!(
+                        "log {} already holds uncompressed values without codec tags",
+                        self.path.display()
+                    )));
+                }
+                self.codec = codec;
+                self.truncate(self.format)
+            }
+        }
+    }
+
+    /// 以只读方式重新打开同一个日志文件，得到独立的文件句柄，读取位置互不影响，也不加锁
+    pub(crate) fn reopen_read_only(&self) -> CResult<Log> {
+        let file = std::fs::OpenOptions::new().read(true).open(&self.path)?;
+        Ok(Self { path: self.path.clone(), file, io_stats: self.io_stats.clone(), format: self.format, codec: self.codec, write_buffer: WriteBuffer::default(), read_only: true })
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
     }
 
     /// 用于在数据库启动时，根据日志重建LogCask，恢复出内存当中的BTreeMap
@@ -59,61 +607,157 @@ impl Log {
     ///    5. 错误处理
     ///    6. 循环直至日志文件末尾
     pub fn build_keydir(&mut self) -> CResult<KeyDir> {
-        let mut len_buf = [0u8; 4];
-        let mut keydir = KeyDir::new();
+        self.build_index()
+    }
+
+    /// 同 build_keydir，但可以指定内存索引的实现，见 [`Index`]
+    pub fn build_index<I: Index>(&mut self) -> CResult<I> {
+        let mut keydir = I::default();
+        self.load_index(0, &mut keydir)?;
+        Ok(keydir)
+    }
+
+    /// 将日志中的 entry 按顺序应用到已有的索引上，索引中记录的 file id 为 file_id。
+    /// 多个日志文件时，按 file id 从小到大依次加载，后写入的 entry 覆盖之前的。
+    ///
+    /// 日志中包含 Append entry 时返回错误，需要使用 [`Self::load_index_with_chains`] 记录 delta 链。
+    pub fn load_index<I: Index>(&mut self, file_id: u32, keydir: &mut I) -> CResult<()> {
+        self.load_index_with_chains(file_id, keydir, None, None)
+    }
+
+    /// 同 load_index，同时在 chains 中维护 Append entry 组成的 delta 链，在 expiries 中记录 SetWithTtl entry 的过期时间
+    pub(crate) fn load_index_with_chains<I: Index>(
+        &mut self,
+        file_id: u32,
+        keydir: &mut I,
+        mut chains: Option<&mut DeltaChains>,
+        mut expiries: Option<&mut Expiries>,
+    ) -> CResult<()> {
+        self.flush_buffer()?;
+        let format = self.format;
+        let checksum_len = format.checksum_len();
         let file_len = self.file.metadata()?.len();
         let mut r = BufReader::new(&mut self.file);
 
         // step 1
-        let mut pos = r.seek(SeekFrom::Start(0))?;
+        let mut pos = r.seek(SeekFrom::Start(format.file_header_len()))?;
 
         while pos < file_len {
             // Read the next entry from the file, returning the key, value
-            // position, and value length or None for tombstones.
-            let mut result = || -> Result<(Vec<u8>, u64, Option<u32>), std::io::Error> {
+            // position, and value length or None for tombstones, and whether
+            // the checksum (if any) matches.
+            let mut result = || -> Result<LoadedEntry, std::io::Error> {
                 // step 2
-                r.read_exact(&mut len_buf)?;
-                let key_len = u32::from_be_bytes(len_buf);
-                r.read_exact(&mut len_buf)?;
-                let value_len_or_tombstone = match i32::from_be_bytes(len_buf) {
-                    l if l >= 0 => Some(l as u32),
-                    _ => None, // -1 for tombstones
-                };
+                let header = EntryHeader::read(&mut r, format)?;
                 // step 3
-                let value_pos = pos + 4 + 4 + key_len as u64;
+                let value_pos = pos + header.len(format) + header.key_len as u64;
 
-                let mut key = vec![0; key_len as usize];
+                let mut key = vec![0; header.key_len as usize];
                 r.read_exact(&mut key)?;
 
-                if let Some(value_len) = value_len_or_tombstone {
-                    if value_pos + value_len as u64 > file_len {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::UnexpectedEof,
-                            "value extends beyond end of file",
-                        ));
-                    }
-                    r.seek_relative(value_len as i64)?; // avoids discarding buffer
+                if value_pos + header.value_len as u64 + checksum_len > file_len {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "value extends beyond end of file",
+                    ));
+                }
+                if checksum_len == 0 && header.entry_type != EntryType::Batch {
+                    r.seek_relative(header.value_len as i64)?; // avoids discarding buffer
+                    return Ok((header, key, value_pos, true));
                 }
 
-                Ok((key, value_pos, value_len_or_tombstone))
+                // 有校验和时需读出 value 参与计算
+                let mut value = vec![0; header.value_len as usize];
+                r.read_exact(&mut value)?;
+                let valid = checksum_len == 0 || {
+                    let mut crc_buf = [0u8; 4];
+                    r.read_exact(&mut crc_buf)?;
+                    u32::from_be_bytes(crc_buf) == crc32(&[&key, &value])
+                };
+                if header.entry_type == EntryType::Batch {
+                    // 批次不完整时视为未完成的写入，从批次标记处截断，批次中的 entry 都不可见
+                    let batch_len = u64::from_be_bytes(value.as_slice().try_into().map_err(|_| {
+                        std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid batch marker")
+                    })?);
+                    if value_pos + header.value_len as u64 + checksum_len + batch_len > file_len {
+                        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "batch extends beyond end of file"));
+                    }
+                }
+                Ok((header, key, value_pos, valid))
             };
 
             // step 4
             match result() {
+                Ok((_, _, _, false)) => {
+                    return Err(Error::Corruption(format!(
+                        "checksum mismatch for entry at offset {} in {}",
+                        pos,
+                        self.path.display()
+                    )));
+                }
+                // 批次中的 entry 紧随其后，按普通 entry 加载
+                Ok((header, _, value_pos, _)) if header.entry_type == EntryType::Batch => {
+                    pos = value_pos + header.value_len as u64 + checksum_len;
+                }
                 // Populate the keydir with the entry, or remove it on tombstones.
-                Ok((key, value_pos, Some(value_len))) => {
-                    keydir.insert(key, (value_pos, value_len));
-                    pos = value_pos + value_len as u64;
+                Ok((header, key, value_pos, _)) if header.entry_type != EntryType::Tombstone => {
+                    let value_len = header.value_len;
+                    let entry = (file_id, value_pos, value_len);
+                    let old = keydir.insert(key, entry);
+                    if let Some(expiries) = expiries.as_deref_mut() {
+                        if let Some(old) = old {
+                            expiries.remove(&old);
+                        }
+                        if let Some(expires_at) = header.expires_at {
+                            expiries.insert(entry, expires_at);
+                        }
+                    }
+                    match (chains.as_deref_mut(), header.entry_type) {
+                        (Some(chains), EntryType::Append) => {
+                            // 将上一个版本(可能本身也是 delta 链)作为新链的前缀，key 不存在时 value 即为追加的字节
+                            let prior = old.map(|old| {
+                                let mut prior = chains.remove(&old).unwrap_or_default();
+                                prior.push(old);
+                                prior
+                            });
+                            chains.insert(entry, prior.unwrap_or_default());
+                        }
+                        (None, EntryType::Append) => {
+                            return Err(Error::Internal(format!(
+                                "append entry at offset {} requires delta chains to be tracked",
+                                pos
+                            )));
+                        }
+                        (Some(chains), _) => {
+                            if let Some(old) = old {
+                                chains.remove(&old);
+                            }
+                        }
+                        (None, _) => {}
+                    }
+                    pos = value_pos + value_len as u64 + checksum_len;
                 }
-                Ok((key, value_pos, None)) => {
-                    keydir.remove(&key);
-                    pos = value_pos;
+                Ok((_, key, value_pos, _)) => {
+                    if let Some(old) = keydir.remove(&key) {
+                        if let Some(chains) = chains.as_deref_mut() {
+                            chains.remove(&old);
+                        }
+                        if let Some(expiries) = expiries.as_deref_mut() {
+                            expiries.remove(&old);
+                        }
+                    }
+                    pos = value_pos + checksum_len;
                 }
 
                 // step 5
                 // If an incomplete entry was found at the end of the file, assume an
                 // incomplete write and truncate the file.
                 Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    // 只读打开时保留文件原样，索引只包含不完整 entry 之前的部分
+                    if self.read_only {
+                        log::warn!("Found incomplete entry at offset {}, ignoring the rest of the read-only file", pos);
+                        break;
+                    }
                     log::error!("Found incomplete entry at offset {}, truncating file", pos);
                     self.file.set_len(pos)?;
                     break;
@@ -122,42 +766,266 @@ impl Log {
             }
         }
 
-        Ok(keydir)
+        Ok(())
     }
 
     /// 根据传入的偏移量和长度读取相应的值。
-    pub fn read_value(&mut self, value_pos: u64, value_len: u32) -> CResult<Vec<u8>> {
+    ///
+    /// 按位置读取(pread)，不移动文件的读写位置，因此只需要 `&self`，多个线程可以同时读取。
+    /// 开启 read_your_writes 时，尚在写缓冲中的部分直接从缓冲中读取
+    pub fn read_value(&self, value_pos: u64, value_len: u32) -> CResult<Vec<u8>> {
+        let end = value_pos + value_len as u64;
+        let buffer = &self.write_buffer;
+        // 缓冲为空时 flushed_len 可能落后于文件长度，只在缓冲非空时从中读取
+        let split = if buffer.read_your_writes && !buffer.pending.is_empty() {
+            buffer.flushed_len.clamp(value_pos, end)
+        } else {
+            end
+        };
+
         let mut value = vec![0; value_len as usize];
-        self.file.seek(SeekFrom::Start(value_pos))?;
-        self.file.read_exact(&mut value)?;
+        let (flushed, pending) = value.split_at_mut((split - value_pos) as usize);
+        read_exact_at(&self.file, flushed, value_pos)?;
+        if !pending.is_empty() {
+            let start = (split - buffer.flushed_len) as usize;
+            let buffered = buffer
+                .pending
+                .get(start..start + pending.len())
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::UnexpectedEof))?;
+            pending.copy_from_slice(buffered);
+        }
+        self.io_stats.bytes_read.fetch_add(value_len as u64, Ordering::Relaxed);
         Ok(value)
     }
 
+    /// 读取 entry 的写入时间(unix millis)，value_pos 和 key_len 为该 entry 的 value 位置和 key 长度。
+    /// 只有 [`LogFormat::V4`] 记录写入时间，其他格式返回 None
+    pub fn read_written_at(&self, key_len: u32, value_pos: u64) -> CResult<Option<u64>> {
+        if self.format != LogFormat::V4 {
+            return Ok(None);
+        }
+        let buf = self.read_value(value_pos - key_len as u64 - 8, 8)?;
+        Ok(Some(u64::from_be_bytes(buf.as_slice().try_into()?)))
+    }
+
+    /// 同 read_value，日志带有校验和时一并读出并校验，key 为该 entry 的 key，不一致时返回 [`Error::Corruption`]。
+    /// 压缩日志中的 value 在校验之后解码，返回原始的 value
+    pub fn read_checked_value(&self, key: &[u8], value_pos: u64, value_len: u32) -> CResult<Vec<u8>> {
+        let checksum_len = self.format.checksum_len();
+        if checksum_len == 0 {
+            let value = self.read_value(value_pos, value_len)?;
+            return self.codec.decode(value);
+        }
+        let mut value = self.read_value(value_pos, value_len + checksum_len as u32)?;
+        let crc_buf = value.split_off(value_len as usize);
+        if u32::from_be_bytes(crc_buf.as_slice().try_into()?) != crc32(&[key, &value]) {
+            return Err(Error::Corruption(format!(
+                "checksum mismatch for value at offset {} in {}",
+                value_pos,
+                self.path.display()
+            )));
+        }
+        self.codec.decode(value)
+    }
+
+    /// value 解码后的长度。压缩日志中只读取 value 开头的编码标记和原长度，不解压
+    pub fn read_logical_len(&self, value_pos: u64, value_len: u32) -> CResult<u64> {
+        if self.codec == ValueCodec::None || value_len == 0 {
+            return Ok(value_len as u64);
+        }
+        let prefix = self.read_value(value_pos, value_len.min(5))?;
+        match prefix.first() {
+            Some(&TAG_DEFLATE) if prefix.len() == 5 => Ok(u32::from_be_bytes(prefix[1..5].try_into()?) as u64),
+            _ => Ok(value_len as u64 - 1),
+        }
+    }
+
     /// 分别写入key_len，value_len(or tombstone)，key_bytes，value_bytes(如果是删除那么使用None值)，最后调用flush持久化到磁盘，
-    /// 最后返回一个offset和len，用于保存到BTreeMap当中
-    pub fn write_entry(&mut self, key: &[u8], value: Option<&[u8]>) -> CResult<(u64, u32)> {
-        let key_len = key.len() as u32;
-        let value_len = value.map_or(0, |v| v.len() as u32);
-        let value_len_or_tombstone = value.map_or(-1, |v| v.len() as i32);
-        let len = 4 + 4 + key_len + value_len;
+    /// 最后返回 entry 的位置，其中 value_pos 和 value_len 用于保存到BTreeMap当中
+    pub fn write_entry(&mut self, key: &[u8], value: Option<&[u8]>) -> CResult<EntryLocation> {
+        self.write_entry_at(key, value, None)
+    }
+
+    /// 同 write_entry，V4 格式记录 written_at 作为写入时间(None 时为当前时间)，用于 compact 时保留 entry 原来的写入时间
+    pub(crate) fn write_entry_at(&mut self, key: &[u8], value: Option<&[u8]>, written_at: Option<u64>) -> CResult<EntryLocation> {
+        let header = EntryHeader {
+            entry_type: if value.is_some() { EntryType::Set } else { EntryType::Tombstone },
+            key_len: key.len() as u32,
+            value_len: value.map_or(0, |v| v.len() as u32),
+            expires_at: None,
+            written_at,
+        };
+        self.append(header, key, value)
+    }
+
+    /// 写入一个带过期时间(unix millis)的 entry，仅 V2 格式支持
+    pub fn write_entry_with_ttl(&mut self, key: &[u8], value: &[u8], expires_at: u64) -> CResult<EntryLocation> {
+        self.write_ttl_entry_at(key, value, expires_at, None)
+    }
+
+    /// 同 write_entry_with_ttl，written_at 的含义同 [`Self::write_entry_at`]
+    pub(crate) fn write_ttl_entry_at(&mut self, key: &[u8], value: &[u8], expires_at: u64, written_at: Option<u64>) -> CResult<EntryLocation> {
+        if self.format == LogFormat::V1 {
+            return Err(Error::Value("entry type SetWithTtl requires log format v2".to_string()));
+        }
+        let header = EntryHeader {
+            entry_type: EntryType::SetWithTtl,
+            key_len: key.len() as u32,
+            value_len: value.len() as u32,
+            expires_at: Some(expires_at),
+            written_at,
+        };
+        self.append(header, key, Some(value))
+    }
+
+    /// 写入一个 Append entry，value 为追加到该 key 上一个版本末尾的字节，仅 V2 格式支持
+    pub fn write_append_entry(&mut self, key: &[u8], suffix: &[u8]) -> CResult<EntryLocation> {
+        if self.format == LogFormat::V1 {
+            return Err(Error::Value("entry type Append requires log format v2".to_string()));
+        }
+        let header = EntryHeader {
+            entry_type: EntryType::Append,
+            key_len: key.len() as u32,
+            value_len: suffix.len() as u32,
+            expires_at: None,
+            written_at: None,
+        };
+        self.append(header, key, Some(suffix))
+    }
 
+    /// 原子地写入一组 entry，value 为 None 表示 tombstone，返回每个 entry 的位置。仅 V2 及以上格式支持。
+    ///
+    /// 先写入一个 Batch 标记 entry 记录批次的总长度，再写入各个 entry，整个批次编码后通过一次写调用写入文件。
+    /// 打开日志时如果批次不完整(写入中途崩溃)，从标记处截断，批次中的 entry 都不会生效。
+    pub fn write_batch(&mut self, entries: &[(&[u8], Option<&[u8]>)]) -> CResult<Vec<EntryLocation>> {
+        if self.format == LogFormat::V1 {
+            return Err(Error::Value("atomic batches require log format v2".to_string()));
+        }
+        self.flush_buffer()?;
         let pos = self.file.seek(SeekFrom::End(0))?;
-        let mut w = BufWriter::with_capacity(len as usize, &mut self.file);
-        w.write_all(&key_len.to_be_bytes())?;
-        w.write_all(&value_len_or_tombstone.to_be_bytes())?;
-        w.write_all(key)?;
+
+        let mut body = Vec::new();
+        let mut locations = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let encoded = value.map(|value| self.codec.encode(value));
+            let value = encoded.as_deref();
+            let header = EntryHeader {
+                entry_type: if value.is_some() { EntryType::Set } else { EntryType::Tombstone },
+                key_len: key.len() as u32,
+                value_len: value.map_or(0, |v| v.len() as u32),
+                expires_at: None,
+                written_at: None,
+            };
+            locations.push(encode_entry(self.format, &mut body, header, key, value)?);
+        }
+
+        let mut buf = Vec::with_capacity(body.len() + 64);
+        let marker = EntryHeader { entry_type: EntryType::Batch, key_len: 0, value_len: 8, expires_at: None, written_at: None };
+        let marker_len = encode_entry(self.format, &mut buf, marker, &[], Some(&(body.len() as u64).to_be_bytes()))?.entry_end();
+        buf.extend_from_slice(&body);
+        self.file.write_all(&buf)?;
+        self.file.flush()?;
+        self.io_stats.bytes_written.fetch_add(buf.len() as u64, Ordering::Relaxed);
+
+        Ok(locations.into_iter().map(|loc| loc.offset_by(pos + marker_len)).collect())
+    }
+
+    fn append(&mut self, mut header: EntryHeader, key: &[u8], value: Option<&[u8]>) -> CResult<EntryLocation> {
+        let encoded = value.map(|value| self.codec.encode(value));
+        let value = encoded.as_deref();
         if let Some(value) = value {
-            w.write_all(value)?;
+            header.value_len = value.len() as u32;
+        }
+        let loc = if self.write_buffer.capacity > 0 {
+            if self.write_buffer.pending.is_empty() {
+                self.write_buffer.flushed_len = self.file.seek(SeekFrom::End(0))?;
+            }
+            // 位置相对于缓冲的起点，需在 flush 移动 flushed_len 之前换算为文件中的位置
+            let loc = encode_entry(self.format, &mut self.write_buffer.pending, header, key, value)?
+                .offset_by(self.write_buffer.flushed_len);
+            if self.write_buffer.pending.len() >= self.write_buffer.capacity {
+                self.flush_buffer()?;
+            }
+            loc
+        } else {
+            let pos = self.file.seek(SeekFrom::End(0))?;
+            let mut buf = Vec::new();
+            let loc = encode_entry(self.format, &mut buf, header, key, value)?;
+            self.file.write_all(&buf)?;
+            self.file.flush()?;
+            loc.offset_by(pos)
+        };
+        self.io_stats.bytes_written.fetch_add(loc.entry_len(), Ordering::Relaxed);
+        Ok(loc)
+    }
+
+    /// 设置写缓冲：capacity 大于 0 时 entry 先写入内存缓冲，累计达到 capacity 或 flush_buffer 时才写入文件，减少小 entry 的写调用。
+    /// read_your_writes 开启时，尚在缓冲中的 value 直接从缓冲中读取，保证写入后立即读取能读到
+    pub(crate) fn set_write_buffer(&mut self, capacity: usize, read_your_writes: bool) -> CResult<()> {
+        self.flush_buffer()?;
+        self.write_buffer.capacity = capacity;
+        self.write_buffer.read_your_writes = read_your_writes;
+        Ok(())
+    }
+
+    /// 将写缓冲中的 entry 写入文件
+    pub(crate) fn flush_buffer(&mut self) -> CResult<()> {
+        if self.write_buffer.pending.is_empty() {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(self.write_buffer.flushed_len))?;
+        self.file.write_all(&self.write_buffer.pending)?;
+        self.write_buffer.flushed_len += self.write_buffer.pending.len() as u64;
+        self.write_buffer.pending.clear();
+        Ok(())
+    }
+
+    /// 写缓冲中尚未写入文件的字节数
+    pub(crate) fn pending_len(&self) -> u64 {
+        self.write_buffer.pending.len() as u64
+    }
+}
+
+/// 从文件的 pos 处读满 buf，不移动文件的读写位置
+#[cfg(unix)]
+fn read_exact_at(file: &std::fs::File, buf: &mut [u8], pos: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, pos)
+}
+
+/// 从文件的 pos 处读满 buf。Windows 的 seek_read 会移动文件的读写位置，写入前都会重新 seek，不受影响
+#[cfg(windows)]
+fn read_exact_at(file: &std::fs::File, mut buf: &mut [u8], mut pos: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, pos) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                pos += n as u64;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
         }
-        w.flush()?;
+    }
+    Ok(())
+}
 
-        Ok((pos, len))
+impl Drop for Log {
+    /// 关闭时写入缓冲中剩余的 entry
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_buffer() {
+            log::error!("failed to flush the write buffer of {}: {}", self.path.display(), err);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::storage::log::Log;
+    use std::io::{Seek, SeekFrom};
+    use crate::error::CResult;
+    use crate::error::Error;
+    use crate::storage::log::{crc32, EntryHeader, EntryLocation, EntryType, LockMode, Log, LogFormat, ValueCodec, LOG_HEADER_LEN};
+    use crate::storage::{DeltaChains, KeyDir};
 
     #[test]
     fn test() {
@@ -178,4 +1046,188 @@ mod test {
 
         assert_eq!(1, 1);
     }
+
+    #[test]
+    /// Tests that each entry type roundtrips through the v2 format.
+    fn entry_types_roundtrip() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("v2");
+
+        let mut log = Log::new_with_format(path.clone(), false, LogFormat::V2)?;
+        let set_pos = log.write_entry(b"a", Some(&[1, 2]))?.entry_pos;
+        let ttl_pos = log.write_entry_with_ttl(b"b", &[3], 1_700_000_000_000)?.entry_pos;
+        let del_pos = log.write_entry(b"c", None)?.entry_pos;
+        drop(log);
+
+        let mut log = Log::new_with_lock(path, false)?;
+        assert_eq!(log.format(), LogFormat::V2);
+
+        let mut read_header = |pos: u64| -> CResult<EntryHeader> {
+            log.file.seek(SeekFrom::Start(pos))?;
+            Ok(EntryHeader::read(&mut log.file, LogFormat::V2)?)
+        };
+        assert_eq!(set_pos, LOG_HEADER_LEN);
+        assert_eq!(
+            read_header(set_pos)?,
+            EntryHeader { entry_type: EntryType::Set, key_len: 1, value_len: 2, expires_at: None, written_at: None }
+        );
+        assert_eq!(
+            read_header(ttl_pos)?,
+            EntryHeader { entry_type: EntryType::SetWithTtl, key_len: 1, value_len: 1, expires_at: Some(1_700_000_000_000), written_at: None }
+        );
+        assert_eq!(
+            read_header(del_pos)?,
+            EntryHeader { entry_type: EntryType::Tombstone, key_len: 1, value_len: 0, expires_at: None, written_at: None }
+        );
+
+        let keydir = log.build_keydir()?;
+        assert_eq!(keydir.len(), 2);
+        let (_, value_pos_a, value_len) = keydir[b"a".as_slice()];
+        assert_eq!(log.read_value(value_pos_a, value_len)?, vec![1, 2]);
+        let (_, value_pos, value_len) = keydir[b"b".as_slice()];
+        assert_eq!(log.read_value(value_pos, value_len)?, vec![3]);
+
+        // append entries can only be loaded while tracking delta chains
+        log.write_append_entry(b"a", &[4])?;
+        assert!(log.build_keydir().is_err());
+        let (mut keydir, mut chains) = (KeyDir::new(), DeltaChains::new());
+        log.load_index_with_chains(0, &mut keydir, Some(&mut chains), None)?;
+        assert_eq!(chains[&keydir[b"a".as_slice()]], vec![(0, value_pos_a, 2)]);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that v1 files are still read as v1, and reject typed entries.
+    fn v1_compat() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("v1");
+
+        let mut log = Log::new_with_lock(path.clone(), false)?;
+        log.write_entry(b"a", Some(&[1]))?;
+        log.write_entry(b"a", None)?;
+        log.write_entry(b"b", Some(&[2]))?;
+        assert!(log.write_entry_with_ttl(b"c", &[3], 0).is_err());
+        assert!(log.write_append_entry(b"a", &[3]).is_err());
+        drop(log);
+
+        // the requested format is ignored for a non-empty file
+        let mut log = Log::new_with_format(path, false, LogFormat::V2)?;
+        assert_eq!(log.format(), LogFormat::V1);
+        let keydir = log.build_keydir()?;
+        assert_eq!(keydir.keys().cloned().collect::<Vec<_>>(), vec![b"b".to_vec()]);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests the EntryLocation returned by write_entry, for empty and non-empty values and tombstones.
+    fn entry_location() -> CResult<()> {
+        for format in [LogFormat::V1, LogFormat::V2, LogFormat::V3, LogFormat::V4] {
+            let dir = tempdir::TempDir::new("demo")?;
+            let mut log = Log::new_with_format(dir.path().join("loc"), false, format)?;
+            let header_len = format.file_header_len();
+            let overhead = format.entry_overhead();
+            let checksum_len = format.checksum_len();
+
+            let loc = log.write_entry(b"key", Some(&[1, 2, 3]))?;
+            assert_eq!(
+                loc,
+                EntryLocation {
+                    entry_pos: header_len,
+                    value_pos: header_len + overhead - checksum_len + 3,
+                    value_len: 3,
+                    checksum_len: checksum_len as u32,
+                }
+            );
+            assert_eq!(log.read_value(loc.value_pos, loc.value_len)?, vec![1, 2, 3]);
+
+            let empty = log.write_entry(b"key", Some(&[]))?;
+            assert_eq!(empty.entry_pos, loc.entry_end());
+            assert_eq!(empty.value_pos, empty.entry_pos + overhead - checksum_len + 3);
+            assert_eq!(empty.value_len, 0);
+            assert_eq!(log.read_value(empty.value_pos, empty.value_len)?, Vec::<u8>::new());
+
+            let tombstone = log.write_entry(b"k", None)?;
+            assert_eq!(tombstone.entry_len(), overhead + 1);
+            assert_eq!(tombstone.entry_end(), log.file.metadata()?.len());
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that values are tagged as raw or deflate depending on whether compression pays off,
+    /// and that unknown tags are reported as corruption.
+    fn value_codec() -> CResult<()> {
+        let codec = ValueCodec::Deflate(6);
+        let long = b"abcabcabc".repeat(20);
+        let encoded = codec.encode(&long).into_owned();
+        assert_eq!(encoded[0], 1);
+        assert!(encoded.len() < long.len());
+        assert_eq!(codec.decode(encoded)?, long);
+
+        assert_eq!(codec.encode(b"ab").into_owned(), vec![0, b'a', b'b']);
+        assert_eq!(codec.decode(vec![0, b'a', b'b'])?, b"ab".to_vec());
+        assert_eq!(ValueCodec::None.encode(b"ab").into_owned(), b"ab".to_vec());
+        assert!(matches!(codec.decode(vec![7, 1]), Err(Error::Corruption(_))));
+        assert!(matches!(codec.decode(vec![1, 0, 0, 0, 9, 0xff]), Err(Error::Corruption(_))));
+        Ok(())
+    }
+
+    #[test]
+    /// Tests the CRC32 check value, and that a log opened with checksums verifies them on read.
+    fn checksum() -> CResult<()> {
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xCBF4_3926);
+
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("checksum");
+        let mut log = Log::new_with_checksum(path.clone(), true)?;
+        assert_eq!(log.format(), LogFormat::V3);
+        let loc = log.write_entry(b"key", Some(&[1, 2, 3]))?;
+        log.write_entry(b"key", None)?;
+        assert_eq!(log.read_checked_value(b"key", loc.value_pos, loc.value_len)?, vec![1, 2, 3]);
+        assert!(matches!(log.read_checked_value(b"kez", loc.value_pos, loc.value_len), Err(Error::Corruption(_))));
+        assert!(log.build_keydir()?.is_empty());
+        drop(log);
+
+        // an existing log keeps its format, with or without checksums requested
+        let log = Log::new_with_checksum(path, false)?;
+        assert_eq!(log.format(), LogFormat::V3);
+        drop(log);
+        let mut log = Log::new(dir.path().join("plain"))?;
+        log.write_entry(b"key", Some(&[1]))?;
+        drop(log);
+        assert_eq!(Log::new_with_checksum(dir.path().join("plain"), true)?.format(), LogFormat::V1);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that shared-lock readers coexist, and block an exclusive writer until they are dropped.
+    fn shared_lock() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("shared");
+        let mut log = Log::new(path.clone())?;
+        let loc = log.write_entry(b"key", Some(&[1]))?;
+        drop(log);
+
+        let mut reader1 = Log::new_with_lock_mode(path.clone(), LockMode::Shared, LogFormat::V1)?;
+        let reader2 = Log::new_with_lock_mode(path.clone(), LockMode::Shared, LogFormat::V1)?;
+        assert_eq!(reader1.read_value(loc.value_pos, loc.value_len)?, vec![1]);
+        assert_eq!(reader2.read_value(loc.value_pos, loc.value_len)?, vec![1]);
+        assert!(reader1.write_entry(b"key", Some(&[2])).is_err());
+
+        assert!(Log::new(path.clone()).is_err());
+        drop(reader1);
+        assert!(Log::new(path.clone()).is_err());
+        drop(reader2);
+
+        let writer = Log::new(path.clone())?;
+        assert!(Log::new_with_lock_mode(path.clone(), LockMode::Shared, LogFormat::V1).is_err());
+        drop(writer);
+
+        // a shared open never creates the file
+        assert!(Log::new_with_lock_mode(dir.path().join("missing"), LockMode::Shared, LogFormat::V1).is_err());
+        Ok(())
+    }
 }
@@ -1,8 +1,20 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
 use crate::error::{CResult, Error};
-use crate::storage::{KeyDir, ScanIteratorT, Status};
+use crate::storage::{DeltaChains, Expiries, KeyDir, KeyDirEntry, ScanIteratorT, Status};
+use crate::storage::index::Index;
 use crate::storage::engine::Engine;
-use crate::storage::log::Log;
+use crate::storage::key_transform::KeyTransform;
+use crate::storage::read_cache::ReadCache;
+use crate::storage::log::{now_millis, EntryHeader, EntryLocation, EntryType, LockMode, Log, LogFormat, ValueCodec};
 
 /// LogCask 是一个非常简单的日志结构的键值引擎。
 ///
@@ -11,23 +23,162 @@ use crate::storage::log::Log;
 /// 所有活动的key都必须出现在内存索引中。
 /// 删除某个key是将逻辑删除值写入日志文件。去除该key的索引。
 ///
-/// - 该实现不写多个固定大小的日志文件，而是使用单个任意大小的日志文件，且只做追加。这样实现的好处是：增加了紧密度，避免小文件产生，但坏处是，不适合大数据量的场景。
+/// - 默认不写多个固定大小的日志文件，而是使用单个任意大小的日志文件，且只做追加。这样实现的好处是：增加了紧密度，避免小文件产生，但坏处是，不适合大数据量的场景，且单个文件可能超过文件系统的大小限制。
+///   设置 `max_file_size` 后(或通过 `new_segmented` 打开)，活动文件超过该大小时会被封存(sealed)为 `<path>.<file_id>`，并在 `<path>` 新建活动文件，keydir 记录每个key所在的 file id。
+///   compact 会将所有文件合并为一个活动文件，`compact_sealed` 只合并封存的文件，不重写活动文件。
 ///
-/// - 打开数据文件时会扫描日志本身以构建 keydir。
+/// - 打开数据文件时会扫描日志本身以构建 keydir。compact 之后会额外写入 hint 文件 `<path>.hint`，只包含每个key的位置，
+///   打开时如果 hint 文件比日志文件新且与日志长度相符，直接从 hint 文件构建 keydir，无需逐个跳过日志中的 value。
 ///
-/// - log entry 不包含timestamps or checksums.
+/// - 开启 `delta_appends` 后(需要 V2 日志格式)，[`Engine::append`] 只写入追加的字节(Append entry)，不再重写完整的 value。
+///   读取时沿 delta 链([`DeltaChains`])拼接出完整的 value，compact 将每条链合并为一个完整的 value。
+///
+/// - 通过 `new_compressed` 打开时，每个 value 写入前经过 deflate 压缩，读取时解压，见 [`ValueCodec`]。keydir 中记录的是磁盘上(压缩后)的长度。
+///
+/// - 默认的 V1 格式不包含 timestamps or checksums。[`LogFormat::V3`] 为每个 entry 追加 CRC32，读取时校验；[`LogFormat::V4`] 还记录每个 entry 的写入时间，见 [`Engine::get_with_meta`]。
 ///
 /// log entry 的结构为：
 /// - Key length as big-endian u32.
 /// - Value length as big-endian i32, or -1 for tombstones.
 /// - Key as raw bytes (max 2 GB).
 /// - Value as raw bytes (max 2 GB).
-pub struct LogCask {
+///
+/// 内存索引默认使用 BTreeMap(KeyDir)，也可通过 `new_with_index` 指定其他 [`Index`] 实现，如 HashKeyDir。
+pub struct LogCask<I: Index = KeyDir> {
     /// The active append-only log file
     log: Log,
 
+    /// 活动文件的 file id，封存后沿用该 id
+    active_id: u32,
+
+    /// 已封存的只读日志文件，按 file id 排序
+    sealed: BTreeMap<u32, Log>,
+
+    /// 活动文件超过该大小时封存并新建活动文件。None 表示不限制，只使用单个日志文件
+    max_file_size: Option<u64>,
+
     /// use index, Maps keys to a value position and length in the log file.
-    keydir: KeyDir,
+    keydir: I,
+
+    /// 每次写入后的 fsync 策略
+    sync_policy: SyncPolicy,
+
+    /// 未 fsync 的字节数超过该值时强制执行 sync_all，与 sync_policy 相互独立。None 表示不限制
+    max_unsynced_bytes: Option<u64>,
+
+    /// 自上次 sync 以来写入的字节数
+    unsynced_bytes: u64,
+
+    /// 自上次 sync 以来写入的 entry 数
+    unsynced_writes: u64,
+
+    /// 累计执行 sync 的次数
+    sync_count: u64,
+
+    /// 开启后，set/delete 拒绝非 UTF-8 的key。默认关闭，以支持二进制key
+    utf8_keys: bool,
+
+    /// compact 前将当前日志复制为 `<path>.bak`
+    backup_before_compact: bool,
+
+    /// 保留的备份数量，更早的备份依次轮转为 `<path>.bak.1`、`<path>.bak.2` ...
+    max_backups: usize,
+
+    /// 存活key数量的上限，新增key超过该值时 set 返回 Error::QuotaExceeded，更新已有的key不受影响。None 表示不限制
+    max_keys: Option<u64>,
+
+    /// 日志路径是符号链接时，compact 是否解析链接并重写其指向的文件。关闭时 compact 返回错误。默认开启
+    follow_symlinks: bool,
+
+    /// 通过 open_shared 打开时为 true，拒绝所有写入
+    read_only: bool,
+
+    /// compact 替换日志前，是否从新日志重建 keydir 并校验 key 和 value 与预期一致。默认关闭
+    verify_compaction: bool,
+
+    /// 每写入(set/delete)该数量的 entry 后自动执行一次 compact。None 表示不按写入次数触发
+    compact_every_writes: Option<u64>,
+
+    /// 自上次 compact 以来写入的 entry 数
+    writes_since_compact: u64,
+
+    /// 开启后 append 只写入追加的字节，见 [`Self::set_delta_appends`]
+    delta_appends: bool,
+
+    /// 以 Append entry 结尾的key的 delta 链
+    chains: DeltaChains,
+
+    /// 通过 set_ex 写入的key的过期时间
+    expiries: Expiries,
+
+    /// set/get/delete/append 时对 key 做的变换，见 [`KeyTransform`]
+    key_transform: KeyTransform,
+
+    /// 活动日志的写缓冲大小，0 表示不缓冲
+    write_buffer_size: usize,
+
+    /// 是否可以读取尚在写缓冲中的 value，默认开启
+    read_your_writes: bool,
+
+    /// get 的读缓存，默认容量为 0，即不缓存。get 只需要 `&self`，因此放在锁中
+    read_cache: Mutex<ReadCache>,
+}
+
+/// compact 的预估结果，不会写入任何数据
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionEstimate {
+    /// compact 后可以回收的磁盘空间，即当前的 garbage_disk_size
+    pub reclaimable_bytes: u64,
+
+    /// 需要重写的存活 entry 数
+    pub live_entries: u64,
+
+    /// 需要重写的存活数据在磁盘上的大小，即 compact 后的文件大小
+    pub live_bytes: u64,
+}
+
+/// status 以及自打开以来的累计 IO 字节数，可用于观察 IO 放大(如 compact 重写了多少数据)
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusDetailed {
+    pub status: Status,
+
+    /// 通过 read_value 读取的字节数
+    pub bytes_read: u64,
+
+    /// 通过 write_entry 写入的字节数，包含 compact 重写的数据
+    pub bytes_written: u64,
+}
+
+/// checkpoint 时已持久化的日志状态，同时写入 `<path>.checkpoint` 标记文件，供监控工具校验持久性以及发现日志文件的意外缩小
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// 所有日志文件的总长度，即 status 的 total_disk_size
+    pub log_len: u64,
+
+    /// 存活的key数量
+    pub keys: u64,
+}
+
+impl Checkpoint {
+    /// 读取标记文件
+    pub fn load(path: &Path) -> CResult<Self> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data).map_err(|err| Error::Parse(format!("invalid checkpoint {}: {}", path.display(), err)))
+    }
+}
+
+/// 写入后何时调用 sync_all 将数据持久化到磁盘
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// 仅在显式 flush 或关闭引擎时 sync
+    #[default]
+    Never,
+
+    /// 每次写入后都 sync
+    Always,
+
+    /// 每写入 N 条 entry 后 sync
+    EveryN(u64),
 }
 
 impl LogCask {
@@ -37,11 +188,45 @@ impl LogCask {
     }
 
     pub fn new_with_lock(path: PathBuf, try_lock: bool) -> CResult<Self> {
-        let mut log = Log::new_with_lock(path, try_lock)?;
+        Self::new_with_index(path, try_lock)
+    }
+
+    /// 以只读方式打开已有的 LogCask，对日志文件加共享锁：多个只读实例可以同时打开，而写者在它们关闭前无法打开。
+    /// set、delete 和 compact 返回 [`Error::ReadOnly`]。
+    pub fn open_shared(path: PathBuf) -> CResult<Self> {
+        Self::open_with_lock(path, LockMode::Shared, true, LogFormat::V1)
+    }
 
-        let keydir = log.build_keydir()?;
+    /// 以只读方式打开已有的 LogCask，不加任何锁，可以在写者运行期间查看日志。
+    /// 从不截断文件：末尾不完整的 entry(如写者崩溃或正在写入)被忽略，索引只包含其之前的部分。
+    /// set、delete 和 compact 返回 [`Error::ReadOnly`]。
+    pub fn open_read_only(path: PathBuf) -> CResult<Self> {
+        Self::open_with_lock(path, LockMode::None, true, LogFormat::V1)
+    }
+
+    /// 使用指定的日志格式新建 LogCask，如 [`LogFormat::V2`] 的 entry 带有显式的类型字节
+    pub fn new_with_format(path: PathBuf, format: LogFormat) -> CResult<Self> {
+        Self::open(path, true, format)
+    }
+
+    /// 新建一个压缩 value 的 LogCask，level 为 deflate 的压缩级别(0-10)。新建的日志使用 V2 格式；
+    /// 已有的压缩日志以新的 level 继续写入，已有数据的未压缩日志返回错误。
+    ///
+    /// status 的 size 为压缩前的大小，需要从磁盘读取每个 value 开头的几个字节；live_disk_size 为压缩后的大小。
+    pub fn new_compressed(path: PathBuf, level: u32) -> CResult<Self> {
+        if level > 10 {
+            return Err(Error::Value(format!("compression level must be between 0 and 10, got {}", level)));
+        }
+        let mut s = Self::open(path, true, LogFormat::V2)?;
+        s.log.set_value_codec(ValueCodec::Deflate(level as u8))?;
+        Ok(s)
+    }
 
-        Ok(Self { log, keydir })
+    /// 新建一个分段的 LogCask：活动文件超过 max_segment_bytes 后被封存，之后的写入进入新的活动文件，见 [`Self::set_max_file_size`]
+    pub fn new_segmented(path: PathBuf, max_segment_bytes: u64) -> CResult<Self> {
+        let mut s = Self::new(path)?;
+        s.set_max_file_size(Some(max_segment_bytes));
+        Ok(s)
     }
 
     /// 用于处理小规模数据集的引擎模式。
@@ -49,11 +234,28 @@ impl LogCask {
     /// 只有在kvdb启动时才会执行 Compact 操作，并且此过程将锁定日志文件。
     /// 在new_compact当中，会计算当前的garbage_ratio，无效数据(垃圾量)超过阈值，就进行compact。
     pub fn new_compact(path: PathBuf, garbage_ratio_threshold: f64) -> CResult<Self> {
+        Self::new_compact_min_reclaim(path, garbage_ratio_threshold, 0)
+    }
+
+    /// 同 new_compact，但预估可回收的字节数(见 [`Self::compaction_estimate`])低于 min_reclaim_bytes 时，即使达到了比例阈值也不执行 compact，
+    /// 避免为回收少量空间反复重写日志
+    pub fn new_compact_min_reclaim(path: PathBuf, garbage_ratio_threshold: f64, min_reclaim_bytes: u64) -> CResult<Self> {
         let mut s = Self::new(path)?;
 
         let status = s.status()?;
         let garbage_ratio = status.garbage_disk_size as f64 / status.total_disk_size as f64;
         if status.garbage_disk_size > 0 && garbage_ratio >= garbage_ratio_threshold {
+            let reclaimable_bytes = s.compaction_estimate()?.reclaimable_bytes;
+            if reclaimable_bytes < min_reclaim_bytes {
+                log::info!(
+                    "Skipping compaction of {}: {} reclaimable bytes is below min_reclaim_bytes {}",
+                    s.log.path.display(),
+                    reclaimable_bytes,
+                    min_reclaim_bytes
+                );
+                return Ok(s);
+            }
+
             log::info!(
                 "Compacting {} to remove {:.3}MB garbage ({:.0}% of {:.3}MB)",
                 s.log.path.display(),
@@ -73,532 +275,3428 @@ impl LogCask {
         Ok(s)
     }
 
-    pub fn get_path(&self) -> Option<&str> {
-        self.log.path.to_str()
-    }
 }
 
-impl std::fmt::Display for LogCask {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "log cask")
+impl<I: Index> LogCask<I> {
+    /// 使用指定的内存索引实现新建一个 LogCask，例如 `LogCask::<HashKeyDir>::new_with_index(path, true)`
+    pub fn new_with_index(path: PathBuf, try_lock: bool) -> CResult<Self> {
+        Self::open(path, try_lock, LogFormat::V1)
     }
-}
 
-impl Engine for LogCask {
-    type ScanIterator<'a> = LogScanIterator<'a>;
+    /// 打开或新建一个 LogCask。新建的日志文件使用 format 指定的格式，已有的日志文件沿用其自身的格式。
+    /// 如果存在封存的日志文件 `<path>.<file_id>`，按 file id 依次加载，最后加载活动文件。
+    pub fn open(path: PathBuf, try_lock: bool, format: LogFormat) -> CResult<Self> {
+        let lock = if try_lock { LockMode::Exclusive } else { LockMode::None };
+        Self::open_with_lock(path, lock, false, format)
+    }
 
-    fn delete(&mut self, key: &[u8]) -> CResult<i64> {
-        // 写入的内容为tombstone(None)，标志key对应的val已经被删除，同时删除内存索引中的kv
-        self.log.write_entry(key, None)?;
-        self.keydir.remove(key);
-        Ok(1)
+    fn open_with_lock(path: PathBuf, lock: LockMode, read_only: bool, format: LogFormat) -> CResult<Self> {
+        let open_log = |path| match read_only && lock == LockMode::None {
+            true => Log::open_read_only(path, format),
+            false => Log::new_with_lock_mode(path, lock, format),
+        };
+        let mut log = open_log(path)?;
+
+        let mut keydir = I::default();
+        let mut chains = DeltaChains::new();
+        let mut expiries = Expiries::new();
+        let mut sealed = BTreeMap::new();
+        let mut sealed_paths = Self::sealed_paths(&log.path)?;
+        // compact_sealed 已提交但没有完成的替换：只读打开时以合并文件代替它所替换的封存文件，否则先完成替换
+        for (merged_id, merged_path) in Self::merged_paths(&log.path)? {
+            if read_only {
+                sealed_paths.retain(|(id, _)| *id > merged_id);
+                sealed_paths.push((merged_id, merged_path));
+                sealed_paths.sort();
+            } else {
+                Self::finish_sealed_merge(&log.path, merged_id)?;
+                sealed_paths = Self::sealed_paths(&log.path)?;
+            }
+        }
+        for (id, sealed_path) in sealed_paths {
+            let mut sealed_log = open_log(sealed_path)?;
+            sealed_log.io_stats = log.io_stats.clone();
+            sealed_log.load_index_with_chains(id, &mut keydir, Some(&mut chains), Some(&mut expiries))?;
+            sealed.insert(id, sealed_log);
+        }
+        let active_id = sealed.keys().next_back().map_or(0, |id| id + 1);
+        // hint 文件只描述 compact 得到的单个日志文件
+        match sealed.is_empty().then(|| Self::load_hint(&log, active_id)).flatten() {
+            Some((hint_keydir, hint_expiries)) => (keydir, expiries) = (hint_keydir, hint_expiries),
+            None => log.load_index_with_chains(active_id, &mut keydir, Some(&mut chains), Some(&mut expiries))?,
+        }
+
+        Ok(Self {
+            log,
+            active_id,
+            sealed,
+            max_file_size: None,
+            keydir,
+            sync_policy: SyncPolicy::default(),
+            max_unsynced_bytes: None,
+            unsynced_bytes: 0,
+            unsynced_writes: 0,
+            sync_count: 0,
+            utf8_keys: false,
+            backup_before_compact: false,
+            max_backups: 1,
+            max_keys: None,
+            follow_symlinks: true,
+            read_only,
+            verify_compaction: false,
+            compact_every_writes: None,
+            writes_since_compact: 0,
+            delta_appends: false,
+            chains,
+            expiries,
+            key_transform: KeyTransform::Identity,
+            write_buffer_size: 0,
+            read_your_writes: true,
+            read_cache: Mutex::new(ReadCache::default()),
+        })
     }
 
-    fn flush(&mut self) -> CResult<()> {
-        Ok(self.log.file.sync_all()?)
+    pub fn set_sync_policy(&mut self, sync_policy: SyncPolicy) {
+        self.sync_policy = sync_policy;
     }
 
-    fn get(&mut self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
-        // 首先查询内存当中的map，如果不存在返回不存在，如果能查询到，那么就根据metadata去磁盘当中读取出对应的value
-        if let Some((value_pos, value_len)) = self.keydir.get(key) {
-            Ok(Some(self.log.read_value(*value_pos, *value_len)?))
-        } else {
-            Ok(None)
-        }
+    /// 设置未 sync 字节数的上限，使崩溃时丢失的数据量有界
+    pub fn set_max_unsynced_bytes(&mut self, max_unsynced_bytes: Option<u64>) {
+        self.max_unsynced_bytes = max_unsynced_bytes;
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_>
-        where Self: Sized {
-        LogScanIterator { inner: self.keydir.range(range), log: &mut self.log }
+    /// 开启后，set/delete 仅接受合法的 UTF-8 key
+    pub fn set_utf8_keys(&mut self, utf8_keys: bool) {
+        self.utf8_keys = utf8_keys;
     }
 
-    fn scan_dyn<'a>(
-        &'a mut self,
-        range: (std::ops::Bound<Vec<u8>>, std::ops::Bound<Vec<u8>>),
-    ) -> Box<dyn ScanIteratorT + '_> {
-        Box::new(self.scan(range))
+    /// 开启后，每次 compact 前备份当前日志文件
+    pub fn set_backup_before_compact(&mut self, backup_before_compact: bool) {
+        self.backup_before_compact = backup_before_compact;
     }
 
-    fn set(&mut self, key: &[u8], value: Vec<u8>) -> CResult<()> {
-        // 首先向磁盘当中写入一条新的Entry，并且更新内存的map，保存新Entry的offset
-        let (pos, len) = self.log.write_entry(key, Some(&*value))?;
-        let value_len = value.len() as u32;
-        self.keydir.insert(key.to_vec(), (pos + len as u64 - value_len as u64, value_len));
-        Ok(())
+    /// 保留的备份数量，至少为1
+    pub fn set_max_backups(&mut self, max_backups: usize) {
+        self.max_backups = max_backups.max(1);
     }
 
-    fn status(&mut self) -> CResult<Status> {
-        let keys = self.keydir.len() as u64;
-        let size = self
-            .keydir
-            .iter()
-            .fold(0, |size, (key, (_, value_len))| size + key.len() as u64 + *value_len as u64);
-        let total_disk_size = self.log.file.metadata()?.len();
-        let live_disk_size = size + 8 * keys; // account for length prefixes
-        let garbage_disk_size = total_disk_size - live_disk_size;
-        Ok(Status {
-            name: self.to_string(),
-            keys,
-            size,
-            total_disk_size,
-            live_disk_size,
-            garbage_disk_size,
-        })
+    /// 设置活动文件的大小上限，超过后封存并新建活动文件
+    pub fn set_max_file_size(&mut self, max_file_size: Option<u64>) {
+        self.max_file_size = max_file_size;
     }
-}
 
-impl LogCask {
-    /// 在写入过程当中，会有key被更新或者删除，但是旧版本的key依旧会存在于日志文件当中，随着时间的增加，日志文件当中的无效数据就会越来越多，占用额外的存储空间。因此就需要compaction将其清除。
-    /// LogCask compact 实现是，遍历当前内存当中存在的key，创建一个新文件，调用“write_log”重建日志文件并保存。并用它替换当前文件。
-    pub fn compact(&mut self) -> CResult<()> {
-        let mut tmp_path = self.log.path.clone();
-        // need double disk size
-        tmp_path.set_extension("new");
+    /// 日志文件的数量，包括活动文件和封存的文件
+    pub fn file_count(&self) -> usize {
+        self.sealed.len() + 1
+    }
 
-        let (mut new_log, new_keydir) = self.write_log(tmp_path)?;
+    /// 封存的日志文件的路径 `<path>.<file_id>`
+    fn sealed_path(path: &Path, file_id: u32) -> PathBuf {
+        let mut path = path.to_path_buf().into_os_string();
+        path.push(format!(".{}", file_id));
+        PathBuf::from(path)
+    }
 
-        if cfg!(target_os = "windows") {
-            // println!("on Windows, from can be anything, \
-            // but to must not be a directory.{}, {}, {}, {}, {}",
-            //          &self.log.path.is_dir(),
-            //          &self.log.path.is_absolute(),
-            //          &self.log.path.is_relative(),
-            //          &self.log.path.is_symlink(),
-            //          &self.log.path.is_file());
+    /// compact_sealed 合并得到的文件在替换封存文件之前的路径 `<path>.<file_id>.merged`，它的存在表示这次合并已经提交
+    fn merged_path(path: &Path, file_id: u32) -> PathBuf {
+        let mut path = Self::sealed_path(path, file_id).into_os_string();
+        path.push(".merged");
+        PathBuf::from(path)
+    }
 
-            match std::fs::rename(&new_log.path, &self.log.path) {
-                Ok(_) => {}
-                Err(err) => {
-                    return Err(Error::Value(
-                        format!("db file compact error on Windows, from {:?} to {:?}, cause:{}.",
-                                &new_log.path.to_str(),
-                                &self.log.path.to_str(), err.to_string())
-                    ))
-                }
-            };
-        } else if cfg!(target_os = "linux"){
-            match std::fs::rename(&new_log.path, &self.log.path) {
-                Ok(_) => {}
-                Err(err) => {
-                    return Err(Error::Value(
-                        format!("db file compact error on Linux, from {:?} to {:?}, cause:{}.",
-                                &new_log.path.to_str(),
-                                &self.log.path.to_str(), err.to_string())
-                    ))
-                }
-            };
-        } else {
-            match std::fs::rename(&new_log.path, &self.log.path) {
-                Ok(_) => {}
-                Err(err) => {
-                    return Err(Error::Value(
-                        format!("db file compact error on Unknown os, from {:?} to {:?}, cause:{}.",
-                                &new_log.path.to_str(),
-                                &self.log.path.to_str(), err.to_string())
-                    ))
-                }
-            };
+    /// 查找活动文件 path 对应的封存文件，按 file id 排序
+    fn sealed_paths(path: &Path) -> CResult<Vec<(u32, PathBuf)>> {
+        Self::numbered_paths(path, "")
+    }
+
+    /// 查找已提交但还没有完成替换的合并文件，见 [`Self::finish_sealed_merge`]
+    fn merged_paths(path: &Path) -> CResult<Vec<(u32, PathBuf)>> {
+        Self::numbered_paths(path, ".merged")
+    }
+
+    /// 查找名为 `<path>.<file_id><suffix>` 的文件，按 file id 排序
+    fn numbered_paths(path: &Path, suffix: &str) -> CResult<Vec<(u32, PathBuf)>> {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => format!("{}.", name),
+            None => return Ok(vec![]),
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
         };
 
-        new_log.path = self.log.path.clone();
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&file_name))
+                .and_then(|id| id.strip_suffix(suffix))
+                .filter(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|id| id.parse::<u32>().ok());
+            if let Some(id) = id {
+                paths.push((id, entry.path()));
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
 
-        self.log = new_log;
-        self.keydir = new_keydir;
-        Ok(())
+    /// 完成已提交的合并：删除 file id 不大于 merged_id 的封存文件，再将合并文件改名为 merged_id 的封存文件，返回改名后的路径。
+    /// 每一步都可以重复执行，中途崩溃后重新打开时再执行一遍即可
+    fn finish_sealed_merge(path: &Path, merged_id: u32) -> CResult<PathBuf> {
+        for (id, sealed_path) in Self::sealed_paths(path)? {
+            if id <= merged_id {
+                std::fs::remove_file(sealed_path)?;
+            }
+        }
+        let sealed_path = Self::sealed_path(path, merged_id);
+        std::fs::rename(Self::merged_path(path, merged_id), &sealed_path)?;
+        Ok(sealed_path)
+    }
+
+    /// hint 文件的路径 `<path>.hint`
+    fn hint_path(path: &Path) -> PathBuf {
+        let mut path = path.to_path_buf().into_os_string();
+        path.push(".hint");
+        PathBuf::from(path)
     }
 
-    /// 遍历当前的map，去原本的日志文件当中读取，写入到新的日志文件当中，并且构建新的map
-    fn write_log(&mut self, path: PathBuf) -> CResult<(Log, KeyDir)> {
-        let mut new_keydir = KeyDir::new();
-        let mut new_log = Log::new(path)?;
-        new_log.file.set_len(0)?; // truncate file if it exists
-        for (key, (value_pos, value_len)) in self.keydir.iter() {
-            let value = self.log.read_value(*value_pos, *value_len)?;
-            let (pos, len) = new_log.write_entry(key, Some(&value))?;
-            new_keydir.insert(key.clone(), (pos + len as u64 - *value_len as u64, *value_len));
+    /// 将 keydir 写入 hint 文件，compact 之后调用，此时活动文件是唯一的日志文件且没有 delta 链。
+    ///
+    /// hint 文件以日志文件的长度(big-endian u64)开头，之后每个key一条记录：
+    /// key 长度(u32)、value 位置(u64)、value 长度(u32)、过期时间(u64，0 表示不过期)，以及 key 本身。
+    /// 先写入临时文件再重命名，旧的 hint 文件先被删除，写入失败时不会留下与日志不符的 hint。
+    fn write_hint(&mut self) -> CResult<()> {
+        let path = Self::hint_path(&self.log.path);
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let file = std::fs::File::create(&tmp_path)?;
+        let mut w = BufWriter::new(file);
+        w.write_all(&self.log.file.metadata()?.len().to_be_bytes())?;
+        for (key, (_, value_pos, value_len)) in self.keydir.iter() {
+            let expires_at = self.expiries.get(&(self.active_id, *value_pos, *value_len)).copied().unwrap_or(0);
+            w.write_all(&(key.len() as u32).to_be_bytes())?;
+            w.write_all(&value_pos.to_be_bytes())?;
+            w.write_all(&value_len.to_be_bytes())?;
+            w.write_all(&expires_at.to_be_bytes())?;
+            w.write_all(key)?;
         }
-        Ok((new_log, new_keydir))
+        let file = w.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
     }
-}
 
-/// Attempt to flush the file when the LogCask is closed.
-impl Drop for LogCask {
-    fn drop(&mut self) {
-        if let Err(error) = self.flush() {
-            log::error!("failed to flush file: {}", error)
+    /// 从 hint 文件构建 log 的 keydir。hint 文件不存在、不比日志文件新、记录的日志长度与日志文件不符或无法解析时返回 None，
+    /// 由调用方扫描日志本身
+    fn load_hint(log: &Log, file_id: u32) -> Option<(I, Expiries)> {
+        let path = Self::hint_path(&log.path);
+        let hint_meta = std::fs::metadata(&path).ok()?;
+        let log_meta = log.file.metadata().ok()?;
+        if hint_meta.modified().ok()? < log_meta.modified().ok()? {
+            return None;
+        }
+        match Self::read_hint(&path, log_meta.len(), file_id) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                log::warn!("ignoring hint file {}: {}", path.display(), err);
+                None
+            }
         }
     }
-}
 
-/// 用于进行范围读取
-pub struct LogScanIterator<'a> {
-    inner: std::collections::btree_map::Range<'a, Vec<u8>, (u64, u32)>,
-    log: &'a mut Log,
-}
+    fn read_hint(path: &Path, log_len: u64, file_id: u32) -> CResult<Option<(I, Expiries)>> {
+        let mut r = BufReader::new(std::fs::File::open(path)?);
+        let mut u64_buf = [0u8; 8];
+        let mut u32_buf = [0u8; 4];
+        r.read_exact(&mut u64_buf)?;
+        if u64::from_be_bytes(u64_buf) != log_len {
+            return Ok(None);
+        }
 
-impl<'a> LogScanIterator<'a> {
-    /// map函数，调用self.log.read_value()去磁盘当中进行读取，用于将BTreeMap当中的key与offset转换为真实的kv。
-    /// 由于inner和log都是引用类型，因此标注了生命周期
-    fn map(&mut self, item: (&Vec<u8>, &(u64, u32))) -> <Self as Iterator>::Item {
-        let (key, (value_pos, value_len)) = item;
-        Ok((key.clone(), self.log.read_value(*value_pos, *value_len)?))
+        let mut keydir = I::default();
+        let mut expiries = Expiries::new();
+        while !r.fill_buf()?.is_empty() {
+            r.read_exact(&mut u32_buf)?;
+            let key_len = u32::from_be_bytes(u32_buf);
+            r.read_exact(&mut u64_buf)?;
+            let value_pos = u64::from_be_bytes(u64_buf);
+            r.read_exact(&mut u32_buf)?;
+            let value_len = u32::from_be_bytes(u32_buf);
+            r.read_exact(&mut u64_buf)?;
+            let expires_at = u64::from_be_bytes(u64_buf);
+            let mut key = vec![0; key_len as usize];
+            r.read_exact(&mut key)?;
+
+            if value_pos + value_len as u64 > log_len {
+                return Err(Error::Corruption(format!("entry for {:?} is past the end of the log", String::from_utf8_lossy(&key))));
+            }
+            let entry = (file_id, value_pos, value_len);
+            if expires_at != 0 {
+                expiries.insert(entry, expires_at);
+            }
+            keydir.insert(key, entry);
+        }
+        Ok(Some((keydir, expiries)))
     }
-}
 
-impl<'a> Iterator for LogScanIterator<'a> {
-    type Item = CResult<(Vec<u8>, Vec<u8>)>;
+    /// 读缓存。缓存中只是 value 的副本，持有锁的线程 panic 后仍可继续使用
+    fn read_cache(&self) -> std::sync::MutexGuard<'_, ReadCache> {
+        self.read_cache.lock().unwrap_or_else(|err| err.into_inner())
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|item| self.map(item))
+    /// 按 file id 从活动文件或封存文件中读取 value
+    fn files(&self) -> LogFiles<'_> {
+        LogFiles { active: &self.log, active_id: self.active_id, sealed: &self.sealed, chains: &self.chains, expiries: &self.expiries }
     }
-}
 
-impl<'a> DoubleEndedIterator for LogScanIterator<'a> {
-    fn next_back(&mut self) -> Option<Self::Item> {
-        self.inner.next_back().map(|item| self.map(item))
+    /// 活动文件超过 max_file_size 时，将其封存为 `<path>.<file_id>`，并新建活动文件
+    fn maybe_roll_over(&mut self, file_len: u64) -> CResult<()> {
+        match self.max_file_size {
+            Some(max) if file_len > max => self.roll_over(),
+            _ => Ok(()),
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::{Cursor, Read};
-    use std::path::PathBuf;
-    use byteorder::ReadBytesExt;
-    use bytes::{BufMut, BytesMut};
-    use serde_derive::{Deserialize, Serialize};
-    use crate::codec::json_codec::JsonCodec;
-    use crate::codec::{Codec,};
-    use crate::codec::bytes_codec::BytesCodec;
-    use crate::error::{CResult, Error};
-    use crate::storage::engine::Engine;
-    use crate::storage::log::Log;
-    use crate::storage::log_cask::LogCask;
-    use crate::storage::Status;
+    fn roll_over(&mut self) -> CResult<()> {
+        self.sync()?;
+        let sealed_path = Self::sealed_path(&self.log.path, self.active_id);
+        std::fs::rename(&self.log.path, &sealed_path)?;
+
+        let mut active = Log::new_with_format(self.log.path.clone(), true, self.log.format)?;
+        active.set_value_codec(self.log.codec)?;
+        active.io_stats = self.log.io_stats.clone();
+        active.set_write_buffer(self.write_buffer_size, self.read_your_writes)?;
+        let mut sealed = std::mem::replace(&mut self.log, active);
+        sealed.path = sealed_path;
+        self.sealed.insert(self.active_id, sealed);
+        self.active_id += 1;
+        Ok(())
+    }
 
-    super::super::tests::test_engine!({
-        let path = tempdir::TempDir::new("demo")?.path().join("whosdb");
-        LogCask::new(path)?
-    });
+    /// 完整 value 的长度，包含 delta 链上的各段
+    fn value_len(&self, entry: &KeyDirEntry) -> u64 {
+        chain_value_len(&self.chains, entry)
+    }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct Persion {
-        name: String,
+    /// 存活key的数量，key 与 value 的字节数之和，以及存活的 entry 在磁盘上的大小(不含文件头)。
+    /// 带有过期时间的 entry 在 header 中多出 8 字节的过期时间
+    fn live_size(&self) -> (u64, u64, u64) {
+        let overhead = self.log.format.entry_overhead();
+        self.live_entries().fold((0, 0, 0), |(keys, size, disk_size), (key, entry)| {
+            let len = key.len() as u64 + self.value_len(entry);
+            let ttl_len = if self.expiries.contains_key(entry) { 8 } else { 0 };
+            (keys + 1, size + len, disk_size + len + overhead + ttl_len)
+        })
+    }
 
-        age: i16,
+    /// key 在 keydir 中的 entry，已过期时为 None
+    fn live_entry(&self, key: &[u8]) -> Option<KeyDirEntry> {
+        let entry = self.keydir.get(key).copied()?;
+        (!is_expired(&self.expiries, &entry, now_millis())).then_some(entry)
+    }
 
-        address: String,
+    /// 存活(未过期)的 key 及其 entry
+    fn live_entries(&self) -> impl Iterator<Item = (&Vec<u8>, &KeyDirEntry)> {
+        let now = now_millis();
+        self.keydir.iter().filter(move |(_, entry)| !is_expired(&self.expiries, entry, now))
     }
 
-    fn setup() -> CResult<LogCask> {
-        let path = tempdir::TempDir::new("demo")?.path().join("mydb");
-        println!("path:{:?}", &path);
+    /// 删除已完成 key 变换的 key
+    fn write_tombstone(&mut self, key: &[u8]) -> CResult<i64> {
+        // 写入的内容为tombstone(None)，标志key对应的val已经被删除，同时删除内存索引中的kv
+        self.check_key(key)?;
+        let loc = self.log.write_entry(key, None)?;
+        self.read_cache().remove(key);
+        if let Some(old) = self.keydir.remove(key) {
+            self.chains.remove(&old);
+            self.expiries.remove(&old);
+        }
+        self.after_write(loc.entry_len())?;
+        self.maybe_roll_over(loc.entry_end())?;
+        self.count_write();
+        Ok(1)
+    }
 
-        LogCask::new_with_lock(path, false)
+    /// 写入已完成 key 变换的 kv
+    fn write_value(&mut self, key: &[u8], value: Vec<u8>) -> CResult<()> {
+        self.write_value_with_expiry(key, value, None)
     }
 
-    /// Writes various values primarily for testing log file handling.
-    ///
-    /// - '': empty key and value
-    /// - a: write
-    /// - b: write, write
-    /// - c: write, delete, write
-    /// - d: delete, write
-    /// - e: write, delete
-    /// - f: delete
-    fn setup_log(s: &mut LogCask) -> CResult<()> {
-        s.set(b"b", vec![0x01])?;
-        s.set(b"b", vec![0x02])?;
+    /// 同 write_value，expires_at 为过期时间(unix millis)。None 时写入不过期的 entry，同时清除该key之前的过期时间
+    fn write_value_with_expiry(&mut self, key: &[u8], value: Vec<u8>, expires_at: Option<u64>) -> CResult<()> {
+        // 首先向磁盘当中写入一条新的Entry，并且更新内存的map，保存新Entry的offset
+        self.check_key(key)?;
+        self.check_quota(key)?;
+        let loc = match expires_at {
+            Some(expires_at) => self.log.write_entry_with_ttl(key, &value, expires_at)?,
+            None => self.log.write_entry(key, Some(&*value))?,
+        };
+        self.read_cache().remove(key);
+        let entry = (self.active_id, loc.value_pos, loc.value_len);
+        if let Some(old) = self.keydir.insert(key.to_vec(), entry) {
+            self.chains.remove(&old);
+            self.expiries.remove(&old);
+        }
+        if let Some(expires_at) = expires_at {
+            self.expiries.insert(entry, expires_at);
+        }
+        self.after_write(loc.entry_len())?;
+        self.maybe_roll_over(loc.entry_end())?;
+        self.count_write();
+        Ok(())
+    }
 
-        s.set(b"e", vec![0x05])?;
-        s.delete(b"e")?;
+    /// 开启后 append 只写入追加的字节(Append entry)，读取时沿 delta 链拼接，减少反复追加同一个key时的写放大。
+    /// Append entry 只存在于 V2 格式，V1 格式的日志开启时返回错误。
+    pub fn set_delta_appends(&mut self, delta_appends: bool) -> CResult<()> {
+        if delta_appends && self.log.format == LogFormat::V1 {
+            return Err(Error::Value("delta appends require log format v2".to_string()));
+        }
+        self.delta_appends = delta_appends;
+        Ok(())
+    }
 
-        s.set(b"c", vec![0x00])?;
-        s.delete(b"c")?;
-        s.set(b"c", vec![0x03])?;
+    /// 设置活动日志的写缓冲大小(字节)：写入先累积在内存中，达到该大小、sync 或关闭时才写入文件。0 表示每次写入直接写文件(默认)。
+    /// 缓冲中的数据在进程崩溃时会丢失，sync_policy 的持久性保证只针对已 sync 的写入
+    pub fn set_write_buffer_size(&mut self, write_buffer_size: usize) -> CResult<()> {
+        self.write_buffer_size = write_buffer_size;
+        self.log.set_write_buffer(self.write_buffer_size, self.read_your_writes)
+    }
 
-        s.set(b"", vec![])?;
+    /// 开启时(默认)，get/scan 读取的 value 尚在写缓冲中时直接从缓冲中读取，保证写入后立即读取能读到。
+    /// 关闭后这样的读取会返回错误，仅用于不会读取刚写入数据的场景
+    pub fn set_read_your_writes(&mut self, read_your_writes: bool) -> CResult<()> {
+        self.read_your_writes = read_your_writes;
+        self.log.set_write_buffer(self.write_buffer_size, self.read_your_writes)
+    }
 
-        s.set(b"a", vec![0x01])?;
+    /// 设置 get 读缓存的容量(key 与 value 的字节数之和)，0 表示不缓存。修改容量会清空已缓存的 value
+    pub fn set_read_cache_size(&mut self, read_cache_size: usize) {
+        *self.read_cache() = ReadCache::new(read_cache_size);
+    }
 
-        s.delete(b"f")?;
+    /// 将以任一 prefix 开头的key的 value 预先读入读缓存，使这些热点key的首次 get 无需读取磁盘，以启动耗时换取首次读取的延迟。
+    /// 返回缓存的key数量，超出缓存容量的部分按缓存的淘汰规则丢弃
+    pub fn warmup(&mut self, prefixes: &[Vec<u8>]) -> CResult<usize> {
+        if self.read_cache().capacity() == 0 {
+            return Ok(0);
+        }
+        let now = now_millis();
+        let files = self.files();
+        for prefix in prefixes {
+            for (key, entry) in self.keydir.range(prefix.clone()..).take_while(|(key, _)| key.starts_with(prefix)) {
+                if files.expired(entry, now) {
+                    continue;
+                }
+                let value = files.read_value(key, entry)?;
+                self.read_cache().insert(key.clone(), value);
+            }
+        }
+        Ok(self.read_cache().len())
+    }
+
+    /// 设置 set/get/delete/append 时对 key 做的变换。scan 等遍历接口返回的是变换后的 key
+    pub fn set_key_transform(&mut self, key_transform: KeyTransform) {
+        self.key_transform = key_transform;
+    }
+
+    /// 所有日志文件的大小之和
+    fn total_disk_size(&self) -> CResult<u64> {
+        let mut size = self.log.file.metadata()?.len() + self.log.pending_len();
+        for sealed in self.sealed.values() {
+            size += sealed.file.metadata()?.len();
+        }
+        Ok(size)
+    }
+
+    /// 开启后，compact 在替换日志前校验新日志，校验失败时返回错误并保留原日志
+    pub fn set_verify_compaction(&mut self, verify_compaction: bool) {
+        self.verify_compaction = verify_compaction;
+    }
+
+    /// 设置按写入次数触发 compact 的阈值。写入只计数，不会执行 compact：达到阈值后由后台 compact 线程(见 [`Self::spawn_compactor`])执行
+    pub fn set_compact_every_writes(&mut self, compact_every_writes: Option<u64>) {
+        self.compact_every_writes = compact_every_writes;
+    }
+
+    /// 自上次 compact 以来写入的 entry 数
+    pub fn writes_since_compact(&self) -> u64 {
+        self.writes_since_compact
+    }
+
+    /// 写入次数是否达到了 compact_every_writes
+    pub fn compaction_due(&self) -> bool {
+        self.compact_every_writes.is_some_and(|n| self.writes_since_compact >= n)
+    }
+
+    /// 每次 set/delete 之后调用，累计写入次数
+    fn count_write(&mut self) {
+        self.writes_since_compact += 1;
+    }
+
+    /// 日志路径是符号链接时，compact 是否跟随链接。关闭后 compact 拒绝执行，而不是替换掉链接本身
+    pub fn set_follow_symlinks(&mut self, follow_symlinks: bool) {
+        self.follow_symlinks = follow_symlinks;
+    }
+
+    /// compact 实际重写的文件：日志路径是符号链接时为链接指向的文件，从而保留链接本身
+    fn compaction_target(&self) -> CResult<PathBuf> {
+        if !std::fs::symlink_metadata(&self.log.path)?.file_type().is_symlink() {
+            return Ok(self.log.path.clone());
+        }
+        if !self.follow_symlinks {
+            return Err(Error::Value(format!(
+                "log path {} is a symlink, enable follow_symlinks to compact its target",
+                self.log.path.display()
+            )));
+        }
+        Ok(std::fs::canonicalize(&self.log.path)?)
+    }
+
+    /// 设置存活key数量的上限
+    pub fn set_max_keys(&mut self, max_keys: Option<u64>) {
+        self.max_keys = max_keys;
+    }
+
+    /// 第 n 个备份的路径，0 为最新的 `<path>.bak`
+    pub fn backup_path(&self, n: usize) -> PathBuf {
+        let mut path = self.log.path.clone().into_os_string();
+        path.push(".bak");
+        if n > 0 {
+            path.push(format!(".{}", n));
+        }
+        PathBuf::from(path)
+    }
+
+    /// 轮转已有的备份，并将当前日志复制为 `<path>.bak`
+    fn backup(&mut self) -> CResult<()> {
+        for n in (0..self.max_backups).rev() {
+            let from = self.backup_path(n);
+            if !from.exists() {
+                continue;
+            }
+            if n + 1 >= self.max_backups {
+                std::fs::remove_file(&from)?;
+            } else {
+                std::fs::rename(&from, self.backup_path(n + 1))?;
+            }
+        }
+        std::fs::copy(&self.log.path, self.backup_path(0))?;
+        Ok(())
+    }
+
+    fn check_key(&self, key: &[u8]) -> CResult<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if self.utf8_keys && std::str::from_utf8(key).is_err() {
+            return Err(Error::Value("key is not valid UTF-8".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 新增key时检查 max_keys 的上限，数量取自 keydir 的长度
+    fn check_quota(&self, key: &[u8]) -> CResult<()> {
+        match self.max_keys {
+            Some(max_keys) if self.keydir.len() as u64 >= max_keys && self.keydir.get(key).is_none() => {
+                Err(Error::QuotaExceeded(max_keys))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 自上次 sync 以来写入的字节数
+    pub fn unsynced_bytes(&self) -> u64 {
+        self.unsynced_bytes
+    }
+
+    /// 累计执行 sync 的次数
+    pub fn sync_count(&self) -> u64 {
+        self.sync_count
+    }
+
+    /// 每次 write_entry 之后调用，根据 sync_policy 和 max_unsynced_bytes 决定是否 sync
+    fn after_write(&mut self, len: u64) -> CResult<()> {
+        self.unsynced_bytes += len;
+        self.unsynced_writes += 1;
+
+        let by_policy = match self.sync_policy {
+            SyncPolicy::Never => false,
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryN(n) => self.unsynced_writes >= n,
+        };
+        let by_volume = self.max_unsynced_bytes.is_some_and(|max| self.unsynced_bytes >= max);
+
+        if by_policy || by_volume {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> CResult<()> {
+        self.log.flush_buffer()?;
+        self.log.file.sync_all()?;
+        self.unsynced_bytes = 0;
+        self.unsynced_writes = 0;
+        self.sync_count += 1;
+        Ok(())
+    }
+
+    /// 预估 compact 能回收的空间以及需要重写的数据量(dry-run)，可用于判断是否值得执行耗时的 compact。
+    pub fn compaction_estimate(&self) -> CResult<CompactionEstimate> {
+        let (live_entries, _, disk_size) = self.live_size();
+        let live_bytes = self.log.format.file_header_len() + disk_size;
+        let total_disk_size = self.total_disk_size()?;
+
+        Ok(CompactionEstimate {
+            reclaimable_bytes: total_disk_size - live_bytes,
+            live_entries,
+            live_bytes,
+        })
+    }
+
+    /// 重新扫描所有日志文件构建内存索引，并替换当前的索引，用于修复与磁盘不一致的 keydir 而无需重启。
+    /// 新索引构建完成后才会替换，构建失败时保留原索引。
+    pub fn rebuild_index(&mut self) -> CResult<()> {
+        let mut keydir = I::default();
+        let mut chains = DeltaChains::new();
+        let mut expiries = Expiries::new();
+        for (id, sealed) in self.sealed.iter_mut() {
+            sealed.load_index_with_chains(*id, &mut keydir, Some(&mut chains), Some(&mut expiries))?;
+        }
+        self.log.load_index_with_chains(self.active_id, &mut keydir, Some(&mut chains), Some(&mut expiries))?;
+        self.keydir = keydir;
+        self.chains = chains;
+        self.expiries = expiries;
+        self.read_cache().clear();
+        Ok(())
+    }
+
+    /// 反向查找：返回 value 等于给定 value 的所有key，按key排序。
+    ///
+    /// 需要扫描全部存活的 entry 并从磁盘读取 value，代价为 O(n) 次读取(长度不同的 value 直接跳过，不读磁盘)，只适用于小规模的数据集。
+    /// 可以通过 [`Self::keys_with_value_in`] 限定扫描的范围。
+    pub fn keys_with_value(&mut self, value: &[u8]) -> CResult<Vec<Vec<u8>>> {
+        self.keys_with_value_in(.., value)
+    }
+
+    /// 同 keys_with_value，只扫描 range 范围内的key
+    pub fn keys_with_value_in(&self, range: impl std::ops::RangeBounds<Vec<u8>>, value: &[u8]) -> CResult<Vec<Vec<u8>>> {
+        let files = self.files();
+        let mut keys = Vec::new();
+        let now = now_millis();
+        for (key, entry) in self.keydir.range(range) {
+            if files.expired(entry, now) {
+                continue;
+            }
+            let same_len = files.compressed() || files.value_len(entry) == value.len() as u64;
+            if same_len && files.read_value(key, entry)? == value {
+                keys.push(key.clone());
+            }
+        }
+        Ok(keys)
+    }
+
+    /// 将所有存活的 key/value 按key的顺序以导出格式写入 w，返回写入的key数量。
+    /// 导出格式与 V1 日志相同(不含 tombstone)，w 可以是文件，也可以是 stdout，便于通过管道交给 gzip、ssh 等工具。
+    /// key 为存储时的 key，即经过 [`KeyTransform`] 变换后的 key；delta 链会合并为完整的 value。
+    pub fn stream_snapshot(&self, w: &mut dyn Write) -> CResult<u64> {
+        let mut w = BufWriter::new(w);
+        let files = self.files();
+        let mut count = 0;
+        let now = now_millis();
+        for (key, entry) in self.keydir.iter() {
+            if files.expired(entry, now) {
+                continue;
+            }
+            let value = files.read_value(key, entry)?;
+            let header = EntryHeader {
+                entry_type: EntryType::Set,
+                key_len: key.len() as u32,
+                value_len: value.len() as u32,
+                expires_at: None,
+                written_at: None,
+            };
+            header.write(&mut w, LogFormat::V1)?;
+            w.write_all(key)?;
+            w.write_all(&value)?;
+            count += 1;
+        }
+        w.flush()?;
+        Ok(count)
+    }
+
+    /// 将快照导出到文件 path，见 [`Self::stream_snapshot`]。导出的文件本身即为一个可以直接打开的 V1 日志
+    pub fn export_snapshot(&mut self, path: &Path) -> CResult<u64> {
+        let mut file = std::fs::File::create(path)?;
+        let count = self.stream_snapshot(&mut file)?;
+        file.sync_all()?;
+        Ok(count)
+    }
+
+    /// 从 r 读取 [`Self::stream_snapshot`] 导出的 key/value 并写入，已有的同名key会被覆盖，返回导入的key数量。
+    /// key 按存储时的 key 写入，不再经过 [`KeyTransform`] 变换
+    pub fn import_snapshot(&mut self, r: &mut dyn Read) -> CResult<u64> {
+        let mut r = BufReader::new(r);
+        let mut count = 0;
+        while !r.fill_buf()?.is_empty() {
+            let header = EntryHeader::read(&mut r, LogFormat::V1)?;
+            if header.entry_type == EntryType::Tombstone {
+                return Err(Error::Value(format!("unexpected tombstone in snapshot after {} keys", count)));
+            }
+            let mut key = vec![0; header.key_len as usize];
+            r.read_exact(&mut key)?;
+            let mut value = vec![0; header.value_len as usize];
+            r.read_exact(&mut value)?;
+            self.write_value(&key, value)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn keydir_mut(&mut self) -> &mut I {
+        &mut self.keydir
+    }
+
+    /// checkpoint 标记文件的路径 `<path>.checkpoint`
+    pub fn checkpoint_path(&self) -> PathBuf {
+        let mut path = self.log.path.clone().into_os_string();
+        path.push(".checkpoint");
+        PathBuf::from(path)
+    }
+
+    /// fsync 日志文件，并将当前的日志长度和key数量写入标记文件。标记文件先写入临时文件再重命名，不会出现写了一半的标记。
+    pub fn checkpoint(&mut self) -> CResult<Checkpoint> {
+        self.sync()?;
+        let checkpoint = Checkpoint { log_len: self.total_disk_size()?, keys: self.keydir.len() as u64 };
+
+        let path = self.checkpoint_path();
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let data = serde_json::to_vec(&checkpoint).map_err(|err| Error::Internal(err.to_string()))?;
+        let mut file = std::fs::File::create(&tmp_path)?;
+        std::io::Write::write_all(&mut file, &data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        Ok(checkpoint)
+    }
+
+    /// 宽松模式的范围扫描：某个value读取失败(如文件在打开后被外部截断)时，仅将该key对应的条目作为错误返回，并继续扫描其余的key。
+    pub fn scan_lenient(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> LogLenientScanIterator<'_, I> {
+        let files = self.files();
+        LogLenientScanIterator { inner: self.keydir.range(range), files, now: now_millis() }
+    }
+
+    /// 同 scan，每一项额外带有 entry 的写入时间(unix millis)。只有 [`LogFormat::V4`] 记录写入时间，其他格式每一项都返回错误
+    pub fn scan_with_meta(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> LogMetaScanIterator<'_, I> {
+        let files = self.files();
+        LogMetaScanIterator { inner: self.keydir.range(range), files, now: now_millis() }
+    }
+
+    /// 自检：读取 keydir 中每个 entry 记录的位置和长度处的 value(带有校验和时一并校验)，返回读取失败的key，按key排序。
+    /// 用于主动发现 keydir 与磁盘数据不一致，如日志文件在打开后被外部截断或损坏
+    pub fn self_check(&mut self) -> CResult<Vec<Vec<u8>>> {
+        let mut bad_keys = Vec::new();
+        for (key, value) in self.scan_lenient(..) {
+            if let Err(err) = value {
+                log::warn!("self check: failed to read key {:?}: {}", String::from_utf8_lossy(&key), err);
+                bad_keys.push(key);
+            }
+        }
+        Ok(bad_keys)
+    }
+
+    /// status 以及读写字节计数
+    pub fn status_detailed(&mut self) -> CResult<StatusDetailed> {
+        Ok(StatusDetailed {
+            status: self.status()?,
+            bytes_read: self.log.io_stats.bytes_read(),
+            bytes_written: self.log.io_stats.bytes_written(),
+        })
+    }
+
+    /// 将运行期的读写字节计数清零，便于统计某一时间窗口内的 IO。不影响由 keydir 及日志文件得出的 status
+    pub fn reset_counters(&self) {
+        self.log.io_stats.reset();
+    }
+
+    /// 从 keydir 中随机抽取至多 n 个存活的key及其 value 长度(蓄水池抽样)，用于在不全量扫描的情况下估计 value 大小的分布。
+    /// value 长度直接取自 keydir，不读取日志文件；n 不小于key总数时返回全部key
+    pub fn sample_keys(&self, n: usize) -> Vec<(Vec<u8>, u64)> {
+        let mut rng = rand::thread_rng();
+        let mut reservoir: Vec<(Vec<u8>, u64)> = Vec::with_capacity(n.min(self.keydir.len()));
+        for (i, (key, entry)) in self.live_entries().enumerate() {
+            if i < n {
+                reservoir.push((key.clone(), self.value_len(entry)));
+            } else {
+                let j = rng.gen_range(0..=i);
+                if j < n {
+                    reservoir[j] = (key.clone(), self.value_len(entry));
+                }
+            }
+        }
+        reservoir
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.log.path.to_str()
+    }
+}
+
+impl<I: Index> std::fmt::Display for LogCask<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "log cask")
+    }
+}
+
+impl<I: Index> Engine for LogCask<I> {
+    type ScanIterator<'a> = LogScanIterator<'a, I> where I: 'a;
+
+    /// 开启 delta_appends 且key已存在时只写入一个 Append entry，否则读取完整的 value 后重写
+    fn append(&mut self, key: &[u8], suffix: &[u8]) -> CResult<u64> {
+        let key = &*self.key_transform.apply(key);
+        let old = match self.live_entry(key) {
+            Some(old) if self.delta_appends => old,
+            Some(old) => {
+                let mut value = self.files().read_value(key, &old)?;
+                value.extend_from_slice(suffix);
+                let len = value.len() as u64;
+                self.write_value(key, value)?;
+                return Ok(len);
+            }
+            None => {
+                self.write_value(key, suffix.to_vec())?;
+                return Ok(suffix.len() as u64);
+            }
+        };
+
+        self.check_key(key)?;
+        let loc = self.log.write_append_entry(key, suffix)?;
+        self.read_cache().remove(key);
+        let entry = (self.active_id, loc.value_pos, loc.value_len);
+        let mut prior = self.chains.remove(&old).unwrap_or_default();
+        prior.push(old);
+        self.chains.insert(entry, prior);
+        // Append entry 不带过期时间，追加后的key不再过期
+        self.expiries.remove(&old);
+        self.keydir.insert(key.to_vec(), entry);
+        let len = self.files().logical_len(&entry)?;
+
+        self.after_write(loc.entry_len())?;
+        self.maybe_roll_over(loc.entry_end())?;
+        self.count_write();
+        Ok(len)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> CResult<i64> {
+        let key = &*self.key_transform.apply(key);
+        self.write_tombstone(key)
+    }
+
+    fn flush(&mut self) -> CResult<()> {
+        self.sync()
+    }
+
+    fn get_with_meta(&mut self, key: &[u8]) -> CResult<Option<(Vec<u8>, u64)>> {
+        if self.log.format != LogFormat::V4 {
+            return Err(Error::Value(format!("log format {:?} does not record write times", self.log.format)));
+        }
+        let Some(value) = self.get(key)? else {
+            return Ok(None);
+        };
+        let key = &*self.key_transform.apply(key);
+        let entry = self.keydir.get(key).copied().ok_or_else(|| Error::Internal("key vanished from keydir".to_string()))?;
+        let written_at = self.files().written_at(key, &entry)?.unwrap_or_default();
+        Ok(Some((value, written_at)))
+    }
+
+    /// 过期的key视为不存在，同 get
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.live_entry(&self.key_transform.apply(key)).is_some()
+    }
+
+    /// 只需要 `&self`：按位置读取日志文件，读缓存放在锁中，多个线程可以通过 `Arc<LogCask>` 同时读取。
+    /// 已过期的key返回 None。读取从不写入日志：过期的key不写入 tombstone，由 compact 丢弃
+    fn get(&self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
+        // 首先查询内存当中的map，如果不存在返回不存在，如果能查询到，那么就根据metadata去磁盘当中读取出对应的value
+        let key = &*self.key_transform.apply(key);
+        let Some(entry) = self.live_entry(key) else {
+            return Ok(None);
+        };
+        if let Some(value) = self.read_cache().get(key) {
+            return Ok(Some(value.clone()));
+        }
+        let value = self.files().read_value(key, &entry)?;
+        let mut read_cache = self.read_cache();
+        if read_cache.capacity() > 0 {
+            read_cache.insert(key.to_vec(), value.clone());
+        }
+        Ok(Some(value))
+    }
+
+    /// 直接从 keydir 中取出范围内的key写入 tombstone，不读取 value。与 scan 相同，range 为存储时(经过 key 变换)的key
+    fn delete_range(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> CResult<u64>
+        where Self: Sized {
+        let now = now_millis();
+        let keys: Vec<(Vec<u8>, bool)> =
+            self.keydir.range(range).map(|(key, entry)| (key.clone(), !is_expired(&self.expiries, entry, now))).collect();
+        let mut deleted = 0;
+        for (key, live) in keys {
+            // 已过期的key同样写入 tombstone，但不计入删除的数量
+            self.write_tombstone(&key)?;
+            deleted += live as u64;
+        }
+        Ok(deleted)
+    }
+
+    /// 先在 keydir 中查出所有key的位置，再按 (file id, value 位置) 排序后读取，使磁盘读取尽量顺序进行。
+    /// 与 get 相同，已过期的key返回 None，不写入 tombstone
+    fn get_many(&mut self, keys: &[&[u8]]) -> CResult<Vec<Option<Vec<u8>>>> {
+        let mut values = vec![None; keys.len()];
+        let mut reads = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let key = self.key_transform.apply(key).into_owned();
+            let Some(entry) = self.live_entry(&key) else {
+                continue;
+            };
+            match self.read_cache().get(&key) {
+                Some(value) => values[i] = Some(value.clone()),
+                None => reads.push((entry, key, i)),
+            }
+        }
+
+        reads.sort_unstable_by_key(|(entry, _, _)| (entry.0, entry.1));
+        let files = self.files();
+        let mut read_cache = self.read_cache();
+        for (entry, key, i) in reads {
+            let value = files.read_value(&key, &entry)?;
+            if read_cache.capacity() > 0 {
+                read_cache.insert(key, value.clone());
+            }
+            values[i] = Some(value);
+        }
+        Ok(values)
+    }
+
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_>
+        where Self: Sized {
+        LogScanIterator { inner: self.keydir.range(range), files: self.files(), now: now_millis() }
+    }
+
+    /// 只遍历 keydir 并跳过已过期的 key，不读取日志文件
+    fn scan_keys(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> impl Iterator<Item = CResult<Vec<u8>>> + '_
+        where Self: Sized {
+        let (expiries, now) = (&self.expiries, now_millis());
+        self.keydir.range(range).filter(move |(_, entry)| !is_expired(expiries, entry, now)).map(|(key, _)| Ok(key.clone()))
+    }
+
+    fn scan_dyn<'a>(
+        &'a self,
+        range: (std::ops::Bound<Vec<u8>>, std::ops::Bound<Vec<u8>>),
+    ) -> Box<dyn ScanIteratorT + '_> {
+        Box::new(self.scan(range))
+    }
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> CResult<()> {
+        let key = &*self.key_transform.apply(key);
+        self.write_value(key, value)
+    }
+
+    /// 整组 entry 编码后一次写入并 flush，写入成功后才更新 keydir，需要 V2 及以上的日志格式，见 [`Log::write_batch`]
+    fn write_batch(&mut self, ops: Vec<(Vec<u8>, Option<Vec<u8>>)>) -> CResult<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let ops: Vec<(Vec<u8>, Option<Vec<u8>>)> =
+            ops.into_iter().map(|(key, value)| (self.key_transform.apply(&key).into_owned(), value)).collect();
+        let mut new_keys = std::collections::HashSet::new();
+        for (key, value) in &ops {
+            self.check_key(key)?;
+            if value.is_some() && self.keydir.get(key).is_none() {
+                new_keys.insert(key.as_slice());
+            }
+        }
+        if let Some(max_keys) = self.max_keys {
+            if self.keydir.len() as u64 + new_keys.len() as u64 > max_keys {
+                return Err(Error::QuotaExceeded(max_keys));
+            }
+        }
+
+        let entries: Vec<(&[u8], Option<&[u8]>)> = ops.iter().map(|(key, value)| (key.as_slice(), value.as_deref())).collect();
+        let locations = self.log.write_batch(&entries)?;
+        for ((key, value), loc) in ops.into_iter().zip(&locations) {
+            self.read_cache().remove(&key);
+            let old = match value {
+                Some(_) => self.keydir.insert(key, (self.active_id, loc.value_pos, loc.value_len)),
+                None => self.keydir.remove(&key),
+            };
+            if let Some(old) = old {
+                self.chains.remove(&old);
+                self.expiries.remove(&old);
+            }
+        }
+
+        let end = locations.last().map_or(0, |loc| loc.entry_end());
+        self.after_write(locations.iter().map(|loc| loc.entry_len()).sum())?;
+        self.maybe_roll_over(end)?;
+        self.count_write();
+        Ok(())
+    }
+
+    /// 过期时间写入 SetWithTtl entry，需要 V2 及以上的日志格式
+    fn set_ex(&mut self, key: &[u8], value: Vec<u8>, ttl: std::time::Duration) -> CResult<()> {
+        let key = &*self.key_transform.apply(key);
+        let expires_at = now_millis().saturating_add(ttl.as_millis() as u64);
+        self.write_value_with_expiry(key, value, Some(expires_at))
+    }
+
+    fn status(&mut self) -> CResult<Status> {
+        // 已过期但尚未被 compact 的key不计入存活数据
+        let (keys, mut size, disk_size) = self.live_size();
+        if self.files().compressed() {
+            // size 为压缩前的大小
+            let now = now_millis();
+            let files = self.files();
+            size = 0;
+            for (key, entry) in self.keydir.iter().filter(|(_, entry)| !is_expired(&self.expiries, entry, now)) {
+                size += key.len() as u64 + files.logical_len(entry)?;
+            }
+        }
+        let total_disk_size = self.total_disk_size()?;
+        // account for the file header and entry headers(length prefixes)
+        let live_disk_size = self.log.format.file_header_len() + disk_size;
+        let garbage_disk_size = total_disk_size - live_disk_size;
+        Ok(Status {
+            name: self.to_string(),
+            keys,
+            size,
+            total_disk_size,
+            live_disk_size,
+            garbage_disk_size,
+        })
+    }
+}
+
+impl<I: Index> LogCask<I> {
+    /// 在写入过程当中，会有key被更新或者删除，但是旧版本的key依旧会存在于日志文件当中，随着时间的增加，日志文件当中的无效数据就会越来越多，占用额外的存储空间。因此就需要compaction将其清除。
+    /// LogCask compact 实现是，遍历当前内存当中存在的key，创建一个新文件，调用“write_log”重建日志文件并保存。并用它替换当前文件。
+    ///
+    /// compact 分为三步：[`Self::compaction_snapshot`]、[`CompactionSnapshot::write`] 和 [`Self::finish_compaction`]。
+    /// 这里在 `&mut self` 下依次执行；共享的引擎可以只在第一步和最后一步持有锁，见 `AsyncEngine::compact`。
+    pub fn compact(&mut self) -> CResult<()> {
+        self.compact_with(|_, _| {}, &AtomicBool::new(false))
+    }
+
+    /// 同 compact，重写日志时通过 progress 报告进度 (entries_done, entries_total)。
+    /// 重写期间 cancel 被置为 true 时放弃 compact，删除 `.new` 临时文件并返回 [`Error::Abort`]，原日志和 keydir 保持不变。
+    pub fn compact_with(&mut self, progress: impl FnMut(u64, u64), cancel: &AtomicBool) -> CResult<()> {
+        let snapshot = self.compaction_snapshot()?;
+        let compacted = snapshot.write_with(progress, cancel)?;
+        self.finish_compaction(compacted)
+    }
+
+    /// 启动后台 compact 线程：每隔 check_interval 检查一次 status，垃圾数据占比达到 garbage_ratio_threshold
+    /// 或写入次数达到 compact_every_writes(见 [`Self::compaction_due`])时执行 compact，
+    /// 用于长时间运行的进程，避免垃圾数据只能在启动时(见 `new_compact`)回收。
+    ///
+    /// 锁的约定：线程只在读取 status、拷贝快照([`Self::compaction_snapshot`])和替换日志([`Self::finish_compaction`])时持有 engine 的锁，
+    /// 重写日志期间不持有锁，因此其他线程的读写和 scan 不会被整个 compact 阻塞。调用方不应长时间持有锁(如在锁内做无界的 scan)，
+    /// 否则后台线程会一直等待。compact 失败时记录日志，在下一个周期重试。
+    pub fn spawn_compactor(engine: Arc<Mutex<Self>>, garbage_ratio_threshold: f64, check_interval: Duration) -> CompactionHandle
+    where
+        I: 'static,
+    {
+        let (stop, stopped) = std::sync::mpsc::channel::<()>();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = cancel.clone();
+        let thread = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(check_interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+            if thread_cancel.load(Ordering::SeqCst) {
+                return;
+            }
+            match Self::compact_if_needed(&engine, garbage_ratio_threshold, &thread_cancel) {
+                Ok(_) => {}
+                Err(Error::Abort) => return,
+                Err(err) => log::error!("background compaction failed: {}", err),
+            }
+        });
+        CompactionHandle { stop: Some(stop), cancel, thread: Some(thread) }
+    }
+
+    /// 垃圾数据占比或写入次数达到阈值时 compact，返回是否执行了 compact
+    fn compact_if_needed(engine: &Mutex<Self>, garbage_ratio_threshold: f64, cancel: &AtomicBool) -> CResult<bool> {
+        let lock = || engine.lock().map_err(|err| Error::Internal(err.to_string()));
+        let snapshot = {
+            let mut engine = lock()?;
+            let status = engine.status()?;
+            let by_ratio = status.garbage_disk_size > 0
+                && (status.garbage_disk_size as f64 / status.total_disk_size as f64) >= garbage_ratio_threshold;
+            if !by_ratio && !engine.compaction_due() {
+                return Ok(false);
+            }
+            engine.compaction_snapshot()?
+        };
+        let compacted = snapshot.write_with(|_, _| {}, cancel)?;
+        lock()?.finish_compaction(compacted)?;
+        Ok(true)
+    }
+
+    /// 只合并封存的日志文件：将其中存活的 entry 写入一个新文件，替换所有封存文件，活动文件保持不变，之后的写入照常追加到活动文件。
+    ///
+    /// 合并后的文件沿用最大的封存 file id，仍然在活动文件之前加载。活动文件中的 delta 链如果有位于封存文件中的前几段，
+    /// 这几段被合并为一个完整的 value 写入新文件，重新打开时活动文件中的 Append entry 依然接在其后。
+    ///
+    /// 新文件不包含 tombstone，不能与旧的封存文件同时加载。新文件写入并 sync 后改名为 `<path>.<file_id>.merged` 作为提交点：
+    /// 在此之前崩溃，封存文件保持不变；在此之后崩溃，重新打开时先删除旧的封存文件再完成改名。
+    pub fn compact_sealed(&mut self) -> CResult<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let Some(merged_id) = self.sealed.keys().next_back().copied() else {
+            return Ok(());
+        };
+        let merged_path = Self::sealed_path(&self.log.path, merged_id);
+        let mut tmp_path = merged_path.clone().into_os_string();
+        tmp_path.push(".new");
+
+        let mut new_log = Log::new(PathBuf::from(tmp_path))?;
+        new_log.codec = self.log.codec;
+        new_log.truncate(self.log.format)?;
+        new_log.io_stats = self.log.io_stats.clone();
+
+        let now = now_millis();
+        let active_id = self.active_id;
+        // 位于封存文件中的 entry 及其在新文件中的位置和过期时间，过期的 entry 没有新位置
+        let mut moved = Vec::new();
+        // 活动文件中的 delta 链，以及其位于封存文件中的前几段合并后的新位置
+        let mut rebased: Vec<(KeyDirEntry, KeyDirEntry, usize)> = Vec::new();
+        let files = LogFiles { active: &self.log, active_id, sealed: &self.sealed, chains: &self.chains, expiries: &self.expiries };
+        for (key, entry) in self.keydir.iter() {
+            if entry.0 != active_id {
+                let copied = if files.expired(entry, now) {
+                    None
+                } else {
+                    let (loc, expires_at) = files.copy_entry(&mut new_log, key, entry)?;
+                    Some(((merged_id, loc.value_pos, loc.value_len), expires_at))
+                };
+                moved.push((key.clone(), *entry, copied));
+                continue;
+            }
+            let Some(prior) = files.chains.get(entry) else {
+                continue;
+            };
+            let sealed_len = prior.iter().take_while(|segment| segment.0 != active_id).count();
+            if sealed_len == 0 {
+                continue;
+            }
+            let mut value = Vec::new();
+            for segment in &prior[..sealed_len] {
+                value.extend_from_slice(&files.read_segment(key, segment)?);
+            }
+            let written_at = files.written_at(key, &prior[sealed_len - 1])?;
+            let loc = new_log.write_entry_at(key, Some(&value), written_at)?;
+            rebased.push((*entry, (merged_id, loc.value_pos, loc.value_len), sealed_len));
+        }
+        new_log.file.sync_all()?;
+
+        // 提交点，之前的错误不会改变封存文件和内存中的状态
+        let committed_path = Self::merged_path(&self.log.path, merged_id);
+        std::fs::rename(&new_log.path, &committed_path)?;
+        new_log.path = committed_path;
+        // 合并已提交，内存中总是切换到新文件，同时释放旧的封存文件的句柄，之后才能删除它们
+        self.sealed = BTreeMap::from([(merged_id, new_log)]);
+
+        for (key, old, copied) in moved {
+            self.chains.remove(&old);
+            self.expiries.remove(&old);
+            match copied {
+                Some((entry, expires_at)) => {
+                    self.keydir.insert(key, entry);
+                    if let Some(expires_at) = expires_at {
+                        self.expiries.insert(entry, expires_at);
+                    }
+                }
+                None => {
+                    self.read_cache().remove(&key);
+                    self.keydir.remove(&key);
+                }
+            }
+        }
+        for (entry, merged, sealed_len) in rebased {
+            if let Some(prior) = self.chains.get_mut(&entry) {
+                prior.splice(..sealed_len, [merged]);
+            }
+        }
+
+        // 删除旧文件失败时返回错误，新文件仍以 `.merged` 的路径使用，剩余的替换在下次打开时完成
+        let sealed_path = Self::finish_sealed_merge(&self.log.path, merged_id)?;
+        if let Some(log) = self.sealed.get_mut(&merged_id) {
+            log.path = sealed_path;
+        }
+        Ok(())
+    }
+
+    /// compact 的第一步：拷贝当前的 keydir，并为所有日志文件打开独立的只读句柄。
+    /// 之后重写日志(write)无需访问引擎，期间引擎可以继续读写。
+    pub fn compaction_snapshot(&mut self) -> CResult<CompactionSnapshot<I>> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        // 写入计数从这次尝试开始重新累计，失败时不会让之后的每个周期都立即重试
+        self.writes_since_compact = 0;
+        // 快照通过独立的文件句柄读取，缓冲中的 entry 需先写入文件
+        self.log.flush_buffer()?;
+        let target = self.compaction_target()?;
+        if self.backup_before_compact {
+            self.backup()?;
+        }
+
+        let mut sealed = BTreeMap::new();
+        for (id, log) in self.sealed.iter() {
+            sealed.insert(*id, log.reopen_read_only()?);
+        }
+        Ok(CompactionSnapshot {
+            keydir: self.keydir.clone(),
+            chains: self.chains.clone(),
+            expiries: self.expiries.clone(),
+            now: now_millis(),
+            active: self.log.reopen_read_only()?,
+            active_id: self.active_id,
+            sealed,
+            target,
+        })
+    }
+
+    /// compact 的最后一步：将快照之后的写入合并到新日志中，然后用新日志替换当前的所有日志文件。
+    /// 快照之后新写入或更新的key追加到新日志，删除的key在新日志中写入 tombstone。已过期的key与删除的key相同处理。
+    pub fn finish_compaction(&mut self, compacted: CompactedLog<I>) -> CResult<()> {
+        let CompactedLog { log: mut new_log, keydir: mut new_keydir, expiries: mut new_expiries, snapshot, file_id, target } = compacted;
+
+        let now = now_millis();
+        let files = self.files();
+        for (key, entry) in self.keydir.iter() {
+            if files.expired(entry, now) {
+                if let Some(old) = new_keydir.remove(key) {
+                    new_log.write_entry(key, None)?;
+                    new_expiries.remove(&old);
+                }
+            } else if snapshot.get(key) != Some(entry) {
+                let (loc, expires_at) = files.copy_entry(&mut new_log, key, entry)?;
+                let new_entry = (file_id, loc.value_pos, loc.value_len);
+                if let Some(old) = new_keydir.insert(key.clone(), new_entry) {
+                    new_expiries.remove(&old);
+                }
+                if let Some(expires_at) = expires_at {
+                    new_expiries.insert(new_entry, expires_at);
+                }
+            }
+        }
+        for (key, _) in snapshot.iter() {
+            if self.keydir.get(key).is_none() {
+                // 新日志中已经写入了快照时的 value，需要 tombstone 才能在重新打开时保持删除
+                new_log.write_entry(key, None)?;
+                if let Some(old) = new_keydir.remove(key) {
+                    new_expiries.remove(&old);
+                }
+            }
+        }
+
+        if self.verify_compaction {
+            if let Err(err) = self.verify_compacted(&mut new_log, &new_keydir, file_id) {
+                let tmp_path = new_log.path.clone();
+                drop(new_log);
+                std::fs::remove_file(tmp_path)?;
+                return Err(err);
+            }
+        }
+
+        if cfg!(target_os = "windows") {
+            // println!("on Windows, from can be anything, \
+            // but to must not be a directory.{}, {}, {}, {}, {}",
+            //          &self.log.path.is_dir(),
+            //          &self.log.path.is_absolute(),
+            //          &self.log.path.is_relative(),
+            //          &self.log.path.is_symlink(),
+            //          &self.log.path.is_file());
+
+            match std::fs::rename(&new_log.path, &target) {
+                Ok(_) => {}
+                Err(err) => {
+                    return Err(Error::Value(
+                        format!("db file compact error on Windows, from {} to {}, cause: {}.",
+                                new_log.path.display(),
+                                target.display(), err)
+                    ))
+                }
+            };
+        } else if cfg!(target_os = "linux"){
+            match std::fs::rename(&new_log.path, &target) {
+                Ok(_) => {}
+                Err(err) => {
+                    return Err(Error::Value(
+                        format!("db file compact error on Linux, from {} to {}, cause: {}.",
+                                new_log.path.display(),
+                                target.display(), err)
+                    ))
+                }
+            };
+        } else {
+            match std::fs::rename(&new_log.path, &target) {
+                Ok(_) => {}
+                Err(err) => {
+                    return Err(Error::Value(
+                        format!("db file compact error on Unknown os, from {} to {}, cause: {}.",
+                                new_log.path.display(),
+                                target.display(), err)
+                    ))
+                }
+            };
+        };
+
+        new_log.path = self.log.path.clone();
+
+        new_log.set_write_buffer(self.write_buffer_size, self.read_your_writes)?;
+        self.log = new_log;
+        self.keydir = new_keydir;
+        self.expiries = new_expiries;
+        self.active_id = file_id;
+        // 新日志中的 value 都是完整的
+        self.chains.clear();
+
+        // 封存文件中的存活数据都已合并到新的活动文件中
+        for sealed in std::mem::take(&mut self.sealed).into_values() {
+            let path = sealed.path.clone();
+            drop(sealed);
+            std::fs::remove_file(path)?;
+        }
+
+        // hint 文件只用于加速启动，写入失败不影响 compact 的结果
+        if let Err(err) = self.write_hint() {
+            log::warn!("failed to write hint file for {}: {}", self.log.path.display(), err);
+        }
+        Ok(())
+    }
+}
+
+impl<I: Index> LogCask<I> {
+    /// 从新日志重建 keydir，校验其与 compact 得到的 keydir 一致，且每个 value 与当前引擎中的 value 相同
+    fn verify_compacted(&mut self, new_log: &mut Log, new_keydir: &I, file_id: u32) -> CResult<()> {
+        let mut rebuilt = I::default();
+        new_log.load_index(file_id, &mut rebuilt)?;
+        if rebuilt.len() != new_keydir.len() {
+            return Err(Error::Internal(format!(
+                "compaction verification failed: new log holds {} keys, expected {}",
+                rebuilt.len(),
+                new_keydir.len()
+            )));
+        }
+
+        let files = self.files();
+        for (key, entry) in new_keydir.iter() {
+            let mismatch = |reason: &str| {
+                Error::Internal(format!(
+                    "compaction verification failed: key {:?} {}",
+                    String::from_utf8_lossy(key),
+                    reason
+                ))
+            };
+            if rebuilt.get(key) != Some(entry) {
+                return Err(mismatch("is missing or misplaced in the new log"));
+            }
+            let current = self.keydir.get(key).ok_or_else(|| mismatch("is not in the keydir"))?;
+            let (_, value_pos, value_len) = *entry;
+            if new_log.read_checked_value(key, value_pos, value_len)? != files.read_value(key, current)? {
+                return Err(mismatch("has a different value in the new log"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 后台 compact 线程的句柄，见 [`LogCask::spawn_compactor`]。drop 句柄同样会让线程在下一次检查前退出，但不等待其结束
+pub struct CompactionHandle {
+    stop: Option<Sender<()>>,
+    cancel: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CompactionHandle {
+    /// 停止后台线程并等待其退出，正在重写的日志被放弃(见 [`LogCask::compact_with`])
+    pub fn stop(mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("compaction thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for CompactionHandle {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+}
+
+/// compact 使用的 keydir 快照，以及快照时所有日志文件的只读句柄
+pub struct CompactionSnapshot<I: Index = KeyDir> {
+    keydir: I,
+    chains: DeltaChains,
+    expiries: Expiries,
+    /// 快照的时间，此时已过期的key不写入新日志
+    now: u64,
+    active: Log,
+    active_id: u32,
+    sealed: BTreeMap<u32, Log>,
+    /// compact 实际重写的文件，见 compaction_target
+    target: PathBuf,
+}
+
+/// 根据快照重写得到的新日志，尚未替换引擎当前的日志文件
+pub struct CompactedLog<I: Index = KeyDir> {
+    log: Log,
+    keydir: I,
+    /// 新日志中带有过期时间的 entry
+    expiries: Expiries,
+    /// 快照时的 keydir，用于找出快照之后的写入
+    snapshot: I,
+    /// 新日志的 file id
+    file_id: u32,
+    target: PathBuf,
+}
+
+impl<I: Index> CompactionSnapshot<I> {
+    /// compact 的第二步：遍历快照中的key，从旧的日志文件中读取 value，写入到新的日志文件 `<path>.new` 当中，并且构建新的map
+    pub fn write(self) -> CResult<CompactedLog<I>> {
+        self.write_with(|_, _| {}, &AtomicBool::new(false))
+    }
+
+    /// 同 write，见 [`LogCask::compact_with`]
+    pub fn write_with(self, mut progress: impl FnMut(u64, u64), cancel: &AtomicBool) -> CResult<CompactedLog<I>> {
+        // 临时文件与目标文件在同一目录，保证 rename 不会跨文件系统
+        let mut tmp_path = self.target.clone();
+        // need double disk size
+        tmp_path.set_extension("new");
+
+        let mut new_keydir = I::default();
+        let mut new_expiries = Expiries::new();
+        let mut new_log = Log::new(tmp_path)?;
+        new_log.codec = self.active.codec;
+        new_log.truncate(self.active.format)?; // truncate file if it exists
+        new_log.io_stats = self.active.io_stats.clone();
+
+        let total = self.keydir.len() as u64;
+        let files = LogFiles { active: &self.active, active_id: self.active_id, sealed: &self.sealed, chains: &self.chains, expiries: &self.expiries };
+        for (done, (key, entry)) in self.keydir.iter().enumerate() {
+            if cancel.load(Ordering::SeqCst) {
+                let tmp_path = new_log.path.clone();
+                drop(new_log);
+                std::fs::remove_file(tmp_path)?;
+                return Err(Error::Abort);
+            }
+            if !files.expired(entry, self.now) {
+                let (loc, expires_at) = files.copy_entry(&mut new_log, key, entry)?;
+                let new_entry = (self.active_id, loc.value_pos, loc.value_len);
+                new_keydir.insert(key.clone(), new_entry);
+                if let Some(expires_at) = expires_at {
+                    new_expiries.insert(new_entry, expires_at);
+                }
+            }
+            progress(done as u64 + 1, total);
+        }
+
+        Ok(CompactedLog {
+            log: new_log,
+            keydir: new_keydir,
+            expiries: new_expiries,
+            snapshot: self.keydir,
+            file_id: self.active_id,
+            target: self.target,
+        })
+    }
+}
+
+/// Attempt to flush the file when the LogCask is closed.
+impl<I: Index> Drop for LogCask<I> {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush() {
+            log::error!("failed to flush file: {}", error)
+        }
+    }
+}
+
+/// 带有过期时间的 entry 在 now 时是否已过期
+fn is_expired(expiries: &Expiries, entry: &KeyDirEntry, now: u64) -> bool {
+    expiries.get(entry).is_some_and(|expires_at| *expires_at <= now)
+}
+
+/// entry 对应的完整 value 的长度，entry 是 delta 链的最后一段时包含之前的各段
+fn chain_value_len(chains: &DeltaChains, entry: &KeyDirEntry) -> u64 {
+    let prior = chains.get(entry).map_or(0, |prior| prior.iter().map(|(_, _, len)| *len as u64).sum());
+    prior + entry.2 as u64
+}
+
+/// 活动文件以及封存的日志文件，根据 keydir entry 中的 file id 分派读取
+struct LogFiles<'a> {
+    active: &'a Log,
+    active_id: u32,
+    sealed: &'a BTreeMap<u32, Log>,
+    chains: &'a DeltaChains,
+    expiries: &'a Expiries,
+}
+
+impl<'a> LogFiles<'a> {
+    /// entry 在 now 时是否已过期
+    fn expired(&self, entry: &KeyDirEntry, now: u64) -> bool {
+        is_expired(self.expiries, entry, now)
+    }
+
+    /// 将 entry 的完整 value 写入 new_log，保留过期时间和写入时间，返回新 entry 的位置和过期时间。用于 compact
+    fn copy_entry(&self, new_log: &mut Log, key: &[u8], entry: &KeyDirEntry) -> CResult<(EntryLocation, Option<u64>)> {
+        let value = self.read_value(key, entry)?;
+        let written_at = self.written_at(key, entry)?;
+        let expires_at = self.expiries.get(entry).copied();
+        let loc = match expires_at {
+            Some(expires_at) => new_log.write_ttl_entry_at(key, &value, expires_at, written_at)?,
+            None => new_log.write_entry_at(key, Some(&value), written_at)?,
+        };
+        Ok((loc, expires_at))
+    }
+
+    /// 读取完整的 value，entry 是 delta 链的最后一段时按顺序拼接链上的各段。key 用于校验带有校验和的日志
+    fn read_value(&self, key: &[u8], entry: &KeyDirEntry) -> CResult<Vec<u8>> {
+        let Some(prior) = self.chains.get(entry) else {
+            return self.read_segment(key, entry);
+        };
+        let mut value = Vec::with_capacity(self.value_len(entry) as usize);
+        for segment in prior.iter().chain(std::iter::once(entry)) {
+            value.extend_from_slice(&self.read_segment(key, segment)?);
+        }
+        Ok(value)
+    }
+
+    /// 完整 value 的长度
+    fn value_len(&self, entry: &KeyDirEntry) -> u64 {
+        chain_value_len(self.chains, entry)
+    }
+
+    /// 完整 value 解码后的长度，压缩日志需要读取各段 value 开头的编码标记
+    fn logical_len(&self, entry: &KeyDirEntry) -> CResult<u64> {
+        let prior = self.chains.get(entry).cloned().unwrap_or_default();
+        let mut len = 0;
+        for (file_id, value_pos, value_len) in prior.iter().chain(std::iter::once(entry)) {
+            len += self.log(*file_id)?.read_logical_len(*value_pos, *value_len)?;
+        }
+        Ok(len)
+    }
+
+    /// 是否有日志文件压缩了 value，此时 value_len 是压缩后的长度
+    fn compressed(&self) -> bool {
+        self.active.codec != ValueCodec::None || self.sealed.values().any(|log| log.codec != ValueCodec::None)
+    }
+
+    /// 读取 entry 本身的 value，不跟随 delta 链
+    fn read_segment(&self, key: &[u8], entry: &KeyDirEntry) -> CResult<Vec<u8>> {
+        let (file_id, value_pos, value_len) = *entry;
+        self.log(file_id)?.read_checked_value(key, value_pos, value_len)
+    }
+
+    /// entry 的写入时间，delta 链为最后一段的写入时间。日志格式不记录写入时间时为 None
+    fn written_at(&self, key: &[u8], entry: &KeyDirEntry) -> CResult<Option<u64>> {
+        let (file_id, value_pos, _) = *entry;
+        self.log(file_id)?.read_written_at(key.len() as u32, value_pos)
+    }
+
+    fn log(&self, file_id: u32) -> CResult<&Log> {
+        if file_id == self.active_id {
+            return Ok(self.active);
+        }
+        self.sealed
+            .get(&file_id)
+            .ok_or_else(|| Error::Internal(format!("log file {} not found", file_id)))
+    }
+}
+
+/// 用于进行范围读取
+pub struct LogScanIterator<'a, I: Index + 'a = KeyDir> {
+    inner: I::Range<'a>,
+    files: LogFiles<'a>,
+    /// 创建迭代器时的时间，此时已过期的key被跳过
+    now: u64,
+}
+
+impl<'a, I: Index> LogScanIterator<'a, I> {
+    /// map函数，调用self.log.read_value()去磁盘当中进行读取，用于将BTreeMap当中的key与offset转换为真实的kv。
+    /// 由于inner和log都是引用类型，因此标注了生命周期
+    fn map(&mut self, item: (&Vec<u8>, &KeyDirEntry)) -> <Self as Iterator>::Item {
+        let (key, entry) = item;
+        Ok((key.clone(), self.files.read_value(key, entry)?))
+    }
+}
+
+impl<'a, I: Index> Iterator for LogScanIterator<'a, I> {
+    type Item = CResult<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (files, now) = (&self.files, self.now);
+        let item = self.inner.find(|(_, entry)| !files.expired(entry, now))?;
+        Some(self.map(item))
+    }
+}
+
+impl<'a, I: Index> DoubleEndedIterator for LogScanIterator<'a, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (files, now) = (&self.files, self.now);
+        let item = self.inner.rfind(|(_, entry)| !files.expired(entry, now))?;
+        Some(self.map(item))
+    }
+}
+
+/// scan_lenient 的迭代器，每一项为 key 以及该 key 的 value 读取结果
+pub struct LogLenientScanIterator<'a, I: Index + 'a = KeyDir> {
+    inner: I::Range<'a>,
+    files: LogFiles<'a>,
+    /// 创建迭代器时的时间，此时已过期的key被跳过
+    now: u64,
+}
+
+impl<'a, I: Index> LogLenientScanIterator<'a, I> {
+    fn map(&mut self, item: (&Vec<u8>, &KeyDirEntry)) -> <Self as Iterator>::Item {
+        let (key, entry) = item;
+        (key.clone(), self.files.read_value(key, entry))
+    }
+}
+
+impl<'a, I: Index> Iterator for LogLenientScanIterator<'a, I> {
+    type Item = (Vec<u8>, CResult<Vec<u8>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (files, now) = (&self.files, self.now);
+        let item = self.inner.find(|(_, entry)| !files.expired(entry, now))?;
+        Some(self.map(item))
+    }
+}
+
+impl<'a, I: Index> DoubleEndedIterator for LogLenientScanIterator<'a, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (files, now) = (&self.files, self.now);
+        let item = self.inner.rfind(|(_, entry)| !files.expired(entry, now))?;
+        Some(self.map(item))
+    }
+}
+
+/// scan_with_meta 的迭代器，每一项为 key、value 以及写入时间
+pub struct LogMetaScanIterator<'a, I: Index + 'a = KeyDir> {
+    inner: I::Range<'a>,
+    files: LogFiles<'a>,
+    /// 创建迭代器时的时间，此时已过期的key被跳过
+    now: u64,
+}
+
+impl<'a, I: Index> LogMetaScanIterator<'a, I> {
+    fn map(&mut self, item: (&Vec<u8>, &KeyDirEntry)) -> <Self as Iterator>::Item {
+        let (key, entry) = item;
+        let written_at = self.files.written_at(key, entry)?.ok_or_else(|| {
+            Error::Value(format!("log format {:?} does not record write times", self.files.active.format))
+        })?;
+        Ok((key.clone(), self.files.read_value(key, entry)?, written_at))
+    }
+}
+
+impl<'a, I: Index> Iterator for LogMetaScanIterator<'a, I> {
+    type Item = CResult<(Vec<u8>, Vec<u8>, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (files, now) = (&self.files, self.now);
+        let item = self.inner.find(|(_, entry)| !files.expired(entry, now))?;
+        Some(self.map(item))
+    }
+}
+
+impl<'a, I: Index> DoubleEndedIterator for LogMetaScanIterator<'a, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (files, now) = (&self.files, self.now);
+        let item = self.inner.rfind(|(_, entry)| !files.expired(entry, now))?;
+        Some(self.map(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::ops::Bound;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use rand::Rng;
+    use byteorder::ReadBytesExt;
+    use bytes::{BufMut, BytesMut};
+    use serde_derive::{Deserialize, Serialize};
+    use crate::codec::json_codec::JsonCodec;
+    use crate::codec::{Codec,};
+    use crate::codec::bytes_codec::BytesCodec;
+    use crate::error::{CResult, Error};
+    use crate::storage::engine::Engine;
+    use crate::storage::key_transform::KeyTransform;
+    use crate::storage::log::{Log, LogFormat};
+    use crate::storage::log_cask::{Checkpoint, CompactionEstimate, LogCask, SyncPolicy};
+    use crate::storage::Status;
+
+    super::super::tests::test_engine!({
+        let path = tempdir::TempDir::new("demo")?.path().join("whosdb");
+        LogCask::new(path)?
+    });
+
+    /// 使用带类型字节的 V2 日志格式时，引擎语义保持不变
+    mod log_format_v2 {
+        use crate::error::CResult;
+        use crate::storage::engine::Engine;
+        use crate::storage::log::LogFormat;
+        use crate::storage::log_cask::LogCask;
+
+        super::super::super::tests::test_engine!({
+            let path = tempdir::TempDir::new("demo")?.path().join("whosdb");
+            LogCask::new_with_format(path, LogFormat::V2)?
+        });
+
+        #[test]
+        /// Tests status and compaction sizes for the v2 format, and that the format survives compaction.
+        fn status_compact() -> CResult<()> {
+            let dir = tempdir::TempDir::new("demo")?;
+            let path = dir.path().join("v2");
+            let mut s = LogCask::new_with_format(path.clone(), LogFormat::V2)?;
+            s.set(b"a", vec![1])?;
+            s.set(b"a", vec![2])?;
+            s.delete(b"b")?;
+
+            // header 8, entries 9 + 1 + 1 each, tombstone 9 + 1
+            let status = s.status()?;
+            assert_eq!(status.total_disk_size, 8 + 11 + 11 + 10);
+            assert_eq!(status.live_disk_size, 8 + 11);
+            assert_eq!(s.compaction_estimate()?.reclaimable_bytes, status.garbage_disk_size);
+
+            s.compact()?;
+            assert_eq!(s.status()?.total_disk_size, 8 + 11);
+            drop(s);
+
+            let mut s = LogCask::new_with_lock(path, false)?;
+            assert_eq!(s.get(b"a")?, Some(vec![2]));
+            assert_eq!(s.status()?.garbage_disk_size, 0);
+            Ok(())
+        }
+
+        #[test]
+        /// Tests that delta appends grow the log by the appended bytes only, read back the full value
+        /// (also after reopening), and are collapsed into a single value by compaction.
+        fn delta_appends() -> CResult<()> {
+            let dir = tempdir::TempDir::new("demo")?;
+            let expect: Vec<u8> = (0..100u8).flat_map(|i| [i; 8]).collect();
+
+            let mut full = LogCask::new_with_format(dir.path().join("full"), LogFormat::V2)?;
+            let path = dir.path().join("delta");
+            let mut s = LogCask::new_with_format(path.clone(), LogFormat::V2)?;
+            s.set_delta_appends(true)?;
+            for i in 0..100u8 {
+                assert_eq!(full.append(b"k", &[i; 8])?, 8 * (i as u64 + 1));
+                assert_eq!(s.append(b"k", &[i; 8])?, 8 * (i as u64 + 1));
+            }
+            s.set(b"other", vec![1])?;
+
+            // full rewrites: 8 + sum(10 + 8i); deltas: 8 + 100 * (10 + 8) + 15
+            let full_size = full.status()?.total_disk_size;
+            assert_eq!(full_size, 8 + 100 * 10 + 8 * 5050);
+            let status = s.status()?;
+            assert_eq!(status.total_disk_size, 8 + 100 * 18 + 15);
+            assert_eq!(status.size, 1 + 800 + 5 + 1);
+            assert_eq!(s.compaction_estimate()?.reclaimable_bytes, status.garbage_disk_size);
+
+            assert_eq!(s.get(b"k")?, Some(expect.clone()));
+            assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, vec![(b"k".to_vec(), expect.clone()), (b"other".to_vec(), vec![1])]);
+            assert_eq!(s.keys_with_value(&expect)?, vec![b"k".to_vec()]);
+            drop(s);
+
+            // the delta chain is rebuilt from the log
+            let mut s = LogCask::new_with_lock(path.clone(), false)?;
+            s.set_delta_appends(true)?;
+            assert_eq!(s.get(b"k")?, Some(expect.clone()));
+            assert_eq!(s.append(b"k", b"!")?, 801);
+            s.compact()?;
+            assert!(s.chains.is_empty());
+            assert_eq!(s.status()?.total_disk_size, 8 + 10 + 801 + 15);
+            drop(s);
+
+            let mut s = LogCask::new_with_lock(path, false)?;
+            assert_eq!(s.get(b"k")?, Some([expect, b"!".to_vec()].concat()));
+
+            // a set or delete replaces the chain
+            s.set_delta_appends(true)?;
+            s.append(b"k", b"?")?;
+            s.set(b"k", vec![7])?;
+            assert!(s.chains.is_empty());
+            assert_eq!(s.get(b"k")?, Some(vec![7]));
+            Ok(())
+        }
+    }
+
+    /// 使用 HashMap 作为内存索引时，引擎语义(包括有序扫描)保持不变
+    mod hash_index {
+        use crate::error::CResult;
+        use crate::storage::engine::Engine;
+        use crate::storage::index::HashKeyDir;
+        use crate::storage::log_cask::LogCask;
+
+        super::super::super::tests::test_engine!({
+            let path = tempdir::TempDir::new("demo")?.path().join("whosdb");
+            LogCask::<HashKeyDir>::new_with_index(path, true)?
+        });
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Persion {
+        name: String,
+
+        age: i16,
+
+        address: String,
+    }
+
+    fn setup() -> CResult<LogCask> {
+        let path = tempdir::TempDir::new("demo")?.path().join("mydb");
+        println!("path:{:?}", &path);
+
+        LogCask::new_with_lock(path, false)
+    }
+
+    /// Writes various values primarily for testing log file handling.
+    ///
+    /// - '': empty key and value
+    /// - a: write
+    /// - b: write, write
+    /// - c: write, delete, write
+    /// - d: delete, write
+    /// - e: write, delete
+    /// - f: delete
+    fn setup_log(s: &mut LogCask) -> CResult<()> {
+        s.set(b"b", vec![0x01])?;
+        s.set(b"b", vec![0x02])?;
+
+        s.set(b"e", vec![0x05])?;
+        s.delete(b"e")?;
+
+        s.set(b"c", vec![0x00])?;
+        s.delete(b"c")?;
+        s.set(b"c", vec![0x03])?;
+
+        s.set(b"", vec![])?;
+
+        s.set(b"a", vec![0x01])?;
+
+        s.delete(b"f")?;
 
         s.delete(b"d")?;
         s.set(b"d", vec![0x04])?;
 
-        // Make sure the scan yields the expected results.
-        assert_eq!(
-            vec![
-                (b"".to_vec(), vec![]),
-                (b"a".to_vec(), vec![0x01]),
-                (b"b".to_vec(), vec![0x02]),
-                (b"c".to_vec(), vec![0x03]),
-                (b"d".to_vec(), vec![0x04]),
-            ],
-            s.scan(..).collect::<Result<Vec<_>,Error>> ()?,
-        );
+        // Make sure the scan yields the expected results.
+        assert_eq!(
+            vec![
+                (b"".to_vec(), vec![]),
+                (b"a".to_vec(), vec![0x01]),
+                (b"b".to_vec(), vec![0x02]),
+                (b"c".to_vec(), vec![0x03]),
+                (b"d".to_vec(), vec![0x04]),
+            ],
+            s.scan(..).collect::<Result<Vec<_>,Error>> ()?,
+        );
+
+        let rs = s.flush();
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that writing and then reading a file yields the same results.
+    fn reopen() -> CResult<()> {
+        // NB: Don't use setup(), because the tempdir will be removed when
+        // the path falls out of scope.
+        let path = tempdir::TempDir::new("demo")?.path().join("adb");
+        let mut s = LogCask::new(path.clone())?;
+        setup_log(&mut s)?;
+
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        drop(s);
+        let s = LogCask::new(path)?;
+        assert_eq!(expect, s.scan(..).collect::<CResult<Vec<_>>>()?,);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that new_compact() will automatically compact the file when appropriate.
+    fn new_compact() -> CResult<()> {
+        // Create an initial log file with a few entries.
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("orig");
+        let compactpath = dir.path().join("compact");
+
+        let mut s = LogCask::new_compact(path.clone(), 0.2)?;
+        setup_log(&mut s)?;
+        let status = s.status()?;
+        let garbage_ratio = status.garbage_disk_size as f64 / status.total_disk_size as f64;
+        drop(s);
+
+        // Test a few threshold value and assert whether it should trigger compaction.
+        let cases = vec![
+            (-1.0, true),
+            (0.0, true),
+            (garbage_ratio - 0.001, true),
+            (garbage_ratio, true),
+            (garbage_ratio + 0.001, false),
+            (1.0, false),
+            (2.0, false),
+        ];
+        for (threshold, expect_compact) in cases.into_iter() {
+            std::fs::copy(&path, &compactpath)?;
+            let mut s = LogCask::new_compact(compactpath.clone(), threshold)?;
+            let new_status = s.status()?;
+            assert_eq!(new_status.live_disk_size, status.live_disk_size);
+            if expect_compact {
+                assert_eq!(new_status.total_disk_size, status.live_disk_size);
+                assert_eq!(new_status.garbage_disk_size, 0);
+            } else {
+                assert_eq!(new_status, status);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that new_compact_min_reclaim() skips compaction when too few bytes would be reclaimed.
+    fn new_compact_min_reclaim() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("orig");
+        let compactpath = dir.path().join("compact");
+
+        let mut s = LogCask::new(path.clone())?;
+        setup_log(&mut s)?;
+        let status = s.status()?;
+        assert!(status.garbage_disk_size > 0);
+        drop(s);
+
+        // the ratio threshold is met, but the garbage is below the byte floor
+        std::fs::copy(&path, &compactpath)?;
+        let mut s = LogCask::new_compact_min_reclaim(compactpath.clone(), 0.0, status.garbage_disk_size + 1)?;
+        assert_eq!(s.status()?, status);
+        drop(s);
+
+        let mut s = LogCask::new_compact_min_reclaim(compactpath, 0.0, status.garbage_disk_size)?;
+        assert_eq!(s.status()?.garbage_disk_size, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that exclusive locks are taken out on log files, released when the
+    /// cask is closed, and that an error is returned if a lock is already
+    /// held.
+    fn log_lock() -> CResult<()> {
+        let path = tempdir::TempDir::new("demo")?.path().join("t_app");
+        let s = LogCask::new(path.clone())?;
+
+        assert!(LogCask::new(path.clone()).is_err());
+        drop(s);
+        assert!(LogCask::new(path.clone()).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that an incomplete write at the end of the log file can be
+    /// recovered by discarding the last entry.
+    fn recovery() -> CResult<()> {
+        // Create an initial log file with a few entries.
+        let dir = tempdir::TempDir::new("demmo")?;
+        let path = dir.path().join("complete");
+        let truncpath = dir.path().join("truncated");
+
+        let mut log = Log::new(path.clone())?;
+        let ends = vec![
+            log.write_entry("deleted".as_bytes(), Some(&[1, 2, 3]))?.entry_end(),
+            log.write_entry("deleted".as_bytes(), None)?.entry_end(),
+            log.write_entry(&[], Some(&[]))?.entry_end(),
+            log.write_entry("key".as_bytes(), Some(&[1, 2, 3, 4, 5]))?.entry_end(),
+        ];
+
+        drop(log);
+
+        // Copy the file, and truncate it at each byte, then try to open it
+        // and assert that we always retain a prefix of entries.
+        let size = std::fs::metadata(&path)?.len();
+        for pos in 0..=size {
+            std::fs::copy(&path, &truncpath)?;
+            let f = std::fs::OpenOptions::new().write(true).open(&truncpath)?;
+            f.set_len(pos)?;
+            drop(f);
+
+            let mut expect = vec![];
+            if pos >= ends[0] {
+                expect.push((b"deleted".to_vec(), vec![1, 2, 3]))
+            }
+            if pos >= ends[1] {
+                expect.pop(); // "deleted" key removed
+            }
+            if pos >= ends[2] {
+                expect.push((b"".to_vec(), vec![]))
+            }
+            if pos >= ends[3] {
+                expect.push((b"key".to_vec(), vec![1, 2, 3, 4, 5]))
+            }
+
+            let s = LogCask::new(truncpath.clone())?;
+            assert_eq!(expect, s.scan(..).collect::<CResult<Vec<_>>>()?);
+        }
+
+        // With checksums, flipping a random bit in a value must be detected both
+        // when opening the log and when reading a value from an already open log.
+        let sumpath = dir.path().join("checksum");
+        let corruptpath = dir.path().join("corrupt");
+        let mut log = Log::new_with_checksum(sumpath.clone(), true)?;
+        log.write_entry("deleted".as_bytes(), Some(&[1, 2, 3]))?;
+        let loc = log.write_entry("key".as_bytes(), Some(&[1, 2, 3, 4, 5]))?;
+        drop(log);
+
+        let flip_bit = |path: &PathBuf| -> CResult<()> {
+            let mut rng = rand::thread_rng();
+            let pos = loc.value_pos + rng.gen_range(0..loc.value_len as u64);
+            let mut bytes = std::fs::read(path)?;
+            bytes[pos as usize] ^= 1 << rng.gen_range(0..8);
+            std::fs::write(path, bytes)?;
+            Ok(())
+        };
+        for _ in 0..8 {
+            std::fs::copy(&sumpath, &corruptpath)?;
+            flip_bit(&corruptpath)?;
+            assert!(matches!(LogCask::new(corruptpath.clone()), Err(Error::Corruption(_))));
+
+            std::fs::copy(&sumpath, &corruptpath)?;
+            let s = LogCask::new(corruptpath.clone())?;
+            assert_eq!(s.get(b"deleted")?, Some(vec![1, 2, 3]));
+            flip_bit(&corruptpath)?;
+            assert!(matches!(s.get(b"key"), Err(Error::Corruption(_))));
+            assert_eq!(s.get(b"deleted")?, Some(vec![1, 2, 3]));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests status(), both for a log file with known garbage, and
+    /// after compacting it when the live size must equal the file size.
+    fn test_status_full() -> CResult<()> {
+        let mut s = setup()?;
+        setup_log(&mut s)?;
+
+        // Before compaction.
+        assert_eq!(
+            s.status()?,
+            Status {
+                name: "log cask".to_string(),
+                keys: 5,
+                size: 8,
+                total_disk_size: 114,
+                live_disk_size: 48,
+                garbage_disk_size: 66
+            }
+        );
+
+        // After compaction.
+        s.compact()?;
+        assert_eq!(
+            s.status()?,
+            Status {
+                name: "log cask".to_string(),
+                keys: 5,
+                size: 8,
+                total_disk_size: 48,
+                live_disk_size: 48,
+                garbage_disk_size: 0,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_log() -> CResult<()> {
+        let mut s = setup().unwrap();
+        setup_log(&mut s).unwrap();
+
+        let stat = s.status().unwrap();
+        println!("stat:{:?}", stat);
+
+        // write 4k
+        let mut _4k = Vec::<u8>::with_capacity(1024 * 4);
+        for i in 0..1024*4 {
+            _4k.push(0);
+        }
+        s.set("4k".as_bytes(), _4k)?;
+        s.flush().unwrap();
+
+        let stat = s.status().unwrap();
+        println!("stat:{:?}", stat);
+
+        // test_load_from_log_file
+        let cask = LogCask::new_with_lock(PathBuf::from(s.get_path().unwrap()), false).unwrap();
+        let get = cask.get(b"b");
+        assert!(get.is_ok());
+        let get_val = get.unwrap().unwrap();
+        assert_eq!(get_val, vec![0x02]);
+
+        let get_4k = cask.get("4k".as_bytes());
+        assert!(get_4k.is_ok());
+        let get_4k_val = get_4k.unwrap().unwrap();
+        assert_eq!(get_4k_val.len(), 1024*4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_with_bytes_persion() {
+        let codec = BytesCodec::new();
+
+        let mut log_cask = setup().unwrap();
+
+        let persion_key = "persion_cache_key";
+
+        let mut list_for_cache = Vec::<Persion>::new();
+        let mut buf = BytesMut::with_capacity(1024);
+        for i in 0..1 {
+            let p = Persion {
+                name: format!("name{}", i),
+                age: i % 85,
+                address: format!("address{}", i),
+            };
+            list_for_cache.push(p.clone());
+
+            let b = codec.encode(&p).unwrap();
+            buf.put(b.as_slice());
+        }
+
+        log_cask.set(persion_key.as_bytes(), buf.to_vec()).unwrap();
+        log_cask.flush().unwrap();
+
+        let stat = log_cask.status().unwrap();
+        println!("stat:{:?}", stat);
+
+        // test_load_from_log_file
+        let save_path = log_cask.get_path().unwrap();
+
+        let two_cask = LogCask::new_with_lock(PathBuf::from(save_path), false).unwrap();
+        let persion_list = two_cask.get(persion_key.as_bytes());
+        assert!(persion_list.is_ok());
+        let persion_list_val = persion_list.unwrap().unwrap();
+
+        let mut i_for_test = 0;
+        let mut cursor = Cursor::new(persion_list_val.as_slice());
+        loop {
+            if cursor.position() >= cursor.get_ref().len() as u64 {
+                break;
+            }
+
+            let len = cursor.read_u64::<byteorder::BigEndian>().unwrap() as usize;
+            let mut by = vec![0; len];
+            cursor.read_exact(&mut by).unwrap();
+
+            let r: Persion = codec.decode_bytes(&by, false).unwrap();
+            println!("{:?}", r);
+
+            let cache_p = list_for_cache.get(i_for_test).unwrap();
+            assert_eq!(&r.name, &cache_p.name);
+            assert_eq!(&r.address, &cache_p.address);
+            assert_eq!(&r.age, &cache_p.age);
+
+            i_for_test += 1;
+        }
+
+        assert_eq!(1, 1);
+    }
+
+    #[test]
+    /// Tests that max_unsynced_bytes triggers a sync before the EveryN count is reached.
+    fn max_unsynced_bytes() -> CResult<()> {
+        let mut s = setup()?;
+        s.set_sync_policy(SyncPolicy::EveryN(100));
+        s.set_max_unsynced_bytes(Some(64));
+
+        // each entry is 4 + 4 + 1 + 16 = 25 bytes
+        s.set(b"a", vec![0; 16])?;
+        s.set(b"b", vec![0; 16])?;
+        assert_eq!(s.sync_count(), 0);
+        assert_eq!(s.unsynced_bytes(), 50);
+
+        s.set(b"c", vec![0; 16])?;
+        assert_eq!(s.sync_count(), 1);
+        assert_eq!(s.unsynced_bytes(), 0);
+
+        // without the threshold, only the EveryN policy applies
+        s.set_max_unsynced_bytes(None);
+        for i in 0..10u8 {
+            s.set(&[i], vec![0; 16])?;
+        }
+        assert_eq!(s.sync_count(), 1);
+        assert_eq!(s.unsynced_bytes(), 250);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that compaction_estimate() matches status() and the space an actual compact() frees.
+    fn compaction_estimate() -> CResult<()> {
+        let mut s = setup()?;
+        setup_log(&mut s)?;
+
+        let status = s.status()?;
+        let estimate = s.compaction_estimate()?;
+        assert_eq!(
+            estimate,
+            CompactionEstimate { reclaimable_bytes: status.garbage_disk_size, live_entries: 5, live_bytes: status.live_disk_size }
+        );
+        // the estimate itself must not touch the file
+        assert_eq!(s.status()?, status);
+
+        s.compact()?;
+        let compacted = s.status()?;
+        assert_eq!(status.total_disk_size - compacted.total_disk_size, estimate.reclaimable_bytes);
+        assert_eq!(compacted.total_disk_size, estimate.live_bytes);
+        assert_eq!(s.compaction_estimate()?.reclaimable_bytes, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that utf8_keys rejects non UTF-8 keys on set/delete, and only when enabled.
+    fn utf8_keys() -> CResult<()> {
+        let mut s = setup()?;
+        let invalid = b"key\xff";
+
+        // binary keys are accepted by default
+        s.set(invalid, vec![1])?;
+        assert_eq!(s.get(invalid)?, Some(vec![1]));
+
+        s.set_utf8_keys(true);
+        s.set("键".as_bytes(), vec![2])?;
+        assert_eq!(s.get("键".as_bytes())?, Some(vec![2]));
+
+        assert_eq!(s.set(invalid, vec![3]), Err(Error::Value("key is not valid UTF-8".to_string())));
+        assert_eq!(s.delete(invalid), Err(Error::Value("key is not valid UTF-8".to_string())));
+        assert_eq!(s.get(invalid)?, Some(vec![1]));
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that scan_keys yields the same keys in the same order as scan, including across segments and with expired keys.
+    fn scan_keys() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new_with_format(dir.path().join("keys"), LogFormat::V2)?;
+        s.set_max_file_size(Some(64));
+        setup_log(&mut s)?;
+        for i in (0..20).rev() {
+            s.set(format!("k{:02}", i).as_bytes(), vec![i; 16])?;
+        }
+        s.delete(b"k07")?;
+        s.set_ex(b"k11", vec![0x11], std::time::Duration::from_millis(1))?;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(!s.sealed.is_empty());
+
+        let ranges = [
+            (Bound::Unbounded, Bound::Unbounded),
+            (Bound::Included(b"b".to_vec()), Bound::Excluded(b"k10".to_vec())),
+            (Bound::Excluded(b"k05".to_vec()), Bound::Included(b"k15".to_vec())),
+        ];
+        for range in ranges {
+            let expect = s.scan(range.clone()).map(|item| item.map(|(key, _)| key)).collect::<CResult<Vec<_>>>()?;
+            assert_eq!(s.scan_keys(range).collect::<CResult<Vec<_>>>()?, expect);
+        }
+        let keys = s.scan_keys(..).collect::<CResult<Vec<_>>>()?;
+        assert_eq!(keys.len(), 5 + 18);
+        assert!(!keys.contains(&b"k07".to_vec()) && !keys.contains(&b"k11".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that scan_lenient reports an unreadable value as an error item and keeps scanning.
+    fn scan_lenient() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("lenient");
+        let mut s = LogCask::new_with_lock(path.clone(), false)?;
+        s.set(b"c", vec![0x03])?;
+        s.set(b"a", vec![0x01])?;
+        s.set(b"b", vec![0x02; 8])?;
+
+        // truncate the file externally, cutting into the value of b (the last entry)
+        let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+        file.set_len(file.metadata()?.len() - 4)?;
+
+        let items = s.scan_lenient(..).collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0], (b"a".to_vec(), Ok(vec![0x01])));
+        assert_eq!(items[1].0, b"b".to_vec());
+        assert!(items[1].1.is_err());
+        assert_eq!(items[2], (b"c".to_vec(), Ok(vec![0x03])));
+
+        // the strict scan surfaces the same error
+        assert!(s.scan(..).collect::<CResult<Vec<_>>>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests the read/write byte counters exposed by status_detailed().
+    fn status_detailed() -> CResult<()> {
+        let mut s = setup()?;
+        let before = s.status_detailed()?;
+        assert_eq!((before.bytes_read, before.bytes_written), (0, 0));
+
+        // entry size: 4 + 4 + key 3 + value 5
+        s.set(b"foo", vec![1; 5])?;
+        let after_set = s.status_detailed()?;
+        assert_eq!(after_set.bytes_written, 16);
+        assert_eq!(after_set.bytes_read, 0);
+
+        s.get(b"foo")?;
+        let after_get = s.status_detailed()?;
+        assert_eq!(after_get.bytes_read, 5);
+        assert_eq!(after_get.bytes_written, 16);
+        assert_eq!(after_get.status, s.status()?);
+
+        // compaction rewrites the live data, and the counters are cumulative
+        s.compact()?;
+        let after_compact = s.status_detailed()?;
+        assert_eq!(after_compact.bytes_read, 10);
+        assert_eq!(after_compact.bytes_written, 32);
+
+        // after a reset the counters only reflect the subsequent activity
+        s.reset_counters();
+        s.set(b"bar", vec![2; 5])?;
+        s.get(b"foo")?;
+        let after_reset = s.status_detailed()?;
+        assert_eq!((after_reset.bytes_read, after_reset.bytes_written), (5, 16));
+        assert_eq!(after_reset.status, s.status()?);
+        assert_eq!(after_reset.status.keys, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "sha256-key")]
+    /// Tests that set/get/delete apply the key transform, and the log stores the transformed key.
+    fn key_transform() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("transform");
+        let mut s = LogCask::new_with_lock(path.clone(), false)?;
+        s.set_key_transform(KeyTransform::Sha256Hex);
+
+        s.set(b"abc", vec![1, 2, 3])?;
+        assert_eq!(s.get(b"abc")?, Some(vec![1, 2, 3]));
+        assert_eq!(s.append(b"abc", &[4])?, 4);
+        assert_eq!(s.get(b"abc")?, Some(vec![1, 2, 3, 4]));
+
+        // scans return the stored key, the hex digest
+        let digest = b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_vec();
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, vec![(digest.clone(), vec![1, 2, 3, 4])]);
+        drop(s);
+
+        let mut s = LogCask::new_with_lock(path, false)?;
+        assert_eq!(s.get(&digest)?, Some(vec![1, 2, 3, 4]));
+        assert_eq!(s.get(b"abc")?, None);
+        s.set_key_transform(KeyTransform::Sha256Hex);
+        assert_eq!(s.get(b"abc")?, Some(vec![1, 2, 3, 4]));
+        s.delete(b"abc")?;
+        assert_eq!(s.status()?.keys, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that LowerAscii makes keys case insensitive, and scans return the lowercased key.
+    fn key_transform_lower_ascii() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new_with_lock(dir.path().join("lower"), false)?;
+        s.set_key_transform(KeyTransform::LowerAscii);
+        s.set(b"Foo", vec![1])?;
+        assert_eq!(s.get(b"FOO")?, Some(vec![1]));
+        assert_eq!(s.scan(..).map(|r| r.map(|(k, _)| k)).collect::<CResult<Vec<_>>>()?, vec![b"foo".to_vec()]);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that several threads can read a LogCask shared through an Arc, across sealed and active files and the read cache.
+    fn concurrent_readers() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new_with_format(dir.path().join("shared"), LogFormat::V2)?;
+        s.set_read_cache_size(4096);
+        s.set_write_buffer_size(1024)?;
+        for i in 0..500u32 {
+            s.set(&i.to_be_bytes(), format!("value-{}", i).into_bytes())?;
+            if i == 250 {
+                s.roll_over()?;
+            }
+        }
+        s.set_delta_appends(true)?;
+        s.append(&7u32.to_be_bytes(), b"+")?;
+
+        let s = Arc::new(s);
+        let readers = (0..8u32)
+            .map(|t| {
+                let s = s.clone();
+                std::thread::spawn(move || -> CResult<()> {
+                    let mut rng = rand::thread_rng();
+                    for _ in 0..2000 {
+                        let i = rng.gen_range(0..520u32);
+                        let expect = match i {
+                            7 => Some(b"value-7+".to_vec()),
+                            i if i < 500 => Some(format!("value-{}", i).into_bytes()),
+                            _ => None,
+                        };
+                        assert_eq!(s.get(&i.to_be_bytes())?, expect, "reader {} key {}", t, i);
+                    }
+                    assert_eq!(s.scan(..).count(), 500);
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for reader in readers {
+            reader.join().unwrap()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that reads see writes still held in the write buffer, and that the buffer reaches the file on sync and close.
+    fn read_your_writes() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("buffered");
+        let mut s = LogCask::new_with_lock(path.clone(), false)?;
+        s.set_write_buffer_size(1 << 20)?;
+
+        s.set(b"a", vec![1; 8])?;
+        assert_eq!(std::fs::metadata(&path)?.len(), 0);
+        assert_eq!(s.status()?.total_disk_size, 17);
+        // the value is read from the buffer, which stays unwritten
+        assert_eq!(s.get(b"a")?, Some(vec![1; 8]));
+        assert_eq!(std::fs::metadata(&path)?.len(), 0);
+
+        s.set(b"b", vec![2; 8])?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, vec![(b"a".to_vec(), vec![1; 8]), (b"b".to_vec(), vec![2; 8])]);
+
+        // without read_your_writes a buffered value is not readable until flushed
+        s.set_read_your_writes(false)?;
+        s.set(b"c", vec![3; 8])?;
+        assert!(s.get(b"c").is_err());
+        s.flush()?;
+        assert_eq!(s.get(b"c")?, Some(vec![3; 8]));
+
+        s.set(b"d", vec![4; 8])?;
+        s.compact()?;
+        assert_eq!(s.get(b"d")?, Some(vec![4; 8]));
+        s.set(b"e", vec![5; 8])?;
+        drop(s);
+
+        let mut s = LogCask::new_with_lock(path, false)?;
+        assert_eq!(s.status()?.keys, 5);
+        assert_eq!(s.get(b"e")?, Some(vec![5; 8]));
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that entries appended while the write buffer overflows and flushes are recorded at their file positions.
+    fn write_buffer_overflow() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("buffered");
+        let mut s = LogCask::new_with_format(path.clone(), LogFormat::V3)?;
+        s.set_write_buffer_size(100)?;
+
+        let expect = (0..50u8).map(|i| (format!("key{:02}", i).into_bytes(), vec![i; 20])).collect::<Vec<_>>();
+        for (key, value) in &expect {
+            s.set(key, value.clone())?;
+        }
+        assert!(std::fs::metadata(&path)?.len() > 0);
+        for (key, value) in &expect {
+            assert_eq!(s.get(key)?.as_ref(), Some(value));
+        }
+        drop(s);
+
+        let s = LogCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that warmup preloads values of the given prefixes, so that their first get reads nothing from disk.
+    fn warmup() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("warmup");
+        let mut s = LogCask::new_with_lock(path.clone(), false)?;
+        s.set(b"hot:1", vec![1; 8])?;
+        s.set(b"hot:2", vec![2; 8])?;
+        s.set(b"cold", vec![3; 8])?;
+        drop(s);
+
+        let mut s = LogCask::new_with_lock(path, false)?;
+        assert_eq!(s.warmup(&[b"hot:".to_vec()])?, 0); // no cache configured
+        s.set_read_cache_size(1024);
+        assert_eq!(s.warmup(&[b"hot:".to_vec()])?, 2);
+
+        s.reset_counters();
+        assert_eq!(s.get(b"hot:1")?, Some(vec![1; 8]));
+        assert_eq!(s.get(b"hot:2")?, Some(vec![2; 8]));
+        assert_eq!(s.status_detailed()?.bytes_read, 0);
+        assert_eq!(s.get(b"cold")?, Some(vec![3; 8]));
+        assert_eq!(s.status_detailed()?.bytes_read, 8);
+
+        // writes invalidate the cached value
+        s.set(b"hot:1", vec![4; 8])?;
+        assert_eq!(s.get(b"hot:1")?, Some(vec![4; 8]));
+        s.delete(b"hot:2")?;
+        assert_eq!(s.get(b"hot:2")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that sample_keys returns distinct live keys with their value sizes.
+    fn sample_keys() -> CResult<()> {
+        let mut s = setup()?;
+        for i in 0..100u8 {
+            s.set(&[i], vec![0; i as usize])?;
+        }
+        s.delete(&[0])?;
+
+        let sample = s.sample_keys(10);
+        assert_eq!(sample.len(), 10);
+        let mut keys: Vec<_> = sample.iter().map(|(key, _)| key.clone()).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 10);
+        for (key, size) in &sample {
+            assert_ne!(key, &vec![0]);
+            assert_eq!(*size, key[0] as u64);
+        }
+
+        // a sample larger than the keyspace returns every key
+        let mut all = s.sample_keys(1000);
+        all.sort();
+        assert_eq!(all, (1..100u8).map(|i| (vec![i], i as u64)).collect::<Vec<_>>());
+        assert!(s.sample_keys(0).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that backup_before_compact keeps rotated copies of the pre-compaction log.
+    fn backup_before_compact() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("backup");
+        let mut s = LogCask::new_with_lock(path.clone(), false)?;
+        s.set_backup_before_compact(true);
+        s.set_max_backups(2);
+
+        setup_log(&mut s)?;
+        let first = std::fs::read(&path)?;
+        s.compact()?;
+        assert_eq!(std::fs::read(s.backup_path(0))?, first);
+        assert!(!s.backup_path(1).exists());
+
+        s.set(b"z", vec![0x1a])?;
+        s.delete(b"a")?;
+        let second = std::fs::read(&path)?;
+        s.compact()?;
+        assert_eq!(std::fs::read(s.backup_path(0))?, second);
+        assert_eq!(std::fs::read(s.backup_path(1))?, first);
+
+        // the oldest backup is dropped once max_backups is reached
+        s.compact()?;
+        assert_eq!(std::fs::read(s.backup_path(1))?, second);
+        assert!(!s.backup_path(2).exists());
+        assert_eq!(s.backup_path(0), dir.path().join("backup.bak"));
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that max_file_size rolls the active log over into sealed files, that reads
+    /// span all files before and after reopening, and that compaction merges them.
+    fn max_file_size() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("rollover");
+        let mut s = LogCask::new(path.clone())?;
+        s.set_max_file_size(Some(64));
+
+        // each entry is 4 + 4 + 2 + 16 = 26 bytes, so a file is sealed after every third write
+        let mut expect = std::collections::BTreeMap::new();
+        for i in 0..10u8 {
+            s.set(&[b'k', i], vec![i; 16])?;
+            expect.insert(vec![b'k', i], vec![i; 16]);
+        }
+        assert_eq!(s.file_count(), 4);
+        assert!(dir.path().join("rollover.0").exists());
+        assert!(dir.path().join("rollover.2").exists());
+
+        // overwrite and delete keys living in sealed files
+        s.set(&[b'k', 0], vec![0xff])?;
+        expect.insert(vec![b'k', 0], vec![0xff]);
+        s.delete(&[b'k', 4])?;
+        expect.remove(&vec![b'k', 4]);
+
+        let expect: Vec<_> = expect.into_iter().collect();
+        assert_eq!(s.get(&[b'k', 1])?, Some(vec![1; 16]));
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
+
+        let mut s = LogCask::new(path.clone())?;
+        assert_eq!(s.file_count(), 4);
+        assert_eq!(s.get(&[b'k', 4])?, None);
+        assert_eq!(s.scan(..).rev().collect::<CResult<Vec<_>>>()?, expect.iter().cloned().rev().collect::<Vec<_>>());
+
+        let status = s.status()?;
+        assert_eq!(status.total_disk_size, 10 * 26 + 11 + 10);
+        s.compact()?;
+        assert_eq!(s.file_count(), 1);
+        assert!(!dir.path().join("rollover.0").exists());
+        assert_eq!(s.status()?.total_disk_size, status.live_disk_size);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
+
+        let s = LogCask::new(path)?;
+        assert_eq!(s.file_count(), 1);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that self_check reports no keys for a healthy store, and the keys
+    /// whose values are cut off after the file is truncated behind its back.
+    fn self_check() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("check");
+        let mut s = LogCask::new(path.clone())?;
+        setup_log(&mut s)?;
+        assert_eq!(s.self_check()?, Vec::<Vec<u8>>::new());
+
+        s.set(b"x", vec![1, 2, 3])?;
+        s.set(b"y", vec![4, 5, 6])?;
+        let len = std::fs::metadata(&path)?.len();
+        std::fs::OpenOptions::new().write(true).open(&path)?.set_len(len - 13)?;
+        assert_eq!(s.self_check()?, vec![b"x".to_vec(), b"y".to_vec()]);
+        assert_eq!(s.get(b"a")?, Some(vec![0x01]));
+
+        // with checksums, a flipped bit is reported as well
+        let mut s = LogCask::new_with_format(dir.path().join("checksum"), LogFormat::V3)?;
+        s.set(b"w", vec![0])?;
+        s.set(b"z", vec![7, 8, 9])?;
+        let mut bytes = std::fs::read(dir.path().join("checksum"))?;
+        let last_value_byte = bytes.len() - 4 - 1;
+        bytes[last_value_byte] ^= 0x10;
+        std::fs::write(dir.path().join("checksum"), bytes)?;
+        assert_eq!(s.self_check()?, vec![b"z".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that V4 logs record write times, and that compaction and reopening keep them.
+    fn get_with_meta() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("meta");
+        let now = || std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        let mut s = LogCask::new_with_format(path.clone(), LogFormat::V4)?;
+        let before = now();
+        s.set(b"a", vec![1])?;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        s.set(b"b", vec![2])?;
+        s.set(b"b", vec![3])?;
+        s.delete(b"c")?;
+        let after = now();
+
+        let (value, a_at) = s.get_with_meta(b"a")?.unwrap();
+        assert_eq!(value, vec![1]);
+        assert!(before <= a_at && a_at <= after);
+        let (_, b_at) = s.get_with_meta(b"b")?.unwrap();
+        assert!(a_at < b_at && b_at <= after);
+        assert_eq!(s.get_with_meta(b"c")?, None);
+
+        let expect = vec![(b"a".to_vec(), vec![1], a_at), (b"b".to_vec(), vec![3], b_at)];
+        assert_eq!(s.scan_with_meta(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        // compaction carries the original write times forward
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        s.compact()?;
+        assert_eq!(s.status()?.garbage_disk_size, 0);
+        assert_eq!(s.scan_with_meta(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
+
+        let s = LogCask::new(path)?;
+        assert_eq!(s.scan_with_meta(..).rev().collect::<CResult<Vec<_>>>()?, expect.into_iter().rev().collect::<Vec<_>>());
+
+        // other formats do not record write times
+        let mut s = LogCask::new(dir.path().join("v1"))?;
+        s.set(b"a", vec![1])?;
+        assert!(s.get_with_meta(b"a").is_err());
+        assert!(s.scan_with_meta(..).all(|item| item.is_err()));
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that keys written with set_ex expire from get, scan and status, survive a reopen
+    /// until they expire, and are dropped by compaction.
+    fn set_ex() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("ttl");
+        let short = std::time::Duration::from_millis(50);
+        let long = std::time::Duration::from_secs(3600);
+
+        let mut s = LogCask::new_with_format(path.clone(), LogFormat::V2)?;
+        s.set(b"a", vec![1])?;
+        s.set_ex(b"b", vec![2], short)?;
+        s.set_ex(b"c", vec![3], long)?;
+        s.set_ex(b"d", vec![4], short)?;
+        // a set without ttl clears the expiry
+        s.set(b"d", vec![5])?;
+        assert_eq!(s.get(b"b")?, Some(vec![2]));
+        assert_eq!(s.status()?.keys, 4);
+        drop(s);
+
+        let mut s = LogCask::new(path.clone())?;
+        std::thread::sleep(short * 2);
+        let expect = vec![(b"a".to_vec(), vec![1]), (b"c".to_vec(), vec![3]), (b"d".to_vec(), vec![5])];
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        assert_eq!(s.status()?.keys, 3);
+        // reads skip the expired key without writing a tombstone
+        let len = std::fs::metadata(&path)?.len();
+        assert_eq!(s.get(b"b")?, None);
+        assert_eq!(s.get_many(&[b"b", b"d"])?, vec![None, Some(vec![5])]);
+        assert!(!s.contains_key(b"b"));
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        assert_eq!(s.get(b"d")?, Some(vec![5]));
+
+        // compaction drops expired entries and keeps the expiry of live ones
+        s.set_ex(b"e", vec![6], short)?;
+        std::thread::sleep(short * 2);
+        s.compact()?;
+        assert_eq!(s.status()?.garbage_disk_size, 0);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
+
+        let s = LogCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        assert_eq!(s.get(b"e")?, None);
+
+        // the V1 format cannot record an expiry
+        let mut s = LogCask::new(dir.path().join("v1"))?;
+        assert!(s.set_ex(b"a", vec![1], long).is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a snapshot streamed to a buffer imports back into an equal keyspace,
+    /// and that an exported snapshot file opens as a log.
+    fn stream_snapshot() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new_with_format(dir.path().join("orig"), LogFormat::V2)?;
+        setup_log(&mut s)?;
+        s.set_delta_appends(true)?;
+        s.append(b"b", &[0x0b])?;
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+
+        let mut buf = Vec::new();
+        assert_eq!(s.stream_snapshot(&mut buf)?, 5);
+
+        let mut imported = LogCask::new(dir.path().join("imported"))?;
+        imported.set(b"a", vec![0xff])?;
+        assert_eq!(imported.import_snapshot(&mut Cursor::new(&buf))?, 5);
+        assert_eq!(imported.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        let export = dir.path().join("export");
+        assert_eq!(s.export_snapshot(&export)?, 5);
+        assert_eq!(std::fs::read(&export)?, buf);
+        let opened = LogCask::new(export)?;
+        assert_eq!(opened.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        // a truncated stream is an error
+        assert!(LogCask::new(dir.path().join("partial"))?.import_snapshot(&mut Cursor::new(&buf[..buf.len() - 1])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that keys_with_value returns every key holding the value, optionally within a range.
+    fn keys_with_value() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new(dir.path().join("reverse"))?;
+        s.set(b"a", b"red".to_vec())?;
+        s.set(b"b", b"blue".to_vec())?;
+        s.set(b"c", b"red".to_vec())?;
+        s.set(b"d", b"reddish".to_vec())?;
+        s.set(b"e", b"red".to_vec())?;
+        s.set(b"f", b"red".to_vec())?;
+        s.delete(b"f")?;
+        s.set(b"b", b"red".to_vec())?;
+
+        assert_eq!(s.keys_with_value(b"red")?, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"e".to_vec()]);
+        assert_eq!(s.keys_with_value_in(b"b".to_vec()..b"e".to_vec(), b"red")?, vec![b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(s.keys_with_value(b"blue")?, Vec::<Vec<u8>>::new());
+        assert_eq!(s.keys_with_value(b"")?, Vec::<Vec<u8>>::new());
+
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a compressed log round-trips values, reports logical size in status while
+    /// the disk sizes reflect compressed bytes, and rebuilds its keydir on reopen and compaction.
+    fn new_compressed() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("compressed");
+        let json = br#"{"name":"kv-rs","tags":["a","b","c"],"payload":"#.repeat(50);
+
+        let mut s = LogCask::new_compressed(path.clone(), 6)?;
+        s.set(b"json", json.clone())?;
+        s.set(b"small", b"xy".to_vec())?;
+        s.set(b"empty", vec![])?;
+        s.set(b"gone", json.clone())?;
+        s.delete(b"gone")?;
+        s.set_delta_appends(true)?;
+        s.append(b"small", &json)?;
+        let small = [b"xy".to_vec(), json.clone()].concat();
+
+        let expect = vec![(b"empty".to_vec(), vec![]), (b"json".to_vec(), json.clone()), (b"small".to_vec(), small.clone())];
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        let status = s.status()?;
+        assert_eq!(status.size, (4 + 5 + 5) + json.len() as u64 + small.len() as u64);
+        assert!(status.live_disk_size < status.size / 4);
+        assert!(status.total_disk_size < json.len() as u64);
+        drop(s);
+
+        // the keydir holds on-disk lengths, a full scan on reopen must agree with them
+        let mut s = LogCask::new(path.clone())?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        assert_eq!(s.status()?.size, status.size);
+        s.set(b"after", json.clone())?;
+        s.compact()?;
+        drop(s);
+
+        let mut s = LogCask::new(path)?;
+        assert_eq!(s.get(b"after")?, Some(json.clone()));
+        assert_eq!(s.get(b"small")?, Some(small));
+        assert!(s.status()?.total_disk_size < 2 * json.len() as u64 / 4);
+
+        // an existing log without codec tags cannot be switched to compression
+        let plain = dir.path().join("plain");
+        LogCask::new_with_format(plain.clone(), LogFormat::V2)?.set(b"a", vec![1])?;
+        assert!(LogCask::new_compressed(plain, 6).is_err());
+        assert!(LogCask::new_compressed(dir.path().join("level"), 11).is_err());
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that write_batch applies sets and deletes together, and that a batch cut short
+    /// anywhere inside it is discarded as a whole on reopen.
+    fn write_batch() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        for format in [LogFormat::V2, LogFormat::V3, LogFormat::V4] {
+            let path = dir.path().join(format!("batch_{:?}", format));
+            let mut s = LogCask::new_with_format(path.clone(), format)?;
+            s.set(b"a", vec![1])?;
+            s.set(b"c", vec![3])?;
+            s.write_batch(vec![(b"a".to_vec(), Some(vec![2])), (b"b".to_vec(), Some(vec![2])), (b"c".to_vec(), None)])?;
+            let expect = vec![(b"a".to_vec(), vec![2]), (b"b".to_vec(), vec![2])];
+            assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+            let before = std::fs::metadata(&path)?.len();
+
+            s.write_batch(vec![(b"a".to_vec(), None), (b"d".to_vec(), Some(vec![4; 10])), (b"e".to_vec(), Some(vec![5]))])?;
+            assert_eq!(s.get(b"d")?, Some(vec![4; 10]));
+            drop(s);
+            let after = std::fs::metadata(&path)?.len();
+            let data = std::fs::read(&path)?;
+
+            for cut in before..after {
+                let cut_path = dir.path().join("cut");
+                std::fs::write(&cut_path, &data[..cut as usize])?;
+                let s = LogCask::new(cut_path.clone())?;
+                assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect, "cut at {} of {:?}", cut, format);
+                drop(s);
+                assert_eq!(std::fs::metadata(&cut_path)?.len(), before);
+            }
+
+            let s = LogCask::new(path)?;
+            assert_eq!(s.get(b"a")?, None);
+            assert_eq!(s.scan(..).count(), 3);
+        }
+
+        // the V1 format has no batch marker
+        let mut s = LogCask::new(dir.path().join("v1"))?;
+        assert!(s.write_batch(vec![(b"a".to_vec(), Some(vec![1]))]).is_err());
+        assert_eq!(s.get(b"a")?, None);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that the background compactor reclaims garbage once the threshold is reached,
+    /// while the shared engine stays usable, and that stop() joins the thread.
+    fn spawn_compactor() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let engine = std::sync::Arc::new(std::sync::Mutex::new(LogCask::new(dir.path().join("bg"))?));
+        let handle = LogCask::spawn_compactor(engine.clone(), 0.6, std::time::Duration::from_millis(10));
+
+        // below the threshold nothing is compacted
+        engine.lock()?.set(b"a", vec![1; 16])?;
+        engine.lock()?.set(b"a", vec![2; 16])?;
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(engine.lock()?.status()?.garbage_disk_size > 0);
+
+        for i in 0..10u8 {
+            engine.lock()?.set(b"b", vec![i; 16])?;
+        }
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while engine.lock()?.status()?.garbage_disk_size > 0 {
+            assert!(std::time::Instant::now() < deadline, "compactor did not run");
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(engine.lock()?.get(b"b")?, Some(vec![9; 16]));
+
+        handle.stop();
+        for i in 0..10u8 {
+            engine.lock()?.set(b"b", vec![i; 16])?;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(engine.lock()?.status()?.garbage_disk_size > 0);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a segmented log serves a value whose delta chain spans several old segments,
+    /// and that compact_sealed merges only the sealed segments while writes continue.
+    fn new_segmented() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("segments");
+        LogCask::new_with_format(path.clone(), LogFormat::V2)?;
+
+        let mut s = LogCask::new_segmented(path.clone(), 64)?;
+        s.set_delta_appends(true)?;
+        s.set(b"chain", b"0".to_vec())?;
+        let mut expect_chain = b"0".to_vec();
+        for i in 0..10u8 {
+            s.set(&[b'k', i], vec![i; 16])?;
+            s.append(b"chain", &[b'a' + i])?;
+            expect_chain.push(b'a' + i);
+        }
+        s.set(&[b'k', 0], vec![0xff])?;
+        s.delete(&[b'k', 1])?;
+        assert!(s.file_count() > 3);
+        assert_eq!(s.get(b"chain")?, Some(expect_chain.clone()));
+        assert_eq!(s.get(&[b'k', 2])?, Some(vec![2; 16]));
+
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        let active_len = std::fs::metadata(&path)?.len();
+        let total = s.status()?.total_disk_size;
+        s.compact_sealed()?;
+        assert_eq!(s.file_count(), 2);
+        assert_eq!(std::fs::metadata(&path)?.len(), active_len);
+        assert!(s.status()?.total_disk_size < total);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        // writes continue to the active segment, and the chain survives a reopen
+        s.append(b"chain", b"z")?;
+        expect_chain.push(b'z');
+        assert_eq!(s.get(b"chain")?, Some(expect_chain.clone()));
+        drop(s);
+
+        let s = LogCask::new(path)?;
+        assert_eq!(s.file_count(), 2);
+        assert_eq!(s.get(b"chain")?, Some(expect_chain));
+        assert_eq!(s.get(&[b'k', 1])?, None);
+        assert_eq!(s.get(&[b'k', 9])?, Some(vec![9; 16]));
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that copy_into writes in batches into a V2 log, falls back to single writes for a V1 log,
+    /// and that overwrite removes the keys missing from the source.
+    fn copy_into() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut src = LogCask::new(dir.path().join("src"))?;
+        // several batches worth of data
+        for i in 0..6u8 {
+            src.set(&[b'k', i], vec![i; 1 << 20])?;
+        }
+        src.delete(&[b'k', 5])?;
+        let expect = src.scan(..).collect::<CResult<Vec<_>>>()?;
+
+        for format in [LogFormat::V1, LogFormat::V2] {
+            let mut dest = LogCask::new_with_format(dir.path().join(format!("{:?}", format)), format)?;
+            dest.set(b"stale", vec![1])?;
+            dest.set(&[b'k', 0], vec![0xff])?;
+
+            assert_eq!(src.copy_into(&mut dest, false)?, 5);
+            assert_eq!(dest.get(b"stale")?, Some(vec![1]));
+            assert_eq!(dest.get(&[b'k', 0])?, Some(vec![0; 1 << 20]));
+
+            assert_eq!(src.copy_into(&mut dest, true)?, 5);
+            assert_eq!(dest.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that a crash before or after the commit point of compact_sealed leaves a log that reopens
+    /// to the same data, and that deleted keys do not come back from the old segments.
+    fn compact_sealed_crash() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("segments");
+        let mut s = LogCask::new_segmented(path.clone(), 64)?;
+        for i in 0..10u8 {
+            s.set(&[b'k', i], vec![i; 16])?;
+        }
+        s.delete(&[b'k', 1])?;
+        s.set(&[b'k', 2], vec![0xff])?;
+        for i in 0..6u8 {
+            s.set(&[b'x', i], vec![i; 16])?;
+        }
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        let merged_id = *s.sealed.keys().next_back().unwrap();
+        let old_segments = s.sealed.values().map(|log| Ok((log.path.clone(), std::fs::read(&log.path)?))).collect::<CResult<Vec<_>>>()?;
+        assert!(old_segments.len() > 3);
+
+        s.compact_sealed()?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
+        let merged = std::fs::read(LogCask::<crate::storage::KeyDir>::sealed_path(&path, merged_id))?;
+        let restore_old_segments = || -> CResult<()> {
+            for (old_path, data) in &old_segments {
+                std::fs::write(old_path, data)?;
+            }
+            Ok(())
+        };
+
+        // crash before the commit point: a partial merged file is ignored
+        restore_old_segments()?;
+        let mut tmp_path = LogCask::<crate::storage::KeyDir>::sealed_path(&path, merged_id).into_os_string();
+        tmp_path.push(".new");
+        std::fs::write(&tmp_path, &merged[..merged.len() / 2])?;
+        let s = LogCask::new(path.clone())?;
+        assert_eq!(s.file_count(), old_segments.len() + 1);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
+
+        // crash after the commit point, before the old segments are removed
+        let merged_path = LogCask::<crate::storage::KeyDir>::merged_path(&path, merged_id);
+        std::fs::write(&merged_path, &merged)?;
+        let reader = LogCask::open_read_only(path.clone())?;
+        assert_eq!(reader.file_count(), 2);
+        assert_eq!(reader.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(reader);
+        assert!(merged_path.exists());
+
+        let s = LogCask::new(path.clone())?;
+        assert_eq!(s.file_count(), 2);
+        assert_eq!(s.get(&[b'k', 1])?, None);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        assert!(!merged_path.exists());
+        assert_eq!(LogCask::<crate::storage::KeyDir>::sealed_paths(&path)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that compaction writes a hint file, that reopening from it yields the same keydir
+    /// as a full scan of the log, and that a hint older than the log is ignored.
+    fn hint_file() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("hint");
+        let hint_path = LogCask::<crate::storage::KeyDir>::hint_path(&path);
+
+        let mut s = LogCask::new_with_format(path.clone(), LogFormat::V2)?;
+        for i in 0..100u32 {
+            s.set(format!("key{}", i % 40).as_bytes(), i.to_be_bytes().to_vec())?;
+        }
+        s.delete(b"key3")?;
+        s.set_ex(b"ttl", vec![1], std::time::Duration::from_secs(3600))?;
+        assert!(!hint_path.exists());
+        s.compact()?;
+        assert!(hint_path.exists());
+        drop(s);
+
+        let mut s = LogCask::new(path.clone())?;
+        let (keydir, expiries) = (s.keydir.clone(), s.expiries.clone());
+        assert_eq!(keydir.len(), 40);
+        assert_eq!(expiries.len(), 1);
+        s.rebuild_index()?;
+        assert_eq!((keydir, expiries), (s.keydir.clone(), s.expiries.clone()));
+        assert_eq!(s.get(b"key19")?, Some(99u32.to_be_bytes().to_vec()));
+
+        // writes after the compaction make the hint stale, the log is scanned instead
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        s.set(b"new", vec![2])?;
+        s.delete(b"key0")?;
+        drop(s);
+
+        let mut s = LogCask::new(path)?;
+        assert_eq!(s.get(b"new")?, Some(vec![2]));
+        assert_eq!(s.get(b"key0")?, None);
+        assert_eq!(s.status()?.keys, 40);
+        Ok(())
+    }
+
+    #[test]
+    /// Tests that rebuild_index repairs a keydir that was desynced from the log.
+    fn rebuild_index() -> CResult<()> {
+        let mut s = setup()?;
+        s.set_max_file_size(Some(32));
+        setup_log(&mut s)?;
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
 
-        let rs = s.flush();
+        let keydir = s.keydir_mut();
+        keydir.remove(b"a".as_slice());
+        let (file_id, value_pos, _) = keydir[b"b".as_slice()];
+        keydir.insert(b"b".to_vec(), (file_id, value_pos, 0));
+        keydir.insert(b"zombie".to_vec(), (file_id, value_pos, 1));
+        assert_eq!(s.get(b"a")?, None);
+        assert_eq!(s.get(b"b")?, Some(vec![]));
+
+        s.rebuild_index()?;
+        assert_eq!(s.get(b"a")?, Some(vec![0x01]));
+        assert_eq!(s.get(b"b")?, Some(vec![0x02]));
+        assert_eq!(s.get(b"zombie")?, None);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
 
         Ok(())
     }
 
     #[test]
-    /// Tests that writing and then reading a file yields the same results.
-    fn reopen() -> CResult<()> {
-        // NB: Don't use setup(), because the tempdir will be removed when
-        // the path falls out of scope.
-        let path = tempdir::TempDir::new("demo")?.path().join("adb");
+    /// Tests that writes made between compaction_snapshot() and finish_compaction() are kept.
+    fn compaction_snapshot() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("snapshot");
         let mut s = LogCask::new(path.clone())?;
         setup_log(&mut s)?;
 
-        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        let snapshot = s.compaction_snapshot()?;
+        s.set(b"a", vec![0x0a])?;
+        s.set(b"x", vec![0x18])?;
+        s.delete(b"b")?;
+        let compacted = snapshot.write()?;
+        s.delete(b"c")?;
+        s.finish_compaction(compacted)?;
+
+        let expect = vec![
+            (b"".to_vec(), vec![]),
+            (b"a".to_vec(), vec![0x0a]),
+            (b"d".to_vec(), vec![0x04]),
+            (b"x".to_vec(), vec![0x18]),
+        ];
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        // the snapshot versions of a, b and c were rewritten before being replaced (10 bytes each),
+        // plus the tombstones for b and c (9 bytes each)
+        assert_eq!(s.status()?.garbage_disk_size, 48);
         drop(s);
-        let mut s = LogCask::new(path)?;
-        assert_eq!(expect, s.scan(..).collect::<CResult<Vec<_>>>()?,);
+
+        let s = LogCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
 
         Ok(())
     }
 
     #[test]
-    /// Tests that new_compact() will automatically compact the file when appropriate.
-    fn new_compact() -> CResult<()> {
-        // Create an initial log file with a few entries.
+    /// Tests that compact_with() reports progress, and that cancelling it leaves the log and keydir untouched.
+    fn compact_cancel() -> CResult<()> {
         let dir = tempdir::TempDir::new("demo")?;
-        let path = dir.path().join("orig");
-        let compactpath = dir.path().join("compact");
-
-        let mut s = LogCask::new_compact(path.clone(), 0.2)?;
+        let path = dir.path().join("cancel");
+        let mut s = LogCask::new(path.clone())?;
         setup_log(&mut s)?;
+
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
         let status = s.status()?;
-        let garbage_ratio = status.garbage_disk_size as f64 / status.total_disk_size as f64;
+        let len = std::fs::metadata(&path)?.len();
+
+        let cancel = AtomicBool::new(false);
+        let mut reported = Vec::new();
+        let result = s.compact_with(
+            |done, total| {
+                reported.push((done, total));
+                if done == 2 {
+                    cancel.store(true, Ordering::SeqCst);
+                }
+            },
+            &cancel,
+        );
+        assert_eq!(result, Err(Error::Abort));
+        assert_eq!(reported, vec![(1, 5), (2, 5)]);
+
+        assert!(!path.with_extension("new").exists());
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        assert_eq!(s.status()?, status);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
         drop(s);
 
-        // Test a few threshold value and assert whether it should trigger compaction.
-        let cases = vec![
-            (-1.0, true),
-            (0.0, true),
-            (garbage_ratio - 0.001, true),
-            (garbage_ratio, true),
-            (garbage_ratio + 0.001, false),
-            (1.0, false),
-            (2.0, false),
-        ];
-        for (threshold, expect_compact) in cases.into_iter() {
-            std::fs::copy(&path, &compactpath)?;
-            let mut s = LogCask::new_compact(compactpath.clone(), threshold)?;
-            let new_status = s.status()?;
-            assert_eq!(new_status.live_disk_size, status.live_disk_size);
-            if expect_compact {
-                assert_eq!(new_status.total_disk_size, status.live_disk_size);
-                assert_eq!(new_status.garbage_disk_size, 0);
-            } else {
-                assert_eq!(new_status, status);
-            }
-        }
+        let s = LogCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
 
         Ok(())
     }
 
     #[test]
-    /// Tests that exclusive locks are taken out on log files, released when the
-    /// cask is closed, and that an error is returned if a lock is already
-    /// held.
-    fn log_lock() -> CResult<()> {
-        let path = tempdir::TempDir::new("demo")?.path().join("t_app");
-        let s = LogCask::new(path.clone())?;
-
-        assert!(LogCask::new(path.clone()).is_err());
-        drop(s);
-        assert!(LogCask::new(path.clone()).is_ok());
+    /// Tests that verify_compaction catches a new log missing a key, and leaves the original log in place.
+    fn verify_compaction() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("verify");
+        let mut s = LogCask::new(path.clone())?;
+        setup_log(&mut s)?;
+        s.set_verify_compaction(true);
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        let len = std::fs::metadata(&path)?.len();
+
+        // a faulty writer that drops the last key: cut its entry off the new log
+        let compacted = s.compaction_snapshot()?.write()?;
+        let tmp_path = compacted.log.path.clone();
+        let (last_key, (_, value_pos, _)) = compacted.keydir.iter().max_by_key(|(_, entry)| entry.1).unwrap();
+        let entry_pos = value_pos - s.log.format.entry_overhead() - last_key.len() as u64;
+        compacted.log.file.set_len(entry_pos)?;
+
+        let err = s.finish_compaction(compacted).unwrap_err();
+        assert!(err.to_string().starts_with("compaction verification failed"), "{}", err);
+        assert!(!tmp_path.exists());
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+
+        // an intact compaction passes the verification
+        s.compact()?;
+        assert_eq!(s.status()?.garbage_disk_size, 0);
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
 
         Ok(())
     }
 
     #[test]
-    /// Tests that an incomplete write at the end of the log file can be
-    /// recovered by discarding the last entry.
-    fn recovery() -> CResult<()> {
-        // Create an initial log file with a few entries.
-        let dir = tempdir::TempDir::new("demmo")?;
-        let path = dir.path().join("complete");
-        let truncpath = dir.path().join("truncated");
+    /// Tests that delta appends are rejected for the v1 format, where append rewrites the full value.
+    fn delta_appends_v1() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new(dir.path().join("v1"))?;
+        assert!(s.set_delta_appends(true).is_err());
 
-        let mut log = Log::new(path.clone())?;
-        let mut ends = vec![];
+        assert_eq!(s.append(b"a", b"x")?, 1);
+        assert_eq!(s.append(b"a", b"yz")?, 3);
+        assert_eq!(s.get(b"a")?, Some(b"xyz".to_vec()));
+        Ok(())
+    }
 
-        let (pos, len) = log.write_entry("deleted".as_bytes(), Some(&[1, 2, 3]))?;
-        ends.push(pos + len as u64);
+    #[test]
+    /// Tests that writes only count towards compact_every_writes, and that the background compactor
+    /// compacts once the count is reached and resets it.
+    fn compact_every_writes() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let mut s = LogCask::new(dir.path().join("every"))?;
+        s.set_compact_every_writes(Some(3));
 
-        let (pos, len) = log.write_entry("deleted".as_bytes(), None)?;
-        ends.push(pos + len as u64);
+        s.set(b"a", vec![0x01])?;
+        s.set(b"a", vec![0x02])?;
+        assert_eq!(s.writes_since_compact(), 2);
+        assert!(!s.compaction_due());
+
+        // the third write (a delete) makes the compaction due, but does not run it
+        s.delete(b"b")?;
+        assert_eq!(s.writes_since_compact(), 3);
+        assert!(s.compaction_due());
+        assert!(s.status()?.garbage_disk_size > 0);
+
+        // a garbage ratio threshold above 1 never fires, so only the write count triggers the compaction
+        let engine = Arc::new(std::sync::Mutex::new(s));
+        let handle = LogCask::spawn_compactor(engine.clone(), 2.0, std::time::Duration::from_millis(10));
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while engine.lock()?.status()?.garbage_disk_size > 0 {
+            assert!(std::time::Instant::now() < deadline, "compaction did not run");
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        handle.stop();
 
-        let (pos, len) = log.write_entry(&[], Some(&[]))?;
-        ends.push(pos + len as u64);
+        let mut s = engine.lock()?;
+        assert_eq!(s.writes_since_compact(), 0);
+        assert_eq!(s.get(b"a")?, Some(vec![0x02]));
+        s.set(b"a", vec![0x03])?;
+        assert_eq!(s.writes_since_compact(), 1);
 
-        let (pos, len) = log.write_entry("key".as_bytes(), Some(&[1, 2, 3, 4, 5]))?;
-        ends.push(pos + len as u64);
+        Ok(())
+    }
 
-        drop(log);
+    #[test]
+    /// Tests that open_shared() instances read concurrently, reject writes, and keep a writer out until dropped.
+    fn open_shared() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("shared");
+        let mut s = LogCask::new(path.clone())?;
+        setup_log(&mut s)?;
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        drop(s);
 
-        // Copy the file, and truncate it at each byte, then try to open it
-        // and assert that we always retain a prefix of entries.
-        let size = std::fs::metadata(&path)?.len();
-        for pos in 0..=size {
-            std::fs::copy(&path, &truncpath)?;
-            let f = std::fs::OpenOptions::new().write(true).open(&truncpath)?;
-            f.set_len(pos)?;
-            drop(f);
+        let mut reader1 = LogCask::open_shared(path.clone())?;
+        let reader2 = LogCask::open_shared(path.clone())?;
+        assert_eq!(reader1.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        assert_eq!(reader2.get(b"d")?, Some(vec![0x04]));
 
-            let mut expect = vec![];
-            if pos >= ends[0] {
-                expect.push((b"deleted".to_vec(), vec![1, 2, 3]))
-            }
-            if pos >= ends[1] {
-                expect.pop(); // "deleted" key removed
-            }
-            if pos >= ends[2] {
-                expect.push((b"".to_vec(), vec![]))
-            }
-            if pos >= ends[3] {
-                expect.push((b"key".to_vec(), vec![1, 2, 3, 4, 5]))
-            }
+        assert_eq!(reader1.set(b"a", vec![0x0a]), Err(Error::ReadOnly));
+        assert_eq!(reader1.delete(b"a"), Err(Error::ReadOnly));
+        assert_eq!(reader1.compact(), Err(Error::ReadOnly));
+        assert!(LogCask::new(path.clone()).is_err());
 
-            let mut s = LogCask::new(truncpath.clone())?;
-            assert_eq!(expect, s.scan(..).collect::<CResult<Vec<_>>>()?);
-        }
+        drop(reader1);
+        drop(reader2);
+        let s = LogCask::new(path)?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
 
         Ok(())
     }
 
     #[test]
-    /// Tests status(), both for a log file with known garbage, and
-    /// after compacting it when the live size must equal the file size.
-    fn test_status_full() -> CResult<()> {
-        let mut s = setup()?;
-        setup_log(&mut s)?;
+    fn open_read_only_truncated() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("readonly");
+        let mut s = LogCask::new(path.clone())?;
+        s.set(b"a", vec![0x01])?;
+        s.set(b"b", vec![0x02])?;
+        s.set(b"c", vec![0x03, 0x04, 0x05])?;
+        drop(s);
 
-        // Before compaction.
+        // 模拟写入中途崩溃：最后一个 entry 只写入了一部分
+        let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+        let len = file.metadata()?.len() - 2;
+        file.set_len(len)?;
+        drop(file);
+
+        // 写者持有独占锁时也能以只读方式打开
+        let _writer_lock = {
+            let file = std::fs::File::open(&path)?;
+            fs4::FileExt::try_lock_exclusive(&file)?;
+            file
+        };
+        let mut reader = LogCask::open_read_only(path.clone())?;
         assert_eq!(
-            s.status()?,
-            Status {
-                name: "log cask".to_string(),
-                keys: 5,
-                size: 8,
-                total_disk_size: 114,
-                live_disk_size: 48,
-                garbage_disk_size: 66
-            }
+            reader.scan(..).collect::<CResult<Vec<_>>>()?,
+            vec![(b"a".to_vec(), vec![0x01]), (b"b".to_vec(), vec![0x02])]
         );
+        assert_eq!(reader.get(b"c")?, None);
+        assert_eq!(reader.set(b"c", vec![0x03]), Err(Error::ReadOnly));
+        assert_eq!(reader.delete(b"a"), Err(Error::ReadOnly));
+        assert_eq!(reader.compact(), Err(Error::ReadOnly));
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
 
-        // After compaction.
-        s.compact()?;
-        assert_eq!(
-            s.status()?,
-            Status {
-                name: "log cask".to_string(),
-                keys: 5,
-                size: 8,
-                total_disk_size: 48,
-                live_disk_size: 48,
-                garbage_disk_size: 0,
-            }
-        );
+        drop(reader);
+        assert_eq!(std::fs::metadata(&path)?.len(), len);
 
         Ok(())
     }
 
     #[test]
-    fn test_log() -> CResult<()> {
-        let mut s = setup().unwrap();
-        setup_log(&mut s).unwrap();
-
-        let stat = s.status().unwrap();
-        println!("stat:{:?}", stat);
-
-        // write 4k
-        let mut _4k = Vec::<u8>::with_capacity(1024 * 4);
-        for i in 0..1024*4 {
-            _4k.push(0);
-        }
-        s.set("4k".as_bytes(), _4k)?;
-        s.flush().unwrap();
-
-        let stat = s.status().unwrap();
-        println!("stat:{:?}", stat);
+    /// Tests that checkpoint() records the current status, and that it matches after reopening.
+    fn checkpoint() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        let path = dir.path().join("checkpoint");
+        let mut s = LogCask::new(path.clone())?;
+        setup_log(&mut s)?;
 
-        // test_load_from_log_file
-        let mut cask = LogCask::new_with_lock(PathBuf::from(s.get_path().unwrap()), false).unwrap();
-        let get = cask.get(b"b");
-        assert!(get.is_ok());
-        let get_val = get.unwrap().unwrap();
-        assert_eq!(get_val, vec![0x02]);
+        let checkpoint = s.checkpoint()?;
+        let status = s.status()?;
+        assert_eq!(checkpoint, Checkpoint { log_len: status.total_disk_size, keys: status.keys });
+        assert_eq!(Checkpoint::load(&s.checkpoint_path())?, checkpoint);
+        assert_eq!(s.checkpoint_path(), dir.path().join("checkpoint.checkpoint"));
+
+        // later writes are not part of the marker until the next checkpoint
+        s.set(b"z", vec![0x1a])?;
+        assert_eq!(Checkpoint::load(&s.checkpoint_path())?, checkpoint);
+        let checkpoint = s.checkpoint()?;
+        drop(s);
 
-        let get_4k = cask.get("4k".as_bytes());
-        assert!(get_4k.is_ok());
-        let get_4k_val = get_4k.unwrap().unwrap();
-        assert_eq!(get_4k_val.len(), 1024*4);
+        let mut s = LogCask::new(path)?;
+        let status = s.status()?;
+        assert_eq!(Checkpoint::load(&s.checkpoint_path())?, Checkpoint { log_len: status.total_disk_size, keys: status.keys });
+        assert_eq!(checkpoint.keys, 6);
 
         Ok(())
     }
 
     #[test]
-    fn test_log_with_bytes_persion() {
-        let codec = BytesCodec::new();
-
-        let mut log_cask = setup().unwrap();
-
-        let persion_key = "persion_cache_key";
-
-        let mut list_for_cache = Vec::<Persion>::new();
-        let mut buf = BytesMut::with_capacity(1024);
-        for i in 0..1 {
-            let p = Persion {
-                name: format!("name{}", i),
-                age: i % 85,
-                address: format!("address{}", i),
-            };
-            list_for_cache.push(p.clone());
+    /// Tests that max_keys blocks new keys once reached, but allows overwrites and deletes.
+    fn max_keys() -> CResult<()> {
+        let mut s = setup()?;
+        s.set_max_keys(Some(2));
 
-            let b = codec.encode(&p).unwrap();
-            buf.put(b.as_slice());
-        }
+        s.set(b"a", vec![1])?;
+        s.set(b"b", vec![2])?;
+        assert_eq!(s.set(b"c", vec![3]), Err(Error::QuotaExceeded(2)));
+        assert_eq!(s.get(b"c")?, None);
 
-        log_cask.set(persion_key.as_bytes(), buf.to_vec()).unwrap();
-        log_cask.flush().unwrap();
+        // overwriting an existing key is allowed at the cap
+        s.set(b"a", vec![0x0a])?;
+        assert_eq!(s.get(b"a")?, Some(vec![0x0a]));
 
-        let stat = log_cask.status().unwrap();
-        println!("stat:{:?}", stat);
+        // deleting frees capacity
+        s.delete(b"b")?;
+        s.set(b"c", vec![3])?;
+        assert_eq!(s.status()?.keys, 2);
 
-        // test_load_from_log_file
-        let save_path = log_cask.get_path().unwrap();
+        s.set_max_keys(None);
+        s.set(b"d", vec![4])?;
+        assert_eq!(s.status()?.keys, 3);
 
-        let mut two_cask = LogCask::new_with_lock(PathBuf::from(save_path), false).unwrap();
-        let persion_list = two_cask.get(persion_key.as_bytes());
-        assert!(persion_list.is_ok());
-        let persion_list_val = persion_list.unwrap().unwrap();
+        Ok(())
+    }
 
-        let mut i_for_test = 0;
-        let mut cursor = Cursor::new(persion_list_val.as_slice());
-        loop {
-            if cursor.position() >= cursor.get_ref().len() as u64 {
-                break;
-            }
+    #[cfg(unix)]
+    #[test]
+    /// Tests that compacting through a symlinked log path rewrites the target and keeps the symlink.
+    fn compact_symlink() -> CResult<()> {
+        let dir = tempdir::TempDir::new("demo")?;
+        std::fs::create_dir(dir.path().join("data"))?;
+        let target = dir.path().join("data").join("real");
+        let link = dir.path().join("link");
+        std::fs::File::create(&target)?;
+        std::os::unix::fs::symlink(&target, &link)?;
 
-            let len = cursor.read_u64::<byteorder::BigEndian>().unwrap() as usize;
-            let mut by = vec![0; len];
-            cursor.read_exact(&mut by).unwrap();
+        let mut s = LogCask::new(link.clone())?;
+        setup_log(&mut s)?;
+        let expect = s.scan(..).collect::<CResult<Vec<_>>>()?;
+        let status = s.status()?;
 
-            let r: Persion = codec.decode_bytes(&by, false).unwrap();
-            println!("{:?}", r);
+        s.set_follow_symlinks(false);
+        assert!(matches!(s.compact(), Err(Error::Value(_))));
+        assert_eq!(s.status()?, status);
 
-            let cache_p = list_for_cache.get(i_for_test).unwrap();
-            assert_eq!(&r.name, &cache_p.name);
-            assert_eq!(&r.address, &cache_p.address);
-            assert_eq!(&r.age, &cache_p.age);
+        s.set_follow_symlinks(true);
+        s.compact()?;
+        assert!(std::fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(std::fs::read_link(&link)?, target);
+        assert_eq!(std::fs::metadata(&target)?.len(), status.live_disk_size);
+        assert!(!dir.path().join("data").join("real.new").exists());
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
+        drop(s);
 
-            i_for_test += 1;
-        }
+        let s = LogCask::new(link)?;
+        assert_eq!(s.scan(..).collect::<CResult<Vec<_>>>()?, expect);
 
-        assert_eq!(1, 1);
+        Ok(())
     }
-}
\ No newline at end of file
+}
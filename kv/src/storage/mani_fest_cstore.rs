@@ -27,15 +27,15 @@ impl Engine for ManiFestCStore {
         todo!()
     }
 
-    fn get(&mut self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
+    fn get(&self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
         todo!()
     }
 
-    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_> where Self: Sized {
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_> where Self: Sized {
         todo!()
     }
 
-    fn scan_dyn(&mut self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn ScanIteratorT + '_> {
+    fn scan_dyn(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Box<dyn ScanIteratorT + '_> {
         Box::new(self.scan(range))
     }
 
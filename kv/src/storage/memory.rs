@@ -33,17 +33,26 @@ impl Engine for Memory {
         Ok(())
     }
 
-    fn get(&mut self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
+    fn get(&self, key: &[u8]) -> CResult<Option<Vec<u8>>> {
         Ok(self.data.get(key).cloned())
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_>
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.data.contains_key(key)
+    }
+
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::ScanIterator<'_>
         where Self: Sized {
         MemoryScanIterator { inner: self.data.range(range) }
     }
 
+    fn scan_keys(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> impl Iterator<Item = CResult<Vec<u8>>> + '_
+        where Self: Sized {
+        self.data.range(range).map(|(key, _)| Ok(key.clone()))
+    }
+
     fn scan_dyn(
-        &mut self,
+        &self,
         range: (std::ops::Bound<Vec<u8>>, std::ops::Bound<Vec<u8>>),
     ) -> Box<dyn ScanIteratorT + '_> {
         Box::new(self.scan(range))
@@ -1,16 +1,32 @@
 pub mod log;
+pub mod index;
+pub mod key_transform;
+pub mod read_cache;
 pub mod engine;
+pub mod glob;
+pub mod dump;
+pub mod async_engine;
 pub mod log_cask;
 pub mod memory;
 pub mod mani_fest_cstore;
 
 use serde_derive::{Deserialize, Serialize};
-use crate::error::CResult;
+use crate::error::{CResult, Error};
 
 /// KeyDir是一个内存当中的map，这里使用的是BTreeMap的实现方式，便于进行顺序遍历进行compaction。
 /// key为存储的key，而value为Entry的metadata，记录长度和位置，用于进行偏移读取.
 /// map当中始终保存当前key的最新版本的位置。 它便于顺序遍历和压缩。
-pub type KeyDir = std::collections::BTreeMap<Vec<u8>, (u64, u32)>;
+pub type KeyDir = std::collections::BTreeMap<Vec<u8>, KeyDirEntry>;
+
+/// keydir 中每个key的元数据: (file_id, value_pos, value_len)，file_id 标识 value 所在的日志文件
+pub type KeyDirEntry = (u32, u64, u32);
+
+/// delta 链：key 最新的 Append entry 对应的 keydir entry -> 之前的各段，第一段为完整的 value，其余为依次追加的字节。
+/// 完整的 value 为之前的各段加上最新一段按顺序拼接。只包含以 Append entry 结尾的key
+pub type DeltaChains = std::collections::HashMap<KeyDirEntry, Vec<KeyDirEntry>>;
+
+/// 带有过期时间的 keydir entry -> 过期时间(unix millis)。只包含以 SetWithTtl entry 写入的key，判断是否过期无需读取磁盘
+pub type Expiries = std::collections::HashMap<KeyDirEntry, u64>;
 
 /// 用于表示当前存储引擎的状态
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -34,6 +50,91 @@ pub struct Status {
     pub garbage_disk_size: u64,
 }
 
+impl Status {
+    /// 垃圾数据占磁盘总大小的比例(0 ~ 1)，磁盘上没有数据时为 0
+    pub fn garbage_ratio(&self) -> f64 {
+        if self.total_disk_size > 0 {
+            self.garbage_disk_size as f64 / self.total_disk_size as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let garbage_ratio = self.garbage_ratio() * 100.0;
+        writeln!(f, "name: {}", self.name)?;
+        writeln!(f, "keys: {}", self.keys)?;
+        writeln!(f, "size: {}", self.size)?;
+        writeln!(f, "total_disk_size: {}", self.total_disk_size)?;
+        writeln!(f, "live_disk_size: {}", self.live_disk_size)?;
+        write!(f, "garbage_disk_size: {} ({:.1}%)", self.garbage_disk_size, garbage_ratio)
+    }
+}
+
+/// scan_from 返回的一页：本页的 key/value，以及下一页的游标(没有更多数据时为 None)
+pub type ScanPage = (Vec<(Vec<u8>, Vec<u8>)>, Option<ScanCursor>);
+
+/// 分页扫描(scan_from)的游标，由key和一个标志位组成。
+///
+/// 游标记录的是上一页最后返回的key，下一页严格从该key之后开始，与该key当前是否仍然存在无关，
+/// 因此在两次分页之间删除或重新写入游标key，不会导致其他key被跳过或重复返回。
+///
+/// 一致性保证较弱：在整个扫描期间始终存在的key至少会被返回一次；扫描期间新增或删除的key可能返回，也可能不返回。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanCursor {
+    key: Vec<u8>,
+    /// true 表示从 key 之后(不包含key)开始
+    after: bool,
+}
+
+impl ScanCursor {
+    /// 从头开始扫描的游标
+    pub fn start() -> Self {
+        ScanCursor { key: vec![], after: false }
+    }
+
+    /// 严格从 key 之后开始扫描的游标
+    pub fn after(key: Vec<u8>) -> Self {
+        ScanCursor { key, after: true }
+    }
+
+    pub fn is_start(&self) -> bool {
+        !self.after && self.key.is_empty()
+    }
+
+    /// 游标对应的扫描起点
+    pub fn lower_bound(&self) -> std::ops::Bound<Vec<u8>> {
+        if self.after {
+            std::ops::Bound::Excluded(self.key.clone())
+        } else {
+            std::ops::Bound::Included(self.key.clone())
+        }
+    }
+
+    /// 编码为分页token：标志位('a' 或 's') + hex(key)，起始游标编码为 "0"
+    pub fn encode(&self) -> String {
+        if self.is_start() {
+            return "0".to_string();
+        }
+        format!("{}{}", if self.after { 'a' } else { 's' }, hex::encode(&self.key))
+    }
+
+    pub fn decode(token: &str) -> CResult<Self> {
+        if token == "0" {
+            return Ok(Self::start());
+        }
+        let after = match token.chars().next() {
+            Some('a') => true,
+            Some('s') => false,
+            _ => return Err(Error::Parse(format!("invalid scan cursor: {}", token))),
+        };
+        let key = hex::decode(&token[1..]).map_err(|_| Error::Parse(format!("invalid scan cursor: {}", token)))?;
+        Ok(ScanCursor { key, after })
+    }
+}
+
 /// A scan iterator, with a blanket implementation (in lieu of trait aliases).
 pub trait ScanIteratorT: DoubleEndedIterator<Item = CResult<(Vec<u8>, Vec<u8>)>> {}
 
@@ -47,6 +148,22 @@ mod tests {
         assert_eq!(1, 1);
     }
 
+    #[test]
+    fn status_display() {
+        let status = super::Status {
+            name: "log cask".to_string(),
+            keys: 2,
+            size: 10,
+            total_disk_size: 40,
+            live_disk_size: 26,
+            garbage_disk_size: 14,
+        };
+        assert_eq!(
+            status.to_string(),
+            "name: log cask\nkeys: 2\nsize: 10\ntotal_disk_size: 40\nlive_disk_size: 26\ngarbage_disk_size: 14 (35.0%)"
+        );
+    }
+
     /// Generates common tests for any Engine implementation.
     macro_rules! test_engine {
         ($setup:expr) => {
@@ -189,6 +306,221 @@ mod tests {
                 Ok(())
             }
 
+            #[test]
+            /// Tests paginated scans, deleting the cursor key between pages.
+            fn scan_from() -> CResult<()> {
+                use crate::storage::ScanCursor;
+
+                let mut s = $setup;
+                for key in [b"a", b"b", b"c", b"d", b"e"] {
+                    s.set(key, key.to_vec())?;
+                }
+
+                let (page, cursor) = s.scan_from(&ScanCursor::start(), 2)?;
+                assert_eq!(page, vec![(b"a".to_vec(), b"a".to_vec()), (b"b".to_vec(), b"b".to_vec())]);
+                let cursor = ScanCursor::decode(&cursor.unwrap().encode())?;
+                assert_eq!(cursor, ScanCursor::after(b"b".to_vec()));
+
+                // The cursor key no longer exists, the next page still starts right after it.
+                s.delete(b"b")?;
+                let (page, cursor) = s.scan_from(&cursor, 2)?;
+                assert_eq!(page, vec![(b"c".to_vec(), b"c".to_vec()), (b"d".to_vec(), b"d".to_vec())]);
+
+                // Re-inserting the cursor key does not repeat it either.
+                s.set(b"d", vec![0])?;
+                let (page, cursor) = s.scan_from(&cursor.unwrap(), 2)?;
+                assert_eq!(page, vec![(b"e".to_vec(), b"e".to_vec())]);
+                assert_eq!(cursor, None);
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests swap, with both keys present and with one of them missing.
+            fn swap() -> CResult<()> {
+                let mut s = $setup;
+                s.set(b"a", vec![1])?;
+                s.set(b"b", vec![2])?;
+
+                s.swap(b"a", b"b")?;
+                assert_eq!(s.get(b"a")?, Some(vec![2]));
+                assert_eq!(s.get(b"b")?, Some(vec![1]));
+
+                // The present key moves, the missing one becomes present.
+                s.swap(b"a", b"c")?;
+                assert_eq!(s.get(b"a")?, None);
+                assert_eq!(s.get(b"c")?, Some(vec![2]));
+                s.swap(b"a", b"c")?;
+                assert_eq!(s.get(b"a")?, Some(vec![2]));
+                assert_eq!(s.get(b"c")?, None);
+
+                // Swapping a key with itself, or two missing keys, is a noop.
+                s.swap(b"b", b"b")?;
+                s.swap(b"x", b"y")?;
+                assert_eq!(
+                    s.scan(..).collect::<CResult<Vec<_>>>()?,
+                    vec![(b"a".to_vec(), vec![2]), (b"b".to_vec(), vec![1])]
+                );
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests rename with and without overwriting the destination, and a missing source.
+            fn rename() -> CResult<()> {
+                let mut s = $setup;
+                s.set(b"a", vec![1])?;
+                s.set(b"b", vec![2])?;
+
+                s.rename(b"a", b"c", false)?;
+                assert_eq!(s.get(b"a")?, None);
+                assert_eq!(s.get(b"c")?, Some(vec![1]));
+
+                // A missing source is an error.
+                assert!(s.rename(b"a", b"d", true).is_err());
+                assert_eq!(s.get(b"d")?, None);
+
+                // An existing destination is only replaced when overwriting.
+                assert!(s.rename(b"c", b"b", false).is_err());
+                assert_eq!(s.get(b"b")?, Some(vec![2]));
+                assert_eq!(s.get(b"c")?, Some(vec![1]));
+                s.rename(b"c", b"b", true)?;
+                assert_eq!(s.get(b"b")?, Some(vec![1]));
+                assert_eq!(s.get(b"c")?, None);
+
+                // Renaming a key to itself keeps the value.
+                s.rename(b"b", b"b", true)?;
+                assert!(s.rename(b"b", b"b", false).is_err());
+                assert_eq!(
+                    s.scan(..).collect::<CResult<Vec<_>>>()?,
+                    vec![(b"b".to_vec(), vec![1])]
+                );
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests merge with integer-add and list-append operators.
+            fn merge() -> CResult<()> {
+                use crate::storage::engine::MergeOp;
+
+                let mut s = $setup;
+                let add: &MergeOp = &|value, operand| {
+                    let current = value.map_or(0, |v| i64::from_be_bytes(v.try_into().unwrap()));
+                    let delta = i64::from_be_bytes(operand.try_into().unwrap());
+                    (current + delta).to_be_bytes().to_vec()
+                };
+                assert_eq!(s.merge(b"counter", &5i64.to_be_bytes(), add)?, 5i64.to_be_bytes().to_vec());
+                s.merge(b"counter", &(-2i64).to_be_bytes(), add)?;
+                s.merge(b"counter", &10i64.to_be_bytes(), add)?;
+                assert_eq!(s.get(b"counter")?, Some(13i64.to_be_bytes().to_vec()));
+
+                let push: &MergeOp = &|value, operand| match value {
+                    Some(value) if !value.is_empty() => [value, b",", operand].concat(),
+                    _ => operand.to_vec(),
+                };
+                for item in ["a", "b", "c"] {
+                    s.merge(b"list", item.as_bytes(), push)?;
+                }
+                assert_eq!(s.get(b"list")?, Some(b"a,b,c".to_vec()));
+                assert_eq!(s.get(b"counter")?, Some(13i64.to_be_bytes().to_vec()));
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests incr_by on missing keys, non-integer values and overflow.
+            fn incr_by() -> CResult<()> {
+                let mut s = $setup;
+
+                // a missing key starts from 0
+                assert_eq!(s.incr_by(b"counter", 5)?, 5);
+                assert_eq!(s.incr_by(b"counter", -7)?, -2);
+                assert_eq!(s.get(b"counter")?, Some(b"-2".to_vec()));
+
+                // invalid values and overflow are errors and leave the value unchanged
+                s.set(b"text", b"abc".to_vec())?;
+                assert!(s.incr_by(b"text", 1).is_err());
+                assert_eq!(s.get(b"text")?, Some(b"abc".to_vec()));
+
+                s.set(b"max", i64::MAX.to_string().into_bytes())?;
+                assert!(s.incr_by(b"max", 1).is_err());
+                assert_eq!(s.get(b"max")?, Some(i64::MAX.to_string().into_bytes()));
+                assert_eq!(s.incr_by(b"max", -1)?, i64::MAX - 1);
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests that get_many returns values in key order, with None for missing keys.
+            fn get_many() -> CResult<()> {
+                let mut s = $setup;
+                s.set(b"c", vec![3])?;
+                s.set(b"a", vec![1])?;
+                s.set(b"b", vec![])?;
+                s.set(b"a", vec![1, 1])?;
+                s.delete(b"c")?;
+
+                let keys: [&[u8]; 5] = [b"c", b"a", b"x", b"b", b"a"];
+                assert_eq!(s.get_many(&keys)?, vec![None, Some(vec![1, 1]), None, Some(vec![]), Some(vec![1, 1])]);
+                assert_eq!(s.get_many(&[])?, Vec::<Option<Vec<u8>>>::new());
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests contains_key for present, missing and deleted keys.
+            fn contains_key() -> CResult<()> {
+                let mut s = $setup;
+                assert!(!s.contains_key(b"a"));
+
+                s.set(b"a", vec![1])?;
+                s.set(b"b", vec![])?;
+                s.set(b"c", vec![3])?;
+                s.delete(b"c")?;
+
+                assert!(s.contains_key(b"a"));
+                assert!(s.contains_key(b"b"));
+                assert!(!s.contains_key(b"c"));
+                assert!(!s.contains_key(b"x"));
+
+                s.set(b"c", vec![3, 3])?;
+                assert!(s.contains_key(b"c"));
+
+                Ok(())
+            }
+
+            #[test]
+            /// Tests delete_range with inclusive and exclusive bounds, and delete_prefix
+            /// including the 0xff prefix edge cases.
+            fn delete_range() -> CResult<()> {
+                use std::ops::Bound;
+
+                let mut s = $setup;
+                for key in [&b"a"[..], b"b", b"ba", b"bb", b"b\xff", b"b\xff\x00", b"b\xff\xff", b"c", b"\xff", b"\xff\xff"] {
+                    s.set(key, vec![1])?;
+                }
+                fn keys(s: &mut impl Engine) -> CResult<Vec<Vec<u8>>> {
+                    s.scan(..).map(|item| item.map(|(key, _)| key)).collect()
+                }
+
+                assert_eq!(s.delete_range(b"ba".to_vec()..b"bb".to_vec())?, 1);
+                assert_eq!(s.get(b"ba")?, None);
+                assert_eq!(s.get(b"bb")?, Some(vec![1]));
+                assert_eq!(s.delete_range((Bound::Excluded(b"a".to_vec()), Bound::Included(b"bb".to_vec())))?, 2);
+                assert_eq!(s.get(b"a")?, Some(vec![1]));
+                assert_eq!(s.delete_range(b"x".to_vec()..b"y".to_vec())?, 0);
+
+                assert_eq!(s.delete_prefix(b"b\xff")?, 3);
+                assert_eq!(keys(&mut s)?, vec![b"a".to_vec(), b"c".to_vec(), b"\xff".to_vec(), b"\xff\xff".to_vec()]);
+                assert_eq!(s.delete_prefix(b"\xff\xff")?, 1);
+                assert_eq!(s.delete_prefix(b"\xff")?, 1);
+                assert_eq!(s.delete_prefix(b"")?, 2);
+                assert_eq!(keys(&mut s)?, Vec::<Vec<u8>>::new());
+
+                Ok(())
+            }
+
             #[test]
             /// Tests prefix scans.
             fn scan_prefix() -> CResult<()> {
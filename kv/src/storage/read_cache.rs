@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// LogCask 的读缓存：缓存 get 读取的 value，命中时不读取日志文件。
+///
+/// 按 key 和 value 的字节数之和计算容量，超出时按插入顺序(FIFO)淘汰。容量为 0 时不缓存。
+#[derive(Debug, Default)]
+pub struct ReadCache {
+    /// 容量，字节
+    capacity: usize,
+    /// 当前缓存的字节数
+    size: usize,
+    /// key -> (插入序号, value)
+    entries: HashMap<Vec<u8>, (u64, Vec<u8>)>,
+    /// 插入序号 -> key，淘汰时从最早插入的开始
+    order: BTreeMap<u64, Vec<u8>>,
+    next_seq: u64,
+}
+
+impl ReadCache {
+    pub fn new(capacity: usize) -> Self {
+        ReadCache { capacity, ..Default::default() }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 当前缓存的字节数
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&Vec<u8>> {
+        self.entries.get(key).map(|(_, value)| value)
+    }
+
+    /// 缓存一个 value，单个超过容量的 value 不缓存
+    pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.remove(&key);
+        let len = key.len() + value.len();
+        if len > self.capacity {
+            return;
+        }
+        while self.size + len > self.capacity {
+            match self.order.first_key_value() {
+                Some((_, old)) => self.remove(&old.clone()),
+                None => break,
+            }
+        }
+        self.size += len;
+        self.order.insert(self.next_seq, key.clone());
+        self.entries.insert(key, (self.next_seq, value));
+        self.next_seq += 1;
+    }
+
+    /// 移除key的缓存，key被更新或删除时调用
+    pub fn remove(&mut self, key: &[u8]) {
+        if let Some((seq, value)) = self.entries.remove(key) {
+            self.order.remove(&seq);
+            self.size -= key.len() + value.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.size = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_in_insertion_order() {
+        let mut cache = ReadCache::new(8);
+        cache.insert(b"a".to_vec(), vec![1; 3]);
+        cache.insert(b"b".to_vec(), vec![2; 3]);
+        assert_eq!((cache.len(), cache.size()), (2, 8));
+
+        cache.insert(b"c".to_vec(), vec![3; 1]);
+        assert_eq!(cache.get(b"a"), None);
+        assert_eq!(cache.get(b"b"), Some(&vec![2; 3]));
+        assert_eq!(cache.size(), 6);
+
+        // re-inserting a key replaces its value, oversized values are not cached
+        cache.insert(b"b".to_vec(), vec![4; 1]);
+        assert_eq!((cache.get(b"b"), cache.size()), (Some(&vec![4; 1]), 4));
+        cache.insert(b"d".to_vec(), vec![0; 8]);
+        assert_eq!(cache.get(b"d"), None);
+
+        cache.remove(b"c");
+        assert_eq!(cache.size(), 2);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}